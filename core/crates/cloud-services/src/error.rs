@@ -5,7 +5,7 @@ use sd_cloud_schema::{
 };
 use sd_utils::error::FileIOError;
 
-use std::{io, net::AddrParseError};
+use std::{io, net::AddrParseError, path::Path};
 
 use quic_rpc::{
 	pattern::{bidi_streaming, rpc, server_streaming},
@@ -177,6 +177,14 @@ pub enum Error {
 	ReadNonceStreamDecryption(io::Error),
 	#[error("Incomplete download bytes sync messages")]
 	IncompleteDownloadBytesSyncMessages,
+	#[error("Failed to access sync relay directory: {0}")]
+	SyncRelayDirectory(FileIOError),
+	#[error("Invalid sync messages bundle in sync relay directory: {}", .0.display())]
+	InvalidSyncRelayBundle(Box<Path>),
+	#[error("Failed to serialize sync messages bundle header: {0}")]
+	SyncRelayBundleSerialization(rmp_serde::encode::Error),
+	#[error("Failed to deserialize sync messages bundle header: {0}")]
+	SyncRelayBundleDeserialization(rmp_serde::decode::Error),
 	#[error("Timed out while waiting to recive thumbnail data")]
 	ThumbnailRequestTimeout,
 
@@ -44,7 +44,7 @@ pub use p2p::{
 };
 pub use sync::{
 	declare_actors as declare_cloud_sync, SyncActors as CloudSyncActors,
-	SyncActorsState as CloudSyncActorsState,
+	SyncActorsState as CloudSyncActorsState, SyncTransportKind as CloudSyncTransport,
 };
 
 // Re-exports
@@ -13,16 +13,19 @@ use std::{
 	time::Duration,
 };
 
-use futures_concurrency::future::TryJoin;
 use tokio::sync::Notify;
 
 mod ingest;
 mod receive;
 mod send;
+mod transport;
 
 use ingest::Ingester;
 use receive::Receiver;
 use send::Sender;
+use transport::{CloudTransport, DirectoryTransport, SyncTransport};
+
+pub use transport::SyncTransportKind;
 
 const ONE_MINUTE: Duration = Duration::from_secs(60);
 
@@ -77,6 +80,7 @@ impl ReceiveAndIngestNotifiers {
 	}
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn declare_actors(
 	data_dir: Box<Path>,
 	cloud_services: Arc<CloudServices>,
@@ -85,28 +89,91 @@ pub async fn declare_actors(
 	sync_group_pub_id: groups::PubId,
 	sync: SyncManager,
 	rng: CryptoRng,
+	transport: SyncTransportKind,
 ) -> Result<Arc<ReceiveAndIngestNotifiers>, Error> {
-	let (sender, receiver) = (
-		Sender::new(
-			sync_group_pub_id,
-			sync.clone(),
-			Arc::clone(&cloud_services),
-			Arc::clone(&actors_state.send_active),
-			Arc::clone(&actors_state.state_change_notifier),
-			rng,
-		),
-		Receiver::new(
-			data_dir,
-			sync_group_pub_id,
-			cloud_services.clone(),
-			sync.clone(),
-			Arc::clone(&actors_state.receiver_and_ingester_notifiers),
-			Arc::clone(&actors_state.receive_active),
-			Arc::clone(&actors_state.state_change_notifier),
-		),
+	match transport {
+		SyncTransportKind::Cloud => {
+			let transport =
+				CloudTransport::new(sync_group_pub_id, Arc::clone(&cloud_services)).await?;
+
+			declare_transport_actors(
+				data_dir,
+				&cloud_services,
+				transport,
+				actors,
+				actors_state,
+				sync_group_pub_id,
+				sync,
+				rng,
+			)
+			.await?;
+
+			cloud_services
+				.cloud_p2p()
+				.await?
+				.register_sync_messages_receiver_notifier(
+					sync_group_pub_id,
+					Arc::clone(&actors_state.receiver_and_ingester_notifiers),
+				)
+				.await;
+		}
+
+		SyncTransportKind::Directory { path } => {
+			let transport = DirectoryTransport::new(path, sync_group_pub_id).await?;
+
+			declare_transport_actors(
+				data_dir,
+				&cloud_services,
+				transport,
+				actors,
+				actors_state,
+				sync_group_pub_id,
+				sync,
+				rng,
+			)
+			.await?;
+		}
+	}
+
+	Ok(Arc::clone(&actors_state.receiver_and_ingester_notifiers))
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn declare_transport_actors(
+	data_dir: Box<Path>,
+	cloud_services: &CloudServices,
+	transport: impl SyncTransport,
+	actors: &ActorsCollection<SyncActors>,
+	actors_state: &SyncActorsState,
+	sync_group_pub_id: groups::PubId,
+	sync: SyncManager,
+	rng: CryptoRng,
+) -> Result<(), Error> {
+	// Sync group keys are always managed by the cloud services key manager, whatever the transport
+	let key_manager = cloud_services.key_manager().await?;
+	let transport = Arc::new(transport);
+
+	let sender = Sender::new(
+		sync_group_pub_id,
+		sync.clone(),
+		Arc::clone(&transport),
+		Arc::clone(&key_manager),
+		Arc::clone(&actors_state.send_active),
+		Arc::clone(&actors_state.state_change_notifier),
+		rng,
+	);
+
+	let receiver = Receiver::new(
+		data_dir,
+		sync_group_pub_id,
+		transport,
+		key_manager,
+		sync.clone(),
+		Arc::clone(&actors_state.receiver_and_ingester_notifiers),
+		Arc::clone(&actors_state.receive_active),
+		Arc::clone(&actors_state.state_change_notifier),
 	)
-		.try_join()
-		.await?;
+	.await?;
 
 	let ingester = Ingester::new(
 		sync,
@@ -123,14 +190,5 @@ pub async fn declare_actors(
 		])
 		.await;
 
-	cloud_services
-		.cloud_p2p()
-		.await?
-		.register_sync_messages_receiver_notifier(
-			sync_group_pub_id,
-			Arc::clone(&actors_state.receiver_and_ingester_notifiers),
-		)
-		.await;
-
-	Ok(Arc::clone(&actors_state.receiver_and_ingester_notifiers))
+	Ok(())
 }
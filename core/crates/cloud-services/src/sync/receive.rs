@@ -1,13 +1,6 @@
-use crate::{CloudServices, Error, KeyManager};
+use crate::{Error, KeyManager};
 
-use sd_cloud_schema::{
-	devices,
-	sync::{
-		groups,
-		messages::{pull, MessagesCollection},
-	},
-	Client, Request, Response,
-};
+use sd_cloud_schema::{devices, sync::groups};
 use sd_core_sync::{
 	cloud_crdt_op_db, CRDTOperation, CompressedCRDTOperationsPerModel, SyncManager,
 };
@@ -23,6 +16,7 @@ use std::{
 	collections::{hash_map::Entry, HashMap},
 	future::IntoFuture,
 	path::Path,
+	pin::pin,
 	sync::{
 		atomic::{AtomicBool, Ordering},
 		Arc,
@@ -31,25 +25,26 @@ use std::{
 
 use chrono::{DateTime, Utc};
 use futures::{FutureExt, StreamExt};
-use futures_concurrency::future::{Race, TryJoin};
-use quic_rpc::transport::quinn::QuinnConnector;
+use futures_concurrency::future::Race;
 use serde::{Deserialize, Serialize};
 use tokio::{fs, io, sync::Notify, time::sleep};
 use tracing::{debug, error, instrument, warn};
 use uuid::Uuid;
 
-use super::{ReceiveAndIngestNotifiers, SyncActors, ONE_MINUTE};
+use super::{
+	transport::{SyncMessagesBundle, SyncTransport},
+	ReceiveAndIngestNotifiers, SyncActors, ONE_MINUTE,
+};
 
 const CLOUD_SYNC_DATA_KEEPER_DIRECTORY: &str = "cloud_sync_data_keeper";
 
-/// Responsible for downloading sync operations from the cloud to be processed by the ingester
+/// Responsible for downloading sync operations from the transport to be processed by the ingester
 
-pub struct Receiver {
+pub struct Receiver<Transport> {
 	keeper: LastTimestampKeeper,
 	sync_group_pub_id: groups::PubId,
 	device_pub_id: devices::PubId,
-	cloud_services: Arc<CloudServices>,
-	cloud_client: Client<QuinnConnector<Response, Request>>,
+	transport: Arc<Transport>,
 	key_manager: Arc<KeyManager>,
 	sync: SyncManager,
 	notifiers: Arc<ReceiveAndIngestNotifiers>,
@@ -57,7 +52,7 @@ pub struct Receiver {
 	active_notifier: Arc<Notify>,
 }
 
-impl Actor<SyncActors> for Receiver {
+impl<Transport: SyncTransport> Actor<SyncActors> for Receiver<Transport> {
 	const IDENTIFIER: SyncActors = SyncActors::Receiver;
 
 	async fn run(&mut self, stop: Stopper) {
@@ -100,30 +95,30 @@ impl Actor<SyncActors> for Receiver {
 	}
 }
 
-impl Receiver {
+impl<Transport: SyncTransport> Receiver<Transport> {
+	#[allow(clippy::too_many_arguments)]
 	pub async fn new(
 		data_dir: impl AsRef<Path> + Send,
 		sync_group_pub_id: groups::PubId,
-		cloud_services: Arc<CloudServices>,
+		transport: Arc<Transport>,
+		key_manager: Arc<KeyManager>,
 		sync: SyncManager,
 		notifiers: Arc<ReceiveAndIngestNotifiers>,
 		active: Arc<AtomicBool>,
 		active_notify: Arc<Notify>,
 	) -> Result<Self, Error> {
-		let (keeper, cloud_client, key_manager) = (
-			LastTimestampKeeper::load(data_dir.as_ref(), sync_group_pub_id),
-			cloud_services.client(),
-			cloud_services.key_manager(),
+		let keeper = LastTimestampKeeper::load(
+			data_dir.as_ref(),
+			sync_group_pub_id,
+			Transport::TIMESTAMP_KEEPER_SUFFIX,
 		)
-			.try_join()
-			.await?;
+		.await?;
 
 		Ok(Self {
 			keeper,
 			sync_group_pub_id,
 			device_pub_id: devices::PubId(Uuid::from(&sync.device_pub_id)),
-			cloud_services,
-			cloud_client,
+			transport,
 			key_manager,
 			sync,
 			notifiers,
@@ -133,29 +128,19 @@ impl Receiver {
 	}
 
 	async fn run_loop_iteration(&mut self) -> Result<(), Error> {
-		let mut responses_stream = self
-			.cloud_client
-			.sync()
-			.messages()
-			.pull(pull::Request {
-				access_token: self
-					.cloud_services
-					.token_refresher
-					.get_access_token()
-					.await?,
-				group_pub_id: self.sync_group_pub_id,
-				current_device_pub_id: self.device_pub_id,
-				start_time_per_device: self
-					.keeper
-					.timestamps
-					.iter()
-					.map(|(device_pub_id, timestamp)| (*device_pub_id, *timestamp))
-					.collect(),
-			})
-			.await?;
+		let transport = Arc::clone(&self.transport);
+
+		let mut responses_stream = pin!(transport.pull(
+			self.device_pub_id,
+			self.keeper
+				.timestamps
+				.iter()
+				.map(|(device_pub_id, timestamp)| (*device_pub_id, *timestamp))
+				.collect(),
+		));
 
 		while let Some(new_messages_res) = responses_stream.next().await {
-			let pull::Response(new_messages) = new_messages_res??;
+			let new_messages = new_messages_res?;
 			if new_messages.is_empty() {
 				break;
 			}
@@ -170,7 +155,7 @@ impl Receiver {
 
 	async fn handle_new_messages(
 		&mut self,
-		new_messages: Vec<MessagesCollection>,
+		new_messages: Vec<SyncMessagesBundle>,
 	) -> Result<(), Error> {
 		debug!(
 			new_messages_collections_count = new_messages.len(),
@@ -230,14 +215,14 @@ impl Receiver {
 )]
 async fn handle_single_message(
 	sync_group_pub_id: groups::PubId,
-	MessagesCollection {
+	SyncMessagesBundle {
 		original_device_pub_id,
 		end_time,
 		operations_count,
 		key_hash,
 		encrypted_messages,
 		..
-	}: MessagesCollection,
+	}: SyncMessagesBundle,
 	key_manager: &KeyManager,
 	sync: &SyncManager,
 ) -> Result<(devices::PubId, DateTime<Utc>), Error> {
@@ -317,7 +302,11 @@ struct LastTimestampKeeper {
 }
 
 impl LastTimestampKeeper {
-	async fn load(data_dir: &Path, sync_group_pub_id: groups::PubId) -> Result<Self, Error> {
+	async fn load(
+		data_dir: &Path,
+		sync_group_pub_id: groups::PubId,
+		suffix: &str,
+	) -> Result<Self, Error> {
 		let cloud_sync_data_directory = data_dir.join(CLOUD_SYNC_DATA_KEEPER_DIRECTORY);
 
 		fs::create_dir_all(&cloud_sync_data_directory)
@@ -325,7 +314,7 @@ impl LastTimestampKeeper {
 			.map_err(Error::FailedToCreateTimestampKeepersDirectory)?;
 
 		let file_path = cloud_sync_data_directory
-			.join(format!("{sync_group_pub_id}.bin"))
+			.join(format!("{sync_group_pub_id}{suffix}.bin"))
 			.into_boxed_path();
 
 		match fs::read(&file_path).await {
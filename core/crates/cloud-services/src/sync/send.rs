@@ -1,14 +1,9 @@
-use crate::{CloudServices, Error, KeyManager};
+use crate::{Error, KeyManager};
 
 use sd_core_sync::{CompressedCRDTOperationsPerModelPerDevice, SyncEvent, SyncManager, NTP64};
 
 use sd_actors::{Actor, Stopper};
-use sd_cloud_schema::{
-	devices,
-	error::{ClientSideError, NotFoundError},
-	sync::{groups, messages},
-	Client, Request, Response,
-};
+use sd_cloud_schema::{devices, sync::groups};
 use sd_crypto::{
 	cloud::{OneShotEncryption, SecretKey, StreamEncryption},
 	primitives::EncryptedBlock,
@@ -28,8 +23,7 @@ use std::{
 
 use chrono::{DateTime, Utc};
 use futures::{FutureExt, StreamExt, TryStreamExt};
-use futures_concurrency::future::{Race, TryJoin};
-use quic_rpc::transport::quinn::QuinnConnector;
+use futures_concurrency::future::Race;
 use tokio::{
 	sync::{broadcast, Notify},
 	time::sleep,
@@ -37,7 +31,10 @@ use tokio::{
 use tracing::{debug, error};
 use uuid::Uuid;
 
-use super::{SyncActors, ONE_MINUTE};
+use super::{
+	transport::{SyncMessagesBundle, SyncTransport},
+	SyncActors, ONE_MINUTE,
+};
 
 const TEN_SECONDS: Duration = Duration::from_secs(10);
 
@@ -56,11 +53,10 @@ enum LoopStatus {
 type LatestTimestamp = NTP64;
 
 #[derive(Debug)]
-pub struct Sender {
+pub struct Sender<Transport> {
 	sync_group_pub_id: groups::PubId,
 	sync: SyncManager,
-	cloud_services: Arc<CloudServices>,
-	cloud_client: Client<QuinnConnector<Response, Request>>,
+	transport: Arc<Transport>,
 	key_manager: Arc<KeyManager>,
 	is_active: Arc<AtomicBool>,
 	state_notify: Arc<Notify>,
@@ -68,7 +64,7 @@ pub struct Sender {
 	maybe_latest_timestamp: Option<LatestTimestamp>,
 }
 
-impl Actor<SyncActors> for Sender {
+impl<Transport: SyncTransport> Actor<SyncActors> for Sender<Transport> {
 	const IDENTIFIER: SyncActors = SyncActors::Sender;
 
 	async fn run(&mut self, stop: Stopper) {
@@ -81,15 +77,7 @@ impl Actor<SyncActors> for Sender {
 			self.is_active.store(false, Ordering::Relaxed);
 
 			match res {
-				Ok(LoopStatus::SentMessages) => {
-					if let Ok(cloud_p2p) = self.cloud_services.cloud_p2p().await.map_err(|e| {
-						error!(?e, "Failed to get cloud p2p client on sender actor");
-					}) {
-						cloud_p2p
-							.notify_new_sync_messages(self.sync_group_pub_id)
-							.await;
-					}
-				}
+				Ok(LoopStatus::SentMessages) => self.transport.notify_new_messages().await,
 
 				Ok(LoopStatus::Idle) => {}
 
@@ -120,30 +108,26 @@ impl Actor<SyncActors> for Sender {
 	}
 }
 
-impl Sender {
-	pub async fn new(
+impl<Transport: SyncTransport> Sender<Transport> {
+	pub const fn new(
 		sync_group_pub_id: groups::PubId,
 		sync: SyncManager,
-		cloud_services: Arc<CloudServices>,
+		transport: Arc<Transport>,
+		key_manager: Arc<KeyManager>,
 		is_active: Arc<AtomicBool>,
 		state_notify: Arc<Notify>,
 		rng: CryptoRng,
-	) -> Result<Self, Error> {
-		let (cloud_client, key_manager) = (cloud_services.client(), cloud_services.key_manager())
-			.try_join()
-			.await?;
-
-		Ok(Self {
+	) -> Self {
+		Self {
 			sync_group_pub_id,
 			sync,
-			cloud_services,
-			cloud_client,
+			transport,
 			key_manager,
 			is_active,
 			state_notify,
 			rng,
 			maybe_latest_timestamp: None,
-		})
+		}
 	}
 
 	async fn run_loop_iteration(&mut self) -> Result<LoopStatus, Error> {
@@ -206,30 +190,23 @@ impl Sender {
 
 			debug!(
 				operations_count,
-				encrypted_messages_size, "Sending sync messages to cloud",
+				encrypted_messages_size, "Sending sync messages",
 			);
 
-			self.cloud_client
-				.sync()
-				.messages()
-				.push(messages::push::Request {
-					access_token: self
-						.cloud_services
-						.token_refresher
-						.get_access_token()
-						.await?,
-					group_pub_id: self.sync_group_pub_id,
-					device_pub_id: current_device_pub_id,
+			self.transport
+				.push(SyncMessagesBundle {
+					original_device_pub_id: current_device_pub_id,
 					key_hash: key_hash.clone(),
 					operations_count,
-					time_range: (start_time, end_time),
+					start_time,
+					end_time,
 					encrypted_messages,
 				})
-				.await??;
+				.await?;
 
 			debug!(
 				operations_count,
-				encrypted_messages_size, "Sent sync messages to cloud",
+				encrypted_messages_size, "Sent sync messages",
 			);
 
 			status = LoopStatus::SentMessages;
@@ -249,35 +226,11 @@ impl Sender {
 		if let Some(latest_timestamp) = &self.maybe_latest_timestamp {
 			Ok(*latest_timestamp)
 		} else {
-			let latest_time = match self
-				.cloud_client
-				.sync()
-				.messages()
-				.get_latest_time(messages::get_latest_time::Request {
-					access_token: self
-						.cloud_services
-						.token_refresher
-						.get_access_token()
-						.await?,
-					group_pub_id: self.sync_group_pub_id,
-					kind: messages::get_latest_time::Kind::ForCurrentDevice(current_device_pub_id),
-				})
+			let latest_time = self
+				.transport
+				.latest_time(current_device_pub_id)
 				.await?
-			{
-				Ok(messages::get_latest_time::Response {
-					latest_time,
-					latest_device_pub_id,
-				}) => {
-					assert_eq!(latest_device_pub_id, current_device_pub_id);
-					latest_time
-				}
-
-				Err(sd_cloud_schema::Error::Client(ClientSideError::NotFound(
-					NotFoundError::LatestSyncMessageTime,
-				))) => DateTime::<Utc>::from(UNIX_EPOCH),
-
-				Err(e) => return Err(e.into()),
-			};
+				.unwrap_or_else(|| DateTime::<Utc>::from(UNIX_EPOCH));
 
 			Ok(datetime_to_timestamp(latest_time))
 		}
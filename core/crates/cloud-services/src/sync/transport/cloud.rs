@@ -0,0 +1,155 @@
+use crate::{CloudServices, Error};
+
+use sd_cloud_schema::{
+	devices,
+	error::{ClientSideError, NotFoundError},
+	sync::{groups, messages},
+	Client, Request, Response,
+};
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt};
+use quic_rpc::transport::quinn::QuinnConnector;
+use tracing::error;
+
+use super::{SyncMessagesBundle, SyncTransport};
+
+/// Sync messages exchanged through Spacedrive Cloud Services
+#[derive(Debug)]
+pub struct CloudTransport {
+	sync_group_pub_id: groups::PubId,
+	cloud_services: Arc<CloudServices>,
+	cloud_client: Client<QuinnConnector<Response, Request>>,
+}
+
+impl CloudTransport {
+	pub async fn new(
+		sync_group_pub_id: groups::PubId,
+		cloud_services: Arc<CloudServices>,
+	) -> Result<Self, Error> {
+		Ok(Self {
+			sync_group_pub_id,
+			cloud_client: cloud_services.client().await?,
+			cloud_services,
+		})
+	}
+}
+
+impl SyncTransport for CloudTransport {
+	const TIMESTAMP_KEEPER_SUFFIX: &'static str = "";
+
+	async fn latest_time(
+		&self,
+		device_pub_id: devices::PubId,
+	) -> Result<Option<DateTime<Utc>>, Error> {
+		match self
+			.cloud_client
+			.sync()
+			.messages()
+			.get_latest_time(messages::get_latest_time::Request {
+				access_token: self
+					.cloud_services
+					.token_refresher
+					.get_access_token()
+					.await?,
+				group_pub_id: self.sync_group_pub_id,
+				kind: messages::get_latest_time::Kind::ForCurrentDevice(device_pub_id),
+			})
+			.await?
+		{
+			Ok(messages::get_latest_time::Response {
+				latest_time,
+				latest_device_pub_id,
+			}) => {
+				assert_eq!(latest_device_pub_id, device_pub_id);
+				Ok(Some(latest_time))
+			}
+
+			Err(sd_cloud_schema::Error::Client(ClientSideError::NotFound(
+				NotFoundError::LatestSyncMessageTime,
+			))) => Ok(None),
+
+			Err(e) => Err(e.into()),
+		}
+	}
+
+	async fn push(
+		&self,
+		SyncMessagesBundle {
+			original_device_pub_id,
+			key_hash,
+			operations_count,
+			start_time,
+			end_time,
+			encrypted_messages,
+		}: SyncMessagesBundle,
+	) -> Result<(), Error> {
+		self.cloud_client
+			.sync()
+			.messages()
+			.push(messages::push::Request {
+				access_token: self
+					.cloud_services
+					.token_refresher
+					.get_access_token()
+					.await?,
+				group_pub_id: self.sync_group_pub_id,
+				device_pub_id: original_device_pub_id,
+				key_hash,
+				operations_count,
+				time_range: (start_time, end_time),
+				encrypted_messages,
+			})
+			.await??;
+
+		Ok(())
+	}
+
+	async fn notify_new_messages(&self) {
+		if let Ok(cloud_p2p) = self.cloud_services.cloud_p2p().await.map_err(|e| {
+			error!(
+				?e,
+				"Failed to get cloud p2p client to notify new sync messages"
+			);
+		}) {
+			cloud_p2p
+				.notify_new_sync_messages(self.sync_group_pub_id)
+				.await;
+		}
+	}
+
+	fn pull(
+		&self,
+		current_device_pub_id: devices::PubId,
+		start_time_per_device: Vec<(devices::PubId, DateTime<Utc>)>,
+	) -> impl Stream<Item = Result<Vec<SyncMessagesBundle>, Error>> + Send + '_ {
+		async_stream::try_stream! {
+			let mut responses_stream = self
+				.cloud_client
+				.sync()
+				.messages()
+				.pull(messages::pull::Request {
+					access_token: self
+						.cloud_services
+						.token_refresher
+						.get_access_token()
+						.await?,
+					group_pub_id: self.sync_group_pub_id,
+					current_device_pub_id,
+					start_time_per_device,
+				})
+				.await?;
+
+			while let Some(new_messages_res) = responses_stream.next().await {
+				let messages::pull::Response(new_messages) = new_messages_res??;
+
+				yield new_messages
+					.into_iter()
+					.map(SyncMessagesBundle::from)
+					.collect::<Vec<_>>();
+			}
+		}
+	}
+}
@@ -0,0 +1,418 @@
+use crate::Error;
+
+use sd_cloud_schema::{
+	devices,
+	sync::{groups, KeyHash},
+};
+use sd_utils::error::FileIOError;
+
+use std::{
+	collections::HashMap,
+	path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+use tokio::{fs, io};
+use tracing::{trace, warn};
+use uuid::Uuid;
+
+use super::{SyncMessagesBundle, SyncTransport};
+
+const BUNDLE_EXTENSION: &str = "sdsync";
+const TEMP_EXTENSION: &str = "sdsync-tmp";
+
+/// How many bundles are read from disk before yielding them to the receiver
+const PULL_CHUNK_SIZE: usize = 16;
+
+/// Sync messages exchanged through a directory shared between devices.
+///
+/// Each device only writes to its own sub-directory, so the directory can be synchronized by any
+/// tool without conflicts. The layout is:
+/// `<root>/<sync_group_pub_id>/<device_pub_id>/<start_time>-<end_time>.sdsync`
+///
+/// Bundles are written to a temporary file and renamed when complete, so readers never see
+/// partially written bundles.
+#[derive(Debug)]
+pub struct DirectoryTransport {
+	group_dir: PathBuf,
+}
+
+/// Metadata written before the encrypted messages on each bundle file
+#[derive(Serialize, Deserialize)]
+struct BundleHeader {
+	key_hash: String,
+	operations_count: u32,
+	start_time: DateTime<Utc>,
+	end_time: DateTime<Utc>,
+}
+
+struct BundleFile {
+	path: PathBuf,
+	end_time: DateTime<Utc>,
+}
+
+impl DirectoryTransport {
+	pub async fn new(
+		root: impl AsRef<Path>,
+		sync_group_pub_id: groups::PubId,
+	) -> Result<Self, Error> {
+		let group_dir = root.as_ref().join(sync_group_pub_id.to_string());
+
+		fs::create_dir_all(&group_dir)
+			.await
+			.map_err(|e| Error::SyncRelayDirectory(FileIOError::from((&group_dir, e))))?;
+
+		Ok(Self { group_dir })
+	}
+
+	fn device_dir(&self, devices::PubId(device_pub_id): devices::PubId) -> PathBuf {
+		self.group_dir.join(device_pub_id.to_string())
+	}
+
+	async fn devices(&self) -> Result<Vec<devices::PubId>, Error> {
+		let mut read_dir = fs::read_dir(&self.group_dir)
+			.await
+			.map_err(|e| Error::SyncRelayDirectory(FileIOError::from((&self.group_dir, e))))?;
+
+		let mut devices = vec![];
+
+		while let Some(entry) = read_dir
+			.next_entry()
+			.await
+			.map_err(|e| Error::SyncRelayDirectory(FileIOError::from((&self.group_dir, e))))?
+		{
+			if let Some(device_pub_id) = entry
+				.file_name()
+				.to_str()
+				.and_then(|name| Uuid::parse_str(name).ok())
+			{
+				devices.push(devices::PubId(device_pub_id));
+			}
+		}
+
+		Ok(devices)
+	}
+}
+
+/// Lists the complete bundles of a device directory sorted by their end time, ignoring any
+/// other files like temporary ones from us or from the tool synchronizing the directory
+async fn list_bundles(device_dir: &Path) -> Result<Vec<BundleFile>, Error> {
+	let mut read_dir = match fs::read_dir(device_dir).await {
+		Ok(read_dir) => read_dir,
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+		Err(e) => {
+			return Err(Error::SyncRelayDirectory(FileIOError::from((
+				device_dir, e,
+			))))
+		}
+	};
+
+	let mut bundles = vec![];
+
+	while let Some(entry) = read_dir
+		.next_entry()
+		.await
+		.map_err(|e| Error::SyncRelayDirectory(FileIOError::from((device_dir, e))))?
+	{
+		let path = entry.path();
+
+		if path.extension().and_then(|ext| ext.to_str()) != Some(BUNDLE_EXTENSION) {
+			continue;
+		}
+
+		let Some(end_time) = bundle_end_time(&path) else {
+			warn!(path = %path.display(), "Ignoring file with invalid name in sync relay directory");
+			continue;
+		};
+
+		bundles.push(BundleFile { path, end_time });
+	}
+
+	bundles.sort_by_key(|bundle| bundle.end_time);
+
+	Ok(bundles)
+}
+
+/// End time of a bundle, taken from its file name
+fn bundle_end_time(path: &Path) -> Option<DateTime<Utc>> {
+	path.file_stem()
+		.and_then(|stem| stem.to_str())
+		.and_then(|stem| stem.split_once('-'))
+		.and_then(|(_, end)| end.parse::<i64>().ok())
+		.map(DateTime::from_timestamp_nanos)
+}
+
+fn bundle_file_name(start_time: DateTime<Utc>, end_time: DateTime<Utc>) -> String {
+	format!(
+		"{:020}-{:020}",
+		start_time.timestamp_nanos_opt().unwrap_or_default(),
+		end_time.timestamp_nanos_opt().unwrap_or(i64::MAX)
+	)
+}
+
+async fn read_bundle(
+	original_device_pub_id: devices::PubId,
+	path: &Path,
+) -> Result<SyncMessagesBundle, Error> {
+	let bytes = fs::read(path)
+		.await
+		.map_err(|e| Error::SyncRelayDirectory(FileIOError::from((path, e))))?;
+
+	decode_bundle(original_device_pub_id, path, &bytes)
+}
+
+/// A bundle file is the size of its header as a big endian `u32`, the header and then the
+/// encrypted messages
+fn encode_bundle(
+	SyncMessagesBundle {
+		key_hash: KeyHash(key_hash),
+		operations_count,
+		start_time,
+		end_time,
+		encrypted_messages,
+		..
+	}: SyncMessagesBundle,
+) -> Result<Vec<u8>, Error> {
+	let header = rmp_serde::to_vec_named(&BundleHeader {
+		key_hash,
+		operations_count,
+		start_time,
+		end_time,
+	})
+	.map_err(Error::SyncRelayBundleSerialization)?;
+
+	let mut bytes = Vec::with_capacity(4 + header.len() + encrypted_messages.len());
+	#[allow(clippy::cast_possible_truncation)] // A header is just a few bytes
+	bytes.extend_from_slice(&(header.len() as u32).to_be_bytes());
+	bytes.extend(header);
+	bytes.extend(encrypted_messages);
+
+	Ok(bytes)
+}
+
+fn decode_bundle(
+	original_device_pub_id: devices::PubId,
+	path: &Path,
+	bytes: &[u8],
+) -> Result<SyncMessagesBundle, Error> {
+	let invalid = || Error::InvalidSyncRelayBundle(path.into());
+
+	let (header_size, rest) = bytes.split_first_chunk::<4>().ok_or_else(invalid)?;
+	let header_size = u32::from_be_bytes(*header_size) as usize;
+
+	if rest.len() < header_size {
+		return Err(invalid());
+	}
+
+	let (header, encrypted_messages) = rest.split_at(header_size);
+
+	let BundleHeader {
+		key_hash,
+		operations_count,
+		start_time,
+		end_time,
+	} = rmp_serde::from_slice(header).map_err(Error::SyncRelayBundleDeserialization)?;
+
+	Ok(SyncMessagesBundle {
+		original_device_pub_id,
+		key_hash: KeyHash(key_hash),
+		operations_count,
+		start_time,
+		end_time,
+		encrypted_messages: encrypted_messages.to_vec(),
+	})
+}
+
+impl SyncTransport for DirectoryTransport {
+	const TIMESTAMP_KEEPER_SUFFIX: &'static str = "-directory";
+
+	async fn latest_time(
+		&self,
+		device_pub_id: devices::PubId,
+	) -> Result<Option<DateTime<Utc>>, Error> {
+		Ok(list_bundles(&self.device_dir(device_pub_id))
+			.await?
+			.last()
+			.map(|bundle| bundle.end_time))
+	}
+
+	async fn push(&self, bundle: SyncMessagesBundle) -> Result<(), Error> {
+		let device_dir = self.device_dir(bundle.original_device_pub_id);
+
+		fs::create_dir_all(&device_dir)
+			.await
+			.map_err(|e| Error::SyncRelayDirectory(FileIOError::from((&device_dir, e))))?;
+
+		let file_name = bundle_file_name(bundle.start_time, bundle.end_time);
+		let operations_count = bundle.operations_count;
+		let bytes = encode_bundle(bundle)?;
+
+		let temp_path = device_dir.join(&file_name).with_extension(TEMP_EXTENSION);
+		let path = device_dir.join(file_name).with_extension(BUNDLE_EXTENSION);
+
+		fs::write(&temp_path, bytes)
+			.await
+			.map_err(|e| Error::SyncRelayDirectory(FileIOError::from((&temp_path, e))))?;
+
+		fs::rename(&temp_path, &path)
+			.await
+			.map_err(|e| Error::SyncRelayDirectory(FileIOError::from((&path, e))))?;
+
+		trace!(path = %path.display(), operations_count, "Wrote sync messages bundle");
+
+		Ok(())
+	}
+
+	async fn notify_new_messages(&self) {
+		// Other devices poll the directory, there's no one to notify
+	}
+
+	fn pull(
+		&self,
+		current_device_pub_id: devices::PubId,
+		start_time_per_device: Vec<(devices::PubId, DateTime<Utc>)>,
+	) -> impl Stream<Item = Result<Vec<SyncMessagesBundle>, Error>> + Send + '_ {
+		async_stream::try_stream! {
+			let start_time_per_device = start_time_per_device
+				.into_iter()
+				.collect::<HashMap<_, _>>();
+
+			for device_pub_id in self.devices().await? {
+				if device_pub_id == current_device_pub_id {
+					continue;
+				}
+
+				let start_time = start_time_per_device.get(&device_pub_id).copied();
+
+				let bundles = list_bundles(&self.device_dir(device_pub_id))
+					.await?
+					.into_iter()
+					.filter(|bundle| start_time.map_or(true, |start_time| bundle.end_time > start_time))
+					.collect::<Vec<_>>();
+
+				for chunk in bundles.chunks(PULL_CHUNK_SIZE) {
+					let mut new_messages = Vec::with_capacity(chunk.len());
+
+					for BundleFile { path, .. } in chunk {
+						new_messages.push(read_bundle(device_pub_id, path).await?);
+					}
+
+					yield new_messages;
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use futures::StreamExt;
+	use tempfile::TempDir;
+
+	use super::*;
+
+	fn bundle(
+		device_pub_id: devices::PubId,
+		start_time: DateTime<Utc>,
+		end_time: DateTime<Utc>,
+	) -> SyncMessagesBundle {
+		SyncMessagesBundle {
+			original_device_pub_id: device_pub_id,
+			key_hash: KeyHash("key-hash".to_string()),
+			operations_count: 3,
+			start_time,
+			end_time,
+			encrypted_messages: vec![1, 2, 3, 4],
+		}
+	}
+
+	fn time(nanos: i64) -> DateTime<Utc> {
+		DateTime::from_timestamp_nanos(nanos)
+	}
+
+	#[test]
+	fn bundle_file_names_are_parsed_back() {
+		let file_name = bundle_file_name(time(10), time(1_000));
+		assert_eq!(file_name, "00000000000000000010-00000000000000001000");
+
+		let path = Path::new("device")
+			.join(file_name)
+			.with_extension(BUNDLE_EXTENSION);
+		assert_eq!(bundle_end_time(&path), Some(time(1_000)));
+
+		assert_eq!(bundle_end_time(Path::new("device/notes.sdsync")), None);
+		assert_eq!(bundle_end_time(Path::new("device/10-later.sdsync")), None);
+	}
+
+	#[test]
+	fn bundle_header_round_trip() {
+		let device_pub_id = devices::PubId(Uuid::new_v4());
+		let path = Path::new("bundle.sdsync");
+
+		let bytes = encode_bundle(bundle(device_pub_id, time(10), time(20))).unwrap();
+		let decoded = decode_bundle(device_pub_id, path, &bytes).unwrap();
+
+		assert_eq!(decoded.original_device_pub_id, device_pub_id);
+		assert_eq!(decoded.key_hash.0, "key-hash");
+		assert_eq!(decoded.operations_count, 3);
+		assert_eq!(decoded.start_time, time(10));
+		assert_eq!(decoded.end_time, time(20));
+		assert_eq!(decoded.encrypted_messages, vec![1, 2, 3, 4]);
+
+		// A header size larger than the file means it was truncated
+		assert!(matches!(
+			decode_bundle(device_pub_id, path, &bytes[..8]),
+			Err(Error::InvalidSyncRelayBundle(_))
+		));
+		assert!(matches!(
+			decode_bundle(device_pub_id, path, &[0, 0]),
+			Err(Error::InvalidSyncRelayBundle(_))
+		));
+	}
+
+	#[tokio::test]
+	async fn pull_skips_own_and_already_received_bundles() {
+		let root = TempDir::new().unwrap();
+		let transport = DirectoryTransport::new(root.path(), groups::PubId(Uuid::new_v4()))
+			.await
+			.unwrap();
+
+		let current_device = devices::PubId(Uuid::new_v4());
+		let synced_device = devices::PubId(Uuid::new_v4());
+		let new_device = devices::PubId(Uuid::new_v4());
+
+		for (device_pub_id, start, end) in [
+			(current_device, 0, 10),
+			(synced_device, 0, 10),
+			(synced_device, 10, 20),
+			(new_device, 0, 5),
+		] {
+			transport
+				.push(bundle(device_pub_id, time(start), time(end)))
+				.await
+				.unwrap();
+		}
+
+		assert_eq!(
+			transport.latest_time(synced_device).await.unwrap(),
+			Some(time(20))
+		);
+
+		let mut pulled = transport
+			.pull(current_device, vec![(synced_device, time(10))])
+			.map(Result::unwrap)
+			.concat()
+			.await
+			.into_iter()
+			.map(|bundle| (bundle.original_device_pub_id, bundle.end_time))
+			.collect::<Vec<_>>();
+		pulled.sort_by_key(|(_, end_time)| *end_time);
+
+		assert_eq!(
+			pulled,
+			vec![(new_device, time(5)), (synced_device, time(20))]
+		);
+	}
+}
@@ -0,0 +1,96 @@
+use crate::Error;
+
+use sd_cloud_schema::{
+	devices,
+	sync::{messages::MessagesCollection, KeyHash},
+};
+
+use std::{future::Future, path::PathBuf};
+
+use chrono::{DateTime, Utc};
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+mod cloud;
+mod directory;
+
+pub use cloud::CloudTransport;
+pub use directory::DirectoryTransport;
+
+/// Which backend a library uses to exchange its encrypted sync messages with other devices
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[specta(rename = "CloudSyncTransport")]
+pub enum SyncTransportKind {
+	/// Spacedrive Cloud Services
+	#[default]
+	Cloud,
+	/// A directory shared between devices, like a NAS mount or a Syncthing folder, where each
+	/// device writes its own bundles of sync messages and reads the ones from other devices
+	Directory { path: PathBuf },
+}
+
+/// A collection of sync messages from a single device, already encrypted with the sync group key
+#[derive(Debug, Clone)]
+pub struct SyncMessagesBundle {
+	pub original_device_pub_id: devices::PubId,
+	pub key_hash: KeyHash,
+	pub operations_count: u32,
+	pub start_time: DateTime<Utc>,
+	pub end_time: DateTime<Utc>,
+	pub encrypted_messages: Vec<u8>,
+}
+
+impl From<MessagesCollection> for SyncMessagesBundle {
+	fn from(
+		MessagesCollection {
+			original_device_pub_id,
+			key_hash,
+			operations_count,
+			start_time,
+			end_time,
+			encrypted_messages,
+			..
+		}: MessagesCollection,
+	) -> Self {
+		Self {
+			original_device_pub_id,
+			key_hash,
+			operations_count,
+			start_time,
+			end_time,
+			encrypted_messages,
+		}
+	}
+}
+
+/// Backend used by the sync sender and receiver actors to push and pull encrypted sync messages.
+///
+/// Implementors only move opaque encrypted bundles around, encryption and decryption are done by
+/// the actors with the sync group keys from the [`KeyManager`](crate::KeyManager).
+pub trait SyncTransport: Send + Sync + 'static {
+	/// Suffix for the receiver's last timestamps file, so switching transports doesn't make the
+	/// receiver skip messages it never pulled from the new one
+	const TIMESTAMP_KEEPER_SUFFIX: &'static str;
+
+	/// End time of the latest bundle pushed by the given device, `None` if it never pushed any
+	fn latest_time(
+		&self,
+		device_pub_id: devices::PubId,
+	) -> impl Future<Output = Result<Option<DateTime<Utc>>, Error>> + Send;
+
+	fn push(&self, bundle: SyncMessagesBundle) -> impl Future<Output = Result<(), Error>> + Send;
+
+	/// Called after a batch of bundles was pushed, so other devices can pull them right away
+	fn notify_new_messages(&self) -> impl Future<Output = ()> + Send;
+
+	/// Streams chunks of bundles from other devices ending after the given start times, devices
+	/// missing from `start_time_per_device` are pulled from the beginning.
+	/// Bundles from each device MUST be yielded in order.
+	fn pull(
+		&self,
+		current_device_pub_id: devices::PubId,
+		start_time_per_device: Vec<(devices::PubId, DateTime<Utc>)>,
+	) -> impl Stream<Item = Result<Vec<SyncMessagesBundle>, Error>> + Send + '_;
+}
@@ -1,6 +1,11 @@
 use sd_core_prisma_helpers::CasId;
 
-use std::path::Path;
+use std::{
+	collections::HashMap,
+	path::{Path, PathBuf},
+	sync::{LazyLock, Mutex, PoisonError},
+	time::{Duration, Instant, SystemTime},
+};
 
 use blake3::Hasher;
 use static_assertions::const_assert;
@@ -23,6 +28,58 @@ const_assert!((HEADER_OR_FOOTER_SIZE * 2 + SAMPLE_COUNT * SAMPLE_SIZE) < MINIMUM
 // Asserting that the sample size is larger than header/footer size, as the same buffer is used for both
 const_assert!(SAMPLE_SIZE > HEADER_OR_FOOTER_SIZE);
 
+/// Known cas ids are dropped after this long, in case their files never get identified
+const KNOWN_CAS_IDS_TTL: Duration = Duration::from_secs(60 * 60);
+
+struct KnownCasId {
+	date_modified: SystemTime,
+	registered_at: Instant,
+	cas_id: CasId<'static>,
+}
+
+static KNOWN_CAS_IDS: LazyLock<Mutex<HashMap<PathBuf, KnownCasId>>> =
+	LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Registers cas ids already generated for files outside of a location, like while browsing
+/// them before they were indexed, so identifying these files doesn't have to read them again.
+///
+/// Each cas id is only used once, and only while its file keeps the given modification date.
+pub fn register_known_cas_ids(
+	known_cas_ids: impl IntoIterator<Item = (PathBuf, SystemTime, CasId<'static>)>,
+) {
+	let mut known = KNOWN_CAS_IDS.lock().unwrap_or_else(PoisonError::into_inner);
+
+	known.retain(|_, known_cas_id| known_cas_id.registered_at.elapsed() < KNOWN_CAS_IDS_TTL);
+
+	let registered_at = Instant::now();
+	known.extend(
+		known_cas_ids
+			.into_iter()
+			.map(|(path, date_modified, cas_id)| {
+				(
+					path,
+					KnownCasId {
+						date_modified,
+						registered_at,
+						cas_id,
+					},
+				)
+			}),
+	);
+}
+
+pub(super) fn take_known_cas_id(path: &Path, date_modified: SystemTime) -> Option<CasId<'static>> {
+	KNOWN_CAS_IDS
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+		.remove(path)
+		.filter(|known_cas_id| {
+			known_cas_id.date_modified == date_modified
+				&& known_cas_id.registered_at.elapsed() < KNOWN_CAS_IDS_TTL
+		})
+		.map(|known_cas_id| known_cas_id.cas_id)
+}
+
 #[instrument(
 	skip(path),
 	ret(level = Level::TRACE),
@@ -78,3 +135,39 @@ pub async fn generate_cas_id(
 
 	Ok(hasher.finalize().to_hex()[..16].to_string().into())
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn known_cas_ids_are_used_once_and_only_while_unmodified() {
+		let date_modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+		let unmodified = PathBuf::from("/known_cas_ids/unmodified.txt");
+		let modified = PathBuf::from("/known_cas_ids/modified.txt");
+
+		register_known_cas_ids([
+			(
+				unmodified.clone(),
+				date_modified,
+				CasId::from("0123456789abcdef".to_string()),
+			),
+			(
+				modified.clone(),
+				date_modified,
+				CasId::from("fedcba9876543210".to_string()),
+			),
+		]);
+
+		assert_eq!(
+			take_known_cas_id(&unmodified, date_modified),
+			Some(CasId::from("0123456789abcdef".to_string()))
+		);
+		assert_eq!(take_known_cas_id(&unmodified, date_modified), None);
+
+		assert_eq!(
+			take_known_cas_id(&modified, date_modified + Duration::from_secs(1)),
+			None
+		);
+	}
+}
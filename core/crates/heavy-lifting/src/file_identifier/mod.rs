@@ -1,4 +1,4 @@
-use crate::{utils::sub_path, OuterContext};
+use crate::{utils::sub_path, NonCriticalErrorSubject, OuterContext};
 
 use sd_core_file_path_helper::{FilePathError, IsolatedFilePathData};
 use sd_core_prisma_helpers::CasId;
@@ -30,7 +30,7 @@ pub mod job;
 mod shallow;
 mod tasks;
 
-pub use cas_id::generate_cas_id;
+pub use cas_id::{generate_cas_id, register_known_cas_ids};
 
 pub use job::FileIdentifier;
 pub use shallow::shallow;
@@ -88,6 +88,23 @@ pub enum NonCriticalFileIdentifierError {
 	FilePathWithoutIsDirField(file_path::id::Type),
 }
 
+impl NonCriticalFileIdentifierError {
+	#[must_use]
+	pub const fn subject(&self) -> Option<NonCriticalErrorSubject> {
+		match self {
+			Self::FailedToExtractFileMetadata(_) => None,
+			#[cfg(target_os = "windows")]
+			Self::FailedToExtractMetadataFromOnDemandFile(_) => None,
+			Self::FailedToExtractIsolatedFilePathData {
+				file_path_pub_id, ..
+			} => Some(NonCriticalErrorSubject::FilePathPubId(*file_path_pub_id)),
+			Self::FilePathWithoutIsDirField(file_path_id) => {
+				Some(NonCriticalErrorSubject::FilePath(*file_path_id))
+			}
+		}
+	}
+}
+
 #[derive(Debug, Clone)]
 pub struct FileMetadata {
 	pub cas_id: Option<CasId<'static>>,
@@ -126,9 +143,19 @@ impl FileMetadata {
 			.map_or(ObjectKind::Unknown, Into::into);
 
 		let cas_id = if fs_metadata.len() != 0 {
-			generate_cas_id(&path, fs_metadata.len())
-				.await
-				.map_err(|e| FileIOError::from((&path, e)))?
+			let known_cas_id = fs_metadata
+				.modified()
+				.ok()
+				.and_then(|date_modified| cas_id::take_known_cas_id(&path, date_modified));
+
+			if let Some(cas_id) = known_cas_id {
+				trace!(path = %path.display(), "Reusing known cas_id;");
+				cas_id
+			} else {
+				generate_cas_id(&path, fs_metadata.len())
+					.await
+					.map_err(|e| FileIOError::from((&path, e)))?
+			}
 		} else {
 			// We can't do shit with empty files
 			trace!(path = %path.display(), %kind, "Skipping empty file;");
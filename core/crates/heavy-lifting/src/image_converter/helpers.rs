@@ -0,0 +1,344 @@
+use super::{ConflictPolicy, ConversionOptions, NonCriticalImageConverterError};
+
+use sd_images::{format_image, ConvertibleExtension};
+use sd_media_metadata::exif::{ExifReader, Orientation};
+
+use std::{
+	io::Cursor,
+	ops::Deref,
+	path::{Path, PathBuf},
+};
+
+use image::{
+	codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, GenericImageView, ImageFormat,
+};
+use tokio::fs;
+use webp::Encoder;
+
+const JPEG_SOI_MARKER: [u8; 2] = [0xFF, 0xD8];
+const JPEG_APP0_MARKER: [u8; 2] = [0xFF, 0xE0];
+const JPEG_APP1_MARKER: [u8; 2] = [0xFF, 0xE1];
+const EXIF_HEADER: &[u8; 6] = b"Exif\0\0";
+
+/// Maps the desired extension to a format that we're able to encode, as we can decode way
+/// more formats than we can encode
+const fn target_format(extension: ConvertibleExtension) -> Option<ImageFormat> {
+	use ConvertibleExtension as Ext;
+
+	match extension {
+		Ext::Bmp | Ext::Dib => Some(ImageFormat::Bmp),
+		Ext::Ff => Some(ImageFormat::Farbfeld),
+		Ext::Gif => Some(ImageFormat::Gif),
+		Ext::Ico => Some(ImageFormat::Ico),
+		Ext::Jpg | Ext::Jpeg => Some(ImageFormat::Jpeg),
+		Ext::Png => Some(ImageFormat::Png),
+		Ext::Pnm => Some(ImageFormat::Pnm),
+		Ext::Qoi => Some(ImageFormat::Qoi),
+		Ext::Tga | Ext::Icb | Ext::Vda | Ext::Vst => Some(ImageFormat::Tga),
+		Ext::Tiff | Ext::Tif => Some(ImageFormat::Tiff),
+		Ext::Webp => Some(ImageFormat::WebP),
+		Ext::Hif
+		| Ext::Heif
+		| Ext::Heifs
+		| Ext::Heic
+		| Ext::Heics
+		| Ext::Avif
+		| Ext::Avci
+		| Ext::Avcs
+		| Ext::Svg
+		| Ext::Svgz
+		| Ext::Pdf => None,
+	}
+}
+
+#[must_use]
+pub const fn can_convert_to(extension: ConvertibleExtension) -> bool {
+	target_format(extension).is_some()
+}
+
+/// Decodes the source image, applies all requested transformations and encodes it on the desired
+/// format, returning the encoded bytes ready to be written to disk.
+///
+/// This function is CPU bound and must be called from a blocking thread.
+pub fn convert_image(
+	source_path: &Path,
+	options: &ConversionOptions,
+) -> Result<Vec<u8>, NonCriticalImageConverterError> {
+	let format = target_format(options.desired_extension)
+		.expect("the job must check if we can encode to the desired format before running");
+
+	let mut img = format_image(source_path).map_err(|e| {
+		NonCriticalImageConverterError::Decode(source_path.to_path_buf(), e.to_string())
+	})?;
+
+	// Not all images have exif data, so we don't error here
+	let maybe_exif = ExifReader::from_path(source_path).ok();
+
+	// We're only able to carry the exif block over to JPEG files
+	let keep_exif = !options.strip_exif && maybe_exif.is_some() && format == ImageFormat::Jpeg;
+
+	// If the exif block goes along with the converted image, its orientation tag goes too,
+	// so we only rotate the pixels when the exif data is being dropped.
+	// We also don't rotate HEIF as that's against the spec
+	if options.preserve_orientation && !keep_exif {
+		if let Some(orientation) = maybe_exif.as_ref().and_then(Orientation::from_reader) {
+			if ConvertibleExtension::try_from(source_path).is_ok_and(|ext| ext.should_rotate()) {
+				img = orientation.correct_thumbnail(img);
+			}
+		}
+	}
+
+	if let Some(max_dimension) = options.max_dimension {
+		let (w, h) = img.dimensions();
+		if w > max_dimension || h > max_dimension {
+			// `resize` preserves the aspect ratio, fitting the image inside the given bounds
+			img = img.resize(max_dimension, max_dimension, FilterType::Lanczos3);
+		}
+	}
+
+	let encoded = encode(img, format, options.quality()).map_err(|reason| {
+		NonCriticalImageConverterError::Encode(source_path.to_path_buf(), reason)
+	})?;
+
+	Ok(match maybe_exif {
+		Some(exif) if keep_exif => embed_exif_in_jpeg(encoded, exif.raw_bytes()),
+		_ => encoded,
+	})
+}
+
+fn encode(img: DynamicImage, format: ImageFormat, quality: u8) -> Result<Vec<u8>, String> {
+	// Normalizing color types to the ones supported by each encoder
+	let img = match format {
+		ImageFormat::Jpeg => DynamicImage::ImageRgb8(img.to_rgb8()),
+		ImageFormat::Farbfeld => DynamicImage::ImageRgba16(img.to_rgba16()),
+		ImageFormat::Png | ImageFormat::Tiff => img,
+		_ => DynamicImage::ImageRgba8(img.to_rgba8()),
+	};
+
+	match format {
+		ImageFormat::WebP => {
+			let encoder = Encoder::from_image(&img).map_err(ToString::to_string)?;
+
+			// Type `WebPMemory` is !Send, so we copy it to a `Vec<u8>` right away
+			Ok(encoder.encode(f32::from(quality)).deref().to_owned())
+		}
+
+		ImageFormat::Jpeg => {
+			let mut bytes = Vec::new();
+			img.write_with_encoder(JpegEncoder::new_with_quality(&mut bytes, quality))
+				.map_err(|e| e.to_string())?;
+
+			Ok(bytes)
+		}
+
+		_ => {
+			let mut bytes = Cursor::new(Vec::new());
+			img.write_to(&mut bytes, format)
+				.map_err(|e| e.to_string())?;
+
+			Ok(bytes.into_inner())
+		}
+	}
+}
+
+/// Inserts an APP1 segment holding the exif data right after the JPEG's start of image marker, or
+/// after its APP0 segment when present, as JFIF requires APP0 to come first
+fn embed_exif_in_jpeg(jpeg: Vec<u8>, exif: &[u8]) -> Vec<u8> {
+	// The segment length counts its own 2 bytes, and a single segment can't go over `u16::MAX`
+	let Ok(segment_len) = u16::try_from(2 + EXIF_HEADER.len() + exif.len()) else {
+		return jpeg;
+	};
+
+	if !jpeg.starts_with(&JPEG_SOI_MARKER) {
+		return jpeg;
+	}
+
+	let mut insert_at = JPEG_SOI_MARKER.len();
+
+	if jpeg[insert_at..].starts_with(&JPEG_APP0_MARKER) {
+		let Some(app0_len) = jpeg
+			.get(insert_at + 2..insert_at + 4)
+			.map(|len| usize::from(u16::from_be_bytes([len[0], len[1]])))
+			.filter(|app0_len| insert_at + 2 + app0_len <= jpeg.len())
+		else {
+			return jpeg;
+		};
+
+		insert_at += JPEG_APP0_MARKER.len() + app0_len;
+	}
+
+	let mut out =
+		Vec::with_capacity(jpeg.len() + JPEG_APP1_MARKER.len() + usize::from(segment_len));
+	out.extend_from_slice(&jpeg[..insert_at]);
+	out.extend_from_slice(&JPEG_APP1_MARKER);
+	out.extend_from_slice(&segment_len.to_be_bytes());
+	out.extend_from_slice(EXIF_HEADER);
+	out.extend_from_slice(exif);
+	out.extend_from_slice(&jpeg[insert_at..]);
+
+	out
+}
+
+/// Checks if the output path is free, applying the conflict policy otherwise.
+///
+/// Returns `None` if this image must be skipped.
+pub async fn resolve_output_path(
+	output_path: PathBuf,
+	conflict_policy: ConflictPolicy,
+) -> Result<Option<PathBuf>, NonCriticalImageConverterError> {
+	let exists = |path: PathBuf| async move {
+		fs::try_exists(&path).await.map_err(|e| {
+			NonCriticalImageConverterError::CheckOutputConflict(path.clone(), e.to_string())
+		})
+	};
+
+	if !exists(output_path.clone()).await? {
+		return Ok(Some(output_path));
+	}
+
+	match conflict_policy {
+		ConflictPolicy::Skip => Ok(None),
+		ConflictPolicy::Overwrite => Ok(Some(output_path)),
+		ConflictPolicy::KeepBoth => {
+			let stem = output_path
+				.file_stem()
+				.map(|stem| stem.to_string_lossy().to_string())
+				.unwrap_or_default();
+			let extension = output_path
+				.extension()
+				.map(|ext| ext.to_string_lossy().to_string());
+
+			for i in 1..u32::MAX {
+				let candidate = output_path.with_file_name(extension.as_ref().map_or_else(
+					|| format!("{stem} ({i})"),
+					|ext| format!("{stem} ({i}).{ext}"),
+				));
+
+				if !exists(candidate.clone()).await? {
+					return Ok(Some(candidate));
+				}
+			}
+
+			Ok(None)
+		}
+	}
+}
+
+/// Checks if the output path points to the source image itself, comparing extensions ignoring
+/// case as we always save with lowercase extensions, like `photo.JPG` resized to `photo.jpg`
+pub fn is_source_path(source_path: &Path, output_path: &Path) -> bool {
+	source_path.with_extension("") == output_path.with_extension("")
+		&& match (source_path.extension(), output_path.extension()) {
+			(Some(source), Some(output)) => source.eq_ignore_ascii_case(output),
+			(source, output) => source == output,
+		}
+}
+
+/// The deepest directory containing all the materialized paths, used to mirror the directory tree
+/// of explicitly selected images, so images with the same name from different directories don't
+/// collide
+pub fn common_materialized_prefix<'path>(
+	materialized_paths: impl IntoIterator<Item = &'path str>,
+) -> String {
+	let mut common: Option<Vec<&str>> = None;
+
+	for materialized_path in materialized_paths {
+		let directories = materialized_path
+			.split('/')
+			.filter(|directory| !directory.is_empty());
+
+		common = Some(match common {
+			None => directories.collect(),
+			Some(mut common) => {
+				let shared = common
+					.iter()
+					.zip(directories)
+					.take_while(|(common, directory)| *common == directory)
+					.count();
+				common.truncate(shared);
+				common
+			}
+		});
+	}
+
+	common
+		.unwrap_or_default()
+		.into_iter()
+		.fold(String::from("/"), |mut prefix, directory| {
+			prefix.push_str(directory);
+			prefix.push('/');
+			prefix
+		})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn exif_is_embedded_right_after_start_of_image() {
+		let jpeg = vec![0xFF, 0xD8, 0xFF, 0xDB, 0x00, 0x02, 0xFF, 0xD9];
+		let exif = [b'M', b'M', 0x00, 0x2A];
+
+		let out = embed_exif_in_jpeg(jpeg, &exif);
+
+		assert_eq!(&out[..4], &[0xFF, 0xD8, 0xFF, 0xE1]);
+		assert_eq!(u16::from_be_bytes([out[4], out[5]]), 2 + 6 + 4);
+		assert_eq!(&out[6..12], EXIF_HEADER);
+		assert_eq!(&out[12..16], &exif);
+		assert_eq!(&out[16..], &[0xFF, 0xDB, 0x00, 0x02, 0xFF, 0xD9]);
+	}
+
+	#[test]
+	fn exif_is_embedded_after_jfif_app0_segment() {
+		let app0 = [0xFF, 0xE0, 0x00, 0x04, b'J', b'F'];
+		let jpeg = [&[0xFF, 0xD8][..], &app0, &[0xFF, 0xD9]].concat();
+		let exif = [b'M', b'M', 0x00, 0x2A];
+
+		let out = embed_exif_in_jpeg(jpeg, &exif);
+
+		assert_eq!(&out[..2], &[0xFF, 0xD8]);
+		assert_eq!(&out[2..8], &app0);
+		assert_eq!(&out[8..10], &[0xFF, 0xE1]);
+		assert_eq!(&out[12..18], EXIF_HEADER);
+		assert_eq!(&out[18..22], &exif);
+		assert_eq!(&out[22..], &[0xFF, 0xD9]);
+	}
+
+	#[test]
+	fn oversized_exif_is_not_embedded() {
+		let jpeg = vec![0xFF, 0xD8, 0xFF, 0xD9];
+		let exif = vec![0; usize::from(u16::MAX)];
+
+		assert_eq!(embed_exif_in_jpeg(jpeg.clone(), &exif), jpeg);
+	}
+
+	#[test]
+	fn only_encodable_formats_are_accepted() {
+		assert!(can_convert_to(ConvertibleExtension::Jpg));
+		assert!(can_convert_to(ConvertibleExtension::Webp));
+		assert!(!can_convert_to(ConvertibleExtension::Heic));
+		assert!(!can_convert_to(ConvertibleExtension::Pdf));
+	}
+
+	#[test]
+	fn output_with_the_source_extension_in_any_case_is_the_source() {
+		let source = Path::new("/photos/beach.JPG");
+
+		assert!(is_source_path(source, Path::new("/photos/beach.jpg")));
+		assert!(is_source_path(source, source));
+		assert!(!is_source_path(source, Path::new("/photos/beach.webp")));
+		assert!(!is_source_path(source, Path::new("/resized/beach.jpg")));
+	}
+
+	#[test]
+	fn common_prefix_of_selected_images() {
+		assert_eq!(common_materialized_prefix([]), "/");
+		assert_eq!(common_materialized_prefix(["/trips/2023/"]), "/trips/2023/");
+		assert_eq!(
+			common_materialized_prefix(["/trips/2023/", "/trips/2024/beach/", "/trips/2023/"]),
+			"/trips/"
+		);
+		assert_eq!(common_materialized_prefix(["/trips/", "/"]), "/");
+	}
+}
@@ -0,0 +1,653 @@
+use crate::{
+	image_converter::{self, can_convert_to, NonCriticalImageConverterError},
+	job_system::{
+		job::{Job, JobReturn, JobTaskDispatcher, ReturnStatus},
+		report::ReportOutputMetadata,
+		utils::cancel_pending_tasks,
+		DispatcherError, JobErrorOrDispatcherError, SerializableJob, SerializedTasks,
+	},
+	utils::sub_path::maybe_get_iso_file_path_from_sub_path,
+	Error, JobContext, JobName, OuterContext, ProgressUpdate,
+};
+
+use sd_core_file_path_helper::IsolatedFilePathData;
+use sd_core_prisma_helpers::file_path_to_isolate_with_id;
+
+use sd_images::ConvertibleExtension;
+use sd_prisma::prisma::{file_path, location, PrismaClient};
+use sd_task_system::{
+	AnyTaskOutput, IntoTask, SerializableTask, Task, TaskDispatcher, TaskHandle, TaskId,
+	TaskOutput, TaskStatus,
+};
+use sd_utils::{db::maybe_missing, u64_to_frontend};
+
+use std::{
+	collections::HashMap,
+	hash::{Hash, Hasher},
+	mem,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use futures_concurrency::future::TryJoin;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use specta::Type;
+use tracing::{debug, error, instrument, trace, warn, Level};
+
+use super::{
+	helpers::common_materialized_prefix,
+	tasks::{self, converter},
+	ConversionOptions, OutputDestination, BATCH_SIZE,
+};
+
+/// Which images of the location must be converted
+#[derive(Debug, Clone, Serialize, Deserialize, Type, Hash)]
+#[serde(rename_all = "snake_case", tag = "type", content = "data")]
+pub enum ConversionSources {
+	/// Files explicitly selected by the user, written to the output directory mirroring the
+	/// directories they have below the deepest one containing all of them
+	FilePaths(Vec<file_path::id::Type>),
+	/// Every convertible image inside this directory, recursively, relative to the location root
+	Directory(PathBuf),
+}
+
+#[derive(Debug)]
+pub struct ImageConverter {
+	// Received arguments
+	location: Arc<location::Data>,
+	location_path: Arc<PathBuf>,
+	sources: ConversionSources,
+	destination: OutputDestination,
+	options: Arc<ConversionOptions>,
+
+	// Job control
+	total_images: u64,
+	total_tasks: u64,
+
+	// Run data
+	metadata: Metadata,
+	errors: Vec<crate::NonCriticalError>,
+
+	// On shutdown data
+	pending_tasks_on_resume: Vec<TaskHandle<Error>>,
+	tasks_for_shutdown: Vec<Box<dyn Task<Error>>>,
+}
+
+impl Job for ImageConverter {
+	const NAME: JobName = JobName::ImageConverter;
+
+	async fn resume_tasks<OuterCtx: OuterContext>(
+		&mut self,
+		dispatcher: &JobTaskDispatcher,
+		_: &impl JobContext<OuterCtx>,
+		SerializedTasks(serialized_tasks): SerializedTasks,
+	) -> Result<(), Error> {
+		if let Ok(tasks) = dispatcher
+			.dispatch_many_boxed(
+				rmp_serde::from_slice::<Vec<Vec<u8>>>(&serialized_tasks)
+					.map_err(image_converter::Error::from)?
+					.into_iter()
+					.map(|task_bytes| async move {
+						tasks::Converter::deserialize(&task_bytes, ())
+							.await
+							.map(IntoTask::into_task)
+					})
+					.collect::<Vec<_>>()
+					.try_join()
+					.await
+					.map_err(image_converter::Error::from)?,
+			)
+			.await
+		{
+			self.pending_tasks_on_resume = tasks;
+		} else {
+			warn!("Failed to dispatch tasks to resume as job was already canceled");
+		}
+
+		Ok(())
+	}
+
+	#[instrument(
+		skip_all,
+		fields(
+			location_id = self.location.id,
+			location_path = ?self.location.path,
+			sources = ?self.sources,
+			destination = ?self.destination,
+			desired_extension = %self.options.desired_extension,
+		),
+		ret(level = Level::TRACE),
+		err,
+	)]
+	async fn run<OuterCtx: OuterContext>(
+		mut self,
+		dispatcher: JobTaskDispatcher,
+		ctx: impl JobContext<OuterCtx>,
+	) -> Result<ReturnStatus, Error> {
+		let mut pending_running_tasks = FuturesUnordered::new();
+
+		match self
+			.init_or_resume(&mut pending_running_tasks, &ctx, &dispatcher)
+			.await
+		{
+			Ok(()) => { /* Everything is awesome! */ }
+			Err(JobErrorOrDispatcherError::JobError(e)) => {
+				return Err(e.into());
+			}
+			Err(JobErrorOrDispatcherError::Dispatcher(DispatcherError::JobCanceled(_))) => {
+				return Ok(self.cancel_job(&mut pending_running_tasks).await);
+			}
+			Err(JobErrorOrDispatcherError::Dispatcher(DispatcherError::Shutdown(tasks))) => {
+				self.tasks_for_shutdown.extend(tasks);
+
+				if pending_running_tasks.is_empty() {
+					// If no task managed to be dispatched, we can just shutdown
+					// otherwise we have to process handles below and wait for them to be shutdown too
+					return Ok(ReturnStatus::Shutdown(
+						SerializableJob::<OuterCtx>::serialize(self).await,
+					));
+				}
+			}
+		}
+
+		if let Some(res) = self.process_handles(&mut pending_running_tasks, &ctx).await {
+			return res;
+		}
+
+		if !self.tasks_for_shutdown.is_empty() {
+			return Ok(ReturnStatus::Shutdown(
+				SerializableJob::<OuterCtx>::serialize(self).await,
+			));
+		}
+
+		// From this point onward, we are done with the job and it can't be interrupted anymore
+		let Self {
+			metadata, errors, ..
+		} = self;
+
+		if metadata.converted > 0 {
+			ctx.invalidate_query("search.paths");
+			ctx.invalidate_query("search.objects");
+		}
+
+		Ok(ReturnStatus::Completed(
+			JobReturn::builder()
+				.with_metadata(metadata)
+				.with_non_critical_errors(errors)
+				.build(),
+		))
+	}
+}
+
+impl ImageConverter {
+	pub fn new(
+		location: location::Data,
+		sources: ConversionSources,
+		destination: OutputDestination,
+		options: ConversionOptions,
+	) -> Result<Self, image_converter::Error> {
+		if !can_convert_to(options.desired_extension) {
+			return Err(image_converter::Error::UnsupportedTargetFormat(
+				options.desired_extension,
+			));
+		}
+
+		if let ConversionSources::FilePaths(file_path_ids) = &sources {
+			if file_path_ids.is_empty() {
+				return Err(image_converter::Error::NothingToConvert);
+			}
+		}
+
+		Ok(Self {
+			location_path: maybe_missing(&location.path, "location.path")
+				.map(PathBuf::from)
+				.map(Arc::new)?,
+			location: Arc::new(location),
+			sources,
+			destination,
+			options: Arc::new(options),
+			total_images: 0,
+			total_tasks: 0,
+			metadata: Metadata::default(),
+			errors: Vec::new(),
+			pending_tasks_on_resume: Vec::new(),
+			tasks_for_shutdown: Vec::new(),
+		})
+	}
+
+	async fn init_or_resume<OuterCtx: OuterContext>(
+		&mut self,
+		pending_running_tasks: &mut FuturesUnordered<TaskHandle<Error>>,
+		job_ctx: &impl JobContext<OuterCtx>,
+		dispatcher: &JobTaskDispatcher,
+	) -> Result<(), JobErrorOrDispatcherError<image_converter::Error>> {
+		// if we don't have any pending task, then this is a fresh job
+		if self.pending_tasks_on_resume.is_empty() {
+			let entries = self.gather_conversion_entries(job_ctx.db()).await?;
+
+			let tasks = entries
+				.into_iter()
+				.chunks(BATCH_SIZE)
+				.into_iter()
+				.map(|chunk| tasks::Converter::new(chunk.collect(), Arc::clone(&self.options)))
+				.map(IntoTask::into_task)
+				.collect::<Vec<_>>();
+
+			self.total_tasks = tasks.len() as u64;
+
+			trace!(
+				tasks_count = self.total_tasks,
+				images_count = self.total_images,
+				"Dispatching image conversion tasks;",
+			);
+
+			job_ctx
+				.progress(vec![
+					ProgressUpdate::TaskCount(self.total_images),
+					ProgressUpdate::Message(format!(
+						"Preparing to convert {} images in {} chunks",
+						self.total_images, self.total_tasks
+					)),
+				])
+				.await;
+
+			pending_running_tasks.extend(dispatcher.dispatch_many_boxed(tasks).await?);
+		} else {
+			job_ctx
+				.progress(vec![
+					ProgressUpdate::TaskCount(self.total_images),
+					ProgressUpdate::CompletedTaskCount(
+						self.metadata.converted + self.metadata.skipped,
+					),
+					ProgressUpdate::Message(format!(
+						"Resuming conversion of {} images in {} chunks",
+						self.total_images, self.total_tasks
+					)),
+				])
+				.await;
+
+			pending_running_tasks.extend(mem::take(&mut self.pending_tasks_on_resume));
+		}
+
+		Ok(())
+	}
+
+	/// Fetch all file paths to be converted and resolve their source and output paths
+	async fn gather_conversion_entries(
+		&mut self,
+		db: &PrismaClient,
+	) -> Result<Vec<converter::ConversionEntry>, image_converter::Error> {
+		let location_id = self.location.id;
+
+		let (file_paths, children_prefix, is_explicit_selection) =
+			match &self.sources {
+				ConversionSources::FilePaths(file_path_ids) => {
+					let file_paths = db
+						.file_path()
+						.find_many(vec![
+							file_path::location_id::equals(Some(location_id)),
+							file_path::id::in_vec(file_path_ids.clone()),
+							file_path::is_dir::equals(Some(false)),
+						])
+						.select(file_path_to_isolate_with_id::select())
+						.exec()
+						.await?;
+
+					let children_prefix = common_materialized_prefix(
+						file_paths
+							.iter()
+							.filter_map(|file_path| file_path.materialized_path.as_deref()),
+					);
+
+					(file_paths, children_prefix, true)
+				}
+
+				ConversionSources::Directory(sub_path) => {
+					let children_prefix = maybe_get_iso_file_path_from_sub_path::<
+						image_converter::Error,
+					>(
+						location_id, Some(sub_path), &*self.location_path, db
+					)
+					.await?
+					.map_or_else(
+						|| "/".to_string(),
+						|iso_file_path| {
+							iso_file_path
+								.materialized_path_for_children()
+								.expect("sub path iso_file_path must be a directory")
+						},
+					);
+
+					(
+						db.file_path()
+							.find_many(vec![
+								file_path::location_id::equals(Some(location_id)),
+								file_path::materialized_path::starts_with(children_prefix.clone()),
+								file_path::is_dir::equals(Some(false)),
+							])
+							.select(file_path_to_isolate_with_id::select())
+							.exec()
+							.await?,
+						children_prefix,
+						false,
+					)
+				}
+			};
+
+		let output_extension = self.options.desired_extension.to_string().to_lowercase();
+
+		let entries = file_paths
+			.into_iter()
+			.filter_map(|file_path| {
+				let file_path_id = file_path.id;
+
+				let is_convertible = file_path
+					.extension
+					.clone()
+					.is_some_and(|extension| ConvertibleExtension::try_from(extension).is_ok());
+
+				if !is_convertible {
+					// Non images found when walking a directory are expected, so we only report
+					// the ones explicitly selected by the user
+					if is_explicit_selection {
+						self.errors.push(
+							NonCriticalImageConverterError::NotConvertible(file_path_id).into(),
+						);
+					}
+					return None;
+				}
+
+				IsolatedFilePathData::try_from(file_path)
+					.map_err(|e| {
+						self.errors.push(
+							NonCriticalImageConverterError::FailedToExtractIsolatedFilePathData(
+								file_path_id,
+								e.to_string(),
+							)
+							.into(),
+						);
+					})
+					.ok()
+					.map(|iso_file_path| (file_path_id, iso_file_path))
+			})
+			.map(|(file_path_id, iso_file_path)| {
+				let source_path = self.location_path.join(&iso_file_path);
+				let parts = iso_file_path.to_parts();
+				let output_file_name = format!("{}.{output_extension}", parts.name);
+
+				let output_path = match &self.destination {
+					OutputDestination::NextToSource => source_path.with_file_name(output_file_name),
+
+					OutputDestination::Directory(target_directory) => {
+						let relative_directory = parts
+							.materialized_path
+							.strip_prefix(children_prefix.as_str())
+							.unwrap_or_default();
+
+						target_directory
+							.join(Path::new(relative_directory))
+							.join(output_file_name)
+					}
+				};
+
+				converter::ConversionEntry {
+					file_path_id,
+					source_path,
+					output_path,
+				}
+			})
+			.collect::<Vec<_>>();
+
+		self.total_images = entries.len() as u64;
+
+		Ok(entries)
+	}
+
+	async fn process_handles<OuterCtx: OuterContext>(
+		&mut self,
+		pending_running_tasks: &mut FuturesUnordered<TaskHandle<Error>>,
+		job_ctx: &impl JobContext<OuterCtx>,
+	) -> Option<Result<ReturnStatus, Error>> {
+		while let Some(task) = pending_running_tasks.next().await {
+			match task {
+				Ok(TaskStatus::Done((task_id, TaskOutput::Out(out)))) => {
+					self.process_task_output(task_id, out, job_ctx).await;
+				}
+
+				Ok(TaskStatus::Done((task_id, TaskOutput::Empty))) => {
+					warn!(%task_id, "Task returned an empty output;");
+				}
+
+				Ok(TaskStatus::Shutdown(task)) => {
+					self.tasks_for_shutdown.push(task);
+				}
+
+				Ok(TaskStatus::Error(e)) => {
+					cancel_pending_tasks(pending_running_tasks).await;
+
+					return Some(Err(e));
+				}
+
+				Ok(TaskStatus::Canceled | TaskStatus::ForcedAbortion) => {
+					return Some(Ok(self.cancel_job(pending_running_tasks).await));
+				}
+
+				Err(e) => {
+					error!(?e, "Task System error;");
+					cancel_pending_tasks(pending_running_tasks).await;
+
+					return Some(Err(e.into()));
+				}
+			}
+		}
+
+		None
+	}
+
+	async fn process_task_output<OuterCtx: OuterContext>(
+		&mut self,
+		task_id: TaskId,
+		any_task_output: Box<dyn AnyTaskOutput>,
+		job_ctx: &impl JobContext<OuterCtx>,
+	) {
+		if any_task_output.is::<converter::Output>() {
+			let converter::Output {
+				converted,
+				skipped,
+				written_bytes,
+				errors,
+				total_time,
+			} = *any_task_output.downcast().expect("just checked");
+
+			self.metadata.converted += converted;
+			self.metadata.skipped += skipped;
+			self.metadata.written_bytes += written_bytes;
+			self.metadata.total_conversion_time += total_time;
+			self.metadata.total_successful_tasks += 1;
+
+			if !errors.is_empty() {
+				warn!(?errors, "Non critical errors while converting images;");
+				self.errors.extend(errors);
+			}
+
+			debug!(
+				"Processed ({}/{}) image conversion tasks, took: {total_time:?};",
+				self.metadata.total_successful_tasks, self.total_tasks,
+			);
+
+			job_ctx
+				.progress(vec![ProgressUpdate::CompletedTaskCount(
+					self.metadata.converted + self.metadata.skipped,
+				)])
+				.await;
+		} else {
+			unreachable!("Unexpected task output type: <id='{task_id}'>");
+		}
+	}
+
+	async fn cancel_job(
+		&mut self,
+		pending_running_tasks: &mut FuturesUnordered<TaskHandle<Error>>,
+	) -> ReturnStatus {
+		cancel_pending_tasks(pending_running_tasks).await;
+
+		ReturnStatus::Canceled(
+			JobReturn::builder()
+				.with_metadata(mem::take(&mut self.metadata))
+				.with_non_critical_errors(mem::take(&mut self.errors))
+				.build(),
+		)
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct Metadata {
+	converted: u64,
+	skipped: u64,
+	written_bytes: u64,
+	total_conversion_time: Duration,
+	total_successful_tasks: u64,
+}
+
+impl From<Metadata> for Vec<ReportOutputMetadata> {
+	fn from(
+		Metadata {
+			converted,
+			skipped,
+			written_bytes,
+			total_conversion_time,
+			total_successful_tasks,
+		}: Metadata,
+	) -> Self {
+		vec![
+			ReportOutputMetadata::ImageConverter {
+				converted: u64_to_frontend(converted),
+				skipped: u64_to_frontend(skipped),
+				written_bytes: u64_to_frontend(written_bytes),
+			},
+			ReportOutputMetadata::Metrics(HashMap::from([
+				("total_conversion_time".into(), json!(total_conversion_time)),
+				(
+					"total_successful_tasks".into(),
+					json!(total_successful_tasks),
+				),
+			])),
+		]
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveState {
+	location: Arc<location::Data>,
+	location_path: Arc<PathBuf>,
+	sources: ConversionSources,
+	destination: OutputDestination,
+	options: Arc<ConversionOptions>,
+
+	total_images: u64,
+	total_tasks: u64,
+
+	metadata: Metadata,
+
+	errors: Vec<crate::NonCriticalError>,
+
+	tasks_for_shutdown_bytes: Option<SerializedTasks>,
+}
+
+impl<OuterCtx: OuterContext> SerializableJob<OuterCtx> for ImageConverter {
+	async fn serialize(self) -> Result<Option<Vec<u8>>, rmp_serde::encode::Error> {
+		let Self {
+			location,
+			location_path,
+			sources,
+			destination,
+			options,
+			total_images,
+			total_tasks,
+			metadata,
+			errors,
+			tasks_for_shutdown,
+			..
+		} = self;
+
+		let serialized_tasks = tasks_for_shutdown
+			.into_iter()
+			.map(|task| async move {
+				task.downcast::<tasks::Converter>()
+					.unwrap_or_else(|task| unreachable!("Unexpected task type: <task='{task:#?}'>"))
+					.serialize()
+					.await
+			})
+			.collect::<Vec<_>>()
+			.try_join()
+			.await?;
+
+		let tasks_for_shutdown_bytes = if serialized_tasks.is_empty() {
+			None
+		} else {
+			Some(SerializedTasks(rmp_serde::to_vec_named(&serialized_tasks)?))
+		};
+
+		rmp_serde::to_vec_named(&SaveState {
+			location,
+			location_path,
+			sources,
+			destination,
+			options,
+			total_images,
+			total_tasks,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes,
+		})
+		.map(Some)
+	}
+
+	async fn deserialize(
+		serialized_job: &[u8],
+		_: &OuterCtx,
+	) -> Result<Option<(Self, Option<SerializedTasks>)>, rmp_serde::decode::Error> {
+		let SaveState {
+			location,
+			location_path,
+			sources,
+			destination,
+			options,
+			total_images,
+			total_tasks,
+			metadata,
+			errors,
+			tasks_for_shutdown_bytes,
+		} = rmp_serde::from_slice::<SaveState>(serialized_job)?;
+
+		Ok(Some((
+			Self {
+				location,
+				location_path,
+				sources,
+				destination,
+				options,
+				total_images,
+				total_tasks,
+				metadata,
+				errors,
+				pending_tasks_on_resume: Vec::new(),
+				tasks_for_shutdown: Vec::new(),
+			},
+			tasks_for_shutdown_bytes,
+		)))
+	}
+}
+
+impl Hash for ImageConverter {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.location.id.hash(state);
+		self.sources.hash(state);
+		self.destination.hash(state);
+		self.options.desired_extension.to_string().hash(state);
+	}
+}
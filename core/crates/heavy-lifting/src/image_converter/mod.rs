@@ -0,0 +1,161 @@
+use crate::{utils::sub_path, NonCriticalErrorSubject};
+
+use sd_core_file_path_helper::FilePathError;
+
+use sd_images::ConvertibleExtension;
+use sd_prisma::prisma::file_path;
+use sd_utils::db::MissingFieldError;
+
+use std::path::PathBuf;
+
+use prisma_client_rust::QueryError;
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+mod helpers;
+pub mod job;
+mod tasks;
+
+pub use helpers::can_convert_to;
+pub use job::ImageConverter;
+pub use tasks::converter::{self, Converter};
+
+#[cfg(target_os = "ios")]
+const BATCH_SIZE: usize = 2; // Much smaller batch size for iOS
+
+#[cfg(target_os = "android")]
+const BATCH_SIZE: usize = 2; // Much smaller batch size for Android
+
+#[cfg(not(any(target_os = "ios", target_os = "android")))]
+const BATCH_SIZE: usize = 10;
+
+/// Quality used when the user doesn't specify one, for the encoders that support lossy compression
+pub const DEFAULT_QUALITY: u8 = 90;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("can't encode images to the desired format: <extension='{0}'>")]
+	UnsupportedTargetFormat(ConvertibleExtension),
+	#[error("no files were selected to be converted")]
+	NothingToConvert,
+	#[error("missing field on database: {0}")]
+	MissingField(#[from] MissingFieldError),
+	#[error("failed to deserialized stored tasks for job resume: {0}")]
+	DeserializeTasks(#[from] rmp_serde::decode::Error),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+
+	#[error(transparent)]
+	FilePathError(#[from] FilePathError),
+	#[error(transparent)]
+	SubPath(#[from] sub_path::Error),
+}
+
+impl From<Error> for rspc::Error {
+	fn from(e: Error) -> Self {
+		match e {
+			Error::UnsupportedTargetFormat(_) | Error::NothingToConvert => {
+				Self::with_cause(ErrorCode::BadRequest, e.to_string(), e)
+			}
+
+			Error::SubPath(sub_path_err) => sub_path_err.into(),
+
+			_ => Self::with_cause(ErrorCode::InternalServerError, e.to_string(), e),
+		}
+	}
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Type, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum NonCriticalImageConverterError {
+	#[error("file path <id='{0}'> doesn't have a convertible image extension")]
+	NotConvertible(file_path::id::Type),
+	#[error("failed to extract isolated file path data from file path <id='{0}'>: {1}")]
+	FailedToExtractIsolatedFilePathData(file_path::id::Type, String),
+	#[error("failed to decode image <path='{path}'>: {1}", path = .0.display())]
+	Decode(PathBuf, String),
+	#[error("failed to encode image <path='{path}'>: {1}", path = .0.display())]
+	Encode(PathBuf, String),
+	#[error("processing thread panicked while converting image <path='{path}'>: {1}", path = .0.display())]
+	PanicWhileConverting(PathBuf, String),
+	#[error("failed to create output directory <path='{path}'>: {1}", path = .0.display())]
+	CreateOutputDirectory(PathBuf, String),
+	#[error("failed to check if output file already exists <path='{path}'>: {1}", path = .0.display())]
+	CheckOutputConflict(PathBuf, String),
+	#[error("failed to save converted image <path='{path}'>: {1}", path = .0.display())]
+	SaveImage(PathBuf, String),
+}
+
+impl NonCriticalImageConverterError {
+	#[must_use]
+	pub fn subject(&self) -> Option<NonCriticalErrorSubject> {
+		match self {
+			Self::NotConvertible(file_path_id)
+			| Self::FailedToExtractIsolatedFilePathData(file_path_id, _) => {
+				Some(NonCriticalErrorSubject::FilePath(*file_path_id))
+			}
+			Self::Decode(path, _)
+			| Self::Encode(path, _)
+			| Self::PanicWhileConverting(path, _)
+			| Self::CreateOutputDirectory(path, _)
+			| Self::CheckOutputConflict(path, _)
+			| Self::SaveImage(path, _) => Some(NonCriticalErrorSubject::Path(path.clone())),
+		}
+	}
+}
+
+/// What to do when the output path for a converted image already exists
+///
+/// Images that would be saved over their own source always keep both files.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+	/// Keep the existing file and don't convert this image
+	#[default]
+	Skip,
+	/// Replace the existing file with the converted image
+	Overwrite,
+	/// Save the converted image with a numeric suffix, like `photo (1).jpg`
+	KeepBoth,
+}
+
+/// Where converted images are written to
+#[derive(Debug, Default, Clone, Serialize, Deserialize, Type, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case", tag = "type", content = "path")]
+pub enum OutputDestination {
+	/// Each converted image is saved in the same directory as its source
+	#[default]
+	NextToSource,
+	/// Converted images are saved under this absolute directory, mirroring the directory tree
+	/// they had relative to the converted sub path, or to the deepest directory containing all
+	/// the selected images
+	Directory(PathBuf),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct ConversionOptions {
+	pub desired_extension: ConvertibleExtension,
+	/// Images larger than this, on any side, are downscaled keeping their aspect ratio
+	pub max_dimension: Option<u32>,
+	/// Encoding quality from 1 to 100, only used by lossy formats
+	pub quality: Option<u8>,
+	/// Drop all exif data from the converted image, otherwise it's carried over when the target
+	/// format supports it (currently only JPEG)
+	#[serde(default)]
+	pub strip_exif: bool,
+	/// Apply the exif orientation to the pixels, so images keep looking upright even when their
+	/// exif data is stripped
+	#[serde(default)]
+	pub preserve_orientation: bool,
+	#[serde(default)]
+	pub conflict_policy: ConflictPolicy,
+}
+
+impl ConversionOptions {
+	#[must_use]
+	pub fn quality(&self) -> u8 {
+		self.quality
+			.map_or(DEFAULT_QUALITY, |quality| quality.clamp(1, 100))
+	}
+}
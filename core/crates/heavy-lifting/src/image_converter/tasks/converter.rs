@@ -0,0 +1,272 @@
+use crate::{
+	image_converter::{
+		helpers::{convert_image, is_source_path, resolve_output_path},
+		ConflictPolicy, ConversionOptions, NonCriticalImageConverterError,
+	},
+	Error,
+};
+
+use sd_prisma::prisma::file_path;
+use sd_task_system::{
+	check_interruption, ExecStatus, Interrupter, IntoAnyTaskOutput, SerializableTask, Task, TaskId,
+};
+
+use std::{
+	mem, panic,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{fs, sync::oneshot, task::spawn_blocking, time::Instant};
+use tracing::{error, instrument, trace, Level};
+
+/// A single image to be converted, with its paths already resolved by the job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversionEntry {
+	pub file_path_id: file_path::id::Type,
+	pub source_path: PathBuf,
+	/// Output path before applying the conflict policy
+	pub output_path: PathBuf,
+}
+
+#[derive(Debug)]
+pub struct Converter {
+	// Task control
+	id: TaskId,
+
+	// Received input args
+	entries: Vec<ConversionEntry>,
+	options: Arc<ConversionOptions>,
+
+	// Out collector
+	output: Output,
+}
+
+/// [`Converter`] task output
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Output {
+	/// How many images were converted and saved
+	pub converted: u64,
+	/// How many images were skipped, due to conflicts or errors
+	pub skipped: u64,
+	/// Total size of the converted images written to disk
+	pub written_bytes: u64,
+	/// Errors encountered during the task
+	pub errors: Vec<crate::NonCriticalError>,
+	/// Time spent converting images
+	pub total_time: Duration,
+}
+
+#[async_trait::async_trait]
+impl Task<Error> for Converter {
+	fn id(&self) -> TaskId {
+		self.id
+	}
+
+	#[instrument(
+		skip_all,
+		fields(
+			task_id = %self.id,
+			entries_count = %self.entries.len(),
+			desired_extension = %self.options.desired_extension,
+		),
+		ret(level = Level::TRACE),
+		err,
+	)]
+	#[allow(clippy::blocks_in_conditions)] // Due to `err` on `instrument` macro above
+	async fn run(&mut self, interrupter: &Interrupter) -> Result<ExecStatus, Error> {
+		let Self {
+			entries,
+			options,
+			output,
+			..
+		} = self;
+
+		// Entries are removed as soon as they're processed, so a resumed task only sees the pending ones
+		while let Some(entry) = entries.last().cloned() {
+			check_interruption!(interrupter);
+
+			let start = Instant::now();
+
+			match convert_entry(entry, Arc::clone(options)).await {
+				Ok(Some(written_bytes)) => {
+					output.converted += 1;
+					output.written_bytes += written_bytes;
+				}
+				Ok(None) => {
+					output.skipped += 1;
+				}
+				Err(e) => {
+					output.skipped += 1;
+					output.errors.push(e.into());
+				}
+			}
+
+			entries.pop();
+			output.total_time += start.elapsed();
+		}
+
+		Ok(ExecStatus::Done(mem::take(output).into_output()))
+	}
+}
+
+impl Converter {
+	#[must_use]
+	pub fn new(mut entries: Vec<ConversionEntry>, options: Arc<ConversionOptions>) -> Self {
+		// We pop entries from the end, so reversing here to convert them in the received order
+		entries.reverse();
+
+		Self {
+			id: TaskId::new_v4(),
+			entries,
+			options,
+			output: Output::default(),
+		}
+	}
+}
+
+/// Converts a single image, returning the amount of bytes written or `None` if the image was
+/// skipped due to the conflict policy
+#[instrument(
+	skip_all,
+	fields(
+		file_path_id = entry.file_path_id,
+		source_path = %entry.source_path.display(),
+	),
+	err,
+)]
+async fn convert_entry(
+	entry: ConversionEntry,
+	options: Arc<ConversionOptions>,
+) -> Result<Option<u64>, NonCriticalImageConverterError> {
+	let ConversionEntry {
+		source_path,
+		output_path,
+		..
+	} = entry;
+
+	// We never replace the source image itself, nor skip it as the user explicitly asked for it to
+	// be converted, like resizing without changing formats, so in this case they get both files
+	let conflict_policy = if is_source_path(&source_path, &output_path) {
+		ConflictPolicy::KeepBoth
+	} else {
+		options.conflict_policy
+	};
+
+	let Some(output_path) = resolve_output_path(output_path, conflict_policy).await? else {
+		trace!("Output path already exists, skipping image");
+		return Ok(None);
+	};
+
+	if let Some(parent) = output_path.parent() {
+		fs::create_dir_all(parent).await.map_err(|e| {
+			NonCriticalImageConverterError::CreateOutputDirectory(
+				parent.to_path_buf(),
+				e.to_string(),
+			)
+		})?;
+	}
+
+	let (tx, rx) = oneshot::channel();
+
+	// Using channel instead of waiting the JoinHandle as for some reason
+	// the JoinHandle can take some extra time to complete
+	let handle = spawn_blocking({
+		let source_path = source_path.clone();
+
+		move || {
+			// Handling error on receiver side
+			let _ = tx.send(
+				panic::catch_unwind(|| convert_image(&source_path, &options)).unwrap_or_else(
+					move |_| {
+						Err(NonCriticalImageConverterError::PanicWhileConverting(
+							source_path,
+							"Internal panic on third party crate".to_string(),
+						))
+					},
+				),
+			);
+		}
+	});
+
+	let bytes = if let Ok(res) = rx.await {
+		res?
+	} else {
+		error!("Failed to convert image");
+		return Err(NonCriticalImageConverterError::PanicWhileConverting(
+			source_path,
+			handle
+				.await
+				.expect_err("as the channel was closed, then the spawned task panicked")
+				.to_string(),
+		));
+	};
+
+	write_output(&output_path, &bytes).await?;
+
+	trace!(output_path = %output_path.display(), "Converted image");
+
+	Ok(Some(bytes.len() as u64))
+}
+
+async fn write_output(
+	output_path: &Path,
+	bytes: &[u8],
+) -> Result<(), NonCriticalImageConverterError> {
+	fs::write(output_path, bytes).await.map_err(|e| {
+		NonCriticalImageConverterError::SaveImage(output_path.to_path_buf(), e.to_string())
+	})
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SaveState {
+	id: TaskId,
+	entries: Vec<ConversionEntry>,
+	options: Arc<ConversionOptions>,
+	output: Output,
+}
+
+impl SerializableTask<Error> for Converter {
+	type SerializeError = rmp_serde::encode::Error;
+
+	type DeserializeError = rmp_serde::decode::Error;
+
+	type DeserializeCtx = ();
+
+	async fn serialize(self) -> Result<Vec<u8>, Self::SerializeError> {
+		let Self {
+			id,
+			entries,
+			options,
+			output,
+		} = self;
+
+		rmp_serde::to_vec_named(&SaveState {
+			id,
+			entries,
+			options,
+			output,
+		})
+	}
+
+	async fn deserialize(
+		data: &[u8],
+		(): Self::DeserializeCtx,
+	) -> Result<Self, Self::DeserializeError> {
+		rmp_serde::from_slice(data).map(
+			|SaveState {
+			     id,
+			     entries,
+			     options,
+			     output,
+			 }| Self {
+				id,
+				entries,
+				options,
+				output,
+			},
+		)
+	}
+}
@@ -0,0 +1,3 @@
+pub mod converter;
+
+pub use converter::Converter;
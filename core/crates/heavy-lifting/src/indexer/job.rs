@@ -45,7 +45,7 @@ use tokio::time::Instant;
 use tracing::{debug, instrument, trace, warn, Level};
 
 use super::{
-	remove_non_existing_file_paths, reverse_update_directories_sizes,
+	location_indexer_ruler, remove_non_existing_file_paths, reverse_update_directories_sizes,
 	tasks::{
 		self, saver, updater,
 		walker::{self, WalkedEntry},
@@ -646,6 +646,9 @@ impl Indexer {
 	) -> Result<(), JobErrorOrDispatcherError<indexer::Error>> {
 		// if we don't have any pending task, then this is a fresh job
 		let updates = if self.pending_tasks_on_resume.is_empty() {
+			// Locations nested in this one since the job was created must be left out too
+			self.indexer_ruler = location_indexer_ruler(&self.location, ctx.db()).await?;
+
 			let walker_root_path = Arc::new(
 				get_full_path_from_sub_path::<indexer::Error>(
 					self.location.id,
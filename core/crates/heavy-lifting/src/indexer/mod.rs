@@ -1,8 +1,10 @@
 use crate::{utils::sub_path, OuterContext};
 
 use sd_core_file_path_helper::{FilePathError, IsolatedFilePathData};
+use sd_core_indexer_rules::{IndexerRule, IndexerRuler};
 use sd_core_prisma_helpers::{
 	file_path_pub_and_cas_ids, file_path_to_isolate_with_pub_id, file_path_walker,
+	location_with_indexer_rules,
 };
 use sd_core_sync::{DevicePubId, SyncManager};
 
@@ -113,6 +115,49 @@ pub enum NonCriticalIndexerError {
 	MissingFilePathData(String),
 }
 
+/// The rules a location is indexed with: its own, plus one leaving out the contents of the
+/// locations nested in it on the same device, as those are indexed by the nested locations
+pub async fn location_indexer_ruler(
+	location: &location_with_indexer_rules::Data,
+	db: &PrismaClient,
+) -> Result<IndexerRuler, Error> {
+	let mut rules = location
+		.indexer_rules
+		.iter()
+		.map(|rule| IndexerRule::try_from(&rule.indexer_rule))
+		.collect::<Result<Vec<_>, _>>()?;
+
+	// Legacy locations without a device can't tell which locations share their device, so the
+	// lookup would match nested paths of other devices
+	if let (Some(location_path), Some(device_id)) = (&location.path, location.device_id) {
+		let nested_paths = db
+			.location()
+			.find_many(vec![
+				location::device_id::equals(Some(device_id)),
+				location::path::starts_with(location_path.clone()),
+			])
+			.select(location::select!({ path }))
+			.exec()
+			.await?
+			.into_iter()
+			.filter_map(|nested| nested.path)
+			.filter(|nested_path| {
+				nested_path != location_path
+					&& Path::new(nested_path).starts_with(Path::new(location_path))
+			})
+			.collect::<Vec<_>>();
+
+		if !nested_paths.is_empty() {
+			rules.push(IndexerRule::reject_contents_of(
+				"Nested locations",
+				nested_paths,
+			)?);
+		}
+	}
+
+	Ok(IndexerRuler::new(rules))
+}
+
 fn chunk_db_queries<'db, 'iso>(
 	iso_file_paths: impl IntoIterator<Item = &'iso IsolatedFilePathData<'iso>>,
 	db: &'db PrismaClient,
@@ -519,6 +564,8 @@ impl walker::WalkerDBProxy for WalkerDBProxy {
 				.find_many(vec![
 					file_path::location_id::equals(Some(self.location_id)),
 					file_path::id::gte(cursor),
+					// Archived file paths are expected to be missing from disk
+					file_path::archived_in::none(vec![]),
 					if existing_inodes.is_empty() {
 						materialized_path_param
 					} else {
@@ -2,7 +2,6 @@ use crate::{
 	indexer, utils::sub_path::get_full_path_from_sub_path, Error, NonCriticalError, OuterContext,
 };
 
-use sd_core_indexer_rules::{IndexerRule, IndexerRuler};
 use sd_core_prisma_helpers::location_with_indexer_rules;
 use sd_core_sync::SyncManager;
 
@@ -21,7 +20,7 @@ use itertools::Itertools;
 use tracing::{debug, instrument, warn};
 
 use super::{
-	remove_non_existing_file_paths, reverse_update_directories_sizes,
+	location_indexer_ruler, remove_non_existing_file_paths, reverse_update_directories_sizes,
 	tasks::{
 		self, saver, updater,
 		walker::{self, ToWalkEntry, WalkedEntry},
@@ -161,13 +160,7 @@ async fn walk(
 		.dispatch(tasks::Walker::new_shallow(
 			ToWalkEntry::from(&*to_walk_path),
 			to_walk_path,
-			location
-				.indexer_rules
-				.iter()
-				.map(|rule| IndexerRule::try_from(&rule.indexer_rule))
-				.collect::<Result<Vec<_>, _>>()
-				.map(IndexerRuler::new)
-				.map_err(indexer::Error::from)?,
+			location_indexer_ruler(location, &db).await?,
 			IsoFilePathFactory {
 				location_id: location.id,
 				location_path,
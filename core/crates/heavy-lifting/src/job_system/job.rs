@@ -54,12 +54,17 @@ pub enum JobName {
 	Indexer,
 	FileIdentifier,
 	MediaProcessor,
+	ImageConverter,
+	Workflow,
+	Tagger,
 	// TODO: Add more job names as needed
 	Copy,
 	Move,
 	Delete,
 	Erase,
 	FileValidator,
+	Archive,
+	RestoreArchive,
 }
 
 pub enum ReturnStatus {
@@ -510,7 +515,7 @@ impl<OuterCtx: OuterContext, JobCtx: JobContext<OuterCtx>> JobHandle<OuterCtx, J
 
 		let now = Utc::now();
 
-		{
+		let location_id = {
 			let mut report = ctx.report_mut().await;
 
 			report.status = Status::Running;
@@ -525,7 +530,9 @@ impl<OuterCtx: OuterContext, JobCtx: JobContext<OuterCtx>> JobHandle<OuterCtx, J
 				// Otherwise it can be a job being resumed or a children job that was already been created
 				report.update(db).await?;
 			}
-		}
+
+			report.location_id
+		};
 
 		// Registering children jobs
 		let res = next_jobs
@@ -538,6 +545,11 @@ impl<OuterCtx: OuterContext, JobCtx: JobContext<OuterCtx>> JobHandle<OuterCtx, J
 					"Parent job registering children;",
 				);
 				if next_job_report.created_at.is_none() {
+					// Children jobs work on the same location as their parent
+					if next_job_report.location_id.is_none() {
+						next_job_report.location_id = location_id;
+					}
+
 					next_job_report
 						.create(db, now + Duration::from_secs((idx + 1) as u64))
 						.await
@@ -1,6 +1,6 @@
 use crate::NonCriticalError;
 
-use sd_prisma::prisma::{file_path, job, location, PrismaClient};
+use sd_prisma::prisma::{archive, file_path, job, location, PrismaClient};
 use sd_utils::db::{maybe_missing, MissingFieldError};
 
 use std::{collections::HashMap, fmt, path::PathBuf, str::FromStr};
@@ -65,6 +65,8 @@ pub enum ReportInputMetadata {
 	// TODO: Add more variants as needed
 	Location(location::Data),
 	SubPath(PathBuf),
+	/// Key of the workflow step that dispatched this job
+	WorkflowStep(String),
 }
 
 #[derive(Debug, Serialize, Deserialize, Type, Clone)]
@@ -86,6 +88,20 @@ pub enum ReportOutputMetadata {
 		thumbnails_generated: (u32, u32),
 		thumbnails_skipped: (u32, u32),
 	},
+	ImageConverter {
+		converted: (u32, u32),
+		skipped: (u32, u32),
+		written_bytes: (u32, u32),
+	},
+	Workflow {
+		completed_steps: u32,
+		failed_steps: u32,
+		skipped_steps: u32,
+	},
+	Tagger {
+		tag_id: i32,
+		tagged_objects: (u32, u32),
+	},
 	Copier {
 		source_location_id: location::id::Type,
 		target_location_id: location::id::Type,
@@ -111,6 +127,15 @@ pub enum ReportOutputMetadata {
 		location_id: location::id::Type,
 		sub_path: Option<PathBuf>,
 	},
+	Archiver {
+		location_id: location::id::Type,
+		sub_path: Option<PathBuf>,
+	},
+	ArchiveRestorer {
+		location_id: location::id::Type,
+		archive_id: archive::id::Type,
+		file_path_ids: Option<Vec<file_path::id::Type>>,
+	},
 }
 
 impl From<ReportInputMetadata> for ReportMetadata {
@@ -140,6 +165,8 @@ pub struct Report {
 	pub completed_at: Option<DateTime<Utc>>,
 
 	pub parent_id: Option<JobId>,
+	/// The location this job worked on, if any, kept so job history can be filtered by location
+	pub location_id: Option<location::id::Type>,
 
 	pub status: Status,
 	pub task_count: i32,
@@ -177,6 +204,7 @@ impl TryFrom<job::Data> for Report {
 			data: _, // Deprecated
 			metadata,
 			parent_id,
+			location_id,
 			task_count,
 			info,
 			completed_task_count,
@@ -206,6 +234,7 @@ impl TryFrom<job::Data> for Report {
 			started_at: date_started.map(DateTime::into),
 			completed_at: date_completed.map(DateTime::into),
 			parent_id: parent_id.map(|id| JobId::from_slice(&id).expect("corrupted database")),
+			location_id,
 			status: Status::try_from(maybe_missing(status, "job.status")?)
 				.expect("corrupted database"),
 			task_count: task_count.unwrap_or(0),
@@ -234,6 +263,7 @@ impl Report {
 			task_count: 0,
 			metadata: vec![],
 			parent_id: None,
+			location_id: None,
 			completed_task_count: 0,
 			info: String::new(),
 			phase: String::new(),
@@ -284,6 +314,7 @@ impl Report {
 						job::task_count::set(Some(0)),
 						job::info::set(Some(self.info.clone())),
 						job::completed_task_count::set(Some(0)),
+						job::location_id::set(self.location_id),
 					],
 					[self
 						.parent_id
@@ -397,6 +428,7 @@ impl ReportBuilder {
 			non_critical_errors: vec![],
 			metadata: self.metadata,
 			parent_id: self.parent_id,
+			location_id: None,
 			completed_task_count: 0,
 			info: String::new(),
 			phase: String::new(),
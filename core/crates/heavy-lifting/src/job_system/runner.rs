@@ -118,7 +118,7 @@ impl<OuterCtx: OuterContext, JobCtx: JobContext<OuterCtx>> JobSystemRunner<Outer
 		&mut self,
 		job_id: JobId,
 		location_id: location::id::Type,
-		dyn_job: Box<dyn DynJob<OuterCtx, JobCtx>>,
+		mut dyn_job: Box<dyn DynJob<OuterCtx, JobCtx>>,
 		ctx: OuterCtx,
 		maybe_existing_tasks: Option<SerializedTasks>,
 	) -> Result<(), JobSystemError> {
@@ -154,6 +154,9 @@ impl<OuterCtx: OuterContext, JobCtx: JobContext<OuterCtx>> JobSystemRunner<Outer
 		job_hashes.insert(job_hash, job_id);
 		job_hashes_by_id.insert(job_id, job_hash);
 
+		// Resumed jobs already have their location loaded from the database
+		dyn_job.report_mut().location_id.get_or_insert(location_id);
+
 		let mut handle = if maybe_existing_tasks.is_some() {
 			dyn_job.resume(
 				base_dispatcher.clone(),
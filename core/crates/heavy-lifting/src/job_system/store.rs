@@ -1,4 +1,4 @@
-use crate::{file_identifier, indexer, media_processor, JobContext};
+use crate::{file_identifier, image_converter, indexer, media_processor, JobContext};
 
 use sd_prisma::prisma::{job, location};
 use sd_utils::uuid_to_bytes;
@@ -228,6 +228,7 @@ async fn load_job<OuterCtx: OuterContext, JobCtx: JobContext<OuterCtx>>(
 			indexer::job::Indexer,
 			file_identifier::job::FileIdentifier,
 			media_processor::job::MediaProcessor,
+			image_converter::job::ImageConverter,
 			// TODO: Add more jobs here
 		]
 	)
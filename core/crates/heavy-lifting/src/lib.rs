@@ -31,11 +31,15 @@
 use sd_prisma::prisma::file_path;
 use sd_task_system::TaskSystemError;
 
+use std::path::PathBuf;
+
 use serde::{Deserialize, Serialize};
 use specta::Type;
 use thiserror::Error;
+use uuid::Uuid;
 
 pub mod file_identifier;
+pub mod image_converter;
 pub mod indexer;
 pub mod job_system;
 pub mod media_processor;
@@ -60,6 +64,8 @@ pub enum Error {
 	FileIdentifier(#[from] file_identifier::Error),
 	#[error(transparent)]
 	MediaProcessor(#[from] media_processor::Error),
+	#[error(transparent)]
+	ImageConverter(#[from] image_converter::Error),
 
 	#[error(transparent)]
 	TaskSystem(#[from] TaskSystemError),
@@ -74,6 +80,7 @@ impl From<Error> for rspc::Error {
 			Error::Indexer(e) => e.into(),
 			Error::FileIdentifier(e) => e.into(),
 			Error::MediaProcessor(e) => e.into(),
+			Error::ImageConverter(e) => e.into(),
 			Error::TaskSystem(e) => {
 				Self::with_cause(rspc::ErrorCode::InternalServerError, e.to_string(), e)
 			}
@@ -92,6 +99,32 @@ pub enum NonCriticalError {
 	FileIdentifier(#[from] file_identifier::NonCriticalFileIdentifierError),
 	#[error(transparent)]
 	MediaProcessor(#[from] media_processor::NonCriticalMediaProcessorError),
+	#[error(transparent)]
+	ImageConverter(#[from] image_converter::NonCriticalImageConverterError),
+}
+
+impl NonCriticalError {
+	/// The file affected by this error, if the error is about a single file
+	#[must_use]
+	pub fn subject(&self) -> Option<NonCriticalErrorSubject> {
+		match self {
+			// Indexer errors only keep their messages, as they can happen before we have a file path
+			Self::Indexer(_) => None,
+			Self::FileIdentifier(e) => e.subject(),
+			Self::MediaProcessor(e) => e.subject(),
+			Self::ImageConverter(e) => e.subject(),
+		}
+	}
+}
+
+/// Non-critical errors refer to the affected file in different ways, depending on
+/// the information available to the task when the error happened
+#[derive(Debug, Clone, Serialize, Type, PartialEq, Eq)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum NonCriticalErrorSubject {
+	Path(PathBuf),
+	FilePath(file_path::id::Type),
+	FilePathPubId(Uuid),
 }
 
 #[repr(i32)]
@@ -1,4 +1,4 @@
-use crate::{utils::sub_path, OuterContext, UpdateEvent};
+use crate::{utils::sub_path, NonCriticalErrorSubject, OuterContext, UpdateEvent};
 
 use sd_core_file_path_helper::{FilePathError, IsolatedFilePathData};
 use sd_core_prisma_helpers::file_path_for_media_processor;
@@ -49,6 +49,10 @@ const BATCH_SIZE: usize = 2; // Much smaller batch size for Android
 #[cfg(not(any(target_os = "ios", target_os = "android")))]
 const BATCH_SIZE: usize = 10; // Keep original batch size for other platforms
 
+/// Concurrency group of tasks decoding media with FFmpeg, which already spawns its own threads,
+/// so running many of these tasks at the same time easily saturates the machine
+pub const FFMPEG_TASKS_GROUP: &str = "ffmpeg";
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
 	#[error("missing field on database: {0}")]
@@ -85,6 +89,16 @@ pub enum NonCriticalMediaProcessorError {
 	Thumbnailer(#[from] NonCriticalThumbnailerError),
 }
 
+impl NonCriticalMediaProcessorError {
+	#[must_use]
+	pub fn subject(&self) -> Option<NonCriticalErrorSubject> {
+		match self {
+			Self::MediaDataExtractor(e) => e.subject(),
+			Self::Thumbnailer(e) => e.subject(),
+		}
+	}
+}
+
 #[derive(Clone)]
 pub struct NewThumbnailsReporter<OuterCtx: OuterContext> {
 	pub ctx: OuterCtx,
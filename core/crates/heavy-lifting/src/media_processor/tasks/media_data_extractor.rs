@@ -3,7 +3,7 @@ use crate::{
 		self,
 		helpers::{exif_media_data, ffmpeg_media_data},
 	},
-	Error,
+	Error, NonCriticalErrorSubject,
 };
 
 use sd_core_file_path_helper::IsolatedFilePathData;
@@ -44,6 +44,21 @@ pub enum NonCriticalMediaDataExtractorError {
 	FailedToConstructIsolatedFilePathData(file_path::id::Type, String),
 }
 
+impl NonCriticalMediaDataExtractorError {
+	#[must_use]
+	pub fn subject(&self) -> Option<NonCriticalErrorSubject> {
+		match self {
+			Self::FailedToExtractImageMediaData(path, _) => {
+				Some(NonCriticalErrorSubject::Path(path.clone()))
+			}
+			Self::FilePathMissingObjectId(file_path_id)
+			| Self::FailedToConstructIsolatedFilePathData(file_path_id, _) => {
+				Some(NonCriticalErrorSubject::FilePath(*file_path_id))
+			}
+		}
+	}
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 enum Kind {
 	Exif,
@@ -120,6 +135,10 @@ impl Task<Error> for MediaDataExtractor {
 		false
 	}
 
+	fn concurrency_group(&self) -> Option<&'static str> {
+		(self.kind == Kind::FFmpeg).then_some(media_processor::FFMPEG_TASKS_GROUP)
+	}
+
 	#[instrument(
 		skip_all,
 		fields(
@@ -16,7 +16,7 @@ use crate::{
 		},
 		ThumbKey, ThumbnailKind,
 	},
-	Error,
+	Error, NonCriticalErrorSubject,
 };
 
 use sd_core_file_path_helper::IsolatedFilePathData;
@@ -88,6 +88,22 @@ impl Task<Error> for Thumbnailer {
 		Some(THUMBNAILER_TASK_TIMEOUT) // The entire task must not take more than this constant
 	}
 
+	/// Batches mix all kinds of thumbnailable files, a single video is enough to make the whole
+	/// batch count as an FFmpeg task
+	#[cfg(feature = "ffmpeg")]
+	fn concurrency_group(&self) -> Option<&'static str> {
+		use sd_file_ext::extensions::VideoExtension;
+
+		use std::str::FromStr;
+
+		self.thumbnails_to_generate
+			.values()
+			.any(|GenerateThumbnailArgs { extension, .. }| {
+				VideoExtension::from_str(extension).is_ok()
+			})
+			.then_some(media_processor::FFMPEG_TASKS_GROUP)
+	}
+
 	#[instrument(
 		skip_all,
 		fields(
@@ -227,6 +243,24 @@ pub enum NonCriticalThumbnailerError {
 	TaskTimeout(TaskId),
 }
 
+impl NonCriticalThumbnailerError {
+	#[must_use]
+	pub fn subject(&self) -> Option<NonCriticalErrorSubject> {
+		match self {
+			Self::MissingCasId(file_path_id)
+			| Self::FailedToExtractIsolatedFilePathData(file_path_id, _) => {
+				Some(NonCriticalErrorSubject::FilePath(*file_path_id))
+			}
+			Self::VideoThumbnailGenerationFailed(path, _)
+			| Self::FormatImage(path, _)
+			| Self::WebPEncoding(path, _)
+			| Self::PanicWhileGeneratingThumbnail(path, _)
+			| Self::SaveThumbnail(path, _) => Some(NonCriticalErrorSubject::Path(path.clone())),
+			Self::CreateShardDirectory(_) | Self::TaskTimeout(_) => None,
+		}
+	}
+}
+
 impl Thumbnailer {
 	fn new(
 		thumbs_kind: ThumbnailKind,
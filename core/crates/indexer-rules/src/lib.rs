@@ -347,6 +347,36 @@ impl IndexerRule {
 
 		inner(&self.rules, source.as_ref(), metadata).await
 	}
+
+	/// Rejects everything inside the given directories, but not the directories themselves, as
+	/// the contents of nested locations are indexed by those locations
+	pub fn reject_contents_of(
+		name: impl Into<String>,
+		directories: impl IntoIterator<Item = impl AsRef<str>>,
+	) -> Result<Self, Error> {
+		let now = Utc::now();
+
+		Ok(Self {
+			id: None,
+			name: name.into(),
+			default: false,
+			rules: vec![RulePerKind::new_reject_files_by_globs_str(
+				directories.into_iter().map(|directory| {
+					let directory = directory.as_ref();
+					// Paths are matched with forward slashes as separators
+					let directory = if cfg!(windows) {
+						directory.replace('\\', "/")
+					} else {
+						directory.to_string()
+					};
+
+					format!("{}/**", globset::escape(directory.trim_end_matches('/')))
+				}),
+			)?],
+			date_created: now,
+			date_modified: now,
+		})
+	}
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -736,6 +766,19 @@ mod tests {
 		assert!(check_rule(&rule, normal_inner_file));
 	}
 
+	#[tokio::test]
+	async fn test_reject_contents_of_nested_locations() {
+		let rule =
+			IndexerRule::reject_contents_of("nested locations", ["/home/user/Projects [old]"])
+				.unwrap();
+
+		assert!(check_rule(&rule, "/home/user/Projects [old]"));
+		assert!(!check_rule(&rule, "/home/user/Projects [old]/src"));
+		assert!(!check_rule(&rule, "/home/user/Projects [old]/src/main.rs"));
+		assert!(check_rule(&rule, "/home/user/Projects"));
+		assert!(check_rule(&rule, "/home/user/Documents/notes.txt"));
+	}
+
 	#[tokio::test]
 	async fn test_reject_specific_dir() {
 		let project_file = Path::new("/test/project/src/main.rs");
@@ -242,6 +242,7 @@ job::select!(job_without_data {
 	action
 	status
 	parent_id
+	location_id
 	errors_text
 	metadata
 	date_created
@@ -284,10 +285,18 @@ impl From<location_with_indexer_rules::Data> for location::Data {
 			hidden: data.hidden,
 			date_created: data.date_created,
 			scan_state: data.scan_state,
+			date_last_seen: data.date_last_seen,
+			volume_id: data.volume_id,
 			file_paths: None,
 			indexer_rules: None,
 			device: None,
+			volume: None,
 			instance: None,
+			job_schedules: None,
+			watching_workflows: None,
+			archives: None,
+			size_snapshots: None,
+			capacity_snapshots: None,
 		}
 	}
 }
@@ -310,10 +319,18 @@ impl From<&location_with_indexer_rules::Data> for location::Data {
 			hidden: data.hidden,
 			date_created: data.date_created,
 			scan_state: data.scan_state,
+			date_last_seen: data.date_last_seen,
+			volume_id: data.volume_id,
 			file_paths: None,
 			indexer_rules: None,
 			device: None,
+			volume: None,
 			instance: None,
+			job_schedules: None,
+			watching_workflows: None,
+			archives: None,
+			size_snapshots: None,
+			capacity_snapshots: None,
 		}
 	}
 }
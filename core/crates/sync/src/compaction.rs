@@ -0,0 +1,464 @@
+//! Compaction of the CRDT operations log, as the `crdt_operation` table would grow forever otherwise.
+//!
+//! Two kinds of operations are removed:
+//! - Fields of update operations superseded by a newer update of the same field on the same record,
+//!   removing the whole operation when all its fields were superseded;
+//! - Create and update operations of deleted records, once we ingested every operation other
+//!   devices generated up to the delete, so no operation older than the delete is still on its way.
+//!
+//! Delete operations are always kept as tombstones, as they're needed to discard late operations
+//! for records that no longer exist. The latest operation of each device is also kept, as it's used
+//! to know which operations we already have from each device when the sync manager is created.
+
+use sd_prisma::prisma::{crdt_operation, device, PrismaClient, SortOrder};
+use sd_sync::{CRDTOperationData, OperationKind};
+
+use std::{
+	collections::{HashMap, HashSet},
+	time::Duration,
+};
+
+use prisma_client_rust::{raw, PrismaValue};
+use serde::Deserialize;
+use tokio::time::Instant;
+use tracing::{debug, instrument};
+
+use super::{DevicePubId, Error, SyncManager, NTP64};
+
+/// How many records are compacted at once, keeping memory usage bounded on huge operation logs
+const RECORDS_PER_BATCH: i32 = 1000;
+
+/// Approximated size of the fixed size columns of a `crdt_operation` row
+const ROW_OVERHEAD_BYTES: u64 = 24;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CompactionReport {
+	pub deleted_operations: u64,
+	pub rewritten_operations: u64,
+	/// Approximated amount of bytes freed from the operations table, the database file only
+	/// shrinks after a `VACUUM`
+	pub reclaimed_bytes: u64,
+	pub elapsed: Duration,
+}
+
+/// A distinct record of the operations log
+#[derive(Deserialize)]
+struct RawRecord {
+	model: crdt_operation::model::Type,
+	/// Hex encoded by SQLite, so it's deserialized as plain text
+	record_id: String,
+}
+
+#[derive(Default)]
+struct BatchChanges {
+	deletes: Vec<crdt_operation::id::Type>,
+	rewrites: Vec<(crdt_operation::id::Type, String, Vec<u8>)>,
+}
+
+/// Compacts the operations log of this library, see the [module docs](self) for what is removed
+#[instrument(skip_all, err)]
+pub async fn compact_operations(sync: &SyncManager) -> Result<CompactionReport, Error> {
+	let start = Instant::now();
+	let db = &sync.db;
+
+	let acknowledged_timestamp = acknowledged_timestamp(sync).await?;
+	let protected_ids = latest_operation_per_device(db).await?;
+
+	let mut report = CompactionReport::default();
+	// Records are visited in (model, record_id) order, so each one is visited exactly once
+	let mut cursor = None;
+
+	loop {
+		let records = records_after(db, cursor.take()).await?;
+
+		let Some(last) = records.last() else {
+			break;
+		};
+		cursor = Some(last.clone());
+
+		let mut record_ids_per_model = HashMap::<_, Vec<_>>::new();
+		for (model, record_id) in records {
+			record_ids_per_model
+				.entry(model)
+				.or_default()
+				.push(record_id);
+		}
+
+		// Holding the sync lock so no operations are written or ingested while we decide what to remove
+		let _lock_guard = sync.sync_lock.lock().await;
+
+		let mut changes = BatchChanges::default();
+
+		for (model, record_ids) in record_ids_per_model {
+			let mut ops_per_record = HashMap::<_, Vec<_>>::new();
+
+			for op in db
+				.crdt_operation()
+				.find_many(vec![
+					crdt_operation::model::equals(model),
+					crdt_operation::record_id::in_vec(record_ids),
+				])
+				.exec()
+				.await?
+			{
+				ops_per_record
+					.entry(op.record_id.clone())
+					.or_default()
+					.push(op);
+			}
+
+			for ops in ops_per_record.into_values() {
+				compact_record(
+					ops,
+					acknowledged_timestamp,
+					&protected_ids,
+					&mut changes,
+					&mut report,
+				)?;
+			}
+		}
+
+		apply_changes(db, changes).await?;
+	}
+
+	report.elapsed = start.elapsed();
+
+	debug!(?report, "Compacted sync operations log");
+
+	Ok(report)
+}
+
+/// The next batch of distinct records after the cursor, a keyset pagination over the
+/// `(model, record_id)` index
+async fn records_after(
+	db: &PrismaClient,
+	cursor: Option<(crdt_operation::model::Type, Vec<u8>)>,
+) -> Result<Vec<(crdt_operation::model::Type, Vec<u8>)>, Error> {
+	// Every record id is greater than the empty blob
+	let (model, record_id) = cursor.unwrap_or((crdt_operation::model::Type::MIN, vec![]));
+
+	Ok(db
+		._query_raw::<RawRecord>(raw!(
+			"SELECT DISTINCT model, hex(record_id) AS record_id
+			FROM crdt_operation
+			WHERE model > {} OR (model = {} AND crdt_operation.record_id > {})
+			ORDER BY model, record_id
+			LIMIT {}",
+			PrismaValue::Int(model),
+			PrismaValue::Int(model),
+			PrismaValue::Bytes(record_id),
+			PrismaValue::Int(RECORDS_PER_BATCH)
+		))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|RawRecord { model, record_id }| {
+			(
+				model,
+				decode_hex(&record_id).expect("SQLite's hex function always returns valid hex"),
+			)
+		})
+		.collect())
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+	(0..hex.len())
+		.step_by(2)
+		.map(|i| {
+			hex.get(i..i + 2)
+				.and_then(|byte| u8::from_str_radix(byte, 16).ok())
+		})
+		.collect()
+}
+
+/// Timestamp up to which we ingested the operations of every other device of the library.
+///
+/// Each device sends its operations in timestamp order, so [`SyncManager::timestamp_per_device`]
+/// holds how far we got on each of them. A delete at or before the oldest of those has no older
+/// operations of the same record still to arrive. `None` if we never ingested anything from some
+/// device, as we can't know what it still has to send us.
+async fn acknowledged_timestamp(sync: &SyncManager) -> Result<Option<NTP64>, Error> {
+	let devices = sync.db.device().find_many(vec![]).exec().await?;
+
+	Ok(ingested_watermark(
+		devices
+			.into_iter()
+			.map(|device::Data { pub_id, .. }| DevicePubId::from(pub_id))
+			.filter(|device_pub_id| *device_pub_id != sync.device_pub_id),
+		&*sync.timestamp_per_device.read().await,
+	))
+}
+
+fn ingested_watermark(
+	other_devices: impl IntoIterator<Item = DevicePubId>,
+	timestamp_per_device: &HashMap<DevicePubId, NTP64>,
+) -> Option<NTP64> {
+	let mut watermark = None::<NTP64>;
+
+	for device_pub_id in other_devices {
+		match timestamp_per_device.get(&device_pub_id) {
+			Some(timestamp) if timestamp.as_u64() > 0 => {
+				watermark = Some(watermark.map_or(*timestamp, |ack| ack.min(*timestamp)));
+			}
+			_ => return None,
+		}
+	}
+
+	// Without other devices, there is nothing left to arrive
+	watermark.or(Some(NTP64(u64::MAX)))
+}
+
+async fn latest_operation_per_device(
+	db: &PrismaClient,
+) -> Result<HashSet<crdt_operation::id::Type>, Error> {
+	let devices = db.device().find_many(vec![]).exec().await?;
+
+	Ok(db
+		._batch(
+			devices
+				.into_iter()
+				.map(|device| {
+					db.crdt_operation()
+						.find_first(vec![crdt_operation::device_pub_id::equals(device.pub_id)])
+						.order_by(crdt_operation::timestamp::order(SortOrder::Desc))
+						.select(crdt_operation::select!({ id }))
+				})
+				.collect::<Vec<_>>(),
+		)
+		.await?
+		.into_iter()
+		.flatten()
+		.map(|op| op.id)
+		.collect())
+}
+
+fn row_size(op: &crdt_operation::Data) -> u64 {
+	(op.data.len() + op.record_id.len() + op.kind.len() + op.device_pub_id.len()) as u64
+		+ ROW_OVERHEAD_BYTES
+}
+
+fn compact_record(
+	mut ops: Vec<crdt_operation::Data>,
+	acknowledged_timestamp: Option<NTP64>,
+	protected_ids: &HashSet<crdt_operation::id::Type>,
+	changes: &mut BatchChanges,
+	report: &mut CompactionReport,
+) -> Result<(), Error> {
+	let delete_kind = OperationKind::Delete.to_string();
+
+	// Newest first
+	ops.sort_by(|a, b| b.timestamp.cmp(&a.timestamp).then(b.id.cmp(&a.id)));
+
+	let acknowledged_delete = acknowledged_timestamp.is_some_and(|acknowledged| {
+		ops.iter().any(|op| {
+			#[allow(clippy::cast_sign_loss)]
+			// SAFETY: we had to store using i64 due to SQLite limitations
+			let timestamp = op.timestamp as u64;
+
+			op.kind == delete_kind && timestamp <= acknowledged.as_u64()
+		})
+	});
+
+	if acknowledged_delete {
+		for op in ops {
+			if op.kind != delete_kind && !protected_ids.contains(&op.id) {
+				report.deleted_operations += 1;
+				report.reclaimed_bytes += row_size(&op);
+				changes.deletes.push(op.id);
+			}
+		}
+
+		return Ok(());
+	}
+
+	let mut superseded_fields = HashSet::new();
+
+	for op in ops.into_iter().filter(|op| op.kind.starts_with('u')) {
+		let CRDTOperationData::Update(mut fields_and_values) = rmp_serde::from_slice(&op.data)?
+		else {
+			continue;
+		};
+
+		let original_fields_count = fields_and_values.len();
+
+		fields_and_values.retain(|field, _| !superseded_fields.contains(field));
+		superseded_fields.extend(fields_and_values.keys().cloned());
+
+		if fields_and_values.len() == original_fields_count || protected_ids.contains(&op.id) {
+			continue;
+		}
+
+		if fields_and_values.is_empty() {
+			report.deleted_operations += 1;
+			report.reclaimed_bytes += row_size(&op);
+			changes.deletes.push(op.id);
+		} else {
+			let data = CRDTOperationData::Update(fields_and_values);
+			let kind = data.as_kind().to_string();
+			let data = rmp_serde::to_vec(&data)?;
+
+			report.rewritten_operations += 1;
+			report.reclaimed_bytes +=
+				(op.data.len() + op.kind.len()).saturating_sub(data.len() + kind.len()) as u64;
+			changes.rewrites.push((op.id, kind, data));
+		}
+	}
+
+	Ok(())
+}
+
+async fn apply_changes(
+	db: &PrismaClient,
+	BatchChanges { deletes, rewrites }: BatchChanges,
+) -> Result<(), Error> {
+	if !deletes.is_empty() {
+		db.crdt_operation()
+			.delete_many(vec![crdt_operation::id::in_vec(deletes)])
+			.exec()
+			.await?;
+	}
+
+	if !rewrites.is_empty() {
+		db._batch(
+			rewrites
+				.into_iter()
+				.map(|(id, kind, data)| {
+					db.crdt_operation().update(
+						crdt_operation::id::equals(id),
+						vec![
+							crdt_operation::kind::set(kind),
+							crdt_operation::data::set(data),
+						],
+					)
+				})
+				.collect::<Vec<_>>(),
+		)
+		.await?;
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::BTreeMap;
+
+	use super::*;
+
+	fn op(
+		id: crdt_operation::id::Type,
+		timestamp: i64,
+		data: &CRDTOperationData,
+	) -> crdt_operation::Data {
+		crdt_operation::Data {
+			id,
+			timestamp,
+			model: 0,
+			record_id: vec![1],
+			kind: data.as_kind().to_string(),
+			data: rmp_serde::to_vec(data).unwrap(),
+			device_pub_id: vec![2],
+		}
+	}
+
+	fn update(fields: &[&str]) -> CRDTOperationData {
+		CRDTOperationData::Update(
+			fields
+				.iter()
+				.map(|field| ((*field).to_string(), rmpv::Value::from(1)))
+				.collect::<BTreeMap<_, _>>(),
+		)
+	}
+
+	fn compact(
+		ops: Vec<crdt_operation::Data>,
+		acknowledged_timestamp: Option<NTP64>,
+	) -> BatchChanges {
+		let mut changes = BatchChanges::default();
+		compact_record(
+			ops,
+			acknowledged_timestamp,
+			&HashSet::new(),
+			&mut changes,
+			&mut CompactionReport::default(),
+		)
+		.unwrap();
+
+		changes
+	}
+
+	#[test]
+	fn record_ids_are_decoded_from_hex() {
+		assert_eq!(decode_hex(""), Some(vec![]));
+		assert_eq!(decode_hex("00FF7a"), Some(vec![0x00, 0xFF, 0x7A]));
+		assert_eq!(decode_hex("ABC"), None);
+		assert_eq!(decode_hex("ZZ"), None);
+	}
+
+	#[test]
+	fn deleted_record_is_kept_until_acknowledged() {
+		let ops = || {
+			vec![
+				op(1, 10, &CRDTOperationData::create()),
+				op(2, 20, &update(&["name"])),
+				op(3, 30, &CRDTOperationData::Delete),
+			]
+		};
+
+		assert!(compact(ops(), None).deletes.is_empty());
+		assert!(compact(ops(), Some(NTP64(29))).deletes.is_empty());
+
+		let mut deletes = compact(ops(), Some(NTP64(30))).deletes;
+		deletes.sort_unstable();
+		// The delete itself stays as a tombstone
+		assert_eq!(deletes, vec![1, 2]);
+	}
+
+	#[test]
+	fn superseded_updates_collapse_to_the_latest_write() {
+		let changes = compact(
+			vec![
+				op(1, 10, &CRDTOperationData::create()),
+				op(2, 20, &update(&["name", "size"])),
+				op(3, 30, &update(&["name"])),
+				op(4, 40, &update(&["name"])),
+			],
+			None,
+		);
+
+		assert_eq!(changes.deletes, vec![3]);
+
+		let [(id, kind, data)] = changes.rewrites.as_slice() else {
+			panic!("expected a single rewrite: {:?}", changes.rewrites);
+		};
+		assert_eq!(*id, 2);
+		assert_eq!(kind, &update(&["size"]).as_kind().to_string());
+		assert!(matches!(
+			rmp_serde::from_slice::<CRDTOperationData>(data).unwrap(),
+			CRDTOperationData::Update(fields) if fields.keys().eq(["size"])
+		));
+	}
+
+	#[test]
+	fn watermark_is_the_oldest_ingested_timestamp() {
+		let first = DevicePubId::new();
+		let second = DevicePubId::new();
+
+		assert_eq!(
+			ingested_watermark([], &HashMap::new()),
+			Some(NTP64(u64::MAX))
+		);
+
+		let mut timestamp_per_device = HashMap::from([(first.clone(), NTP64(50))]);
+		assert_eq!(
+			ingested_watermark([first.clone(), second.clone()], &timestamp_per_device),
+			None
+		);
+
+		timestamp_per_device.insert(second.clone(), NTP64(20));
+		assert_eq!(
+			ingested_watermark([first, second], &timestamp_per_device),
+			Some(NTP64(20))
+		);
+	}
+}
@@ -0,0 +1,505 @@
+//! Conflicts between concurrent edits of user-authored fields.
+//!
+//! Updates are resolved by last-writer-wins, so when two devices edit the same field offline, one
+//! of the values is discarded. For the fields in [`TRACKED_FIELDS`], the discarded value is kept
+//! in a conflict record, so the user can restore it.
+//!
+//! A conflict is recorded when an incoming update loses against a newer value written by this
+//! device. The hybrid logical clock guarantees the writer of the incoming update hadn't seen that
+//! newer value. Updates of other devices that we only relay are never compared, so each conflict
+//! is recorded once, on the device whose edit won.
+
+use sd_core_prisma_helpers::DevicePubId;
+
+use sd_prisma::{
+	prisma::{crdt_operation, object, saved_search, sync_conflict, tag, PrismaClient, SortOrder},
+	prisma_sync::{self, ModelSyncData},
+};
+use sd_sync::{CRDTOperation, CRDTOperationData, ModelId, OperationFactory, RecordId};
+
+use std::{collections::BTreeMap, sync::atomic};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use tracing::{debug, warn};
+use uhlc::NTP64;
+
+use super::{write_crdt_op_to_db, Error, SyncEvent, SyncManager};
+
+/// Fields whose concurrent edits are kept as conflicts
+pub const TRACKED_FIELDS: [(ModelId, &str); 7] = [
+	(prisma_sync::object::MODEL_ID, object::note::NAME),
+	(prisma_sync::tag::MODEL_ID, tag::name::NAME),
+	(prisma_sync::tag::MODEL_ID, tag::color::NAME),
+	(
+		prisma_sync::saved_search::MODEL_ID,
+		saved_search::name::NAME,
+	),
+	(
+		prisma_sync::saved_search::MODEL_ID,
+		saved_search::description::NAME,
+	),
+	(
+		prisma_sync::saved_search::MODEL_ID,
+		saved_search::search::NAME,
+	),
+	(
+		prisma_sync::saved_search::MODEL_ID,
+		saved_search::filters::NAME,
+	),
+];
+
+#[must_use]
+pub fn is_tracked(model_id: ModelId, field: &str) -> bool {
+	TRACKED_FIELDS
+		.iter()
+		.any(|(tracked_model_id, tracked_field)| {
+			*tracked_model_id == model_id && *tracked_field == field
+		})
+}
+
+crdt_operation::select!(crdt_operation_update { kind timestamp device_pub_id data });
+
+/// A value of an incoming update that lost against a newer local value
+#[derive(Debug, PartialEq)]
+pub(crate) struct LostUpdate {
+	pub field: String,
+	pub lost_value: rmpv::Value,
+	pub lost_timestamp: NTP64,
+	pub won_value: rmpv::Value,
+	pub won_timestamp: NTP64,
+	pub won_device_pub_id: Vec<u8>,
+}
+
+/// Removes from the incoming `data` the fields that have newer updates in the log, returning the
+/// tracked values that lost against an update authored by this device
+pub(crate) fn discard_superseded_fields(
+	current_device_pub_id: &DevicePubId,
+	model_id: ModelId,
+	data: &mut BTreeMap<String, (rmpv::Value, NTP64)>,
+	mut newer_updates: Vec<crdt_operation_update::Data>,
+) -> Result<Vec<LostUpdate>, Error> {
+	// Newest first, so lost values are compared against the current value of each field
+	newer_updates.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+
+	let current_device_pub_id = current_device_pub_id.to_db();
+	let mut lost_updates = vec![];
+
+	for candidate in newer_updates {
+		#[allow(clippy::cast_sign_loss)]
+		// we need to store as i64 due to SQLite limitations
+		let candidate_timestamp = NTP64(candidate.timestamp as u64);
+
+		// The first element is "u" meaning that this is an update, so we skip it
+		for key in candidate
+			.kind
+			.split(':')
+			.filter(|field| !field.is_empty())
+			.skip(1)
+		{
+			// remove entries if we possess locally more recent updates for this field
+			if !data
+				.get(key)
+				.is_some_and(|(_, new_timestamp)| *new_timestamp < candidate_timestamp)
+			{
+				continue;
+			}
+
+			let Some((lost_value, lost_timestamp)) = data.remove(key) else {
+				continue;
+			};
+
+			if !is_tracked(model_id, key) || candidate.device_pub_id != current_device_pub_id {
+				continue;
+			}
+
+			let CRDTOperationData::Update(mut won_values) = rmp_serde::from_slice(&candidate.data)?
+			else {
+				continue;
+			};
+
+			if let Some(won_value) = won_values.remove(key) {
+				if won_value != lost_value {
+					lost_updates.push(LostUpdate {
+						field: key.to_string(),
+						lost_value,
+						lost_timestamp,
+						won_value,
+						won_timestamp: candidate_timestamp,
+						won_device_pub_id: candidate.device_pub_id.clone(),
+					});
+				}
+			}
+		}
+
+		if data.is_empty() {
+			break;
+		}
+	}
+
+	Ok(lost_updates)
+}
+
+/// Stores conflicts for the lost updates, skipping the ones already stored, as the same
+/// operations can be received more than once
+pub(crate) async fn record_conflicts(
+	db: &PrismaClient,
+	device_pub_id: &DevicePubId,
+	model_id: ModelId,
+	record_id: &RecordId,
+	lost_updates: Vec<LostUpdate>,
+) -> Result<(), Error> {
+	let record_id = rmp_serde::to_vec(record_id)?;
+
+	let conflicts = lost_updates
+		.into_iter()
+		.map(
+			|LostUpdate {
+			     field,
+			     lost_value,
+			     lost_timestamp,
+			     won_value,
+			     won_timestamp,
+			     won_device_pub_id,
+			 }| {
+				#[allow(clippy::cast_possible_wrap)]
+				// SAFETY: we had to store using i64 due to SQLite limitations
+				let (lost_timestamp, won_timestamp) = (
+					lost_timestamp.as_u64() as i64,
+					won_timestamp.as_u64() as i64,
+				);
+
+				Ok(sync_conflict::CreateUnchecked {
+					model: i32::from(model_id),
+					record_id: record_id.clone(),
+					field,
+					lost_value: rmp_serde::to_vec(&lost_value)?,
+					won_value: rmp_serde::to_vec(&won_value)?,
+					lost_timestamp,
+					lost_device_pub_id: device_pub_id.to_db(),
+					won_timestamp,
+					won_device_pub_id,
+					date_created: Utc::now().into(),
+					_params: vec![],
+				})
+			},
+		)
+		.collect::<Result<Vec<_>, Error>>()?;
+
+	let existing = db
+		._batch(
+			conflicts
+				.iter()
+				.map(|conflict| {
+					db.sync_conflict().count(vec![
+						sync_conflict::model::equals(conflict.model),
+						sync_conflict::record_id::equals(conflict.record_id.clone()),
+						sync_conflict::field::equals(conflict.field.clone()),
+						sync_conflict::lost_timestamp::equals(conflict.lost_timestamp),
+						sync_conflict::lost_device_pub_id::equals(
+							conflict.lost_device_pub_id.clone(),
+						),
+					])
+				})
+				.collect::<Vec<_>>(),
+		)
+		.await?;
+
+	let new_conflicts = conflicts
+		.into_iter()
+		.zip(existing)
+		.filter_map(|(conflict, count)| (count == 0).then_some(conflict))
+		.collect::<Vec<_>>();
+
+	if !new_conflicts.is_empty() {
+		debug!(count = new_conflicts.len(), "Recording sync conflicts;");
+
+		db.sync_conflict().create_many(new_conflicts).exec().await?;
+	}
+
+	Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct Conflict {
+	pub id: sync_conflict::id::Type,
+	pub model_id: ModelId,
+	pub record_id: RecordId,
+	pub field: String,
+	pub lost_value: rmpv::Value,
+	pub lost_timestamp: NTP64,
+	pub lost_device_pub_id: DevicePubId,
+	pub won_value: rmpv::Value,
+	pub won_timestamp: NTP64,
+	pub won_device_pub_id: DevicePubId,
+	pub date_created: DateTime<FixedOffset>,
+}
+
+impl TryFrom<sync_conflict::Data> for Conflict {
+	type Error = Error;
+
+	fn try_from(
+		sync_conflict::Data {
+			id,
+			model,
+			record_id,
+			field,
+			lost_value,
+			won_value,
+			lost_timestamp,
+			lost_device_pub_id,
+			won_timestamp,
+			won_device_pub_id,
+			date_created,
+		}: sync_conflict::Data,
+	) -> Result<Self, Self::Error> {
+		#[allow(clippy::cast_sign_loss)]
+		// SAFETY: we had to store using i64 due to SQLite limitations
+		let (lost_timestamp, won_timestamp) = (lost_timestamp as u64, won_timestamp as u64);
+
+		#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+		// SAFETY: we will not have more than 2^16 models and we had to store using signed
+		// integers due to SQLite limitations
+		let model_id = model as ModelId;
+
+		Ok(Self {
+			id,
+			model_id,
+			record_id: rmp_serde::from_slice(&record_id)?,
+			field,
+			lost_value: rmp_serde::from_slice(&lost_value)?,
+			lost_timestamp: NTP64(lost_timestamp),
+			lost_device_pub_id: DevicePubId::from(lost_device_pub_id),
+			won_value: rmp_serde::from_slice(&won_value)?,
+			won_timestamp: NTP64(won_timestamp),
+			won_device_pub_id: DevicePubId::from(won_device_pub_id),
+			date_created,
+		})
+	}
+}
+
+/// Unresolved conflicts, newest first
+pub async fn list_conflicts(sync: &SyncManager) -> Result<Vec<Conflict>, Error> {
+	sync.db
+		.sync_conflict()
+		.find_many(vec![])
+		.order_by(sync_conflict::date_created::order(SortOrder::Desc))
+		.exec()
+		.await?
+		.into_iter()
+		.map(Conflict::try_from)
+		.collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictResolution {
+	/// Keeps the value that won, discarding the conflict
+	KeepCurrent,
+	/// Writes the lost value as a new edit, so it wins on every device
+	UseLost,
+}
+
+pub async fn resolve_conflict(
+	sync: &SyncManager,
+	id: sync_conflict::id::Type,
+	resolution: ConflictResolution,
+) -> Result<(), Error> {
+	let db = &sync.db;
+
+	let conflict = db
+		.sync_conflict()
+		.find_unique(sync_conflict::id::equals(id))
+		.exec()
+		.await?
+		.ok_or(Error::ConflictNotFound(id))
+		.and_then(Conflict::try_from)?;
+
+	if resolution == ConflictResolution::KeepCurrent {
+		db.sync_conflict()
+			.delete(sync_conflict::id::equals(id))
+			.exec()
+			.await?;
+
+		return Ok(());
+	}
+
+	let op = new_op_for_record(
+		sync,
+		conflict.model_id,
+		conflict.record_id,
+		CRDTOperationData::Update([(conflict.field, conflict.lost_value)].into()),
+	);
+
+	let emit_messages = sync.emit_messages_flag.load(atomic::Ordering::Relaxed);
+
+	let lock_guard = sync.sync_lock.lock().await;
+
+	db._transaction()
+		.with_timeout(30 * 10000)
+		.with_max_wait(30 * 10000)
+		.run(|db| {
+			let op = op.clone();
+
+			async move {
+				ModelSyncData::from_op(op.clone())?.exec(&db).await?;
+
+				if emit_messages {
+					write_crdt_op_to_db(&op, &db).await?;
+				}
+
+				db.sync_conflict()
+					.delete(sync_conflict::id::equals(id))
+					.exec()
+					.await?;
+
+				Ok::<_, Error>(())
+			}
+		})
+		.await?;
+
+	drop(lock_guard);
+
+	if emit_messages {
+		sync.timestamp_per_device
+			.write()
+			.await
+			.insert(sync.device_pub_id.clone(), op.timestamp);
+
+		if sync.tx.send(SyncEvent::Created).is_err() {
+			warn!("failed to send created message on `resolve_conflict`");
+		}
+	}
+
+	Ok(())
+}
+
+/// Like [`OperationFactory::new_op`], for records whose id is already deserialized
+fn new_op_for_record(
+	sync: &SyncManager,
+	model_id: ModelId,
+	record_id: RecordId,
+	data: CRDTOperationData,
+) -> CRDTOperation {
+	CRDTOperation {
+		device_pub_id: sync.get_device_pub_id(),
+		timestamp: *sync.get_clock().new_timestamp().get_time(),
+		model_id,
+		record_id,
+		data,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn update(
+		timestamp: i64,
+		device_pub_id: &DevicePubId,
+		field: &str,
+		value: &str,
+	) -> crdt_operation_update::Data {
+		let data = CRDTOperationData::Update([(field.to_string(), value.into())].into());
+
+		crdt_operation_update::Data {
+			kind: data.as_kind().to_string(),
+			timestamp,
+			device_pub_id: device_pub_id.to_db(),
+			data: rmp_serde::to_vec(&data).unwrap(),
+		}
+	}
+
+	fn incoming(fields: &[(&str, &str, u64)]) -> BTreeMap<String, (rmpv::Value, NTP64)> {
+		fields
+			.iter()
+			.map(|(field, value, timestamp)| {
+				((*field).to_string(), ((*value).into(), NTP64(*timestamp)))
+			})
+			.collect()
+	}
+
+	#[test]
+	fn incoming_update_losing_against_own_edit_is_a_conflict() {
+		let current_device = DevicePubId::new();
+		let mut data = incoming(&[(tag::name::NAME, "Remote", 10)]);
+
+		let lost_updates = discard_superseded_fields(
+			&current_device,
+			prisma_sync::tag::MODEL_ID,
+			&mut data,
+			vec![update(20, &current_device, tag::name::NAME, "Local")],
+		)
+		.unwrap();
+
+		assert!(data.is_empty());
+		assert_eq!(
+			lost_updates,
+			vec![LostUpdate {
+				field: tag::name::NAME.to_string(),
+				lost_value: "Remote".into(),
+				lost_timestamp: NTP64(10),
+				won_value: "Local".into(),
+				won_timestamp: NTP64(20),
+				won_device_pub_id: current_device.to_db(),
+			}]
+		);
+	}
+
+	#[test]
+	fn updates_authored_by_other_devices_are_not_conflicts() {
+		let current_device = DevicePubId::new();
+		let mut data = incoming(&[(tag::name::NAME, "Remote", 10)]);
+
+		let lost_updates = discard_superseded_fields(
+			&current_device,
+			prisma_sync::tag::MODEL_ID,
+			&mut data,
+			vec![update(20, &DevicePubId::new(), tag::name::NAME, "Relayed")],
+		)
+		.unwrap();
+
+		// The newer value still wins, it just isn't ours to report
+		assert!(data.is_empty());
+		assert!(lost_updates.is_empty());
+	}
+
+	#[test]
+	fn newer_incoming_values_and_untracked_fields_are_not_conflicts() {
+		let current_device = DevicePubId::new();
+		let mut data = incoming(&[(tag::name::NAME, "Remote", 30), ("date_modified", "x", 10)]);
+
+		let lost_updates = discard_superseded_fields(
+			&current_device,
+			prisma_sync::tag::MODEL_ID,
+			&mut data,
+			vec![
+				update(20, &current_device, tag::name::NAME, "Local"),
+				update(20, &current_device, "date_modified", "y"),
+			],
+		)
+		.unwrap();
+
+		assert!(lost_updates.is_empty());
+		assert_eq!(data.keys().collect::<Vec<_>>(), vec![tag::name::NAME]);
+	}
+
+	#[test]
+	fn lost_value_is_compared_against_the_latest_own_edit() {
+		let current_device = DevicePubId::new();
+		let mut data = incoming(&[(tag::color::NAME, "#000", 10)]);
+
+		let lost_updates = discard_superseded_fields(
+			&current_device,
+			prisma_sync::tag::MODEL_ID,
+			&mut data,
+			vec![
+				update(20, &current_device, tag::color::NAME, "#111"),
+				update(30, &current_device, tag::color::NAME, "#000"),
+			],
+		)
+		.unwrap();
+
+		// Both ended up with the same value, nothing to restore
+		assert!(lost_updates.is_empty());
+		assert!(data.is_empty());
+	}
+}
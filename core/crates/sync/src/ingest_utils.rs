@@ -16,17 +16,22 @@ use tracing::{debug, instrument, trace, warn};
 use uhlc::{Timestamp, HLC, NTP64};
 use uuid::Uuid;
 
-use super::{db_operation::write_crdt_op_to_db, Error, TimestampPerDevice};
+use super::{
+	conflict::{crdt_operation_update, discard_superseded_fields, record_conflicts},
+	db_operation::write_crdt_op_to_db,
+	Error, TimestampPerDevice,
+};
 
 crdt_operation::select!(crdt_operation_id { id });
 
 // where the magic happens
-#[instrument(skip(clock, ops), fields(operations_count = %ops.len()), err)]
+#[instrument(skip(clock, current_device_pub_id, ops), fields(operations_count = %ops.len()), err)]
 pub async fn process_crdt_operations(
 	clock: &HLC,
 	timestamp_per_device: &TimestampPerDevice,
 	sync_lock: Arc<Mutex<()>>,
 	db: &PrismaClient,
+	current_device_pub_id: &DevicePubId,
 	device_pub_id: DevicePubId,
 	model_id: ModelId,
 	(record_id, mut ops): (RecordId, Vec<CompressedCRDTOperation>),
@@ -117,7 +122,7 @@ pub async fn process_crdt_operations(
 		);
 
 		// conflict resolution
-		let (create, possible_newer_updates_count) = db
+		let (create, possible_newer_updates) = db
 			._batch((
 				db.crdt_operation().count(vec![
 					crdt_operation::model::equals(i32::from(model_id)),
@@ -138,7 +143,7 @@ pub async fn process_crdt_operations(
 						crdt_operation::record_id::equals(rmp_serde::to_vec(&record_id)?),
 						crdt_operation::kind::starts_with("u".to_string()),
 					])
-					.select(crdt_operation::select!({ kind timestamp })),
+					.select(crdt_operation_update::select()),
 			))
 			.await?;
 
@@ -147,29 +152,15 @@ pub async fn process_crdt_operations(
 			return Ok(());
 		}
 
-		for candidate in possible_newer_updates_count {
-			// The first element is "u" meaning that this is an update, so we skip it
-			for key in candidate
-				.kind
-				.split(':')
-				.filter(|field| !field.is_empty())
-				.skip(1)
-			{
-				// remove entries if we possess locally more recent updates for this field
-				if data.get(key).is_some_and(|(_, new_timestamp)| {
-					#[allow(clippy::cast_sign_loss)]
-					{
-						// we need to store as i64 due to SQLite limitations
-						*new_timestamp < NTP64(candidate.timestamp as u64)
-					}
-				}) {
-					data.remove(key);
-				}
-			}
+		let lost_updates = discard_superseded_fields(
+			current_device_pub_id,
+			model_id,
+			&mut data,
+			possible_newer_updates,
+		)?;
 
-			if data.is_empty() {
-				break;
-			}
+		if !lost_updates.is_empty() {
+			record_conflicts(db, &device_pub_id, model_id, &record_id, lost_updates).await?;
 		}
 
 		handle_crdt_updates(db, &sync_lock, &device_pub_id, model_id, record_id, data).await?;
@@ -482,7 +473,7 @@ async fn handle_crdt_deletion(
 		.await
 }
 
-fn update_clock(clock: &HLC, latest_timestamp: NTP64, device_pub_id: &DevicePubId) {
+pub(crate) fn update_clock(clock: &HLC, latest_timestamp: NTP64, device_pub_id: &DevicePubId) {
 	// first, we update the HLC's timestamp with the incoming one.
 	// this involves a drift check + sets the last time of the clock
 	clock
@@ -495,7 +486,7 @@ fn update_clock(clock: &HLC, latest_timestamp: NTP64, device_pub_id: &DevicePubI
 		.expect("timestamp has too much drift!");
 }
 
-async fn update_timestamp_per_device(
+pub(crate) async fn update_timestamp_per_device(
 	timestamp_per_device: &TimestampPerDevice,
 	device_pub_id: DevicePubId,
 	latest_timestamp: NTP64,
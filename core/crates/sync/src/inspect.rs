@@ -0,0 +1,605 @@
+//! Introspection of the sync state, used to find out why the data of two devices diverged.
+//!
+//! Records are compared through checksums of their current state, derived from their operations
+//! with the same last-write-wins rules used on ingestion. So devices with different operation logs,
+//! like after a compaction, still have the same checksums if they converged to the same state.
+//! Deleted records aren't part of the checksums, so a record deleted on a single device shows up
+//! as missing on it.
+
+use sd_prisma::{
+	prisma::{cloud_crdt_operation, crdt_operation, SortOrder},
+	prisma_sync,
+};
+use sd_sync::{CRDTOperation, CRDTOperationData, ModelId, OperationKind};
+use sd_utils::uuid_to_bytes;
+
+use std::collections::{btree_map::Entry, BTreeMap, HashMap};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{cloud_crdt_op_db, from_crdt_ops, Error, SyncManager};
+
+/// Records read at once while computing checksums
+const RECORDS_PER_BATCH: i64 = 1000;
+
+/// Records are split in buckets by the first byte of their id's hash, so diverging records can be
+/// found without exchanging the hashes of every record
+const BUCKETS_COUNT: usize = 256;
+
+pub type RecordHash = [u8; 32];
+
+/// Every synced model, in the order they're ingested
+pub const SYNC_MODELS: [(ModelId, &str); 11] = [
+	(prisma_sync::device::MODEL_ID, "device"),
+	(prisma_sync::volume::MODEL_ID, "volume"),
+	(prisma_sync::tag::MODEL_ID, "tag"),
+	(prisma_sync::location::MODEL_ID, "location"),
+	(prisma_sync::object::MODEL_ID, "object"),
+	(prisma_sync::label::MODEL_ID, "label"),
+	(prisma_sync::saved_search::MODEL_ID, "saved_search"),
+	(prisma_sync::exif_data::MODEL_ID, "exif_data"),
+	(prisma_sync::file_path::MODEL_ID, "file_path"),
+	(prisma_sync::tag_on_object::MODEL_ID, "tag_on_object"),
+	(prisma_sync::label_on_object::MODEL_ID, "label_on_object"),
+];
+
+#[must_use]
+pub fn model_name(model_id: ModelId) -> Option<&'static str> {
+	SYNC_MODELS
+		.iter()
+		.find_map(|(id, name)| (*id == model_id).then_some(*name))
+}
+
+#[derive(Debug, Clone)]
+pub struct IngestErrorEntry {
+	pub at: DateTime<Utc>,
+	pub message: String,
+}
+
+/// Amount of operations received from other devices that are still waiting to be ingested
+pub async fn pending_operations_per_model(
+	sync: &SyncManager,
+) -> Result<Vec<(ModelId, i64)>, Error> {
+	let counts =
+		sync.db
+			._batch(
+				SYNC_MODELS
+					.iter()
+					.map(|(model_id, _)| {
+						sync.db.cloud_crdt_operation().count(vec![
+							cloud_crdt_operation::model::equals(i32::from(*model_id)),
+						])
+					})
+					.collect::<Vec<_>>(),
+			)
+			.await?;
+
+	Ok(SYNC_MODELS
+		.iter()
+		.map(|(model_id, _)| *model_id)
+		.zip(counts)
+		.collect())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ModelChecksum {
+	pub model_id: ModelId,
+	pub records_count: u64,
+	/// XOR of all record hashes, so it doesn't depend on the order records are read
+	pub checksum: RecordHash,
+	pub buckets: Vec<RecordHash>,
+}
+
+impl ModelChecksum {
+	fn new(model_id: ModelId) -> Self {
+		Self {
+			model_id,
+			records_count: 0,
+			checksum: [0; 32],
+			buckets: vec![[0; 32]; BUCKETS_COUNT],
+		}
+	}
+
+	fn add(&mut self, record_id: &[u8], hash: &RecordHash) {
+		self.records_count += 1;
+
+		let bucket = &mut self.buckets[usize::from(record_bucket(record_id))];
+		for ((checksum, bucket), byte) in self.checksum.iter_mut().zip(bucket).zip(hash) {
+			*checksum ^= byte;
+			*bucket ^= byte;
+		}
+	}
+
+	/// Buckets with different checksums between both sides
+	#[must_use]
+	pub fn diverging_buckets(&self, other: &Self) -> Vec<u8> {
+		self.buckets
+			.iter()
+			.zip(&other.buckets)
+			.enumerate()
+			.filter(|(_, (ours, theirs))| ours != theirs)
+			.filter_map(|(bucket, _)| u8::try_from(bucket).ok())
+			.collect()
+	}
+}
+
+fn record_bucket(record_id: &[u8]) -> u8 {
+	blake3::hash(record_id).as_bytes()[0]
+}
+
+/// Computes the current state of a record from its operations, `None` if it was deleted
+fn record_hash(
+	model_id: ModelId,
+	record_id: &[u8],
+	ops: Vec<crdt_operation::Data>,
+) -> Result<Option<RecordHash>, Error> {
+	let mut fields = BTreeMap::<String, ((i64, Vec<u8>), rmpv::Value)>::new();
+
+	for crdt_operation::Data {
+		timestamp,
+		device_pub_id,
+		data,
+		..
+	} in ops
+	{
+		let values = match rmp_serde::from_slice(&data)? {
+			CRDTOperationData::Delete => return Ok(None),
+			CRDTOperationData::Create(values) | CRDTOperationData::Update(values) => values,
+		};
+
+		// Ties are broken by device, so both sides always pick the same value
+		let version = (timestamp, device_pub_id);
+
+		for (field, value) in values {
+			match fields.entry(field) {
+				Entry::Vacant(entry) => {
+					entry.insert((version.clone(), value));
+				}
+				Entry::Occupied(mut entry) => {
+					if entry.get().0 < version {
+						entry.insert((version.clone(), value));
+					}
+				}
+			}
+		}
+	}
+
+	let state = fields
+		.iter()
+		.map(|(field, (_, value))| (field.as_str(), value))
+		.collect::<BTreeMap<_, _>>();
+
+	let mut hasher = blake3::Hasher::new();
+	hasher.update(&model_id.to_le_bytes());
+	hasher.update(record_id);
+	hasher.update(&rmp_serde::to_vec(&state)?);
+
+	Ok(Some(*hasher.finalize().as_bytes()))
+}
+
+/// Calls `f` with the id and hash of every record of the model that wasn't deleted
+async fn for_each_record(
+	sync: &SyncManager,
+	model_id: ModelId,
+	mut f: impl FnMut(Vec<u8>, RecordHash) + Send,
+) -> Result<(), Error> {
+	let db = &sync.db;
+	let mut cursor = 0;
+
+	loop {
+		let creates = db
+			.crdt_operation()
+			.find_many(vec![
+				crdt_operation::id::gt(cursor),
+				crdt_operation::model::equals(i32::from(model_id)),
+				crdt_operation::kind::equals(OperationKind::Create.to_string()),
+			])
+			.order_by(crdt_operation::id::order(SortOrder::Asc))
+			.take(RECORDS_PER_BATCH)
+			.select(crdt_operation::select!({ id record_id }))
+			.exec()
+			.await?;
+
+		let Some(last) = creates.last() else {
+			return Ok(());
+		};
+		cursor = last.id;
+
+		let mut ops_per_record = HashMap::<_, Vec<_>>::new();
+
+		for op in db
+			.crdt_operation()
+			.find_many(vec![
+				crdt_operation::model::equals(i32::from(model_id)),
+				crdt_operation::record_id::in_vec(
+					creates.into_iter().map(|create| create.record_id).collect(),
+				),
+			])
+			.exec()
+			.await?
+		{
+			ops_per_record
+				.entry(op.record_id.clone())
+				.or_default()
+				.push(op);
+		}
+
+		for (record_id, ops) in ops_per_record {
+			if let Some(hash) = record_hash(model_id, &record_id, ops)? {
+				f(record_id, hash);
+			}
+		}
+	}
+}
+
+pub async fn model_checksums(sync: &SyncManager) -> Result<Vec<ModelChecksum>, Error> {
+	let mut checksums = Vec::with_capacity(SYNC_MODELS.len());
+
+	for (model_id, _) in SYNC_MODELS {
+		let mut checksum = ModelChecksum::new(model_id);
+		for_each_record(sync, model_id, |record_id, hash| {
+			checksum.add(&record_id, &hash);
+		})
+		.await?;
+
+		checksums.push(checksum);
+	}
+
+	Ok(checksums)
+}
+
+/// Hashes of every record of the model within the given buckets
+pub async fn record_hashes(
+	sync: &SyncManager,
+	model_id: ModelId,
+	buckets: &[u8],
+) -> Result<Vec<(Vec<u8>, RecordHash)>, Error> {
+	let mut hashes = vec![];
+
+	for_each_record(sync, model_id, |record_id, hash| {
+		if buckets.contains(&record_bucket(&record_id)) {
+			hashes.push((record_id, hash));
+		}
+	})
+	.await?;
+
+	Ok(hashes)
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum DivergenceKind {
+	/// The record only exists on the other device, it was deleted or never received here
+	MissingLocally,
+	/// The record only exists on this device
+	MissingRemotely,
+	/// Both devices have the record, but with different values
+	Different,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordDivergence {
+	pub model_id: ModelId,
+	/// Serialized [`RecordId`](sd_sync::RecordId), as stored in the operations table
+	pub record_id: Vec<u8>,
+	pub kind: DivergenceKind,
+}
+
+#[must_use]
+pub fn diverging_records(
+	model_id: ModelId,
+	local: Vec<(Vec<u8>, RecordHash)>,
+	remote: Vec<(Vec<u8>, RecordHash)>,
+) -> Vec<RecordDivergence> {
+	let mut remote = remote.into_iter().collect::<HashMap<_, _>>();
+
+	let mut divergences = local
+		.into_iter()
+		.filter_map(|(record_id, local_hash)| {
+			let kind = match remote.remove(&record_id) {
+				Some(remote_hash) if remote_hash == local_hash => return None,
+				Some(_) => DivergenceKind::Different,
+				None => DivergenceKind::MissingRemotely,
+			};
+
+			Some(RecordDivergence {
+				model_id,
+				record_id,
+				kind,
+			})
+		})
+		.collect::<Vec<_>>();
+
+	divergences.extend(remote.into_keys().map(|record_id| RecordDivergence {
+		model_id,
+		record_id,
+		kind: DivergenceKind::MissingLocally,
+	}));
+
+	divergences
+}
+
+/// Every operation we have for the given records
+pub async fn record_operations(
+	sync: &SyncManager,
+	model_id: ModelId,
+	record_ids: Vec<Vec<u8>>,
+) -> Result<Vec<CRDTOperation>, Error> {
+	sync.db
+		.crdt_operation()
+		.find_many(vec![
+			crdt_operation::model::equals(i32::from(model_id)),
+			crdt_operation::record_id::in_vec(record_ids),
+		])
+		.order_by(crdt_operation::timestamp::order(SortOrder::Asc))
+		.exec()
+		.await?
+		.into_iter()
+		.map(from_crdt_ops)
+		.collect()
+}
+
+/// Ingests operations of specific records received from another device, skipping the ones we
+/// already have. Returns how many operations were new.
+pub async fn ingest_record_operations(
+	sync: &SyncManager,
+	ops: Vec<CRDTOperation>,
+) -> Result<usize, Error> {
+	let db = &sync.db;
+
+	let existing = db
+		._batch(
+			ops.iter()
+				.map(|op| {
+					#[allow(clippy::cast_possible_wrap)]
+					// SAFETY: we had to store using i64 due to SQLite limitations
+					let timestamp = op.timestamp.as_u64() as i64;
+
+					Ok(db.crdt_operation().count(vec![
+						crdt_operation::model::equals(i32::from(op.model_id)),
+						crdt_operation::record_id::equals(rmp_serde::to_vec(&op.record_id)?),
+						crdt_operation::device_pub_id::equals(uuid_to_bytes(&op.device_pub_id)),
+						crdt_operation::timestamp::equals(timestamp),
+					]))
+				})
+				.collect::<Result<Vec<_>, Error>>()?,
+		)
+		.await?;
+
+	let new_ops = ops
+		.into_iter()
+		.zip(existing)
+		.filter_map(|(op, count)| (count == 0).then_some(op))
+		.collect::<Vec<_>>();
+
+	if new_ops.is_empty() {
+		return Ok(0);
+	}
+
+	db._batch(
+		new_ops
+			.iter()
+			.map(|op| cloud_crdt_op_db(op).map(|op| op.to_query(db)))
+			.collect::<Result<Vec<_>, _>>()?,
+	)
+	.await?;
+
+	sync.ingest_ops().await?;
+
+	Ok(new_ops.len())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashSet;
+
+	use uuid::Uuid;
+
+	use super::*;
+
+	fn op(
+		id: crdt_operation::id::Type,
+		timestamp: i64,
+		device: u8,
+		data: &CRDTOperationData,
+	) -> crdt_operation::Data {
+		crdt_operation::Data {
+			id,
+			timestamp,
+			model: i32::from(prisma_sync::tag::MODEL_ID),
+			record_id: vec![1],
+			kind: data.as_kind().to_string(),
+			data: rmp_serde::to_vec(data).unwrap(),
+			device_pub_id: vec![device],
+		}
+	}
+
+	fn values(fields: &[(&str, &str)]) -> BTreeMap<String, rmpv::Value> {
+		fields
+			.iter()
+			.map(|(field, value)| ((*field).to_string(), rmpv::Value::from(*value)))
+			.collect()
+	}
+
+	fn hash(ops: Vec<crdt_operation::Data>) -> Option<RecordHash> {
+		record_hash(prisma_sync::tag::MODEL_ID, &[1], ops).unwrap()
+	}
+
+	#[test]
+	fn sync_models_match_prisma_sync() {
+		let synced_model_ids = (0..=ModelId::MAX)
+			.filter(|model_id| {
+				!matches!(
+					prisma_sync::ModelSyncData::from_op(CRDTOperation {
+						device_pub_id: Uuid::nil(),
+						timestamp: sd_sync::NTP64(0),
+						model_id: *model_id,
+						record_id: rmpv::Value::Nil,
+						data: CRDTOperationData::Delete,
+					}),
+					Err(prisma_sync::Error::InvalidModelId(_))
+				)
+			})
+			.collect::<HashSet<_>>();
+
+		let listed_model_ids = SYNC_MODELS
+			.iter()
+			.map(|(model_id, _)| *model_id)
+			.collect::<HashSet<_>>();
+
+		assert_eq!(
+			listed_model_ids.len(),
+			SYNC_MODELS.len(),
+			"duplicated model"
+		);
+		assert_eq!(listed_model_ids, synced_model_ids);
+		assert_eq!(
+			model_name(prisma_sync::file_path::MODEL_ID),
+			Some("file_path")
+		);
+	}
+
+	#[test]
+	fn record_hash_only_depends_on_the_final_state() {
+		let full_log = hash(vec![
+			op(
+				1,
+				10,
+				1,
+				&CRDTOperationData::Create(values(&[("name", "a")])),
+			),
+			op(
+				2,
+				20,
+				1,
+				&CRDTOperationData::Update(values(&[("name", "b")])),
+			),
+			op(
+				3,
+				30,
+				2,
+				&CRDTOperationData::Update(values(&[("color", "red")])),
+			),
+		]);
+
+		// Same state after compaction dropped the superseded update, read in another order
+		let compacted_log = hash(vec![
+			op(
+				3,
+				30,
+				2,
+				&CRDTOperationData::Update(values(&[("color", "red")])),
+			),
+			op(
+				2,
+				20,
+				1,
+				&CRDTOperationData::Update(values(&[("name", "b")])),
+			),
+			op(1, 10, 1, &CRDTOperationData::create()),
+		]);
+
+		assert!(full_log.is_some());
+		assert_eq!(full_log, compacted_log);
+
+		let other_state = hash(vec![
+			op(
+				1,
+				10,
+				1,
+				&CRDTOperationData::Create(values(&[("name", "a")])),
+			),
+			op(
+				3,
+				30,
+				2,
+				&CRDTOperationData::Update(values(&[("color", "red")])),
+			),
+		]);
+		assert_ne!(full_log, other_state);
+	}
+
+	#[test]
+	fn record_hash_breaks_timestamp_ties_by_device() {
+		let first = op(
+			1,
+			10,
+			1,
+			&CRDTOperationData::Update(values(&[("name", "a")])),
+		);
+		let second = op(
+			2,
+			10,
+			2,
+			&CRDTOperationData::Update(values(&[("name", "b")])),
+		);
+
+		assert_eq!(
+			hash(vec![first.clone(), second.clone()]),
+			hash(vec![second, first])
+		);
+	}
+
+	#[test]
+	fn deleted_records_have_no_hash() {
+		assert_eq!(
+			hash(vec![
+				op(1, 10, 1, &CRDTOperationData::create()),
+				op(2, 20, 1, &CRDTOperationData::Delete),
+			]),
+			None
+		);
+	}
+
+	#[test]
+	fn model_checksum_is_order_independent_and_finds_diverging_buckets() {
+		let records = [(vec![1], [1; 32]), (vec![2], [2; 32]), (vec![3], [3; 32])];
+
+		let mut ours = ModelChecksum::new(prisma_sync::tag::MODEL_ID);
+		for (record_id, hash) in &records {
+			ours.add(record_id, hash);
+		}
+
+		let mut theirs = ModelChecksum::new(prisma_sync::tag::MODEL_ID);
+		for (record_id, hash) in records.iter().rev() {
+			theirs.add(record_id, hash);
+		}
+
+		assert_eq!(ours, theirs);
+		assert_eq!(ours.records_count, 3);
+		assert!(ours.diverging_buckets(&theirs).is_empty());
+
+		let mut changed = ModelChecksum::new(prisma_sync::tag::MODEL_ID);
+		changed.add(&records[0].0, &records[0].1);
+		changed.add(&records[1].0, &records[1].1);
+		changed.add(&records[2].0, &[4; 32]);
+
+		assert_ne!(ours.checksum, changed.checksum);
+		assert_eq!(
+			ours.diverging_buckets(&changed),
+			vec![record_bucket(&records[2].0)]
+		);
+	}
+
+	#[test]
+	fn diverging_records_are_classified() {
+		let mut divergences = diverging_records(
+			prisma_sync::tag::MODEL_ID,
+			vec![(vec![1], [1; 32]), (vec![2], [2; 32]), (vec![3], [3; 32])],
+			vec![(vec![1], [1; 32]), (vec![2], [9; 32]), (vec![4], [4; 32])],
+		)
+		.into_iter()
+		.map(|divergence| (divergence.record_id, divergence.kind))
+		.collect::<Vec<_>>();
+		divergences.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+		assert_eq!(
+			divergences,
+			vec![
+				(vec![2], DivergenceKind::Different),
+				(vec![3], DivergenceKind::MissingRemotely),
+				(vec![4], DivergenceKind::MissingLocally),
+			]
+		);
+	}
+}
@@ -38,9 +38,13 @@ use std::{collections::HashMap, sync::Arc};
 use tokio::{sync::RwLock, task::JoinError};
 
 pub mod backfill;
+pub mod compaction;
+pub mod conflict;
 mod db_operation;
 mod ingest_utils;
+pub mod inspect;
 mod manager;
+pub mod scope;
 
 pub use db_operation::{from_cloud_crdt_ops, from_crdt_ops, write_crdt_op_to_db};
 pub use manager::Manager as SyncManager;
@@ -77,6 +81,8 @@ pub enum Error {
 	EmptyOperations,
 	#[error("device not found: {0}")]
 	DeviceNotFound(DevicePubId),
+	#[error("sync conflict not found: {0}")]
+	ConflictNotFound(i32),
 	#[error("processes crdt task panicked")]
 	ProcessCrdtPanic(JoinError),
 }
@@ -85,6 +91,9 @@ impl From<Error> for rspc::Error {
 	fn from(e: Error) -> Self {
 		match e {
 			Error::Database(e) => e.into(),
+			Error::ConflictNotFound(_) => {
+				Self::with_cause(rspc::ErrorCode::NotFound, e.to_string(), e)
+			}
 			Error::InvalidModelId(id) => Self::new(
 				rspc::ErrorCode::BadRequest,
 				format!("Invalid model id <id={id}>"),
@@ -10,7 +10,7 @@ use sd_sync::{
 use sd_utils::timestamp_to_datetime;
 
 use std::{
-	collections::{hash_map::Entry, BTreeMap, HashMap},
+	collections::{hash_map::Entry, BTreeMap, HashMap, VecDeque},
 	fmt, mem,
 	num::NonZeroU128,
 	sync::{
@@ -21,6 +21,7 @@ use std::{
 };
 
 use async_stream::stream;
+use chrono::Utc;
 use futures::{stream::FuturesUnordered, Stream, TryStreamExt};
 use futures_concurrency::future::TryJoin;
 use itertools::Itertools;
@@ -37,11 +38,16 @@ use super::{
 	crdt_op_db,
 	db_operation::{from_cloud_crdt_ops, from_crdt_ops},
 	ingest_utils::{bulk_ingest_create_only_ops, process_crdt_operations},
+	inspect::IngestErrorEntry,
+	scope::{self, SyncScope},
 	Error, SyncEvent, TimestampPerDevice, NTP64,
 };
 
 const INGESTION_BATCH_SIZE: i64 = 10_000;
 
+/// How many ingest errors are kept for inspection
+const MAX_INGEST_ERRORS: usize = 20;
+
 /// Wrapper that spawns the ingest actor and provides utilities for reading and writing sync operations.
 #[derive(Clone)]
 pub struct Manager {
@@ -53,8 +59,10 @@ pub struct Manager {
 	pub clock: Arc<HLC>,
 	pub active: Arc<AtomicBool>,
 	pub active_notify: Arc<Notify>,
+	pub scope: Arc<RwLock<SyncScope>>,
 	pub(crate) sync_lock: Arc<Mutex<()>>,
 	pub(crate) available_parallelism: usize,
+	pub(crate) ingest_errors: Arc<Mutex<VecDeque<IngestErrorEntry>>>,
 }
 
 impl fmt::Debug for Manager {
@@ -70,6 +78,7 @@ impl Manager {
 		db: Arc<PrismaClient>,
 		current_device_pub_id: &DevicePubId,
 		emit_messages_flag: Arc<AtomicBool>,
+		scope: SyncScope,
 	) -> Result<(Self, broadcast::Receiver<SyncEvent>), Error> {
 		let existing_devices = db.device().find_many(vec![]).exec().await?;
 
@@ -77,6 +86,7 @@ impl Manager {
 			db,
 			current_device_pub_id,
 			emit_messages_flag,
+			scope,
 			&existing_devices,
 		)
 		.await
@@ -93,6 +103,7 @@ impl Manager {
 		db: Arc<PrismaClient>,
 		current_device_pub_id: &DevicePubId,
 		emit_messages_flag: Arc<AtomicBool>,
+		scope: SyncScope,
 		existing_devices: &[device::Data],
 	) -> Result<(Self, broadcast::Receiver<SyncEvent>), Error> {
 		let latest_timestamp_per_device = db
@@ -140,9 +151,11 @@ impl Manager {
 				emit_messages_flag,
 				active: Arc::default(),
 				active_notify: Arc::default(),
+				scope: Arc::new(RwLock::new(scope)),
 				sync_lock: Arc::new(Mutex::default()),
 				available_parallelism: std::thread::available_parallelism()
 					.map_or(1, std::num::NonZero::get),
+				ingest_errors: Arc::default(),
 			},
 			rx,
 		))
@@ -239,6 +252,13 @@ impl Manager {
 				}
 			}
 
+			let scope = self.scope.read().await.clone();
+			if !scope.is_full() {
+				total_count +=
+					scope::set_aside_out_of_scope(self, &scope, model_id, &mut compressed_map)
+						.await?;
+			}
+
 			// Now that we separated all operations by their record_ids, we can do an optimization
 			// to process all records that only posses a single create operation, batching them together
 			let mut create_only_ops: BTreeMap<Uuid, Vec<(RecordId, CompressedCRDTOperation)>> =
@@ -282,6 +302,7 @@ impl Manager {
 						let clock = Arc::clone(&self.clock);
 						let timestamp_per_device = Arc::clone(&self.timestamp_per_device);
 						let db = Arc::clone(&self.db);
+						let current_device_pub_id = self.device_pub_id.clone();
 						let device_pub_id = device_pub_id.into();
 						let sync_lock = Arc::clone(&self.sync_lock);
 
@@ -293,6 +314,7 @@ impl Manager {
 								&timestamp_per_device,
 								sync_lock,
 								&db,
+								&current_device_pub_id,
 								device_pub_id,
 								model_id,
 								(record_id, ops),
@@ -365,6 +387,36 @@ impl Manager {
 	}
 
 	pub async fn ingest_ops(&self) -> Result<usize, Error> {
+		let res = self.ingest_all_models().await;
+
+		if let Err(e) = &res {
+			let mut ingest_errors = self.ingest_errors.lock().await;
+			if ingest_errors.len() == MAX_INGEST_ERRORS {
+				ingest_errors.pop_front();
+			}
+			ingest_errors.push_back(IngestErrorEntry {
+				at: Utc::now(),
+				message: e.to_string(),
+			});
+		}
+
+		res
+	}
+
+	/// Changes which records this device ingests, applying the operations of records that entered
+	/// the scope. Returns how many operations were applied.
+	pub async fn set_scope(&self, scope: SyncScope) -> Result<usize, Error> {
+		let previous = mem::replace(&mut *self.scope.write().await, scope);
+
+		scope::backfill(self, &previous).await
+	}
+
+	/// The latest errors from ingesting operations, oldest first
+	pub async fn last_ingest_errors(&self) -> Vec<IngestErrorEntry> {
+		self.ingest_errors.lock().await.iter().cloned().collect()
+	}
+
+	async fn ingest_all_models(&self) -> Result<usize, Error> {
 		let mut total_count = 0;
 
 		// WARN: this order here exists because sync messages MUST be processed in this exact order
@@ -377,6 +429,7 @@ impl Manager {
 			self.ingest_by_model(prisma_sync::location::MODEL_ID),
 			self.ingest_by_model(prisma_sync::object::MODEL_ID),
 			self.ingest_by_model(prisma_sync::label::MODEL_ID),
+			self.ingest_by_model(prisma_sync::saved_search::MODEL_ID),
 		]
 		.try_join()
 		.await?
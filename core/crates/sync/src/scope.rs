@@ -0,0 +1,602 @@
+//! Sync scopes, so a device can ingest only part of a library's data.
+//!
+//! A scope limits file paths to some locations and can exclude whole models. Device, volume,
+//! location and tag records are always synced. Operations of out-of-scope records are still
+//! stored in the operations log, but they aren't applied to the database. So this device keeps
+//! relaying them to other devices. When the scope grows, they're applied from the log without
+//! fetching them again.
+
+use sd_prisma::{
+	prisma::{cloud_crdt_operation, crdt_operation, file_path, SortOrder},
+	prisma_sync,
+};
+use sd_sync::{
+	CRDTOperation, CRDTOperationData, CompressedCRDTOperation, ModelId, OperationKind, NTP64,
+};
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tracing::{debug, instrument};
+use uuid::Uuid;
+
+use super::{
+	crdt_op_db,
+	ingest_utils::{update_clock, update_timestamp_per_device},
+	inspect::SYNC_MODELS,
+	DevicePubId, Error, RecordId, SyncManager,
+};
+
+/// Records backfilled at once when a scope grows
+const RECORDS_PER_BATCH: i64 = 1000;
+
+/// Models that a scope can exclude, every other model is always synced
+pub const OPTIONAL_MODELS: [ModelId; 6] = [
+	prisma_sync::object::MODEL_ID,
+	prisma_sync::label::MODEL_ID,
+	prisma_sync::exif_data::MODEL_ID,
+	prisma_sync::file_path::MODEL_ID,
+	prisma_sync::tag_on_object::MODEL_ID,
+	prisma_sync::label_on_object::MODEL_ID,
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct SyncScope {
+	/// Pub ids of the locations whose file paths are synced, `None` syncs every location
+	#[serde(default)]
+	pub locations: Option<Vec<Uuid>>,
+	/// Models that aren't synced at all, only [`OPTIONAL_MODELS`] can be excluded
+	#[serde(default)]
+	pub excluded_models: Vec<ModelId>,
+}
+
+impl SyncScope {
+	#[must_use]
+	pub fn is_full(&self) -> bool {
+		self.locations.is_none() && self.excluded_models.is_empty()
+	}
+
+	#[must_use]
+	pub fn includes_model(&self, model_id: ModelId) -> bool {
+		!self.excluded_models.contains(&model_id)
+	}
+
+	#[must_use]
+	pub fn includes_location(&self, location_pub_id: &[u8]) -> bool {
+		self.locations.as_ref().map_or(true, |locations| {
+			Uuid::from_slice(location_pub_id).is_ok_and(|pub_id| locations.contains(&pub_id))
+		})
+	}
+
+	/// Whether a record is in scope, `location_pub_id` is only used for file paths
+	#[must_use]
+	pub fn includes_record(&self, model_id: ModelId, location_pub_id: Option<&[u8]>) -> bool {
+		self.includes_model(model_id)
+			&& (model_id != prisma_sync::file_path::MODEL_ID
+				|| location_pub_id.map_or(true, |pub_id| self.includes_location(pub_id)))
+	}
+}
+
+/// Pub id of the location of a file path, from the data of its create operation
+fn location_from_create(data: &CRDTOperationData) -> Option<Vec<u8>> {
+	let CRDTOperationData::Create(fields) = data else {
+		return None;
+	};
+
+	fields
+		.get(file_path::location::NAME)
+		.and_then(|value| {
+			rmpv::ext::from_value::<prisma_sync::location::SyncId>(value.clone()).ok()
+		})
+		.map(|sync_id| sync_id.pub_id)
+}
+
+/// Pub ids of the locations of file paths, from the create operations we already have
+async fn stored_locations(
+	sync: &SyncManager,
+	record_ids: Vec<Vec<u8>>,
+) -> Result<HashMap<Vec<u8>, Vec<u8>>, Error> {
+	if record_ids.is_empty() {
+		return Ok(HashMap::new());
+	}
+
+	sync.db
+		.crdt_operation()
+		.find_many(vec![
+			crdt_operation::model::equals(i32::from(prisma_sync::file_path::MODEL_ID)),
+			crdt_operation::kind::equals(OperationKind::Create.to_string()),
+			crdt_operation::record_id::in_vec(record_ids),
+		])
+		.select(crdt_operation::select!({ record_id data }))
+		.exec()
+		.await?
+		.into_iter()
+		.filter_map(|op| {
+			rmp_serde::from_slice(&op.data)
+				.map(|data| location_from_create(&data).map(|location| (op.record_id, location)))
+				.map_err(Error::from)
+				.transpose()
+		})
+		.collect()
+}
+
+/// Operations waiting to be ingested, per device and record
+type RecordsPerDevice = BTreeMap<Uuid, HashMap<Vec<u8>, (RecordId, Vec<CompressedCRDTOperation>)>>;
+
+/// Removes out-of-scope records from operations waiting to be ingested and stores their operations
+/// in the log without applying them. Returns how many operations were set aside.
+#[instrument(skip(sync, scope, records_per_device), err)]
+pub(crate) async fn set_aside_out_of_scope(
+	sync: &SyncManager,
+	scope: &SyncScope,
+	model_id: ModelId,
+	records_per_device: &mut RecordsPerDevice,
+) -> Result<usize, Error> {
+	let mut locations = HashMap::new();
+
+	if scope.includes_model(model_id)
+		&& model_id == prisma_sync::file_path::MODEL_ID
+		&& scope.locations.is_some()
+	{
+		// Records are keyed by their serialized ids, which is also how they're stored in the log
+		let mut missing_locations = vec![];
+
+		for (key, (_, ops)) in records_per_device.values().flatten() {
+			if let Some(location) = ops.iter().find_map(|op| location_from_create(&op.data)) {
+				locations.insert(key.clone(), location);
+			} else {
+				missing_locations.push(key.clone());
+			}
+		}
+
+		locations.extend(stored_locations(sync, missing_locations).await?);
+	}
+
+	let out_of_scope = out_of_scope_records(scope, model_id, records_per_device, &locations);
+
+	if out_of_scope.is_empty() {
+		return Ok(0);
+	}
+
+	let (ops, latest_timestamp_per_device) =
+		take_records(model_id, records_per_device, out_of_scope);
+
+	let db = &sync.db;
+
+	{
+		let _lock_guard = sync.sync_lock.lock().await;
+
+		db._batch(
+			ops.iter()
+				.map(|op| crdt_op_db(op).map(|op| op.to_query(db)))
+				.collect::<Result<Vec<_>, _>>()?,
+		)
+		.await?;
+	}
+
+	for (device_pub_id, latest_timestamp) in latest_timestamp_per_device {
+		let device_pub_id = DevicePubId::from(device_pub_id);
+		update_clock(&sync.clock, latest_timestamp, &device_pub_id);
+		update_timestamp_per_device(&sync.timestamp_per_device, device_pub_id, latest_timestamp)
+			.await;
+	}
+
+	debug!(count = ops.len(), "Set aside operations out of sync scope;");
+
+	Ok(ops.len())
+}
+
+/// Records waiting to be ingested that are out of scope, file paths are matched against the
+/// given locations
+fn out_of_scope_records(
+	scope: &SyncScope,
+	model_id: ModelId,
+	records_per_device: &RecordsPerDevice,
+	locations: &HashMap<Vec<u8>, Vec<u8>>,
+) -> Vec<(Uuid, Vec<u8>)> {
+	records_per_device
+		.iter()
+		.flat_map(|(device_pub_id, records)| records.keys().map(move |key| (*device_pub_id, key)))
+		// Records without a known location are ingested as usual
+		.filter(|(_, key)| !scope.includes_record(model_id, locations.get(*key).map(Vec::as_slice)))
+		.map(|(device_pub_id, key)| (device_pub_id, key.clone()))
+		.collect()
+}
+
+/// Removes the records from the ones waiting to be ingested, returning their operations and the
+/// latest timestamp of each device among them
+fn take_records(
+	model_id: ModelId,
+	records_per_device: &mut RecordsPerDevice,
+	records: Vec<(Uuid, Vec<u8>)>,
+) -> (Vec<CRDTOperation>, HashMap<Uuid, NTP64>) {
+	let mut ops = vec![];
+	let mut latest_timestamp_per_device = HashMap::new();
+
+	for (device_pub_id, key) in records {
+		let Some((record_id, record_ops)) = records_per_device
+			.get_mut(&device_pub_id)
+			.and_then(|records| records.remove(&key))
+		else {
+			continue;
+		};
+
+		for CompressedCRDTOperation { timestamp, data } in record_ops {
+			let latest = latest_timestamp_per_device
+				.entry(device_pub_id)
+				.or_insert(timestamp);
+			if timestamp > *latest {
+				*latest = timestamp;
+			}
+
+			ops.push(CRDTOperation {
+				device_pub_id,
+				timestamp,
+				model_id,
+				record_id: record_id.clone(),
+				data,
+			});
+		}
+	}
+
+	(ops, latest_timestamp_per_device)
+}
+
+/// Whether some records of the model may be in the current scope but weren't in the `previous` one
+fn model_entered_scope(scope: &SyncScope, previous: &SyncScope, model_id: ModelId) -> bool {
+	scope.includes_model(model_id)
+		&& (!previous.includes_model(model_id)
+			|| (model_id == prisma_sync::file_path::MODEL_ID && previous.locations.is_some()))
+}
+
+/// Whether a record is in the current scope but wasn't in the `previous` one
+fn record_entered_scope(
+	scope: &SyncScope,
+	previous: &SyncScope,
+	model_id: ModelId,
+	location_pub_id: Option<&[u8]>,
+) -> bool {
+	scope.includes_record(model_id, location_pub_id)
+		&& !previous.includes_record(model_id, location_pub_id)
+}
+
+/// Applies operations of the records that were out of the `previous` scope but are in the current
+/// one, by moving them from the log back to the ingestion queue. Returns how many operations were
+/// ingested.
+#[instrument(skip(sync), err)]
+pub async fn backfill(sync: &SyncManager, previous: &SyncScope) -> Result<usize, Error> {
+	let scope = sync.scope.read().await.clone();
+	let mut total_count = 0;
+
+	for (model_id, _) in SYNC_MODELS {
+		if !model_entered_scope(&scope, previous, model_id) {
+			continue;
+		}
+
+		total_count += backfill_model(sync, &scope, previous, model_id).await?;
+	}
+
+	debug!(total_count, "Backfilled records that entered sync scope;");
+
+	Ok(total_count)
+}
+
+async fn backfill_model(
+	sync: &SyncManager,
+	scope: &SyncScope,
+	previous: &SyncScope,
+	model_id: ModelId,
+) -> Result<usize, Error> {
+	let db = &sync.db;
+	let mut total_count = 0;
+	let mut cursor = 0;
+
+	loop {
+		let creates = db
+			.crdt_operation()
+			.find_many(vec![
+				crdt_operation::id::gt(cursor),
+				crdt_operation::model::equals(i32::from(model_id)),
+				crdt_operation::kind::equals(OperationKind::Create.to_string()),
+			])
+			.order_by(crdt_operation::id::order(SortOrder::Asc))
+			.take(RECORDS_PER_BATCH)
+			.select(crdt_operation::select!({ id record_id data }))
+			.exec()
+			.await?;
+
+		let Some(last) = creates.last() else {
+			return Ok(total_count);
+		};
+		cursor = last.id;
+
+		let record_ids = creates
+			.into_iter()
+			.map(|create| {
+				let location = if model_id == prisma_sync::file_path::MODEL_ID {
+					location_from_create(&rmp_serde::from_slice(&create.data)?)
+				} else {
+					None
+				};
+
+				Ok((create.record_id, location))
+			})
+			.filter(|res| {
+				res.as_ref().map_or(true, |(_, location)| {
+					record_entered_scope(scope, previous, model_id, location.as_deref())
+				})
+			})
+			.map(|res| res.map(|(record_id, _)| record_id))
+			.collect::<Result<HashSet<_>, Error>>()?;
+
+		if record_ids.is_empty() {
+			continue;
+		}
+
+		{
+			// Holding the sync lock so no operations of these records are ingested while we move them
+			let _lock_guard = sync.sync_lock.lock().await;
+
+			let ops = db
+				.crdt_operation()
+				.find_many(vec![
+					crdt_operation::model::equals(i32::from(model_id)),
+					crdt_operation::record_id::in_vec(record_ids.into_iter().collect()),
+					crdt_operation::device_pub_id::not(sync.device_pub_id.to_db()),
+				])
+				.exec()
+				.await?;
+
+			if ops.is_empty() {
+				continue;
+			}
+
+			let (ids, cloud_ops) = ops
+				.into_iter()
+				.map(
+					|crdt_operation::Data {
+					     id,
+					     timestamp,
+					     model,
+					     record_id,
+					     kind,
+					     data,
+					     device_pub_id,
+					 }| {
+						(
+							id,
+							cloud_crdt_operation::CreateUnchecked {
+								timestamp,
+								model,
+								record_id,
+								kind,
+								data,
+								device_pub_id,
+								_params: vec![],
+							},
+						)
+					},
+				)
+				.unzip::<_, _, Vec<_>, Vec<_>>();
+
+			db._batch((
+				db.crdt_operation()
+					.delete_many(vec![crdt_operation::id::in_vec(ids)]),
+				db.cloud_crdt_operation().create_many(cloud_ops),
+			))
+			.await?;
+		}
+
+		total_count += sync.ingest_ops().await?;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn record(
+		location_pub_id: Option<Uuid>,
+		timestamps: &[u64],
+	) -> (RecordId, Vec<CompressedCRDTOperation>) {
+		let create = CRDTOperationData::Create(
+			location_pub_id
+				.map(|pub_id| {
+					(
+						file_path::location::NAME.to_string(),
+						rmpv::ext::to_value(prisma_sync::location::SyncId {
+							pub_id: pub_id.as_bytes().to_vec(),
+						})
+						.unwrap(),
+					)
+				})
+				.into_iter()
+				.collect(),
+		);
+
+		(
+			rmpv::Value::Nil,
+			timestamps
+				.iter()
+				.enumerate()
+				.map(|(i, timestamp)| CompressedCRDTOperation {
+					timestamp: NTP64(*timestamp),
+					data: if i == 0 {
+						create.clone()
+					} else {
+						CRDTOperationData::Update(BTreeMap::new())
+					},
+				})
+				.collect(),
+		)
+	}
+
+	fn locations_of(records_per_device: &RecordsPerDevice) -> HashMap<Vec<u8>, Vec<u8>> {
+		records_per_device
+			.values()
+			.flatten()
+			.filter_map(|(key, (_, ops))| {
+				ops.iter()
+					.find_map(|op| location_from_create(&op.data))
+					.map(|location| (key.clone(), location))
+			})
+			.collect()
+	}
+
+	#[test]
+	fn out_of_scope_file_paths_are_set_aside() {
+		let device = Uuid::now_v7();
+		let synced_location = Uuid::now_v7();
+		let other_location = Uuid::now_v7();
+
+		let mut records_per_device = RecordsPerDevice::from([(
+			device,
+			HashMap::from([
+				(vec![1], record(Some(synced_location), &[10])),
+				(vec![2], record(Some(other_location), &[20, 30])),
+				// Location unknown, so it's ingested as usual
+				(vec![3], record(None, &[40])),
+			]),
+		)]);
+
+		let scope = SyncScope {
+			locations: Some(vec![synced_location]),
+			excluded_models: vec![],
+		};
+
+		let locations = locations_of(&records_per_device);
+		let out_of_scope = out_of_scope_records(
+			&scope,
+			prisma_sync::file_path::MODEL_ID,
+			&records_per_device,
+			&locations,
+		);
+		assert_eq!(out_of_scope, vec![(device, vec![2])]);
+
+		let (ops, latest_timestamp_per_device) = take_records(
+			prisma_sync::file_path::MODEL_ID,
+			&mut records_per_device,
+			out_of_scope,
+		);
+
+		assert_eq!(ops.len(), 2);
+		assert!(ops
+			.iter()
+			.all(|op| op.model_id == prisma_sync::file_path::MODEL_ID));
+		assert_eq!(
+			latest_timestamp_per_device,
+			HashMap::from([(device, NTP64(30))])
+		);
+
+		let mut remaining = records_per_device[&device]
+			.keys()
+			.cloned()
+			.collect::<Vec<_>>();
+		remaining.sort();
+		assert_eq!(remaining, vec![vec![1], vec![3]]);
+	}
+
+	#[test]
+	fn excluded_models_are_set_aside_entirely() {
+		let device = Uuid::now_v7();
+		let records_per_device = RecordsPerDevice::from([(
+			device,
+			HashMap::from([
+				(vec![1], record(None, &[10])),
+				(vec![2], record(None, &[20])),
+			]),
+		)]);
+
+		let scope = SyncScope {
+			locations: None,
+			excluded_models: vec![prisma_sync::label::MODEL_ID],
+		};
+
+		assert_eq!(
+			out_of_scope_records(
+				&scope,
+				prisma_sync::label::MODEL_ID,
+				&records_per_device,
+				&HashMap::new()
+			)
+			.len(),
+			2
+		);
+		assert!(out_of_scope_records(
+			&scope,
+			prisma_sync::object::MODEL_ID,
+			&records_per_device,
+			&HashMap::new()
+		)
+		.is_empty());
+		assert!(OPTIONAL_MODELS.contains(&prisma_sync::label::MODEL_ID));
+	}
+
+	#[test]
+	fn set_aside_records_are_replayed_when_scope_widens() {
+		let synced_location = Uuid::now_v7();
+		let other_location = Uuid::now_v7();
+		let synced_location_bytes = synced_location.as_bytes().to_vec();
+		let other_location_bytes = other_location.as_bytes().to_vec();
+
+		let previous = SyncScope {
+			locations: Some(vec![synced_location]),
+			excluded_models: vec![prisma_sync::label::MODEL_ID],
+		};
+
+		let widened = SyncScope {
+			locations: Some(vec![synced_location, other_location]),
+			excluded_models: vec![],
+		};
+
+		assert!(model_entered_scope(
+			&widened,
+			&previous,
+			prisma_sync::file_path::MODEL_ID
+		));
+		assert!(model_entered_scope(
+			&widened,
+			&previous,
+			prisma_sync::label::MODEL_ID
+		));
+		assert!(!model_entered_scope(
+			&widened,
+			&previous,
+			prisma_sync::object::MODEL_ID
+		));
+
+		// Only file paths of the newly included location are replayed
+		assert!(record_entered_scope(
+			&widened,
+			&previous,
+			prisma_sync::file_path::MODEL_ID,
+			Some(&other_location_bytes)
+		));
+		assert!(!record_entered_scope(
+			&widened,
+			&previous,
+			prisma_sync::file_path::MODEL_ID,
+			Some(&synced_location_bytes)
+		));
+		assert!(record_entered_scope(
+			&widened,
+			&previous,
+			prisma_sync::label::MODEL_ID,
+			None
+		));
+
+		// Narrowing the scope never replays anything
+		assert!(!model_entered_scope(
+			&previous,
+			&widened,
+			prisma_sync::label::MODEL_ID
+		));
+		assert!(!record_entered_scope(
+			&previous,
+			&widened,
+			prisma_sync::file_path::MODEL_ID,
+			Some(&other_location_bytes)
+		));
+	}
+}
@@ -0,0 +1,22 @@
+-- CreateTable
+CREATE TABLE "job_schedule" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pub_id" BLOB NOT NULL,
+    "name" TEXT,
+    "kind" TEXT NOT NULL,
+    "cron" TEXT,
+    "interval_secs" INTEGER,
+    "only_on_ac_power" BOOLEAN NOT NULL DEFAULT false,
+    "only_when_idle" BOOLEAN NOT NULL DEFAULT false,
+    "catch_up_missed" BOOLEAN NOT NULL DEFAULT true,
+    "enabled" BOOLEAN NOT NULL DEFAULT true,
+    "location_id" INTEGER,
+    "next_run" DATETIME,
+    "last_run" DATETIME,
+    "last_job_id" BLOB,
+    "date_created" DATETIME,
+    CONSTRAINT "job_schedule_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "location" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "job_schedule_pub_id_key" ON "job_schedule"("pub_id");
@@ -0,0 +1,45 @@
+-- CreateTable
+CREATE TABLE "workflow" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pub_id" BLOB NOT NULL,
+    "name" TEXT,
+    "steps" BLOB,
+    "enabled" BOOLEAN NOT NULL DEFAULT true,
+    "watch_location_id" INTEGER,
+    "date_created" DATETIME,
+    "date_modified" DATETIME,
+    CONSTRAINT "workflow_watch_location_id_fkey" FOREIGN KEY ("watch_location_id") REFERENCES "location" ("id") ON DELETE SET NULL ON UPDATE CASCADE
+);
+
+-- RedefineTables
+PRAGMA defer_foreign_keys=ON;
+PRAGMA foreign_keys=OFF;
+CREATE TABLE "new_job_schedule" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pub_id" BLOB NOT NULL,
+    "name" TEXT,
+    "kind" TEXT NOT NULL,
+    "cron" TEXT,
+    "interval_secs" INTEGER,
+    "only_on_ac_power" BOOLEAN NOT NULL DEFAULT false,
+    "only_when_idle" BOOLEAN NOT NULL DEFAULT false,
+    "catch_up_missed" BOOLEAN NOT NULL DEFAULT true,
+    "enabled" BOOLEAN NOT NULL DEFAULT true,
+    "location_id" INTEGER,
+    "workflow_id" INTEGER,
+    "next_run" DATETIME,
+    "last_run" DATETIME,
+    "last_job_id" BLOB,
+    "date_created" DATETIME,
+    CONSTRAINT "job_schedule_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "location" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "job_schedule_workflow_id_fkey" FOREIGN KEY ("workflow_id") REFERENCES "workflow" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+INSERT INTO "new_job_schedule" ("catch_up_missed", "cron", "date_created", "enabled", "id", "interval_secs", "kind", "last_job_id", "last_run", "location_id", "name", "next_run", "only_on_ac_power", "only_when_idle", "pub_id") SELECT "catch_up_missed", "cron", "date_created", "enabled", "id", "interval_secs", "kind", "last_job_id", "last_run", "location_id", "name", "next_run", "only_on_ac_power", "only_when_idle", "pub_id" FROM "job_schedule";
+DROP TABLE "job_schedule";
+ALTER TABLE "new_job_schedule" RENAME TO "job_schedule";
+CREATE UNIQUE INDEX "job_schedule_pub_id_key" ON "job_schedule"("pub_id");
+PRAGMA foreign_keys=ON;
+PRAGMA defer_foreign_keys=OFF;
+
+-- CreateIndex
+CREATE UNIQUE INDEX "workflow_pub_id_key" ON "workflow"("pub_id");
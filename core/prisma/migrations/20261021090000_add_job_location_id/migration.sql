@@ -0,0 +1,5 @@
+-- AlterTable
+ALTER TABLE "job" ADD COLUMN "location_id" INTEGER;
+
+-- CreateIndex
+CREATE INDEX "job_location_id_idx" ON "job"("location_id");
@@ -0,0 +1,17 @@
+-- CreateTable
+CREATE TABLE "sync_conflict" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "model" INTEGER NOT NULL,
+    "record_id" BLOB NOT NULL,
+    "field" TEXT NOT NULL,
+    "lost_value" BLOB NOT NULL,
+    "won_value" BLOB NOT NULL,
+    "lost_timestamp" BIGINT NOT NULL,
+    "lost_device_pub_id" BLOB NOT NULL,
+    "won_timestamp" BIGINT NOT NULL,
+    "won_device_pub_id" BLOB NOT NULL,
+    "date_created" DATETIME NOT NULL
+);
+
+-- CreateIndex
+CREATE INDEX "sync_conflict_model_record_id_idx" ON "sync_conflict"("model", "record_id");
@@ -0,0 +1,30 @@
+-- CreateTable
+CREATE TABLE "archive" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pub_id" BLOB NOT NULL,
+    "name" TEXT,
+    "path" TEXT NOT NULL,
+    "materialized_path" TEXT NOT NULL,
+    "key" BLOB,
+    "location_id" INTEGER,
+    "date_created" DATETIME,
+    CONSTRAINT "archive_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "location" ("id") ON DELETE SET NULL ON UPDATE CASCADE
+);
+
+-- CreateTable
+CREATE TABLE "archived_file_path" (
+    "archive_id" INTEGER NOT NULL,
+    "file_path_id" INTEGER NOT NULL,
+    "part" INTEGER NOT NULL,
+    "entry" TEXT NOT NULL,
+
+    PRIMARY KEY ("archive_id", "file_path_id"),
+    CONSTRAINT "archived_file_path_archive_id_fkey" FOREIGN KEY ("archive_id") REFERENCES "archive" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "archived_file_path_file_path_id_fkey" FOREIGN KEY ("file_path_id") REFERENCES "file_path" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "archive_pub_id_key" ON "archive"("pub_id");
+
+-- CreateIndex
+CREATE INDEX "archived_file_path_file_path_id_idx" ON "archived_file_path"("file_path_id");
@@ -0,0 +1,10 @@
+-- CreateTable
+CREATE TABLE "directory_size_snapshot" (
+    "location_id" INTEGER NOT NULL,
+    "path" TEXT NOT NULL,
+    "size_in_bytes" BLOB NOT NULL,
+    "date_created" DATETIME NOT NULL,
+
+    PRIMARY KEY ("location_id", "path"),
+    CONSTRAINT "directory_size_snapshot_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "location" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
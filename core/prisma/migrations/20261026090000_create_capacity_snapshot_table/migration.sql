@@ -0,0 +1,17 @@
+-- CreateTable
+CREATE TABLE "capacity_snapshot" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "volume_id" INTEGER,
+    "location_id" INTEGER,
+    "used_bytes" BLOB NOT NULL,
+    "capacity_bytes" BLOB,
+    "date_created" DATETIME NOT NULL,
+    CONSTRAINT "capacity_snapshot_volume_id_fkey" FOREIGN KEY ("volume_id") REFERENCES "volume" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "capacity_snapshot_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "location" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE INDEX "capacity_snapshot_volume_id_date_created_idx" ON "capacity_snapshot"("volume_id", "date_created");
+
+-- CreateIndex
+CREATE INDEX "capacity_snapshot_location_id_date_created_idx" ON "capacity_snapshot"("location_id", "date_created");
@@ -0,0 +1,2 @@
+-- CreateIndex
+CREATE INDEX "crdt_operation_model_record_id_idx" ON "crdt_operation"("model", "record_id");
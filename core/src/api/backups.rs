@@ -143,7 +143,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 		})
 }
 
-async fn start_backup(node: Arc<Node>, library: Arc<Library>) -> Uuid {
+pub(crate) async fn start_backup(node: Arc<Node>, library: Arc<Library>) -> Uuid {
 	let bkp_id = Uuid::new_v4();
 
 	spawn(async move {
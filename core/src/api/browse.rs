@@ -0,0 +1,148 @@
+use crate::old_p2p::{browse, PeerMetadata};
+
+use sd_old_p2p::RemoteIdentity;
+use sd_prisma::prisma::instance;
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, FixedOffset};
+use rspc::alpha::AlphaRouter;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use uuid::Uuid;
+
+use super::{search::FilePathSearchArgs, utils::library, Ctx, R};
+
+pub(crate) fn mount() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("nodes", {
+			#[derive(Serialize, Type)]
+			pub struct RemoteNode {
+				identity: RemoteIdentity,
+				name: Option<String>,
+				/// Whether the node can be browsed right now
+				online: bool,
+				last_seen: DateTime<FixedOffset>,
+			}
+
+			R.with2(library())
+				.query(|(node, library), _: ()| async move {
+					let instances = library
+						.db
+						.instance()
+						.find_many(vec![instance::id::not(library.config().await.instance_id)])
+						.select(instance::select!({ node_remote_identity metadata last_seen }))
+						.exec()
+						.await?;
+
+					let mut nodes = HashMap::new();
+
+					for instance in instances {
+						let Some(identity) = instance
+							.node_remote_identity
+							.as_deref()
+							.and_then(|bytes| RemoteIdentity::from_bytes(bytes).ok())
+						else {
+							continue;
+						};
+
+						let peer = node.p2p.get_instance(&library.id, identity);
+
+						// The metadata of the instance is only updated by its node, so the peer's is newer
+						let name = peer
+							.as_ref()
+							.and_then(|peer| PeerMetadata::from_hashmap(&peer.metadata()).ok())
+							.or_else(|| {
+								instance
+									.metadata
+									.as_deref()
+									.and_then(|metadata| {
+										serde_json::from_slice::<HashMap<String, String>>(metadata)
+											.ok()
+									})
+									.and_then(|metadata| PeerMetadata::from_hashmap(&metadata).ok())
+							})
+							.map(|metadata| metadata.name);
+
+						nodes.insert(
+							identity,
+							RemoteNode {
+								identity,
+								name,
+								online: peer.is_some_and(|peer| peer.can_connect()),
+								last_seen: instance.last_seen,
+							},
+						);
+					}
+
+					Ok(nodes.into_values().collect::<Vec<_>>())
+				})
+		})
+		.procedure("locations", {
+			R.with2(library())
+				.query(|(node, library), identity: RemoteIdentity| async move {
+					browse::locations(&node.p2p, &library, identity)
+						.await
+						.map_err(Into::into)
+				})
+		})
+		.procedure("paths", {
+			#[derive(Deserialize, Type)]
+			pub struct BrowsePathsArgs {
+				identity: RemoteIdentity,
+				location_pub_id: Uuid,
+				/// Materialized path of the directory, `/` for the root of the location
+				path: String,
+				#[serde(default)]
+				skip: u32,
+				take: u32,
+			}
+
+			R.with2(library()).query(
+				|(node, library),
+				 BrowsePathsArgs {
+				     identity,
+				     location_pub_id,
+				     path,
+				     skip,
+				     take,
+				 }: BrowsePathsArgs| async move {
+					browse::paths(
+						&node.p2p,
+						&library,
+						identity,
+						location_pub_id,
+						path,
+						skip,
+						take,
+					)
+					.await
+					.map_err(Into::into)
+				},
+			)
+		})
+		.procedure("searchPaths", {
+			#[derive(Deserialize, Type)]
+			pub struct BrowseSearchPathsArgs {
+				identity: RemoteIdentity,
+				args: FilePathSearchArgs,
+			}
+
+			R.with2(library()).query(
+				|(node, library),
+				 BrowseSearchPathsArgs { identity, args }: BrowseSearchPathsArgs| async move {
+					browse::search_paths(&node.p2p, &library, identity, args)
+						.await
+						.map_err(Into::into)
+				},
+			)
+		})
+		.procedure("clearCache", {
+			R.with2(library())
+				.mutation(|(node, library), _: ()| async move {
+					browse::clear_thumbnails_cache(node.config.data_directory(), library.id)
+						.await
+						.map_err(Into::into)
+				})
+		})
+}
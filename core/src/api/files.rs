@@ -1,8 +1,9 @@
 use crate::{
 	api::utils::library,
+	context::NodeContext,
 	invalidate_query,
 	library::Library,
-	location::{get_location_path_from_location_id, LocationError},
+	location::{find_location, get_location_path_from_location_id, LocationError},
 	object::{
 		fs::{
 			error::FileSystemJobsError, find_available_filename_for_duplicate,
@@ -14,8 +15,15 @@ use crate::{
 	old_job::OldJob,
 };
 
-use sd_core_file_path_helper::{FilePathError, IsolatedFilePathData};
-use sd_core_heavy_lifting::media_processor::{exif_media_data, ffmpeg_media_data};
+use sd_core_file_path_helper::{
+	ensure_sub_path_is_in_location, FilePathError, IsolatedFilePathData,
+};
+use sd_core_heavy_lifting::{
+	image_converter::{
+		job::ConversionSources, ConversionOptions, ImageConverter, OutputDestination,
+	},
+	media_processor::{exif_media_data, ffmpeg_media_data},
+};
 use sd_core_prisma_helpers::{
 	file_path_to_isolate, file_path_to_isolate_with_id, object_with_file_paths,
 	object_with_media_data,
@@ -732,6 +740,66 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					Ok(())
 				})
 		})
+		.procedure("convertImages", {
+			#[derive(Type, Deserialize)]
+			struct ConvertImagesTarget {
+				location_id: location::id::Type,
+				sub_path: PathBuf,
+			}
+
+			#[derive(Type, Deserialize)]
+			struct ConvertImagesArgs {
+				location_id: location::id::Type,
+				sources: ConversionSources,
+				/// If not set, each converted image is saved next to its source
+				target: Option<ConvertImagesTarget>,
+				options: ConversionOptions,
+			}
+
+			R.with2(library()).mutation(
+				|(node, library),
+				 ConvertImagesArgs {
+				     location_id,
+				     sources,
+				     target,
+				     options,
+				 }: ConvertImagesArgs| async move {
+					let Some(location) = find_location(&library, location_id).exec().await? else {
+						return Err(LocationError::IdNotFound(location_id).into());
+					};
+
+					let destination = if let Some(ConvertImagesTarget {
+						location_id: target_location_id,
+						sub_path,
+					}) = target
+					{
+						let target_location_path =
+							get_location_path_from_location_id(&library.db, target_location_id)
+								.await?;
+
+						OutputDestination::Directory(
+							ensure_sub_path_is_in_location(&target_location_path, &sub_path)
+								.await
+								.map_err(LocationError::from)?,
+						)
+					} else {
+						OutputDestination::NextToSource
+					};
+
+					node.job_system
+						.dispatch(
+							ImageConverter::new(location, sources, destination, options)?,
+							location_id,
+							NodeContext {
+								node: Arc::clone(&node),
+								library,
+							},
+						)
+						.await
+						.map_err(Into::into)
+				},
+			)
+		})
 		.procedure("getConvertibleImageExtensions", {
 			R.query(|_, _: ()| async move { Ok(sd_images::all_compatible_extensions()) })
 		})
@@ -1,9 +1,12 @@
 use crate::{
 	context::NodeContext,
 	invalidate_query,
+	job_history::{self, ExportFormat, JobHistoryFilter},
 	location::{find_location, LocationError},
+	node::config::JobHistoryPreferences,
 	object::validation::old_validator_job::OldObjectValidatorJobInit,
-	old_job::{JobStatus, OldJob, OldJobReport},
+	old_job::{OldJob, OldJobReport},
+	scheduler::{self, JobScheduleCreateArgs, JobScheduleUpdateArgs},
 };
 
 use sd_core_heavy_lifting::{
@@ -11,7 +14,7 @@ use sd_core_heavy_lifting::{
 	JobId, JobSystemError, Report,
 };
 
-use sd_prisma::prisma::{job, location, SortOrder};
+use sd_prisma::prisma::{job, job_schedule, location, SortOrder};
 
 use std::{
 	collections::{hash_map::Entry, BTreeMap, HashMap, VecDeque},
@@ -21,12 +24,11 @@ use std::{
 };
 
 use chrono::{DateTime, Utc};
-use prisma_client_rust::or;
-use rspc::alpha::AlphaRouter;
+use rspc::{alpha::AlphaRouter, ErrorCode};
 use serde::{Deserialize, Serialize};
 use specta::Type;
 use tokio::time::Duration;
-use tracing::{info, trace};
+use tracing::{error, info, trace};
 use uuid::Uuid;
 
 use super::{utils::library, CoreEvent, Ctx, R};
@@ -220,6 +222,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 						.await?;
 
 					invalidate_query!(library, "jobs.reports");
+					invalidate_query!(library, "jobs.history");
 					Ok(())
 				})
 		})
@@ -230,19 +233,101 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					library
 						.db
 						.job()
-						.delete_many(vec![or![
-							job::status::equals(Some(JobStatus::Canceled as i32)),
-							job::status::equals(Some(JobStatus::Failed as i32)),
-							job::status::equals(Some(JobStatus::Completed as i32)),
-							job::status::equals(Some(JobStatus::CompletedWithErrors as i32)),
-						]])
+						.delete_many(vec![job_history::finished_statuses()])
 						.exec()
 						.await?;
 
 					invalidate_query!(library, "jobs.reports");
+					invalidate_query!(library, "jobs.history");
 					Ok(())
 				})
 		})
+		.procedure("history", {
+			#[derive(Type, Deserialize)]
+			pub struct JobHistoryArgs {
+				#[serde(default)]
+				pub filter: JobHistoryFilter,
+				#[serde(default)]
+				pub skip: u32,
+				pub take: Option<u32>,
+			}
+
+			R.with2(library()).query(
+				|(_, library), JobHistoryArgs { filter, skip, take }: JobHistoryArgs| async move {
+					job_history::history(&library, filter, skip, take)
+						.await
+						.map_err(Into::into)
+				},
+			)
+		})
+		.procedure("errors", {
+			#[derive(Type, Deserialize)]
+			pub struct JobErrorsArgs {
+				pub job_id: JobId,
+				#[serde(default)]
+				pub skip: u32,
+				pub take: Option<u32>,
+			}
+
+			R.with2(library()).query(
+				|(_, library), JobErrorsArgs { job_id, skip, take }: JobErrorsArgs| async move {
+					job_history::errors(&library, job_id, skip, take)
+						.await
+						.map_err(Into::into)
+				},
+			)
+		})
+		.procedure("exportErrors", {
+			#[derive(Type, Deserialize)]
+			pub struct ExportJobErrorsArgs {
+				pub job_id: JobId,
+				pub format: ExportFormat,
+			}
+
+			R.with2(library()).query(
+				|(_, library), ExportJobErrorsArgs { job_id, format }: ExportJobErrorsArgs| async move {
+					job_history::export_errors(&library, job_id, format)
+						.await
+						.map_err(Into::into)
+				},
+			)
+		})
+		.procedure("historyRetention", {
+			R.query(
+				|node, _: ()| async move { Ok(node.config.get().await.preferences.job_history) },
+			)
+		})
+		.procedure("updateHistoryRetention", {
+			R.mutation(|node, preferences: JobHistoryPreferences| async move {
+				if preferences.max_age_days == Some(0) || preferences.max_reports == Some(0) {
+					return Err(rspc::Error::new(
+						ErrorCode::BadRequest,
+						"retention limits must be at least 1".into(),
+					));
+				}
+
+				node.config
+					.update_preferences(|current| current.job_history = preferences.clone())
+					.await
+					.map_err(|e| {
+						error!(?e, "Failed to update job history preferences;");
+						rspc::Error::with_cause(
+							ErrorCode::InternalServerError,
+							"Failed to update job history preferences".to_string(),
+							e,
+						)
+					})?;
+
+				// Applying the new rules right away instead of waiting for the next prune
+				for library in node.libraries.get_all().await {
+					job_history::prune(&library, &preferences).await?;
+				}
+
+				invalidate_query!(node; node, "jobs.historyRetention");
+
+				Ok(())
+			})
+		})
 		// pause job
 		.procedure("pause", {
 			R.with2(library())
@@ -411,6 +496,53 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				},
 			)
 		})
+		.procedure("schedules", {
+			R.with2(library()).query(|(_, library), _: ()| async move {
+				scheduler::list(&library).await.map_err(Into::into)
+			})
+		})
+		.procedure("createSchedule", {
+			R.with2(library())
+				.mutation(|(_, library), args: JobScheduleCreateArgs| async move {
+					let schedule = args.create(&library).await?;
+
+					invalidate_query!(library, "jobs.schedules");
+
+					Ok(schedule)
+				})
+		})
+		.procedure("updateSchedule", {
+			R.with2(library())
+				.mutation(|(_, library), args: JobScheduleUpdateArgs| async move {
+					let schedule = args.update(&library).await?;
+
+					invalidate_query!(library, "jobs.schedules");
+
+					Ok(schedule)
+				})
+		})
+		.procedure("deleteSchedule", {
+			R.with2(library())
+				.mutation(|(_, library), id: job_schedule::id::Type| async move {
+					scheduler::delete(&library, id).await?;
+
+					invalidate_query!(library, "jobs.schedules");
+
+					Ok(())
+				})
+		})
+		.procedure("runScheduleNow", {
+			R.with2(library())
+				.mutation(|(node, library), id: job_schedule::id::Type| async move {
+					let schedule = scheduler::find(&library, id).await?;
+					let maybe_job_id = scheduler::run_now(&node, &library, &schedule).await?;
+
+					invalidate_query!(library, "jobs.schedules");
+					invalidate_query!(library, "jobs.reports");
+
+					Ok(maybe_job_id)
+				})
+		})
 		.procedure("newThumbnail", {
 			R.with2(library())
 				.subscription(|(node, _), _: ()| async move {
@@ -1,10 +1,17 @@
 use crate::{
+	capacity::{self, CapacityTarget},
 	invalidate_query,
 	location::{
-		delete_location, find_location, light_scan_location, non_indexed::NonIndexedPathItem,
-		relink_location, scan_location, scan_location_sub_path, LocationCreateArgs, LocationError,
-		LocationUpdateArgs, ScanState,
+		archive::{
+			delete_archive, ArchiveError, OldArchiveRestorerJobInit, OldLocationArchiverJobInit,
+		},
+		delete_location, find_location, light_scan_location, locations_availability,
+		non_indexed::NonIndexedPathItem,
+		overlapping_locations, relink_location, scan_location, scan_location_sub_path, size_tree,
+		Availability, LocationCreateArgs, LocationError, LocationPromoteArgs, LocationUpdateArgs,
+		Promoted, ScanState, WatcherMode,
 	},
+	old_job::OldJob,
 	old_p2p::PeerMetadata,
 	util::AbortOnDrop,
 };
@@ -15,9 +22,16 @@ use sd_core_prisma_helpers::{
 	file_path_for_frontend, label_with_objects, location_with_indexer_rules, object_with_file_paths,
 };
 
-use sd_prisma::prisma::{file_path, indexer_rule, indexer_rules_in_location, location, SortOrder};
+use sd_prisma::prisma::{
+	archive, archived_file_path, file_path, indexer_rule, indexer_rules_in_location, location,
+	SortOrder,
+};
+use sd_utils::{db::maybe_missing, from_bytes_to_uuid};
 
-use std::path::{Path, PathBuf};
+use std::{
+	path::{Path, PathBuf},
+	sync::Arc,
+};
 
 use chrono::{DateTime, FixedOffset, Utc};
 use directories::UserDirs;
@@ -36,6 +50,8 @@ pub enum ExplorerItem {
 		thumbnail: Option<ThumbKey>,
 		// this tells the frontend if a thumbnail actually exists or not
 		has_created_thumbnail: bool,
+		// whether the file can be opened right now, or which volume must be plugged in for it
+		availability: Availability,
 		// we can't actually modify data from PCR types, thats why computed properties are used on ExplorerItem
 		item: Box<file_path_for_frontend::Data>,
 	},
@@ -183,15 +199,38 @@ impl ExplorerItem {
 pub(crate) fn mount() -> AlphaRouter<Ctx> {
 	R.router()
 		.procedure("list", {
-			R.with2(library()).query(|(_, library), _: ()| async move {
-				Ok(library
-					.db
-					.location()
-					.find_many(vec![])
-					.order_by(location::date_created::order(SortOrder::Desc))
-					.exec()
-					.await?)
-			})
+			#[derive(Type, Serialize)]
+			pub struct LocationWithAvailability {
+				#[serde(flatten)]
+				pub location: location::Data,
+				pub availability: Availability,
+			}
+
+			R.with2(library())
+				.query(|(node, library), _: ()| async move {
+					let locations = library
+						.db
+						.location()
+						.find_many(vec![])
+						.order_by(location::date_created::order(SortOrder::Desc))
+						.exec()
+						.await?;
+
+					let mut availability = locations_availability(
+						&node,
+						&library,
+						locations.iter().map(|location| location.id).collect(),
+					)
+					.await?;
+
+					Ok(locations
+						.into_iter()
+						.map(|location| LocationWithAvailability {
+							availability: availability.remove(&location.id).unwrap_or_default(),
+							location,
+						})
+						.collect::<Vec<_>>())
+				})
 		})
 		.procedure("get", {
 			R.with2(library())
@@ -298,6 +337,231 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 						.map_err(Into::into)
 				})
 		})
+		.procedure("watcherMode", {
+			R.with2(library())
+				.query(|(_, library), location_id: location::id::Type| async move {
+					let location = find_location(&library, location_id)
+						.select(location::select!({ pub_id }))
+						.exec()
+						.await?
+						.ok_or(LocationError::IdNotFound(location_id))?;
+
+					Ok(library
+						.config()
+						.await
+						.location_watchers
+						.get(&from_bytes_to_uuid(&location.pub_id))
+						.copied()
+						.unwrap_or_default())
+				})
+		})
+		.procedure("setWatcherMode", {
+			#[derive(Type, Deserialize)]
+			pub struct SetWatcherModeArgs {
+				pub location_id: location::id::Type,
+				pub mode: WatcherMode,
+			}
+
+			R.with2(library()).mutation(
+				|(node, library), SetWatcherModeArgs { location_id, mode }: SetWatcherModeArgs| async move {
+					let location = find_location(&library, location_id)
+						.select(location::select!({ pub_id }))
+						.exec()
+						.await?
+						.ok_or(LocationError::IdNotFound(location_id))?;
+
+					let pub_id = from_bytes_to_uuid(&location.pub_id);
+
+					library
+						.update_config(|config| {
+							if mode == WatcherMode::default() {
+								config.location_watchers.remove(&pub_id);
+							} else {
+								config.location_watchers.insert(pub_id, mode);
+							}
+						})
+						.await?;
+
+					// Adding the location again replaces its watcher with one using the new mode
+					node.locations
+						.add(location_id, Arc::clone(&library))
+						.await
+						.map_err(LocationError::from)?;
+
+					invalidate_query!(library, "locations.watcherMode");
+
+					Ok(())
+				},
+			)
+		})
+		.procedure("capacityTrend", {
+			#[derive(Type, Deserialize)]
+			pub struct CapacityTrendArgs {
+				pub location_id: location::id::Type,
+				/// Weeks of snapshots to look back at, 12 when missing
+				pub weeks: Option<u32>,
+			}
+
+			R.with2(library()).query(
+				|(_, library), CapacityTrendArgs { location_id, weeks }: CapacityTrendArgs| async move {
+					capacity::trend(
+						&library.db,
+						CapacityTarget::Location(location_id),
+						Utc::now() - chrono::Duration::weeks(i64::from(weeks.unwrap_or(12))),
+					)
+					.await
+					.map_err(Into::into)
+				},
+			)
+		})
+		.procedure("sizeTree", {
+			#[derive(Type, Deserialize)]
+			pub struct SizeTreeArgs {
+				pub location_id: location::id::Type,
+				/// Directory to break down, relative to the location root, the whole location when missing
+				pub sub_path: Option<PathBuf>,
+				/// Levels of directories to descend into
+				pub depth: u8,
+				/// Largest children kept for each directory, and biggest changes returned
+				pub limit: u32,
+			}
+
+			R.with2(library()).query(
+				|(_, library),
+				 SizeTreeArgs {
+				     location_id,
+				     sub_path,
+				     depth,
+				     limit,
+				 }: SizeTreeArgs| async move {
+					size_tree(
+						&library.db,
+						location_id,
+						sub_path.as_deref(),
+						depth,
+						limit as usize,
+					)
+					.await
+					.map_err(Into::into)
+				},
+			)
+		})
+		.procedure("archives", {
+			#[derive(Type, Serialize)]
+			pub struct LocationArchive {
+				pub id: archive::id::Type,
+				pub name: Option<String>,
+				pub path: String,
+				pub materialized_path: String,
+				pub encrypted: bool,
+				pub location_id: Option<location::id::Type>,
+				pub date_created: Option<DateTime<FixedOffset>>,
+				/// File paths which still only exist in this archive
+				pub archived_file_paths: i64,
+			}
+
+			R.with2(library()).query(
+				|(_, library), location_id: Option<location::id::Type>| async move {
+					let db = &library.db;
+
+					let archives = db
+						.archive()
+						.find_many(
+							location_id
+								.map(|location_id| {
+									vec![archive::location_id::equals(Some(location_id))]
+								})
+								.unwrap_or_default(),
+						)
+						.order_by(archive::date_created::order(SortOrder::Desc))
+						.exec()
+						.await?;
+
+					let counts = db
+						._batch(
+							archives
+								.iter()
+								.map(|archive| {
+									db.archived_file_path().count(vec![
+										archived_file_path::archive_id::equals(archive.id),
+									])
+								})
+								.collect::<Vec<_>>(),
+						)
+						.await?;
+
+					Ok(archives
+						.into_iter()
+						.zip(counts)
+						.map(|(archive, archived_file_paths)| LocationArchive {
+							id: archive.id,
+							name: archive.name,
+							path: archive.path,
+							materialized_path: archive.materialized_path,
+							encrypted: archive.key.is_some(),
+							location_id: archive.location_id,
+							date_created: archive.date_created,
+							archived_file_paths,
+						})
+						.collect::<Vec<_>>())
+				},
+			)
+		})
+		.procedure("archive", {
+			R.with2(library()).mutation(
+				|(node, library), args: OldLocationArchiverJobInit| async move {
+					OldJob::new(args)
+						.spawn(&node, &library)
+						.await
+						.map_err(Into::into)
+				},
+			)
+		})
+		.procedure("restoreArchive", {
+			#[derive(Type, Deserialize)]
+			pub struct RestoreArchiveArgs {
+				pub archive_id: archive::id::Type,
+				/// Restore only these file paths, the whole archive when missing
+				pub file_path_ids: Option<Vec<file_path::id::Type>>,
+			}
+
+			R.with2(library()).mutation(
+				|(node, library),
+				 RestoreArchiveArgs {
+				     archive_id,
+				     file_path_ids,
+				 }: RestoreArchiveArgs| async move {
+					let archive = library
+						.db
+						.archive()
+						.find_unique(archive::id::equals(archive_id))
+						.select(archive::select!({ location_id }))
+						.exec()
+						.await?
+						.ok_or(ArchiveError::NotFound(archive_id))?;
+
+					OldJob::new(OldArchiveRestorerJobInit {
+						archive_id,
+						location_id: maybe_missing(archive.location_id, "archive.location_id")
+							.map_err(ArchiveError::from)?,
+						file_path_ids,
+					})
+					.spawn(&node, &library)
+					.await
+					.map_err(Into::into)
+				},
+			)
+		})
+		.procedure("deleteArchive", {
+			R.with2(library())
+				.mutation(|(_, library), archive_id: archive::id::Type| async move {
+					delete_archive(&library.db, archive_id).await?;
+
+					invalidate_query!(library, "locations.archives");
+
+					Ok(())
+				})
+		})
 		.procedure("addLibrary", {
 			R.with2(library())
 				.mutation(|(node, library), args: LocationCreateArgs| async move {
@@ -312,6 +576,34 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					}
 				})
 		})
+		.procedure("overlapping", {
+			R.with2(library())
+				.query(|(_, library), path: PathBuf| async move {
+					overlapping_locations(path, &library)
+						.await
+						.map_err(Into::into)
+				})
+		})
+		.procedure("promote", {
+			R.with2(library())
+				.mutation(|(node, library), args: LocationPromoteArgs| async move {
+					let id = match args.promote(&node, &library).await? {
+						Promoted::Created(location) => {
+							let id = location.id;
+							scan_location(&node, &library, location, ScanState::Pending).await?;
+							id
+						}
+						Promoted::Merged { location, sub_path } => {
+							let id = location.id;
+							scan_location_sub_path(&node, &library, location, sub_path).await?;
+							id
+						}
+					};
+
+					invalidate_query!(library, "locations.list");
+					Ok(id)
+				})
+		})
 		.procedure("fullRescan", {
 			#[derive(Type, Deserialize)]
 			pub struct FullRescanArgs {
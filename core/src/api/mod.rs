@@ -24,7 +24,8 @@ use serde::{Deserialize, Serialize};
 use specta::Type;
 use tracing::warn;
 
-mod backups;
+pub(crate) mod backups;
+mod browse;
 mod cloud;
 mod devices;
 mod ephemeral_files;
@@ -45,6 +46,7 @@ mod tags;
 pub mod utils;
 pub mod volumes;
 mod web_api;
+mod workflows;
 
 use libraries::KindStatistic;
 use utils::{InvalidRequests, InvalidateOperationEvent};
@@ -207,6 +209,7 @@ pub(crate) fn mount() -> Arc<Router> {
 		.merge("files.", files::mount())
 		.merge("jobs.", jobs::mount())
 		.merge("p2p.", p2p::mount())
+		.merge("browse.", browse::mount())
 		.merge("models.", models::mount())
 		.merge("nodes.", nodes::mount())
 		.merge("sync.", sync::mount())
@@ -214,6 +217,7 @@ pub(crate) fn mount() -> Arc<Router> {
 		.merge("notifications.", notifications::mount())
 		.merge("backups.", backups::mount())
 		.merge("keys.", keys::mount())
+		.merge("workflows.", workflows::mount())
 		.merge("invalidation.", utils::mount_invalidate())
 		.sd_patch_types_dangerously(|type_map| {
 			let def =
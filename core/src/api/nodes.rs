@@ -2,14 +2,14 @@ use std::collections::HashSet;
 
 use crate::{
 	invalidate_query,
-	node::config::{P2PDiscoveryState, Port},
+	node::config::{P2PDiscoveryState, Port, TaskSystemPreferences},
 };
 
 use sd_prisma::prisma::{device, location};
 
 use rspc::{alpha::AlphaRouter, ErrorCode};
 use sd_utils::uuid_to_bytes;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use specta::Type;
 use tracing::error;
 use uuid::Uuid;
@@ -29,6 +29,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				pub p2p_discovery: Option<P2PDiscoveryState>,
 				pub p2p_remote_access: Option<bool>,
 				pub p2p_manual_peers: Option<HashSet<String>>,
+				pub p2p_allow_untrusted_spacedrop: Option<bool>,
 			}
 			R.mutation(|node, args: ChangeNodeNameArgs| async move {
 				if let Some(name) = &args.name {
@@ -67,6 +68,9 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 						if let Some(manual_peers) = args.p2p_manual_peers {
 							config.p2p.manual_peers = manual_peers;
 						};
+						if let Some(allow) = args.p2p_allow_untrusted_spacedrop {
+							config.p2p.allow_untrusted_spacedrop = allow;
+						};
 					})
 					.await
 					.map_err(|e| {
@@ -85,6 +89,70 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				Ok(())
 			})
 		})
+		.procedure("taskSystem", {
+			#[derive(Serialize, Type)]
+			pub struct TaskSystemState {
+				/// Workers currently allowed to run tasks, lower than the preferred count while throttled
+				pub workers_count: u32,
+				pub default_workers_count: u32,
+				pub max_workers_count: u32,
+				pub preferences: TaskSystemPreferences,
+			}
+
+			R.query(|node, _: ()| async move {
+				#[allow(clippy::cast_possible_truncation)]
+				Ok(TaskSystemState {
+					workers_count: node.task_system.workers_count() as u32,
+					default_workers_count: node.task_system.default_workers_count() as u32,
+					max_workers_count: node.task_system.max_workers_count() as u32,
+					preferences: node.config.get().await.preferences.task_system,
+				})
+			})
+		})
+		.procedure("updateTaskSystemPreferences", {
+			R.mutation(|node, preferences: TaskSystemPreferences| async move {
+				let max_workers_count = node.task_system.max_workers_count();
+
+				if preferences
+					.workers_count
+					.is_some_and(|count| count == 0 || count as usize > max_workers_count)
+				{
+					return Err(rspc::Error::new(
+						ErrorCode::BadRequest,
+						format!("workers count must be between 1 and {max_workers_count}"),
+					));
+				}
+
+				if preferences
+					.concurrency_limits
+					.values()
+					.any(|limit| *limit == 0)
+				{
+					return Err(rspc::Error::new(
+						ErrorCode::BadRequest,
+						"concurrency limits must be at least 1".into(),
+					));
+				}
+
+				// The task system throttle applies the new preferences as soon as they're saved
+				node.config
+					.update_preferences(|current| current.task_system = preferences)
+					.await
+					.map_err(|e| {
+						error!(?e, "Failed to update task system preferences;");
+						rspc::Error::with_cause(
+							ErrorCode::InternalServerError,
+							"Failed to update task system preferences".to_string(),
+							e,
+						)
+					})?;
+
+				invalidate_query!(node; node, "nodeState");
+				invalidate_query!(node; node, "nodes.taskSystem");
+
+				Ok(())
+			})
+		})
 		// TODO: add pagination!! and maybe ordering etc
 		.procedure("listLocations", {
 			R.with2(library())
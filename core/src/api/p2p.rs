@@ -1,15 +1,20 @@
 //! DEPRICATED FOR NEW SYSTEM. DO NOT USE THIS API
-use crate::old_p2p::{
-	operations, ConnectionMethod, DiscoveryMethod, Header, P2PEvent, PeerMetadata,
+use crate::{
+	invalidate_query,
+	node::config::{
+		BandwidthConfig, BandwidthSchedule, PeerPermissions, SpacedropAutoAccept, TrustedPeer,
+	},
+	old_p2p::{operations, ConnectionMethod, DiscoveryMethod, Header, P2PEvent, PeerMetadata},
 };
 
 use sd_old_p2p::{PeerConnectionCandidate, RemoteIdentity};
 
 use rspc::{alpha::AlphaRouter, ErrorCode};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use specta::Type;
 use std::{path::PathBuf, sync::PoisonError};
 use tokio::io::AsyncWriteExt;
+use tracing::error;
 use uuid::Uuid;
 
 use super::{Ctx, R};
@@ -115,14 +120,14 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 		.procedure("spacedrop", {
 			#[derive(Type, Deserialize)]
 			pub struct SpacedropArgs {
-				identity: RemoteIdentity,
+				identities: Vec<RemoteIdentity>,
 				file_path: Vec<String>,
 			}
 
 			R.mutation(|node, args: SpacedropArgs| async move {
 				operations::spacedrop(
 					node.p2p.clone(),
-					args.identity,
+					args.identities,
 					args.file_path
 						.into_iter()
 						.map(PathBuf::from)
@@ -144,6 +149,182 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				Ok(())
 			})
 		})
+		.procedure("spacedropAutoAccept", {
+			R.query(
+				|node, _: ()| async move { Ok(node.config.get().await.p2p.spacedrop_auto_accept) },
+			)
+		})
+		.procedure("setSpacedropAutoAccept", {
+			R.mutation(
+				|node, auto_accept: Option<SpacedropAutoAccept>| async move {
+					node.config
+						.write(|config| config.p2p.spacedrop_auto_accept = auto_accept)
+						.await
+						.map_err(|e| {
+							error!(?e, "Failed to write config;");
+							rspc::Error::new(
+								ErrorCode::InternalServerError,
+								"error updating config".into(),
+							)
+						})?;
+
+					invalidate_query!(node; node, "p2p.spacedropAutoAccept");
+
+					Ok(())
+				},
+			)
+		})
+		.procedure("bandwidth", {
+			R.query(|node, _: ()| async move { Ok(node.config.get().await.p2p.bandwidth) })
+		})
+		.procedure("setBandwidth", {
+			R.mutation(|node, bandwidth: BandwidthConfig| async move {
+				if !bandwidth.schedules.iter().all(BandwidthSchedule::is_valid) {
+					return Err(rspc::Error::new(
+						ErrorCode::BadRequest,
+						"schedules must start and end within a day".into(),
+					));
+				}
+
+				if !bandwidth.limits_are_valid() {
+					return Err(rspc::Error::new(
+						ErrorCode::BadRequest,
+						"bandwidth limits must be greater than zero".into(),
+					));
+				}
+
+				node.config
+					.write(|config| config.p2p.bandwidth = bandwidth.clone())
+					.await
+					.map_err(|e| {
+						error!(?e, "Failed to write config;");
+						rspc::Error::new(
+							ErrorCode::InternalServerError,
+							"error updating config".into(),
+						)
+					})?;
+
+				node.p2p.apply_bandwidth_config(&bandwidth);
+
+				invalidate_query!(node; node, "p2p.bandwidth");
+
+				Ok(())
+			})
+		})
+		.procedure("trustedPeers", {
+			#[derive(Serialize, Type)]
+			pub struct TrustedPeerItem {
+				identity: RemoteIdentity,
+				#[serde(flatten)]
+				peer: TrustedPeer,
+			}
+
+			R.query(|node, _: ()| async move {
+				Ok(node
+					.config
+					.get()
+					.await
+					.p2p
+					.trusted_peers
+					.into_iter()
+					.map(|(identity, peer)| TrustedPeerItem { identity, peer })
+					.collect::<Vec<_>>())
+			})
+		})
+		.procedure("startPairing", {
+			R.mutation(|node, _: ()| async move { Ok(node.p2p.start_pairing()) })
+		})
+		.procedure("cancelPairing", {
+			R.mutation(|node, _: ()| async move {
+				node.p2p.cancel_pairing();
+
+				Ok(())
+			})
+		})
+		.procedure("pair", {
+			#[derive(Type, Deserialize)]
+			pub struct PairArgs {
+				identity: RemoteIdentity,
+				code: String,
+			}
+
+			R.mutation(|node, PairArgs { identity, code }: PairArgs| async move {
+				operations::pair(&node.p2p, identity, code).await?;
+
+				invalidate_query!(node; node, "p2p.trustedPeers");
+
+				Ok(())
+			})
+		})
+		.procedure("setPeerPermissions", {
+			#[derive(Type, Deserialize)]
+			pub struct SetPeerPermissionsArgs {
+				identity: RemoteIdentity,
+				permissions: PeerPermissions,
+			}
+
+			R.mutation(
+				|node,
+				 SetPeerPermissionsArgs {
+				     identity,
+				     permissions,
+				 }: SetPeerPermissionsArgs| async move {
+					let mut found = false;
+
+					node.config
+						.write(|config| {
+							if let Some(peer) = config.p2p.trusted_peers.get_mut(&identity) {
+								peer.permissions = permissions;
+								found = true;
+							}
+						})
+						.await
+						.map_err(|e| {
+							error!(?e, "Failed to write config;");
+							rspc::Error::new(
+								ErrorCode::InternalServerError,
+								"error updating config".into(),
+							)
+						})?;
+
+					if !found {
+						return Err(rspc::Error::new(
+							ErrorCode::NotFound,
+							"peer isn't trusted".into(),
+						));
+					}
+
+					invalidate_query!(node; node, "p2p.trustedPeers");
+
+					Ok(())
+				},
+			)
+		})
+		.procedure("revokePeer", {
+			R.mutation(|node, identity: RemoteIdentity| async move {
+				node.config
+					.write(|config| {
+						config.p2p.trusted_peers.remove(&identity);
+
+						if let Some(auto_accept) = &mut config.p2p.spacedrop_auto_accept {
+							auto_accept.peers.remove(&identity);
+						}
+					})
+					.await
+					.map_err(|e| {
+						error!(?e, "Failed to write config;");
+						rspc::Error::new(
+							ErrorCode::InternalServerError,
+							"error updating config".into(),
+						)
+					})?;
+
+				invalidate_query!(node; node, "p2p.trustedPeers");
+				invalidate_query!(node; node, "p2p.spacedropAutoAccept");
+
+				Ok(())
+			})
+		})
 		.procedure("cancelSpacedrop", {
 			R.mutation(|node, id: Uuid| async move {
 				node.p2p.cancel_spacedrop(id).await;
@@ -70,6 +70,8 @@ pub enum FilePathFilterArgs {
 	ModifiedAt(Range<DateTime<Utc>>),
 	IndexedAt(Range<DateTime<Utc>>),
 	Hidden(bool),
+	/// File paths whose contents only exist in an archive
+	Archived(bool),
 }
 
 impl FilePathFilterArgs {
@@ -152,11 +154,18 @@ impl FilePathFilterArgs {
 			Self::Hidden(v) => {
 				vec![hidden::equals(Some(v))]
 			}
+			Self::Archived(v) => {
+				vec![if v {
+					archived_in::some(vec![])
+				} else {
+					archived_in::none(vec![])
+				}]
+			}
 		})
 	}
 }
 
-#[derive(Deserialize, Type, Debug)]
+#[derive(Serialize, Deserialize, Type, Debug)]
 #[serde(rename_all = "camelCase")]
 pub enum FilePathObjectCursor {
 	DateAccessed(CursorOrderItem<DateTime<FixedOffset>>),
@@ -193,7 +202,7 @@ impl FilePathObjectCursor {
 	}
 }
 
-#[derive(Deserialize, Type, Debug)]
+#[derive(Serialize, Deserialize, Type, Debug)]
 #[serde(rename_all = "camelCase")]
 pub enum FilePathCursorVariant {
 	None,
@@ -253,7 +262,7 @@ impl FilePathCursorVariant {
 	}
 }
 
-#[derive(Deserialize, Type, Debug)]
+#[derive(Serialize, Deserialize, Type, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct FilePathCursor {
 	pub is_dir: bool,
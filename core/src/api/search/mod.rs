@@ -1,16 +1,17 @@
 use crate::{
 	api::{locations::ExplorerItem, utils::library},
 	library::Library,
-	location::{non_indexed, LocationError},
+	location::{locations_availability, non_indexed, LocationError},
 	util::{unsafe_streamed_query, BatchedStream},
 };
 
 use prisma_client_rust::Operator;
 use sd_core_heavy_lifting::media_processor::ThumbKey;
+use sd_core_indexer_rules::IndexerRule;
 use sd_core_prisma_helpers::{file_path_for_frontend, object_with_file_paths, CasId};
 use sd_prisma::prisma::{self, PrismaClient};
 
-use std::path::PathBuf;
+use std::{collections::HashSet, path::PathBuf};
 
 use async_stream::stream;
 use futures::StreamExt;
@@ -44,6 +45,69 @@ pub enum SearchFilterArgs {
 	Object(ObjectFilterArgs),
 }
 
+#[derive(Serialize, Deserialize, Type, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FilePathSearchArgs {
+	#[specta(optional)]
+	pub take: Option<u8>,
+	#[specta(optional)]
+	pub order_and_pagination: Option<file_path::OrderAndPagination>,
+	#[serde(default)]
+	pub filters: Vec<SearchFilterArgs>,
+	#[serde(default = "default_group_directories")]
+	pub group_directories: bool,
+}
+
+fn default_group_directories() -> bool {
+	true
+}
+
+/// Runs the query of `search.paths`, also used to answer the searches of nodes browsing this one.
+/// `extra_params` further restricts the file paths found.
+pub(crate) async fn find_file_paths(
+	db: &PrismaClient,
+	FilePathSearchArgs {
+		take,
+		order_and_pagination,
+		filters,
+		group_directories,
+	}: FilePathSearchArgs,
+	extra_params: Vec<prisma::file_path::WhereParam>,
+) -> Result<Vec<file_path_for_frontend::Data>, rspc::Error> {
+	let params = {
+		let (mut fp, obj) = merge_filters(filters, db).await?;
+
+		if !obj.is_empty() {
+			fp.push(prisma::file_path::object::is(obj));
+		}
+
+		fp.extend(extra_params);
+
+		fp
+	};
+
+	let mut query = db.file_path().find_many(andify(params));
+
+	if let Some(take) = take {
+		query = query.take(take as i64);
+	}
+
+	// WARN: this order_by for grouping directories MUST always come before the other order_by
+	if group_directories {
+		query = query.order_by(prisma::file_path::is_dir::order(prisma::SortOrder::Desc));
+	}
+
+	// WARN: this order_by for sorting data MUST always come after the other order_by
+	if let Some(order_and_pagination) = order_and_pagination {
+		order_and_pagination.apply(&mut query, group_directories)
+	}
+
+	Ok(query
+		.include(file_path_for_frontend::include())
+		.exec()
+		.await?)
+}
+
 impl SearchFilterArgs {
 	async fn into_params(
 		self,
@@ -78,6 +142,23 @@ pub fn mount() -> AlphaRouter<Ctx> {
 				with_hidden_files: bool,
 				#[specta(optional)]
 				order: Option<EphemeralPathOrder>,
+				/// Search in all sub directories instead of only listing the path
+				#[serde(default)]
+				#[specta(optional)]
+				recursive: bool,
+				#[serde(default)]
+				#[specta(optional)]
+				indexer_rules_ids: Vec<prisma::indexer_rule::id::Type>,
+				#[serde(default)]
+				#[specta(optional)]
+				with_directory_sizes: bool,
+				/// Only entries whose name contains this, ignoring case
+				#[specta(optional)]
+				name: Option<String>,
+				/// Only files with one of these extensions
+				#[serde(default)]
+				#[specta(optional)]
+				extensions: Vec<String>,
 			}
 			#[derive(Serialize, Type, Debug)]
 			struct EphemeralPathsResultItem {
@@ -91,42 +172,65 @@ pub fn mount() -> AlphaRouter<Ctx> {
 				     path,
 				     with_hidden_files,
 				     order,
+				     recursive,
+				     indexer_rules_ids,
+				     with_directory_sizes,
+				     name,
+				     extensions,
 				 }| async move {
-					let paths =
-						non_indexed::walk(path, with_hidden_files, node, library, |entries| {
-							macro_rules! order_match {
-								($order:ident, [$(($variant:ident, |$i:ident| $func:expr)),+]) => {{
-									match $order {
-										$(EphemeralPathOrder::$variant(order) => {
-											entries.sort_unstable_by(|path1, path2| {
-												let func = |$i: &non_indexed::Entry| $func;
-
-												let one = func(path1);
-												let two = func(path2);
-
-												match order {
-													SortOrder::Desc => two.cmp(&one),
-													SortOrder::Asc => one.cmp(&two),
-												}
-											});
-										})+
-									}
-								}};
-							}
+					let indexer_rules = library
+						.db
+						.indexer_rule()
+						.find_many(vec![prisma::indexer_rule::id::in_vec(indexer_rules_ids)])
+						.exec()
+						.await?
+						.iter()
+						.map(IndexerRule::try_from)
+						.collect::<Result<Vec<_>, _>>()?;
+
+					let options = non_indexed::WalkOptions {
+						with_hidden_files,
+						recursive,
+						indexer_rules,
+						name: name.filter(|name| !name.is_empty()),
+						extensions,
+						with_directory_sizes,
+					};
 
-							if let Some(order) = order {
-								order_match!(
-									order,
-									[
-										(Name, |p| p.name().to_lowercase()),
-										(SizeInBytes, |p| p.size_in_bytes()),
-										(DateCreated, |p| p.date_created()),
-										(DateModified, |p| p.date_modified())
-									]
-								)
-							}
-						})
-						.await?;
+					let paths = non_indexed::walk(path, options, node, library, |entries| {
+						macro_rules! order_match {
+							($order:ident, [$(($variant:ident, |$i:ident| $func:expr)),+]) => {{
+								match $order {
+									$(EphemeralPathOrder::$variant(order) => {
+										entries.sort_unstable_by(|path1, path2| {
+											let func = |$i: &non_indexed::Entry| $func;
+
+											let one = func(path1);
+											let two = func(path2);
+
+											match order {
+												SortOrder::Desc => two.cmp(&one),
+												SortOrder::Asc => one.cmp(&two),
+											}
+										});
+									})+
+								}
+							}};
+						}
+
+						if let Some(order) = order {
+							order_match!(
+								order,
+								[
+									(Name, |p| p.name().to_lowercase()),
+									(SizeInBytes, |p| p.size_in_bytes()),
+									(DateCreated, |p| p.date_created()),
+									(DateModified, |p| p.date_modified())
+								]
+							)
+						}
+					})
+					.await?;
 
 					let mut stream = BatchedStream::new(paths);
 					Ok(unsafe_streamed_query(stream! {
@@ -155,64 +259,21 @@ pub fn mount() -> AlphaRouter<Ctx> {
 			)
 		})
 		.procedure("paths", {
-			#[derive(Deserialize, Type, Debug)]
-			#[serde(rename_all = "camelCase")]
-			struct FilePathSearchArgs {
-				#[specta(optional)]
-				take: Option<u8>,
-				#[specta(optional)]
-				order_and_pagination: Option<file_path::OrderAndPagination>,
-				#[serde(default)]
-				filters: Vec<SearchFilterArgs>,
-				#[serde(default = "default_group_directories")]
-				group_directories: bool,
-			}
-
-			fn default_group_directories() -> bool {
-				true
-			}
-
-			R.with2(library()).query(
-				|(node, library),
-				 FilePathSearchArgs {
-				     take,
-				     order_and_pagination,
-				     filters,
-				     group_directories,
-				 }| async move {
-					let Library { db, .. } = library.as_ref();
-
-					let params = {
-						let (mut fp, obj) = merge_filters(filters, db).await?;
-
-						if !obj.is_empty() {
-							fp.push(prisma::file_path::object::is(obj));
-						}
-
-						fp
-					};
-
-					let mut query = db.file_path().find_many(andify(params));
-
-					if let Some(take) = take {
-						query = query.take(take as i64);
-					}
-
-					// WARN: this order_by for grouping directories MUST always come before the other order_by
-					if group_directories {
-						query = query
-							.order_by(prisma::file_path::is_dir::order(prisma::SortOrder::Desc));
-					}
-
-					// WARN: this order_by for sorting data MUST always come after the other order_by
-					if let Some(order_and_pagination) = order_and_pagination {
-						order_and_pagination.apply(&mut query, group_directories)
-					}
+			R.with2(library())
+				.query(|(node, library), args: FilePathSearchArgs| async move {
+					let file_paths = find_file_paths(&library.db, args, vec![]).await?;
 
-					let file_paths = query
-						.include(file_path_for_frontend::include())
-						.exec()
-						.await?;
+					let availability = locations_availability(
+						&node,
+						&library,
+						file_paths
+							.iter()
+							.filter_map(|file_path| file_path.location_id)
+							.collect::<HashSet<_>>()
+							.into_iter()
+							.collect(),
+					)
+					.await?;
 
 					let mut items = Vec::with_capacity(file_paths.len());
 
@@ -235,6 +296,11 @@ pub fn mount() -> AlphaRouter<Ctx> {
 								.map(CasId::into_owned)
 								.map(|cas_id| ThumbKey::new_indexed(cas_id, library.id)),
 							has_created_thumbnail,
+							availability: file_path
+								.location_id
+								.and_then(|location_id| availability.get(&location_id))
+								.cloned()
+								.unwrap_or_default(),
 							item: Box::new(file_path),
 						})
 					}
@@ -243,8 +309,7 @@ pub fn mount() -> AlphaRouter<Ctx> {
 						items,
 						cursor: None,
 					})
-				},
-			)
+				})
 		})
 		.procedure("pathsCount", {
 			#[derive(Deserialize, Type, Debug)]
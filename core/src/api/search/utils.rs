@@ -42,14 +42,14 @@ impl From<SortOrder> for prisma::SortOrder {
 // 	}
 // }
 
-#[derive(Deserialize, Type, Debug)]
+#[derive(Serialize, Deserialize, Type, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct CursorOrderItem<T> {
 	pub order: SortOrder,
 	pub data: T,
 }
 
-#[derive(Deserialize, Type, Debug)]
+#[derive(Serialize, Deserialize, Type, Debug)]
 #[serde(rename_all = "camelCase")]
 pub enum OrderAndPagination<TId, TOrder, TCursor> {
 	OrderOnly(TOrder),
@@ -1,10 +1,50 @@
-use rspc::alpha::AlphaRouter;
+use chrono::{DateTime, FixedOffset, Utc};
+use prisma_client_rust::raw;
+use rspc::{alpha::AlphaRouter, ErrorCode};
+use sd_core_cloud_services::CloudSyncTransport;
+use sd_core_sync::{
+	compaction::{compact_operations, CompactionReport},
+	conflict::{list_conflicts, resolve_conflict, Conflict, ConflictResolution},
+	inspect::{self, DivergenceKind, RecordDivergence},
+	scope::{SyncScope, OPTIONAL_MODELS},
+	DevicePubId, RecordId,
+};
+use sd_old_p2p::RemoteIdentity;
+use sd_prisma::prisma::location;
+use sd_utils::{from_bytes_to_uuid, timestamp_to_datetime, u64_to_frontend, uuid_to_bytes};
+use serde::{Deserialize, Serialize};
+use specta::Type;
 use std::sync::atomic::Ordering;
+use tokio::{fs, spawn};
+use tracing::{debug, error};
 
-use crate::util::MaybeUndefined;
+use crate::{
+	invalidate_query,
+	old_p2p::{self, PeerMetadata},
+	util::MaybeUndefined,
+};
 
 use super::{utils::library, Ctx, R};
 
+/// Sync scope as seen by the frontend, with location ids and model names
+#[derive(Serialize, Deserialize, Type)]
+#[specta(rename = "SyncScopeArgs")]
+struct ScopeArgs {
+	/// Locations whose file paths are synced, `null` syncs every location
+	locations: Option<Vec<location::id::Type>>,
+	excluded_models: Vec<String>,
+}
+
+fn msgpack_to_json(value: &rmpv::Value) -> Result<serde_json::Value, rspc::Error> {
+	serde_json::to_value(value).map_err(|e| {
+		rspc::Error::with_cause(
+			ErrorCode::InternalServerError,
+			"Failed to convert sync value".to_string(),
+			e,
+		)
+	})
+}
+
 pub(crate) fn mount() -> AlphaRouter<Ctx> {
 	R.router()
 		.procedure("backfill", {
@@ -43,6 +83,455 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					.load(Ordering::Relaxed))
 			})
 		})
+		.procedure("transport", {
+			R.with2(library()).query(|(_, library), _: ()| async move {
+				Ok(library.config().await.sync_transport)
+			})
+		})
+		.procedure("setTransport", {
+			R.with2(library())
+				.mutation(|(_, library), transport: CloudSyncTransport| async move {
+					if let CloudSyncTransport::Directory { path } = &transport {
+						if !path.is_absolute() {
+							return Err(rspc::Error::new(
+								ErrorCode::BadRequest,
+								"Sync relay directory must be an absolute path".to_string(),
+							));
+						}
+					}
+
+					// Takes effect the next time sync actors are started for this library
+					library
+						.update_config(|config| config.sync_transport = transport)
+						.await?;
+
+					invalidate_query!(library, "sync.transport");
+
+					Ok(())
+				})
+		})
+		.procedure("scope", {
+			R.with2(library()).query(|(_, library), _: ()| async move {
+				let SyncScope {
+					locations,
+					excluded_models,
+				} = library.config().await.sync_scope;
+
+				let locations = match locations {
+					Some(pub_ids) => Some(
+						library
+							.db
+							.location()
+							.find_many(vec![location::pub_id::in_vec(
+								pub_ids.iter().map(uuid_to_bytes).collect(),
+							)])
+							.select(location::select!({ id }))
+							.exec()
+							.await?
+							.into_iter()
+							.map(|location| location.id)
+							.collect::<Vec<_>>(),
+					),
+					None => None,
+				};
+
+				Ok(ScopeArgs {
+					locations,
+					excluded_models: excluded_models
+						.into_iter()
+						.filter_map(inspect::model_name)
+						.map(str::to_string)
+						.collect(),
+				})
+			})
+		})
+		.procedure("setScope", {
+			R.with2(library()).mutation(
+				|(_, library),
+				 ScopeArgs {
+				     locations,
+				     excluded_models,
+				 }: ScopeArgs| async move {
+					let excluded_models = excluded_models
+						.iter()
+						.map(|name| {
+							inspect::SYNC_MODELS
+								.iter()
+								.find(|(model_id, model_name)| {
+									model_name == name && OPTIONAL_MODELS.contains(model_id)
+								})
+								.map(|(model_id, _)| *model_id)
+								.ok_or_else(|| {
+									rspc::Error::new(
+										ErrorCode::BadRequest,
+										format!("Model '{name}' can't be excluded from sync"),
+									)
+								})
+						})
+						.collect::<Result<Vec<_>, _>>()?;
+
+					let locations = match locations {
+						Some(ids) => Some(
+							library
+								.db
+								.location()
+								.find_many(vec![location::id::in_vec(ids)])
+								.select(location::select!({ pub_id }))
+								.exec()
+								.await?
+								.into_iter()
+								.map(|location| from_bytes_to_uuid(&location.pub_id))
+								.collect::<Vec<_>>(),
+						),
+						None => None,
+					};
+
+					let scope = SyncScope {
+						locations,
+						excluded_models,
+					};
+
+					library
+						.update_config(|config| config.sync_scope = scope.clone())
+						.await?;
+
+					invalidate_query!(library, "sync.scope");
+
+					// Records that entered the scope are applied in background, as it can take a
+					// while on huge libraries
+					spawn(async move {
+						match library.sync.set_scope(scope).await {
+							Ok(count) => {
+								debug!(count, "Backfilled records after sync scope change;")
+							}
+							Err(e) => {
+								error!(?e, "Failed to backfill records after sync scope change;")
+							}
+						}
+					});
+
+					Ok(())
+				},
+			)
+		})
+		.procedure("conflicts", {
+			#[derive(Serialize, Type)]
+			#[specta(rename = "SyncConflict")]
+			struct SyncConflict {
+				id: i32,
+				model: &'static str,
+				/// Human readable record id
+				record: String,
+				field: String,
+				lost_value: serde_json::Value,
+				lost_at: DateTime<Utc>,
+				lost_device_pub_id: DevicePubId,
+				current_value: serde_json::Value,
+				current_at: DateTime<Utc>,
+				current_device_pub_id: DevicePubId,
+				date_created: DateTime<FixedOffset>,
+			}
+
+			R.with2(library()).query(|(_, library), _: ()| async move {
+				list_conflicts(&library.sync)
+					.await?
+					.into_iter()
+					.map(
+						|Conflict {
+						     id,
+						     model_id,
+						     record_id,
+						     field,
+						     lost_value,
+						     lost_timestamp,
+						     lost_device_pub_id,
+						     won_value,
+						     won_timestamp,
+						     won_device_pub_id,
+						     date_created,
+						 }| {
+							Ok(SyncConflict {
+								id,
+								model: inspect::model_name(model_id).unwrap_or("unknown"),
+								record: record_id.to_string(),
+								field,
+								lost_value: msgpack_to_json(&lost_value)?,
+								lost_at: timestamp_to_datetime(lost_timestamp),
+								lost_device_pub_id,
+								current_value: msgpack_to_json(&won_value)?,
+								current_at: timestamp_to_datetime(won_timestamp),
+								current_device_pub_id: won_device_pub_id,
+								date_created,
+							})
+						},
+					)
+					.collect::<Result<Vec<_>, rspc::Error>>()
+			})
+		})
+		.procedure("resolveConflict", {
+			#[derive(Deserialize, Type)]
+			#[serde(rename_all = "snake_case")]
+			#[specta(rename = "SyncConflictResolution")]
+			enum Resolution {
+				KeepCurrent,
+				UseLost,
+			}
+
+			#[derive(Deserialize, Type)]
+			#[specta(rename = "SyncResolveConflictArgs")]
+			struct Args {
+				id: i32,
+				resolution: Resolution,
+			}
+
+			R.with2(library())
+				.mutation(|(_, library), Args { id, resolution }: Args| async move {
+					resolve_conflict(
+						&library.sync,
+						id,
+						match resolution {
+							Resolution::KeepCurrent => ConflictResolution::KeepCurrent,
+							Resolution::UseLost => ConflictResolution::UseLost,
+						},
+					)
+					.await?;
+
+					invalidate_query!(library, "sync.conflicts");
+					invalidate_query!(library, "tags.list");
+					invalidate_query!(library, "search.saved.list");
+					invalidate_query!(library, "search.objects");
+
+					Ok(())
+				})
+		})
+		.procedure("compact", {
+			#[derive(serde::Serialize, specta::Type)]
+			#[specta(rename = "SyncCompactionReport")]
+			struct Report {
+				deleted_operations: u32,
+				rewritten_operations: u32,
+				reclaimed_bytes: (u32, u32),
+				/// Only available when the database was vacuumed after compaction
+				database_size_before: Option<(u32, u32)>,
+				database_size_after: Option<(u32, u32)>,
+			}
+
+			R.with2(library())
+				.mutation(|(node, library), vacuum: bool| async move {
+					let CompactionReport {
+						deleted_operations,
+						rewritten_operations,
+						reclaimed_bytes,
+						..
+					} = compact_operations(&library.sync).await?;
+
+					let (database_size_before, database_size_after) = if vacuum {
+						let db_path = node
+							.libraries
+							.libraries_dir
+							.join(format!("{}.db", library.id));
+						let size_before = fs::metadata(&db_path).await.map(|m| m.len()).ok();
+
+						library.db._execute_raw(raw!("VACUUM;")).exec().await?;
+
+						let size_after = fs::metadata(&db_path).await.map(|m| m.len()).ok();
+
+						(
+							size_before.map(u64_to_frontend),
+							size_after.map(u64_to_frontend),
+						)
+					} else {
+						(None, None)
+					};
+
+					Ok(Report {
+						deleted_operations: u32::try_from(deleted_operations).unwrap_or(u32::MAX),
+						rewritten_operations: u32::try_from(rewritten_operations)
+							.unwrap_or(u32::MAX),
+						reclaimed_bytes: u64_to_frontend(reclaimed_bytes),
+						database_size_before,
+						database_size_after,
+					})
+				})
+		})
+		.procedure("state", {
+			#[derive(Serialize, Type)]
+			#[specta(rename = "SyncDeviceState")]
+			struct DeviceState {
+				device_pub_id: DevicePubId,
+				is_current: bool,
+				latest_operation_at: DateTime<Utc>,
+			}
+
+			#[derive(Serialize, Type)]
+			#[specta(rename = "SyncPendingOperations")]
+			struct PendingOperations {
+				model: &'static str,
+				count: u32,
+			}
+
+			#[derive(Serialize, Type)]
+			#[specta(rename = "SyncIngestError")]
+			struct IngestError {
+				at: DateTime<Utc>,
+				message: String,
+			}
+
+			#[derive(Serialize, Type)]
+			#[specta(rename = "SyncPeer")]
+			struct SyncPeer {
+				remote_identity: RemoteIdentity,
+				name: Option<String>,
+				can_connect: bool,
+			}
+
+			#[derive(Serialize, Type)]
+			#[specta(rename = "SyncInspectorState")]
+			struct State {
+				devices: Vec<DeviceState>,
+				pending_operations: Vec<PendingOperations>,
+				ingest_errors: Vec<IngestError>,
+				peers: Vec<SyncPeer>,
+			}
+
+			R.with2(library())
+				.query(|(node, library), _: ()| async move {
+					let sync = &library.sync;
+
+					let devices = sync
+						.timestamp_per_device
+						.read()
+						.await
+						.iter()
+						.map(|(device_pub_id, timestamp)| DeviceState {
+							is_current: *device_pub_id == sync.device_pub_id,
+							device_pub_id: device_pub_id.clone(),
+							latest_operation_at: timestamp_to_datetime(*timestamp),
+						})
+						.collect();
+
+					let pending_operations = inspect::pending_operations_per_model(sync)
+						.await?
+						.into_iter()
+						.map(|(model_id, count)| PendingOperations {
+							model: inspect::model_name(model_id).unwrap_or("unknown"),
+							count: u32::try_from(count).unwrap_or(u32::MAX),
+						})
+						.collect();
+
+					let ingest_errors = sync
+						.last_ingest_errors()
+						.await
+						.into_iter()
+						.map(|entry| IngestError {
+							at: entry.at,
+							message: entry.message,
+						})
+						.collect();
+
+					let peers = node
+						.p2p
+						.get_library_instances(&library.id)
+						.into_iter()
+						.map(|(remote_identity, peer)| SyncPeer {
+							remote_identity,
+							name: PeerMetadata::from_hashmap(&peer.metadata())
+								.ok()
+								.map(|metadata| metadata.name),
+							can_connect: peer.can_connect(),
+						})
+						.collect();
+
+					Ok(State {
+						devices,
+						pending_operations,
+						ingest_errors,
+						peers,
+					})
+				})
+		})
+		.procedure("compare", {
+			#[derive(Serialize, Type)]
+			#[serde(rename_all = "snake_case")]
+			#[specta(rename = "SyncDivergenceKind")]
+			enum Kind {
+				MissingLocally,
+				MissingRemotely,
+				Different,
+			}
+
+			#[derive(Serialize, Type)]
+			#[specta(rename = "SyncRecordDivergence")]
+			struct Divergence {
+				model_id: u16,
+				model: &'static str,
+				/// Serialized record id, to be sent back to `sync.resyncRecords`
+				record_id: Vec<u8>,
+				/// Human readable record id
+				record: String,
+				kind: Kind,
+			}
+
+			R.with2(library()).mutation(
+				|(node, library), remote_identity: RemoteIdentity| async move {
+					Ok(
+						old_p2p::sync::inspect::compare(&node.p2p, &library, remote_identity)
+							.await?
+							.into_iter()
+							.map(
+								|RecordDivergence {
+								     model_id,
+								     record_id,
+								     kind,
+								 }| Divergence {
+									model_id,
+									model: inspect::model_name(model_id).unwrap_or("unknown"),
+									record: rmp_serde::from_slice::<RecordId>(&record_id)
+										.map_or_else(
+											|_| String::from("<invalid>"),
+											|id| id.to_string(),
+										),
+									record_id,
+									kind: match kind {
+										DivergenceKind::MissingLocally => Kind::MissingLocally,
+										DivergenceKind::MissingRemotely => Kind::MissingRemotely,
+										DivergenceKind::Different => Kind::Different,
+									},
+								},
+							)
+							.collect::<Vec<_>>(),
+					)
+				},
+			)
+		})
+		.procedure("resyncRecords", {
+			#[derive(Deserialize, Type)]
+			#[specta(rename = "SyncResyncRecordsArgs")]
+			struct Args {
+				remote_identity: RemoteIdentity,
+				records: Vec<(u16, Vec<u8>)>,
+			}
+
+			R.with2(library()).mutation(
+				|(node, library),
+				 Args {
+				     remote_identity,
+				     records,
+				 }: Args| async move {
+					let ingested = old_p2p::sync::inspect::resync_records(
+						&node.p2p,
+						&library,
+						remote_identity,
+						records,
+					)
+					.await?;
+
+					invalidate_query!(library, "sync.state");
+
+					Ok(u32::try_from(ingested).unwrap_or(u32::MAX))
+				},
+			)
+		})
 		.procedure("active", {
 			R.with2(library())
 				.subscription(|(_, library), _: ()| async move {
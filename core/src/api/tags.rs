@@ -1,10 +1,14 @@
-use crate::{invalidate_query, library::Library, object::tag::TagCreateArgs};
+use crate::{
+	invalidate_query,
+	library::Library,
+	object::tag::{assign_to_objects, TagCreateArgs},
+};
 
 use sd_prisma::{
 	prisma::{device, file_path, object, tag, tag_on_object},
 	prisma_sync,
 };
-use sd_sync::{option_sync_db_entry, sync_db_entry, sync_entry, OperationFactory};
+use sd_sync::{option_sync_db_entry, sync_db_entry, OperationFactory};
 
 use std::collections::BTreeMap;
 
@@ -221,54 +225,17 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 							sync.write_ops(db, (ops, query)).await?;
 						}
 					} else {
-						let (sync_ops, db_creates) = objects
-							.into_iter()
-							.map(|o| (o.id, o.pub_id))
-							.chain(
+						assign_to_objects(
+							&library,
+							(args.tag_id, &tag.pub_id),
+							device_id,
+							objects.into_iter().map(|o| (o.id, o.pub_id)).chain(
 								file_paths
 									.into_iter()
 									.filter_map(|fp| fp.object.map(|o| (o.id, o.pub_id))),
-							)
-							.map(|(id, pub_id)| {
-								(
-									sync.relation_create(
-										prisma_sync::tag_on_object::SyncId {
-											tag: prisma_sync::tag::SyncId {
-												pub_id: tag.pub_id.clone(),
-											},
-											object: prisma_sync::object::SyncId { pub_id },
-										},
-										[sync_entry!(
-											prisma_sync::device::SyncId {
-												pub_id: sync.device_pub_id.to_db(),
-											},
-											tag_on_object::device
-										)],
-									),
-									tag_on_object::CreateUnchecked {
-										tag_id: args.tag_id,
-										object_id: id,
-										_params: vec![
-											tag_on_object::date_created::set(Some(
-												Utc::now().into(),
-											)),
-											tag_on_object::device_id::set(Some(device_id)),
-										],
-									},
-								)
-							})
-							.unzip::<_, _, Vec<_>, Vec<_>>();
-
-						if !sync_ops.is_empty() && !db_creates.is_empty() {
-							sync.write_ops(
-								db,
-								(
-									sync_ops,
-									db.tag_on_object().create_many(db_creates).skip_duplicates(),
-								),
-							)
-							.await?;
-						}
+							),
+						)
+						.await?;
 					}
 
 					invalidate_query!(library, "tags.getForObject");
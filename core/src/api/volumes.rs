@@ -1,8 +1,27 @@
 use super::{utils::library, Ctx, R};
-use crate::volume::{VolumeEvent, VolumeFingerprint};
-use rspc::alpha::AlphaRouter;
+use crate::{
+	capacity::{self, CapacityTarget},
+	invalidate_query,
+	library::Library,
+	location::LocationError,
+	node::config::CapacityAlertPreferences,
+	volume::{
+		util::volume_for_path, with_library_usage, SpeedTestConfig, Volume, VolumeEvent,
+		VolumeFingerprint,
+	},
+	Node,
+};
+use chrono::Utc;
+use rspc::{alpha::AlphaRouter, ErrorCode};
+use sd_core_heavy_lifting::{job_system::report::Status, JobId};
+use sd_prisma::prisma::{device, job, location, volume};
+use sd_utils::from_bytes_to_uuid;
 use serde::Deserialize;
 use specta::Type;
+use std::{collections::HashSet, path::Path, sync::Arc};
+use tokio::sync::broadcast;
+use tracing::{error, warn};
+use uuid::Uuid;
 
 pub(crate) fn mount() -> AlphaRouter<Ctx> {
 	R.router()
@@ -29,21 +48,122 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					);
 
 					node.volumes
-						.track_volume(fingerprint, library)
+						.track_volume(fingerprint, library.clone())
 						.await
 						.map_err(|e| {
 							tracing::error!("Failed to track volume: {:?}", e);
-							e.into()
-						})
+							rspc::Error::from(e)
+						})?;
+
+					invalidate_query!(library, "volumes.listForLibrary");
+
+					Ok(())
 				},
 			),
 		)
+		.procedure(
+			"untrack",
+			R.with2(library()).mutation(
+				|(node, library), fingerprint: VolumeFingerprint| async move {
+					node.volumes
+						.untrack_volume(fingerprint, library.clone())
+						.await?;
+
+					invalidate_query!(library, "volumes.listForLibrary");
+					invalidate_query!(library, "locations.list");
+
+					Ok(())
+				},
+			),
+		)
+		.procedure("speedTest", {
+			#[derive(Type, Deserialize)]
+			pub struct SpeedTestArgs {
+				pub fingerprint: VolumeFingerprint,
+				/// Defaults to a 10MB file with a 30 seconds timeout
+				pub config: Option<SpeedTestConfig>,
+			}
+
+			R.with2(library()).mutation(
+				|(node, library),
+				 SpeedTestArgs {
+				     fingerprint,
+				     config,
+				 }: SpeedTestArgs| async move {
+					let result = node
+						.volumes
+						.speed_test(fingerprint, library.clone(), config)
+						.await?;
+
+					invalidate_query!(library, "volumes.listForLibrary");
+
+					Ok(result)
+				},
+			)
+		})
+		.procedure("capacityTrend", {
+			#[derive(Type, Deserialize)]
+			pub struct CapacityTrendArgs {
+				pub volume_id: volume::id::Type,
+				/// Weeks of snapshots to look back at, 12 when missing
+				pub weeks: Option<u32>,
+			}
+
+			R.with2(library()).query(
+				|(_, library), CapacityTrendArgs { volume_id, weeks }: CapacityTrendArgs| async move {
+					capacity::trend(
+						&library.db,
+						CapacityTarget::Volume(volume_id),
+						Utc::now() - chrono::Duration::weeks(i64::from(weeks.unwrap_or(12))),
+					)
+					.await
+					.map_err(Into::into)
+				},
+			)
+		})
+		.procedure("capacityAlerts", {
+			R.query(
+				|node, _: ()| async move { Ok(node.config.get().await.preferences.capacity_alerts) },
+			)
+		})
+		.procedure("updateCapacityAlerts", {
+			R.mutation(|node, preferences: CapacityAlertPreferences| async move {
+				if preferences.fill_within_days == Some(0)
+					|| preferences
+						.min_available_percent
+						.is_some_and(|percent| percent == 0 || percent > 100)
+				{
+					return Err(rspc::Error::new(
+						ErrorCode::BadRequest,
+						"alert thresholds must be at least 1 day and between 1 and 100 percent"
+							.into(),
+					));
+				}
+
+				node.config
+					.update_preferences(|current| current.capacity_alerts = preferences)
+					.await
+					.map_err(|e| {
+						error!(?e, "Failed to update capacity alert preferences;");
+						rspc::Error::with_cause(
+							ErrorCode::InternalServerError,
+							"Failed to update capacity alert preferences".to_string(),
+							e,
+						)
+					})?;
+
+				invalidate_query!(node; node, "volumes.capacityAlerts");
+
+				Ok(())
+			})
+		})
 		.procedure(
 			"listForLibrary",
 			R.with2(library())
 				.query(|(node, library), _: ()| async move {
-					node.volumes
-						.list_library_volumes(library)
+					let volumes = node.volumes.list_library_volumes(library.clone()).await?;
+
+					with_library_usage(&library, volumes)
 						.await
 						.map_err(Into::into)
 				}),
@@ -62,10 +182,36 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 			"unmount",
 			R.with2(library())
 				.mutation(|(node, _), fingerprint: Vec<u8>| async move {
-					node.volumes
-						.unmount_volume(VolumeFingerprint(fingerprint).into())
-						.await
-						.map_err(Into::into)
+					let fingerprint = VolumeFingerprint(fingerprint);
+
+					// Subscribing before unmounting, so the volume coming back can't be missed
+					let mut events = node.volumes.subscribe();
+
+					// Nothing may be reading or writing the volume while it is unmounted
+					let paused = pause_locations_on_volume(&node, &fingerprint).await?;
+
+					if let Err(e) = node.volumes.unmount_volume(fingerprint.clone()).await {
+						paused.resume(&node).await;
+						return Err(e.into());
+					}
+
+					// The watchers are resumed by the location manager once it sees the locations
+					// went offline, and the jobs are left paused until the volume is back
+					if !paused.jobs.is_empty() || !paused.old_jobs.is_empty() {
+						tokio::spawn(async move {
+							loop {
+								match events.recv().await {
+									Ok(event) if is_volume_back(&event, &fingerprint) => break,
+									Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+									Err(broadcast::error::RecvError::Closed) => return,
+								}
+							}
+
+							paused.resume_jobs(&node).await;
+						});
+					}
+
+					Ok(())
 				}),
 		)
 		.procedure("events", {
@@ -80,3 +226,243 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 			})
 		})
 }
+
+/// Watchers and jobs paused on the locations of a volume about to be unmounted
+#[derive(Default)]
+struct PausedOnVolume {
+	watchers: Vec<(location::id::Type, Arc<Library>)>,
+	jobs: Vec<JobId>,
+	old_jobs: Vec<Uuid>,
+}
+
+impl PausedOnVolume {
+	async fn resume(self, node: &Node) {
+		for (location_id, library) in &self.watchers {
+			if let Err(e) = node
+				.locations
+				.resume_watcher(*location_id, Arc::clone(library))
+				.await
+			{
+				warn!(?e, %location_id, "Failed to resume location watcher;");
+			}
+		}
+
+		self.resume_jobs(node).await;
+	}
+
+	async fn resume_jobs(self, node: &Node) {
+		for job_id in self.jobs {
+			if let Err(e) = node.job_system.resume(job_id).await {
+				warn!(?e, %job_id, "Failed to resume job;");
+			}
+		}
+
+		for job_id in self.old_jobs {
+			if let Err(e) = node.old_jobs.resume(job_id).await {
+				warn!(?e, %job_id, "Failed to resume job;");
+			}
+		}
+	}
+}
+
+/// Whether the event tells the volume is mounted again
+fn is_volume_back(event: &VolumeEvent, fingerprint: &VolumeFingerprint) -> bool {
+	match event {
+		VolumeEvent::VolumeAdded(volume) => volume.fingerprint.as_ref() == Some(fingerprint),
+		VolumeEvent::VolumeMountChanged {
+			fingerprint: mounted,
+			is_mounted,
+		} => *is_mounted && mounted == fingerprint,
+		_ => false,
+	}
+}
+
+/// The locations whose path is on the volume, locations on volumes mounted inside it excluded
+fn locations_on_volume(
+	volumes: &[Volume],
+	fingerprint: &VolumeFingerprint,
+	locations: impl IntoIterator<Item = (location::id::Type, Option<String>)>,
+) -> HashSet<location::id::Type> {
+	locations
+		.into_iter()
+		.filter(|(_, path)| {
+			path.as_deref()
+				.and_then(|path| volume_for_path(volumes, Path::new(path)))
+				.is_some_and(|volume| volume.fingerprint.as_ref() == Some(fingerprint))
+		})
+		.map(|(location_id, _)| location_id)
+		.collect()
+}
+
+/// Pauses the watchers and running jobs of every location on the volume, in all libraries
+async fn pause_locations_on_volume(
+	node: &Node,
+	fingerprint: &VolumeFingerprint,
+) -> Result<PausedOnVolume, rspc::Error> {
+	let mut paused = PausedOnVolume::default();
+
+	let res = async {
+		let active_reports = node.job_system.get_active_reports().await;
+
+		for library in node.libraries.get_all().await {
+			let volumes = node
+				.volumes
+				.list_system_volumes(Arc::clone(&library))
+				.await?;
+
+			let location_ids = locations_on_volume(
+				&volumes,
+				fingerprint,
+				library
+					.db
+					.location()
+					.find_many(vec![location::device::is(vec![device::pub_id::equals(
+						library.sync.device_pub_id.to_db(),
+					)])])
+					.select(location::select!({ id path }))
+					.exec()
+					.await?
+					.into_iter()
+					.map(|location| (location.id, location.path)),
+			);
+
+			if location_ids.is_empty() {
+				continue;
+			}
+
+			let running_job_ids = active_reports
+				.values()
+				.filter(|report| {
+					report.status == Status::Running
+						&& report
+							.location_id
+							.is_some_and(|location_id| location_ids.contains(&location_id))
+				})
+				.map(|report| report.id.as_bytes().to_vec())
+				.collect::<Vec<_>>();
+
+			// Location ids are only unique within a library, so the jobs must be this library's
+			let library_job_ids = library
+				.db
+				.job()
+				.find_many(vec![job::id::in_vec(running_job_ids)])
+				.select(job::select!({ id }))
+				.exec()
+				.await?;
+
+			for job in library_job_ids {
+				let job_id = from_bytes_to_uuid(&job.id);
+				node.job_system.pause(job_id).await?;
+				paused.jobs.push(job_id);
+			}
+
+			for job_id in node
+				.old_jobs
+				.running_on_locations(library.id, &location_ids)
+				.await
+			{
+				node.old_jobs.pause(job_id).await?;
+				paused.old_jobs.push(job_id);
+			}
+
+			for location_id in location_ids {
+				node.locations
+					.pause_watcher(location_id, Arc::clone(&library))
+					.await
+					.map_err(LocationError::from)?;
+				paused.watchers.push((location_id, Arc::clone(&library)));
+			}
+		}
+
+		Ok::<_, rspc::Error>(())
+	}
+	.await;
+
+	match res {
+		Ok(()) => Ok(paused),
+		Err(e) => {
+			paused.resume(node).await;
+			Err(e)
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::volume::{DiskType, FileSystem, MountType};
+
+	use std::path::PathBuf;
+
+	fn volume(mount_point: &str) -> Volume {
+		let mut volume = Volume::new(
+			mount_point.to_string(),
+			MountType::External,
+			PathBuf::from(mount_point),
+			vec![],
+			DiskType::Unknown,
+			FileSystem::EXT4,
+			1000,
+			500,
+			false,
+		);
+		volume.fingerprint = Some(VolumeFingerprint(mount_point.as_bytes().to_vec()));
+		volume
+	}
+
+	#[test]
+	fn only_locations_on_the_ejected_volume_are_paused() {
+		let volumes = [volume("/"), volume("/mnt/disk"), volume("/mnt/disk/nested")];
+		let fingerprint = volumes[1].fingerprint.clone().unwrap();
+
+		let location_ids = locations_on_volume(
+			&volumes,
+			&fingerprint,
+			[
+				(1, Some("/home/user".to_string())),
+				(2, Some("/mnt/disk/photos".to_string())),
+				(3, Some("/mnt/disk/nested/music".to_string())),
+				(4, None),
+				(5, Some("/mnt/disk".to_string())),
+			],
+		);
+
+		assert_eq!(location_ids, HashSet::from([2, 5]));
+	}
+
+	#[test]
+	fn jobs_resume_once_the_volume_is_mounted_again() {
+		let disk = volume("/mnt/disk");
+		let fingerprint = disk.fingerprint.clone().unwrap();
+		let other = volume("/mnt/other");
+
+		assert!(is_volume_back(
+			&VolumeEvent::VolumeAdded(disk.clone()),
+			&fingerprint
+		));
+		assert!(is_volume_back(
+			&VolumeEvent::VolumeMountChanged {
+				fingerprint: fingerprint.clone(),
+				is_mounted: true,
+			},
+			&fingerprint
+		));
+
+		assert!(!is_volume_back(
+			&VolumeEvent::VolumeAdded(other.clone()),
+			&fingerprint
+		));
+		assert!(!is_volume_back(
+			&VolumeEvent::VolumeMountChanged {
+				fingerprint: fingerprint.clone(),
+				is_mounted: false,
+			},
+			&fingerprint
+		));
+		assert!(!is_volume_back(
+			&VolumeEvent::VolumeRemoved(disk),
+			&fingerprint
+		));
+	}
+}
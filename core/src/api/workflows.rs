@@ -0,0 +1,67 @@
+use crate::{
+	invalidate_query,
+	workflow::{self, RunTrigger, WorkflowCreateArgs, WorkflowUpdateArgs},
+};
+
+use sd_prisma::prisma::workflow as workflow_model;
+
+use rspc::alpha::AlphaRouter;
+
+use super::{utils::library, Ctx, R};
+
+pub(crate) fn mount() -> AlphaRouter<Ctx> {
+	R.router()
+		.procedure("list", {
+			R.with2(library()).query(|(_, library), _: ()| async move {
+				workflow::list(&library).await.map_err(Into::into)
+			})
+		})
+		.procedure("get", {
+			R.with2(library())
+				.query(|(_, library), id: workflow_model::id::Type| async move {
+					workflow::find(&library, id).await.map_err(Into::into)
+				})
+		})
+		.procedure("create", {
+			R.with2(library())
+				.mutation(|(_, library), args: WorkflowCreateArgs| async move {
+					let workflow = args.create(&library).await?;
+
+					invalidate_query!(library, "workflows.list");
+
+					Ok(workflow)
+				})
+		})
+		.procedure("update", {
+			R.with2(library())
+				.mutation(|(_, library), args: WorkflowUpdateArgs| async move {
+					let workflow = args.update(&library).await?;
+
+					invalidate_query!(library, "workflows.list");
+					invalidate_query!(library, "workflows.get");
+
+					Ok(workflow)
+				})
+		})
+		.procedure("delete", {
+			R.with2(library())
+				.mutation(|(_, library), id: workflow_model::id::Type| async move {
+					workflow::delete(&library, id).await?;
+
+					invalidate_query!(library, "workflows.list");
+					invalidate_query!(library, "jobs.schedules");
+
+					Ok(())
+				})
+		})
+		.procedure("run", {
+			R.with2(library()).mutation(
+				|(node, library), id: workflow_model::id::Type| async move {
+					node.workflows
+						.run(&node, &library, id, RunTrigger::Manual)
+						.await
+						.map_err(Into::into)
+				},
+			)
+		})
+}
@@ -0,0 +1,298 @@
+use crate::{library::Library, volume::Volume};
+
+use sd_prisma::prisma::{capacity_snapshot, device, location, volume, PrismaClient, SortOrder};
+use sd_utils::db::{size_in_bytes_from_db, size_in_bytes_to_db};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use prisma_client_rust::QueryError;
+use rspc::ErrorCode;
+use serde::Serialize;
+use serde_with::{serde_as, DisplayFromStr};
+use specta::Type;
+use thiserror::Error;
+
+mod monitor;
+
+pub use monitor::CapacityMonitor;
+
+/// Snapshots older than this are deleted
+const SNAPSHOT_RETENTION_DAYS: i64 = 365;
+const SECONDS_PER_WEEK: f64 = 7.0 * 24.0 * 60.0 * 60.0;
+/// Fill dates further away than this aren't worth projecting
+const MAX_PROJECTION_DAYS: f64 = 10.0 * 365.0;
+
+#[derive(Error, Debug)]
+pub enum CapacityError {
+	#[error("volume not found: <id='{0}'>")]
+	VolumeNotFound(volume::id::Type),
+	#[error("location not found: <id='{0}'>")]
+	LocationNotFound(location::id::Type),
+
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error(transparent)]
+	Volume(#[from] crate::volume::VolumeError),
+}
+
+impl From<CapacityError> for rspc::Error {
+	fn from(e: CapacityError) -> Self {
+		match e {
+			CapacityError::VolumeNotFound(_) | CapacityError::LocationNotFound(_) => {
+				Self::with_cause(ErrorCode::NotFound, e.to_string(), e)
+			}
+			CapacityError::Volume(e) => e.into(),
+			_ => Self::with_cause(ErrorCode::InternalServerError, e.to_string(), e),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum CapacityTarget {
+	Volume(volume::id::Type),
+	Location(location::id::Type),
+}
+
+#[serde_as]
+#[derive(Serialize, Type, Debug, Clone)]
+pub struct CapacityPoint {
+	pub date: DateTime<FixedOffset>,
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub used_bytes: u64,
+	/// Only for volumes
+	#[specta(type = Option<String>)]
+	#[serde_as(as = "Option<DisplayFromStr>")]
+	pub capacity_bytes: Option<u64>,
+}
+
+#[serde_as]
+#[derive(Serialize, Type, Debug)]
+pub struct CapacityTrend {
+	/// Oldest first
+	pub points: Vec<CapacityPoint>,
+	/// Bytes gained per week over the points, negative when shrinking, `None` with less than two
+	/// points to compare
+	#[specta(type = Option<String>)]
+	#[serde_as(as = "Option<DisplayFromStr>")]
+	pub growth_per_week: Option<i64>,
+	/// When a volume will be full if it keeps growing like this, `None` for locations and for
+	/// volumes which aren't growing
+	pub projected_full_date: Option<DateTime<Utc>>,
+}
+
+/// Records the space used on the given volumes, which must be tracked by the library, and by the
+/// library's locations on this device. Snapshots past their retention are deleted along.
+pub(crate) async fn take_snapshots(
+	library: &Library,
+	volumes: &[Volume],
+) -> Result<(), QueryError> {
+	let Library { db, sync, .. } = library;
+
+	let date_created: DateTime<FixedOffset> = Utc::now().into();
+
+	let locations = db
+		.location()
+		.find_many(vec![location::device::is(vec![device::pub_id::equals(
+			sync.device_pub_id.to_db(),
+		)])])
+		.select(location::select!({ id size_in_bytes }))
+		.exec()
+		.await?;
+
+	let snapshots = volumes
+		.iter()
+		.filter_map(|volume| {
+			volume.id.map(|volume_id| {
+				capacity_snapshot::create_unchecked(
+					size_in_bytes_to_db(
+						volume
+							.total_bytes_capacity
+							.saturating_sub(volume.total_bytes_available),
+					),
+					date_created,
+					vec![
+						capacity_snapshot::volume_id::set(Some(volume_id)),
+						capacity_snapshot::capacity_bytes::set(Some(size_in_bytes_to_db(
+							volume.total_bytes_capacity,
+						))),
+					],
+				)
+			})
+		})
+		.chain(locations.into_iter().filter_map(|location| {
+			location.size_in_bytes.map(|size_in_bytes| {
+				capacity_snapshot::create_unchecked(
+					size_in_bytes,
+					date_created,
+					vec![capacity_snapshot::location_id::set(Some(location.id))],
+				)
+			})
+		}))
+		.collect::<Vec<_>>();
+
+	let oldest_kept = Utc::now() - chrono::Duration::days(SNAPSHOT_RETENTION_DAYS);
+
+	db._batch((
+		db.capacity_snapshot()
+			.delete_many(vec![capacity_snapshot::date_created::lt(
+				oldest_kept.into(),
+			)]),
+		db.capacity_snapshot().create_many(snapshots),
+	))
+	.await?;
+
+	Ok(())
+}
+
+/// How the space used on a volume or by a location evolved since the given date
+pub async fn trend(
+	db: &PrismaClient,
+	target: CapacityTarget,
+	since: DateTime<Utc>,
+) -> Result<CapacityTrend, CapacityError> {
+	let target_param = match target {
+		CapacityTarget::Volume(volume_id) => {
+			if db
+				.volume()
+				.count(vec![volume::id::equals(volume_id)])
+				.exec()
+				.await? == 0
+			{
+				return Err(CapacityError::VolumeNotFound(volume_id));
+			}
+
+			capacity_snapshot::volume_id::equals(Some(volume_id))
+		}
+		CapacityTarget::Location(location_id) => {
+			if db
+				.location()
+				.count(vec![location::id::equals(location_id)])
+				.exec()
+				.await? == 0
+			{
+				return Err(CapacityError::LocationNotFound(location_id));
+			}
+
+			capacity_snapshot::location_id::equals(Some(location_id))
+		}
+	};
+
+	let points = db
+		.capacity_snapshot()
+		.find_many(vec![
+			target_param,
+			capacity_snapshot::date_created::gte(since.into()),
+		])
+		.order_by(capacity_snapshot::date_created::order(SortOrder::Asc))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|snapshot| CapacityPoint {
+			date: snapshot.date_created,
+			used_bytes: size_in_bytes_from_db(&snapshot.used_bytes),
+			capacity_bytes: snapshot
+				.capacity_bytes
+				.as_deref()
+				.map(size_in_bytes_from_db),
+		})
+		.collect::<Vec<_>>();
+
+	let growth_per_second = growth_per_second(&points);
+
+	Ok(CapacityTrend {
+		growth_per_week: growth_per_second
+			.map(|growth_per_second| (growth_per_second * SECONDS_PER_WEEK).round() as i64),
+		projected_full_date: growth_per_second
+			.and_then(|growth_per_second| projected_full_date(&points, growth_per_second)),
+		points,
+	})
+}
+
+/// Slope of the least squares line through the points, in bytes per second
+fn growth_per_second(points: &[CapacityPoint]) -> Option<f64> {
+	let first_date = points.first()?.date;
+
+	let samples = points
+		.iter()
+		.map(|point| {
+			(
+				(point.date - first_date).num_seconds() as f64,
+				point.used_bytes as f64,
+			)
+		})
+		.collect::<Vec<_>>();
+
+	let count = samples.len() as f64;
+	let mean_x = samples.iter().map(|(x, _)| x).sum::<f64>() / count;
+	let mean_y = samples.iter().map(|(_, y)| y).sum::<f64>() / count;
+
+	let (covariance, variance) =
+		samples
+			.iter()
+			.fold((0.0, 0.0), |(covariance, variance), (x, y)| {
+				(
+					covariance + (x - mean_x) * (y - mean_y),
+					variance + (x - mean_x).powi(2),
+				)
+			});
+
+	// Less than two points, or all of them taken at the same time
+	(variance > 0.0).then(|| covariance / variance)
+}
+
+fn projected_full_date(points: &[CapacityPoint], growth_per_second: f64) -> Option<DateTime<Utc>> {
+	let last = points.last()?;
+	let capacity_bytes = last.capacity_bytes?;
+
+	if growth_per_second <= 0.0 {
+		return None;
+	}
+
+	let seconds_left = capacity_bytes.saturating_sub(last.used_bytes) as f64 / growth_per_second;
+
+	(seconds_left <= MAX_PROJECTION_DAYS * 24.0 * 60.0 * 60.0).then(|| {
+		last.date.with_timezone(&Utc) + chrono::Duration::seconds(seconds_left.round() as i64)
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn point(days: i64, used_bytes: u64, capacity_bytes: Option<u64>) -> CapacityPoint {
+		CapacityPoint {
+			date: (DateTime::<Utc>::UNIX_EPOCH + chrono::Duration::days(days)).into(),
+			used_bytes,
+			capacity_bytes,
+		}
+	}
+
+	#[test]
+	fn growth_needs_two_points_apart() {
+		assert_eq!(growth_per_second(&[]), None);
+		assert_eq!(growth_per_second(&[point(0, 100, None)]), None);
+		assert_eq!(
+			growth_per_second(&[point(0, 100, None), point(0, 200, None)]),
+			None
+		);
+	}
+
+	#[test]
+	fn projects_fill_date_of_growing_volume() {
+		// Growing 1 GB a day, with 10 GB left after the last point
+		let points = [
+			point(0, 80_000_000_000, Some(100_000_000_000)),
+			point(5, 85_000_000_000, Some(100_000_000_000)),
+			point(10, 90_000_000_000, Some(100_000_000_000)),
+		];
+
+		let growth_per_second = growth_per_second(&points).unwrap();
+		assert!((growth_per_second * SECONDS_PER_WEEK - 7_000_000_000.0).abs() < 1.0);
+
+		assert_eq!(
+			projected_full_date(&points, growth_per_second),
+			Some(DateTime::<Utc>::UNIX_EPOCH + chrono::Duration::days(20))
+		);
+		assert_eq!(projected_full_date(&points, -1.0), None);
+	}
+}
@@ -0,0 +1,255 @@
+use crate::{
+	api::notifications::{NotificationData, NotificationKind},
+	invalidate_query,
+	library::{Library, LibraryId},
+	node::config::CapacityAlertPreferences,
+	Node,
+};
+
+use sd_prisma::prisma::volume;
+
+use std::{collections::HashSet, io::ErrorKind, path::Path, pin::pin, sync::Arc, time::Duration};
+
+use chrono::Utc;
+use futures::StreamExt;
+use futures_concurrency::stream::Merge;
+use serde::{Deserialize, Serialize};
+use tokio::{
+	fs, spawn,
+	time::{interval, MissedTickBehavior},
+};
+use tokio_stream::wrappers::IntervalStream;
+use tracing::{debug, error, trace, warn};
+
+use super::{trend, CapacityError, CapacityTarget};
+
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+/// Available space can drop quickly, so it's checked way more often than snapshots are taken
+const LOW_SPACE_CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// Volumes are projected to fill up at their growth over this many days
+const FORECAST_WINDOW_DAYS: i64 = 28;
+
+/// File in the node's data directory keeping the alerts already sent, so they aren't sent again
+/// on every restart
+const ALERTED_FILE_NAME: &str = "capacity_alerts.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+enum Alert {
+	LowSpace,
+	FillingUp,
+}
+
+type Alerted = HashSet<(LibraryId, volume::id::Type, Alert)>;
+
+/// Periodically takes capacity snapshots of volumes and locations, warning when a volume is
+/// running out of space according to the node's alert rules
+pub struct CapacityMonitor;
+
+impl CapacityMonitor {
+	pub fn start(node: Arc<Node>) {
+		spawn(async move {
+			while let Err(e) = spawn(run(Arc::clone(&node))).await {
+				if e.is_panic() {
+					error!(?e, "Capacity monitor panicked;");
+				} else {
+					trace!("Capacity monitor received shutdown signal and will exit...");
+					break;
+				}
+				trace!("Restarting capacity monitor processing task...");
+			}
+
+			debug!("Capacity monitor gracefully shutdown");
+		});
+	}
+}
+
+async fn run(node: Arc<Node>) {
+	enum StreamMessage {
+		TakeSnapshots,
+		CheckLowSpace,
+	}
+
+	let mut snapshot_ticker = interval(SNAPSHOT_INTERVAL);
+	snapshot_ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+	let mut low_space_ticker = interval(LOW_SPACE_CHECK_INTERVAL);
+	low_space_ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+	let mut msg_stream = pin!((
+		IntervalStream::new(snapshot_ticker).map(|_| StreamMessage::TakeSnapshots),
+		IntervalStream::new(low_space_ticker).map(|_| StreamMessage::CheckLowSpace),
+	)
+		.merge());
+
+	// Alerts already sent, which are only sent again once the volume recovered from them
+	let alerted_path = node.data_dir.join(ALERTED_FILE_NAME);
+	let mut alerted = load_alerted(&alerted_path).await;
+
+	while let Some(msg) = msg_stream.next().await {
+		let preferences = node.config.get().await.preferences.capacity_alerts;
+		let take_snapshots = matches!(msg, StreamMessage::TakeSnapshots);
+		let previously_alerted = alerted.clone();
+
+		for library in node.libraries.get_all().await {
+			if let Err(e) =
+				check_library(&node, &library, &preferences, take_snapshots, &mut alerted).await
+			{
+				error!(library_id = %library.id, ?e, "Failed to check volumes capacity;");
+			}
+		}
+
+		if alerted != previously_alerted {
+			save_alerted(&alerted_path, &alerted).await;
+		}
+	}
+}
+
+async fn load_alerted(path: &Path) -> Alerted {
+	match fs::read(path).await {
+		Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+			warn!(
+				?e,
+				"Failed to parse sent capacity alerts, they may be sent again;"
+			);
+			Alerted::new()
+		}),
+		Err(e) if e.kind() == ErrorKind::NotFound => Alerted::new(),
+		Err(e) => {
+			warn!(
+				?e,
+				"Failed to read sent capacity alerts, they may be sent again;"
+			);
+			Alerted::new()
+		}
+	}
+}
+
+async fn save_alerted(path: &Path, alerted: &Alerted) {
+	let result = match serde_json::to_vec(alerted) {
+		Ok(bytes) => fs::write(path, bytes).await.map_err(|e| e.to_string()),
+		Err(e) => Err(e.to_string()),
+	};
+
+	if let Err(e) = result {
+		error!(%e, "Failed to save sent capacity alerts;");
+	}
+}
+
+async fn check_library(
+	node: &Node,
+	library: &Arc<Library>,
+	CapacityAlertPreferences {
+		fill_within_days,
+		min_available_percent,
+	}: &CapacityAlertPreferences,
+	take_snapshots: bool,
+	alerted: &mut Alerted,
+) -> Result<(), CapacityError> {
+	// Only the volumes mounted on this device and tracked by the library
+	let volumes = node
+		.volumes
+		.list_system_volumes(Arc::clone(library))
+		.await?
+		.into_iter()
+		.filter(|volume| volume.is_mounted && volume.id.is_some())
+		.collect::<Vec<_>>();
+
+	if take_snapshots {
+		super::take_snapshots(library, &volumes).await?;
+
+		invalidate_query!(library, "volumes.capacityTrend");
+		invalidate_query!(library, "locations.capacityTrend");
+	}
+
+	for volume in &volumes {
+		let Some(volume_id) = volume.id else {
+			continue;
+		};
+
+		let low_space = min_available_percent.is_some_and(|min_available_percent| {
+			volume.total_bytes_available * 100
+				< volume.total_bytes_capacity * u64::from(min_available_percent)
+		});
+
+		notify_once(
+			node,
+			alerted,
+			(library.id, volume_id, Alert::LowSpace),
+			low_space,
+			|| NotificationData {
+				title: format!("Volume \"{}\" is almost full", volume.name),
+				content: format!(
+					"Only {} of {} are left",
+					format_size(volume.total_bytes_available),
+					format_size(volume.total_bytes_capacity)
+				),
+				kind: NotificationKind::Warning,
+			},
+		)
+		.await;
+
+		// The forecast only changes with new snapshots
+		if !take_snapshots {
+			continue;
+		}
+
+		let Some(fill_within_days) = fill_within_days else {
+			alerted.remove(&(library.id, volume_id, Alert::FillingUp));
+			continue;
+		};
+
+		let forecast = trend(
+			&library.db,
+			CapacityTarget::Volume(volume_id),
+			Utc::now() - chrono::Duration::days(FORECAST_WINDOW_DAYS),
+		)
+		.await?;
+
+		let full_date = forecast.projected_full_date.filter(|full_date| {
+			*full_date <= Utc::now() + chrono::Duration::days(i64::from(*fill_within_days))
+		});
+
+		notify_once(
+			node,
+			alerted,
+			(library.id, volume_id, Alert::FillingUp),
+			full_date.is_some(),
+			|| NotificationData {
+				title: format!("Volume \"{}\" is filling up", volume.name),
+				content: format!(
+					"Growing {} a week, it is projected to be full by {}",
+					format_size(
+						forecast
+							.growth_per_week
+							.map_or(0, |growth_per_week| growth_per_week.unsigned_abs())
+					),
+					full_date.map_or_else(String::new, |full_date| full_date
+						.format("%Y-%m-%d")
+						.to_string())
+				),
+				kind: NotificationKind::Warning,
+			},
+		)
+		.await;
+	}
+
+	Ok(())
+}
+
+async fn notify_once(
+	node: &Node,
+	alerted: &mut Alerted,
+	key: (LibraryId, volume::id::Type, Alert),
+	active: bool,
+	notification: impl FnOnce() -> NotificationData,
+) {
+	if !active {
+		alerted.remove(&key);
+	} else if alerted.insert(key) {
+		node.emit_notification(notification(), None).await;
+	}
+}
+
+fn format_size(bytes: u64) -> String {
+	format!("{:.1} GB", bytes as f64 / 1_000_000_000.0)
+}
@@ -1,7 +1,10 @@
 use crate::{
 	api::{utils::InvalidateOperationEvent, CoreEvent},
 	library::Library,
-	old_p2p::operations::{self, request_file},
+	old_p2p::{
+		browse::{self, BrowseError},
+		operations::{self, request_file},
+	},
 	util::InfallibleResponse,
 	Node,
 };
@@ -261,6 +264,57 @@ pub fn base_router() -> Router<LocalState> {
 				},
 			),
 		)
+		.route(
+			"/remote-thumbnail/:lib_id/:identity/:cas_id",
+			get(
+				|State(state): State<LocalState>,
+				 extract::Path((lib_id, identity, cas_id)): extract::Path<(
+					String,
+					String,
+					String,
+				)>,
+				 request: Request<Body>| async move {
+					let library_id = Uuid::from_str(&lib_id).map_err(bad_request)?;
+					let identity = RemoteIdentity::from_str(&identity).map_err(bad_request)?;
+					let library = state
+						.node
+						.libraries
+						.get_library(&library_id)
+						.await
+						.ok_or_else(|| not_found(()))?;
+
+					// Served from the cache when available, so it works while the remote node is offline
+					let path = browse::thumbnail(
+						&state.node.p2p,
+						&library,
+						state.node.config.data_directory(),
+						identity,
+						cas_id,
+					)
+					.await
+					.map_err(|e| {
+						warn!(%identity, ?e, "Error fetching thumbnail of remote node;");
+						match e {
+							BrowseError::Offline => not_found(()),
+							BrowseError::InvalidCasId(_) => bad_request(()),
+							_ => internal_server_error(()),
+						}
+					})?
+					.ok_or_else(|| not_found(()))?;
+
+					let file = File::open(&path).await.map_err(internal_server_error)?;
+					let metadata = file.metadata().await;
+					serve_file(
+						file,
+						metadata,
+						request.into_parts().0,
+						InfallibleResponse::builder()
+							.header("Content-Type", HeaderValue::from_static("image/webp")),
+					)
+					.await
+				},
+			),
+		)
 		.route(
 			"/file/:lib_id/:loc_id/:path_id",
 			get(
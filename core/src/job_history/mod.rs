@@ -0,0 +1,398 @@
+use crate::{
+	library::{Library, LibraryManagerError},
+	old_job::{JobStatus, OldJobReport},
+};
+
+use sd_core_heavy_lifting::{
+	job_system::report::{ReportError, Status},
+	JobId, JobName, NonCriticalError, NonCriticalErrorSubject, Report,
+};
+
+use sd_prisma::prisma::{file_path, job, location, SortOrder};
+use sd_utils::{from_bytes_to_uuid, uuid_to_bytes};
+
+use std::{
+	collections::HashMap,
+	fmt::Write as _,
+	path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use prisma_client_rust::{or, QueryError};
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use thiserror::Error;
+use uuid::Uuid;
+
+mod pruner;
+
+pub use pruner::{prune, JobHistoryPruner};
+
+/// Default page size for job history and job errors queries
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 500;
+
+#[derive(Error, Debug)]
+pub enum JobHistoryError {
+	#[error("job not found <id='{0}'>")]
+	NotFound(JobId),
+
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error(transparent)]
+	Report(#[from] ReportError),
+	#[error(transparent)]
+	LibraryManager(#[from] LibraryManagerError),
+	#[error("failed to serialize job errors: {0}")]
+	Serialization(#[from] serde_json::Error),
+}
+
+impl From<JobHistoryError> for rspc::Error {
+	fn from(e: JobHistoryError) -> Self {
+		match e {
+			JobHistoryError::NotFound(_) => Self::with_cause(ErrorCode::NotFound, e.to_string(), e),
+			JobHistoryError::Report(e) => e.into(),
+			_ => Self::with_cause(ErrorCode::InternalServerError, e.to_string(), e),
+		}
+	}
+}
+
+/// All filters are optional, and an empty list means any value is accepted
+#[derive(Debug, Clone, Default, Deserialize, Type)]
+pub struct JobHistoryFilter {
+	#[serde(default)]
+	pub names: Vec<JobName>,
+	#[serde(default)]
+	pub statuses: Vec<Status>,
+	#[serde(default)]
+	pub location_id: Option<location::id::Type>,
+	#[serde(default)]
+	pub created_after: Option<DateTime<Utc>>,
+	#[serde(default)]
+	pub created_before: Option<DateTime<Utc>>,
+}
+
+impl JobHistoryFilter {
+	fn into_params(self) -> Vec<job::WhereParam> {
+		// Jobs without a name or status can't be parsed into reports, so they're left out of the
+		// count as well as the page
+		let mut params = vec![job::name::not(None), job::status::not(None)];
+
+		if !self.names.is_empty() {
+			params.push(job::name::in_vec(
+				self.names
+					.into_iter()
+					.flat_map(db_names)
+					.map(str::to_string)
+					.collect(),
+			));
+		}
+
+		if !self.statuses.is_empty() {
+			params.push(job::status::in_vec(
+				self.statuses
+					.into_iter()
+					.map(|status| status as i32)
+					.collect(),
+			));
+		}
+
+		if let Some(location_id) = self.location_id {
+			params.push(job::location_id::equals(Some(location_id)));
+		}
+
+		if let Some(created_after) = self.created_after {
+			params.push(job::date_created::gte(created_after.into()));
+		}
+
+		if let Some(created_before) = self.created_before {
+			params.push(job::date_created::lte(created_before.into()));
+		}
+
+		params
+	}
+}
+
+/// Names that a job can have on database, as jobs from the old job system were stored with
+/// different names
+fn db_names(name: JobName) -> Vec<&'static str> {
+	match name {
+		JobName::Indexer => vec!["indexer"],
+		JobName::FileIdentifier => vec!["file_identifier"],
+		JobName::MediaProcessor => vec!["media_processor"],
+		JobName::ImageConverter => vec!["image_converter"],
+		JobName::Workflow => vec!["workflow"],
+		JobName::Tagger => vec!["tagger"],
+		JobName::Copy => vec!["copy", "file_copier"],
+		JobName::Move => vec!["move", "file_cutter"],
+		JobName::Delete => vec!["delete", "file_deleter"],
+		JobName::Erase => vec!["erase", "file_eraser"],
+		JobName::FileValidator => vec!["file_validator", "object_validator"],
+		JobName::Archive => vec!["location_archiver"],
+		JobName::RestoreArchive => vec!["archive_restorer"],
+	}
+}
+
+/// Finished statuses, the only ones that can be pruned from the history
+pub(crate) fn finished_statuses() -> job::WhereParam {
+	or![
+		job::status::equals(Some(JobStatus::Canceled as i32)),
+		job::status::equals(Some(JobStatus::Failed as i32)),
+		job::status::equals(Some(JobStatus::Completed as i32)),
+		job::status::equals(Some(JobStatus::CompletedWithErrors as i32)),
+	]
+}
+
+pub(crate) fn report_from_db(job: job::Data) -> Option<Report> {
+	Report::try_from(job.clone())
+		.ok()
+		// Reports from the old job system have a different format
+		.or_else(|| OldJobReport::try_from(job).map(Into::into).ok())
+}
+
+/// A job report without its non-critical errors, as there can be thousands of them.
+/// They're fetched page by page with [`errors`]
+#[derive(Debug, Serialize, Type)]
+pub struct JobHistoryItem {
+	pub report: Report,
+	pub non_critical_errors_count: u32,
+}
+
+#[derive(Debug, Serialize, Type)]
+pub struct JobHistoryPage {
+	pub items: Vec<JobHistoryItem>,
+	/// Total amount of jobs matching the filter
+	pub total: u32,
+}
+
+pub async fn history(
+	library: &Library,
+	filter: JobHistoryFilter,
+	skip: u32,
+	take: Option<u32>,
+) -> Result<JobHistoryPage, JobHistoryError> {
+	let take = take.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+	let (total, jobs) = library
+		.db
+		._batch((
+			library.db.job().count(filter.clone().into_params()),
+			library
+				.db
+				.job()
+				.find_many(filter.into_params())
+				.order_by(job::date_created::order(SortOrder::Desc))
+				.skip(i64::from(skip))
+				.take(i64::from(take)),
+		))
+		.await?;
+
+	Ok(JobHistoryPage {
+		items: jobs
+			.into_iter()
+			.filter_map(report_from_db)
+			.map(|mut report| {
+				let non_critical_errors_count =
+					u32::try_from(report.non_critical_errors.len()).unwrap_or(u32::MAX);
+				report.non_critical_errors = vec![];
+
+				JobHistoryItem {
+					report,
+					non_critical_errors_count,
+				}
+			})
+			.collect(),
+		total: u32::try_from(total).unwrap_or(u32::MAX),
+	})
+}
+
+/// A non-critical error with the full path of the affected file, when we can find it
+#[derive(Debug, Serialize, Type)]
+pub struct JobErrorEntry {
+	/// Position of this error in the job report
+	pub index: u32,
+	pub source: &'static str,
+	pub message: String,
+	pub subject: Option<NonCriticalErrorSubject>,
+	pub path: Option<PathBuf>,
+	pub error: NonCriticalError,
+}
+
+#[derive(Debug, Serialize, Type)]
+pub struct JobErrorsPage {
+	pub errors: Vec<JobErrorEntry>,
+	/// Total amount of non-critical errors of the job
+	pub total: u32,
+}
+
+const fn error_source(error: &NonCriticalError) -> &'static str {
+	match error {
+		NonCriticalError::Indexer(_) => "indexer",
+		NonCriticalError::FileIdentifier(_) => "file_identifier",
+		NonCriticalError::MediaProcessor(_) => "media_processor",
+		NonCriticalError::ImageConverter(_) => "image_converter",
+	}
+}
+
+async fn fetch_report(library: &Library, job_id: JobId) -> Result<Report, JobHistoryError> {
+	library
+		.db
+		.job()
+		.find_unique(job::id::equals(uuid_to_bytes(&job_id)))
+		.exec()
+		.await?
+		.and_then(report_from_db)
+		.ok_or(JobHistoryError::NotFound(job_id))
+}
+
+pub async fn errors(
+	library: &Library,
+	job_id: JobId,
+	skip: u32,
+	take: Option<u32>,
+) -> Result<JobErrorsPage, JobHistoryError> {
+	let report = fetch_report(library, job_id).await?;
+	let total = u32::try_from(report.non_critical_errors.len()).unwrap_or(u32::MAX);
+	let take = take.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+	Ok(JobErrorsPage {
+		errors: resolve_errors(
+			library,
+			report
+				.non_critical_errors
+				.into_iter()
+				.enumerate()
+				.skip(skip as usize)
+				.take(take as usize),
+		)
+		.await?,
+		total,
+	})
+}
+
+/// Fills the full path of each error's affected file, errors on files that were deleted since
+/// the job ran don't have a path
+async fn resolve_errors(
+	library: &Library,
+	errors: impl IntoIterator<Item = (usize, NonCriticalError)> + Send,
+) -> Result<Vec<JobErrorEntry>, JobHistoryError> {
+	let errors = errors
+		.into_iter()
+		.map(|(index, error)| (index, error.subject(), error))
+		.collect::<Vec<_>>();
+
+	let mut file_path_ids = Vec::new();
+	let mut file_path_pub_ids = Vec::new();
+
+	for (_, subject, _) in &errors {
+		match subject {
+			Some(NonCriticalErrorSubject::FilePath(id)) => file_path_ids.push(*id),
+			Some(NonCriticalErrorSubject::FilePathPubId(pub_id)) => {
+				file_path_pub_ids.push(uuid_to_bytes(pub_id));
+			}
+			Some(NonCriticalErrorSubject::Path(_)) | None => {}
+		}
+	}
+
+	let ids_by_pub_id = if file_path_pub_ids.is_empty() {
+		HashMap::new()
+	} else {
+		library
+			.db
+			.file_path()
+			.find_many(vec![file_path::pub_id::in_vec(file_path_pub_ids)])
+			.select(file_path::select!({ id pub_id }))
+			.exec()
+			.await?
+			.into_iter()
+			.map(|file_path| (from_bytes_to_uuid(&file_path.pub_id), file_path.id))
+			.collect::<HashMap<Uuid, _>>()
+	};
+
+	file_path_ids.extend(ids_by_pub_id.values().copied());
+
+	let paths_by_id = if file_path_ids.is_empty() {
+		HashMap::new()
+	} else {
+		library.get_file_paths(file_path_ids).await?
+	};
+
+	let path_of_file_path = |id| paths_by_id.get(&id).cloned().flatten();
+
+	Ok(errors
+		.into_iter()
+		.map(|(index, subject, error)| JobErrorEntry {
+			index: u32::try_from(index).unwrap_or(u32::MAX),
+			source: error_source(&error),
+			message: error.to_string(),
+			path: match &subject {
+				Some(NonCriticalErrorSubject::Path(path)) => Some(path.clone()),
+				Some(NonCriticalErrorSubject::FilePath(id)) => path_of_file_path(*id),
+				Some(NonCriticalErrorSubject::FilePathPubId(pub_id)) => ids_by_pub_id
+					.get(pub_id)
+					.and_then(|id| path_of_file_path(*id)),
+				None => None,
+			},
+			subject,
+			error,
+		})
+		.collect())
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Type)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+	Csv,
+	Json,
+}
+
+/// Exports all non-critical errors of a job, returning the file contents
+pub async fn export_errors(
+	library: &Library,
+	job_id: JobId,
+	format: ExportFormat,
+) -> Result<String, JobHistoryError> {
+	let report = fetch_report(library, job_id).await?;
+	let entries =
+		resolve_errors(library, report.non_critical_errors.into_iter().enumerate()).await?;
+
+	match format {
+		ExportFormat::Json => serde_json::to_string_pretty(&entries).map_err(Into::into),
+		ExportFormat::Csv => Ok(to_csv(&entries)),
+	}
+}
+
+fn to_csv(entries: &[JobErrorEntry]) -> String {
+	let mut out = String::from("index,source,path,message\n");
+
+	for entry in entries {
+		// Writing to a String never fails
+		let _ = writeln!(
+			out,
+			"{},{},{},{}",
+			entry.index,
+			entry.source,
+			csv_field(
+				entry
+					.path
+					.as_deref()
+					.map(Path::to_string_lossy)
+					.as_deref()
+					.unwrap_or_default()
+			),
+			csv_field(&entry.message),
+		);
+	}
+
+	out
+}
+
+/// Quotes a CSV field if needed, as paths and messages can contain commas, quotes or line breaks
+fn csv_field(value: &str) -> String {
+	if value.contains([',', '"', '\n', '\r']) {
+		format!("\"{}\"", value.replace('"', "\"\""))
+	} else {
+		value.to_string()
+	}
+}
@@ -0,0 +1,112 @@
+use crate::{invalidate_query, library::Library, node::config::JobHistoryPreferences, Node};
+
+use sd_prisma::prisma::{job, SortOrder};
+
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+use tokio::{
+	spawn,
+	time::{interval, MissedTickBehavior},
+};
+use tracing::{debug, error, trace};
+
+use super::{finished_statuses, JobHistoryError};
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Periodically deletes finished job reports according to the node's retention rules
+pub struct JobHistoryPruner;
+
+impl JobHistoryPruner {
+	pub fn start(node: Arc<Node>) {
+		spawn(async move {
+			while let Err(e) = spawn(run(Arc::clone(&node))).await {
+				if e.is_panic() {
+					error!(?e, "Job history pruner panicked;");
+				} else {
+					trace!("Job history pruner received shutdown signal and will exit...");
+					break;
+				}
+				trace!("Restarting job history pruner processing task...");
+			}
+
+			debug!("Job history pruner gracefully shutdown");
+		});
+	}
+}
+
+async fn run(node: Arc<Node>) {
+	let mut ticker = interval(PRUNE_INTERVAL);
+	ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+	loop {
+		ticker.tick().await;
+
+		let preferences = node.config.get().await.preferences.job_history;
+
+		for library in node.libraries.get_all().await {
+			if let Err(e) = prune(&library, &preferences).await {
+				error!(library_id = %library.id, ?e, "Failed to prune job history;");
+			}
+		}
+	}
+}
+
+/// Deletes finished job reports older than the maximum age and the oldest ones exceeding the
+/// maximum count, returning how many were deleted. Running and paused jobs are never deleted.
+pub async fn prune(
+	library: &Library,
+	JobHistoryPreferences {
+		max_age_days,
+		max_reports,
+	}: &JobHistoryPreferences,
+) -> Result<i64, JobHistoryError> {
+	let mut deleted = 0;
+
+	if let Some(max_age_days) = max_age_days {
+		let oldest_allowed = Utc::now() - chrono::Duration::days(i64::from(*max_age_days));
+
+		deleted += library
+			.db
+			.job()
+			.delete_many(vec![
+				finished_statuses(),
+				job::date_created::lt(oldest_allowed.into()),
+			])
+			.exec()
+			.await?;
+	}
+
+	if let Some(max_reports) = max_reports {
+		let exceeding_ids = library
+			.db
+			.job()
+			.find_many(vec![finished_statuses()])
+			.order_by(job::date_created::order(SortOrder::Desc))
+			.skip(i64::from(*max_reports))
+			.select(job::select!({ id }))
+			.exec()
+			.await?
+			.into_iter()
+			.map(|job| job.id)
+			.collect::<Vec<_>>();
+
+		if !exceeding_ids.is_empty() {
+			deleted += library
+				.db
+				.job()
+				.delete_many(vec![job::id::in_vec(exceeding_ids)])
+				.exec()
+				.await?;
+		}
+	}
+
+	if deleted > 0 {
+		debug!(library_id = %library.id, %deleted, "Pruned job history;");
+		invalidate_query!(library, "jobs.reports");
+		invalidate_query!(library, "jobs.history");
+	}
+
+	Ok(deleted)
+}
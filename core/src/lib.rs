@@ -41,6 +41,8 @@ pub mod api;
 mod context;
 pub mod custom_uri;
 pub mod library;
+pub(crate) mod capacity;
+pub(crate) mod job_history;
 pub(crate) mod location;
 pub(crate) mod node;
 pub(crate) mod notifications;
@@ -48,9 +50,11 @@ pub(crate) mod object;
 pub(crate) mod old_job;
 pub(crate) mod old_p2p;
 pub(crate) mod preferences;
+pub(crate) mod scheduler;
 #[doc(hidden)] // TODO(@Oscar): Make this private when breaking out `utils` into `sd-utils`
 pub mod util;
 pub(crate) mod volume;
+pub(crate) mod workflow;
 
 use api::notifications::{Notification, NotificationData, NotificationId};
 use context::{JobContext, NodeContext};
@@ -76,6 +80,7 @@ pub struct Node {
 	/// Don't use this as a common RNG, it will fuck up Core's performance due to this Mutex.
 	pub master_rng: Arc<Mutex<CryptoRng>>,
 	pub old_jobs: Arc<old_job::OldJobs>,
+	pub workflows: workflow::Workflows,
 }
 
 impl fmt::Debug for Node {
@@ -105,6 +110,7 @@ impl Node {
 
 		let (locations, locations_actor) = location::Locations::new();
 		let (old_jobs, jobs_actor) = old_job::OldJobs::new();
+		let (workflows, workflows_actor) = workflow::Workflows::new();
 		let libraries = library::Libraries::new(data_dir.join("libraries")).await?;
 
 		let (
@@ -188,6 +194,7 @@ impl Node {
 			),
 			master_rng: Arc::new(Mutex::new(CryptoRng::new()?)),
 			old_jobs,
+			workflows,
 		});
 
 		// Setup start actors that depend on the `Node`
@@ -239,6 +246,13 @@ impl Node {
 			)
 			.await?;
 
+		// Must start after the job system, so missed runs are dispatched alongside resumed jobs
+		scheduler::JobScheduler::start(Arc::clone(&node));
+		workflows_actor.start(Arc::clone(&node));
+		node::TaskSystemThrottle::start(Arc::clone(&node));
+		job_history::JobHistoryPruner::start(Arc::clone(&node));
+		capacity::CapacityMonitor::start(Arc::clone(&node));
+
 		start_p2p(
 			node.clone(),
 			axum::Router::new()
@@ -1,13 +1,17 @@
 use crate::{
+	location::WatcherMode,
 	node::config::NodeConfig,
 	util::version_manager::{Kind, ManagedVersion, VersionManager, VersionManagerError},
 };
 
+use sd_core_cloud_services::CloudSyncTransport;
+use sd_core_sync::scope::SyncScope;
 use sd_old_p2p::{Identity, RemoteIdentity};
 use sd_prisma::prisma::{file_path, indexer_rule, instance, location, PrismaClient};
 use sd_utils::{db::maybe_missing, error::FileIOError};
 
 use std::{
+	collections::HashMap,
 	path::{Path, PathBuf},
 	sync::{atomic::AtomicBool, Arc},
 };
@@ -42,6 +46,15 @@ pub struct LibraryConfig {
 	// true = sync is enabled as either the library is new or it has been manually toggled on
 	#[serde(default)]
 	pub generate_sync_operations: Arc<AtomicBool>,
+	/// sync_transport is the backend used to exchange sync messages with other devices of the sync group.
+	#[serde(default)]
+	pub sync_transport: CloudSyncTransport,
+	/// sync_scope limits which records this device ingests from other devices of the library.
+	#[serde(default)]
+	pub sync_scope: SyncScope,
+	/// location_watchers overrides how changes are noticed for locations, by their pub_id.
+	#[serde(default)]
+	pub location_watchers: HashMap<Uuid, WatcherMode>,
 	version: LibraryConfigVersion,
 
 	#[serde(skip, default)]
@@ -101,6 +114,9 @@ impl LibraryConfig {
 			version: Self::LATEST_VERSION,
 			cloud_id: None,
 			generate_sync_operations: Arc::new(AtomicBool::new(false)),
+			sync_transport: CloudSyncTransport::default(),
+			sync_scope: SyncScope::default(),
+			location_watchers: HashMap::new(),
 			config_path: path.as_ref().to_path_buf(),
 			cloud_email_address: None,
 		};
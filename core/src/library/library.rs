@@ -118,6 +118,7 @@ impl Library {
 			sync_group_pub_id,
 			self.sync.clone(),
 			rng,
+			self.config().await.sync_transport,
 		)
 		.await?;
 
@@ -563,6 +563,7 @@ impl Libraries {
 			Arc::clone(&db),
 			&device_pub_id,
 			Arc::clone(&config.generate_sync_operations),
+			config.sync_scope.clone(),
 			&devices,
 		)
 		.await?;
@@ -641,9 +642,7 @@ async fn sync_rx_actor(
 			SyncEvent::Ingested => node.emit(CoreEvent::InvalidateOperation(
 				InvalidateOperationEvent::all(),
 			)),
-			SyncEvent::Created => {
-				old_p2p::sync::originator(library.clone(), &library.sync, &node.p2p).await
-			}
+			SyncEvent::Created => old_p2p::sync::originator(&library, &node.p2p),
 		}
 	}
 }
@@ -678,6 +677,10 @@ async fn special_sync_indexes(db: &PrismaClient) -> Result<(), LibraryManagerErr
 		(prisma_sync::location::MODEL_ID, prisma::location::NAME),
 		(prisma_sync::object::MODEL_ID, prisma::object::NAME),
 		(prisma_sync::label::MODEL_ID, prisma::label::NAME),
+		(
+			prisma_sync::saved_search::MODEL_ID,
+			prisma::saved_search::NAME,
+		),
 		(prisma_sync::exif_data::MODEL_ID, prisma::exif_data::NAME),
 		(prisma_sync::file_path::MODEL_ID, prisma::file_path::NAME),
 		(
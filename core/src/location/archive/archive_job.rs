@@ -0,0 +1,845 @@
+use crate::{
+	invalidate_query,
+	library::Library,
+	location::{get_location_path_from_location_id, LocationError},
+	old_job::{
+		CurrentStep, JobError, JobInitOutput, JobResult, JobRunErrors, JobRunMetadata,
+		JobStepOutput, StatefulJob, WorkerContext,
+	},
+};
+
+use sd_core_file_path_helper::{
+	ensure_file_path_exists, ensure_sub_path_is_directory, ensure_sub_path_is_in_location,
+	filter_existing_file_path_params, join_location_relative_path, IsolatedFilePathData,
+};
+
+use sd_crypto::{cloud::SecretKey, CryptoRng};
+use sd_prisma::prisma::{archive, archived_file_path, file_path, location, SortOrder};
+use sd_utils::{
+	db::{maybe_missing, size_in_bytes_from_db},
+	error::FileIOError,
+	uuid_to_bytes,
+};
+
+use std::{
+	fs::Metadata,
+	io::{self, BufWriter},
+	path::{Path, PathBuf},
+	time::SystemTime,
+};
+
+use chrono::Utc;
+use flate2::{write::GzEncoder, Compression};
+use prisma_client_rust::{operator::and, or};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use specta::Type;
+use tokio::{fs, task::spawn_blocking};
+use tracing::{debug, trace};
+use uuid::Uuid;
+
+use super::{
+	encrypt_part, not_archived, part_file_name, set_location_archived, sync_directory,
+	temp_part_path, write_manifest, ArchiveError, ARCHIVE_EXTENSION, PART_MAX_BYTES,
+	PART_MAX_ENTRIES,
+};
+
+/// Where the archive directory is created
+#[derive(Serialize, Deserialize, Hash, Type, Debug, Clone)]
+#[serde(rename_all = "snake_case", tag = "type", content = "value")]
+pub enum ArchiveDestination {
+	/// A directory of another location, relative to its root
+	Location {
+		location_id: location::id::Type,
+		sub_path: Option<PathBuf>,
+	},
+	/// Any absolute directory, like the mount point of an external volume
+	Path(PathBuf),
+}
+
+#[derive(Serialize, Deserialize, Hash, Type, Debug)]
+pub struct OldLocationArchiverJobInit {
+	pub location_id: location::id::Type,
+	/// Directory to archive, relative to the location root, the whole location when missing
+	pub sub_path: Option<PathBuf>,
+	pub destination: ArchiveDestination,
+	pub encrypt: bool,
+	/// Remove the archived files from the location once every part was written, their file paths
+	/// are kept in the library. Files which changed since they were archived are kept.
+	pub remove_originals: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OldLocationArchiverJobData {
+	location_path: PathBuf,
+	archive_id: archive::id::Type,
+	archive_path: PathBuf,
+	key: Option<SecretKey>,
+	/// Archived directories, removed along the originals of the last part once they are empty
+	directories: Vec<ArchivedDirectory>,
+	last_part: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ArchiveEntry {
+	file_path_id: file_path::id::Type,
+	is_dir: bool,
+	/// Path relative to the location root, which is also its path inside the archive
+	entry: String,
+	size: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ArchivedDirectory {
+	file_path_id: file_path::id::Type,
+	part: i32,
+	entry: String,
+}
+
+/// The state of an original file when it was written to a part
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OriginalState {
+	size: u64,
+	modified: Option<SystemTime>,
+}
+
+impl From<&Metadata> for OriginalState {
+	fn from(metadata: &Metadata) -> Self {
+		Self {
+			size: metadata.len(),
+			modified: metadata.modified().ok(),
+		}
+	}
+}
+
+impl OriginalState {
+	/// Files without a modification time are never considered unchanged
+	fn is_unchanged(&self, metadata: &Metadata) -> bool {
+		self.modified.is_some() && *self == Self::from(metadata)
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ArchivedOriginal {
+	file_path_id: file_path::id::Type,
+	entry: String,
+	state: OriginalState,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum ArchiverStep {
+	Part {
+		part: i32,
+		entries: Vec<ArchiveEntry>,
+	},
+	/// Queued by each part once it was written, so originals are only removed after every part
+	RemoveOriginals {
+		part: i32,
+		originals: Vec<ArchivedOriginal>,
+	},
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct LocationArchiverJobRunMetadata {
+	archived_files: u64,
+	archived_bytes: u64,
+	removed_files: u64,
+}
+
+impl JobRunMetadata for LocationArchiverJobRunMetadata {
+	fn update(&mut self, new_data: Self) {
+		self.archived_files += new_data.archived_files;
+		self.archived_bytes += new_data.archived_bytes;
+		self.removed_files += new_data.removed_files;
+	}
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for OldLocationArchiverJobInit {
+	type Data = OldLocationArchiverJobData;
+	type Step = ArchiverStep;
+	type RunMetadata = LocationArchiverJobRunMetadata;
+
+	const NAME: &'static str = "location_archiver";
+
+	fn target_location(&self) -> location::id::Type {
+		self.location_id
+	}
+
+	async fn init(
+		&self,
+		ctx: &WorkerContext,
+		data: &mut Option<Self::Data>,
+	) -> Result<JobInitOutput<Self::RunMetadata, Self::Step>, JobError> {
+		let init = self;
+		let Library { db, .. } = &*ctx.library;
+
+		let location = db
+			.location()
+			.find_unique(location::id::equals(init.location_id))
+			.select(location::select!({ name path }))
+			.exec()
+			.await?
+			.ok_or(LocationError::IdNotFound(init.location_id))?;
+
+		let location_path = maybe_missing(location.path, "location.path").map(PathBuf::from)?;
+
+		let (source_path, maybe_sub_iso_file_path) = match &init.sub_path {
+			Some(sub_path) if sub_path != Path::new("") => {
+				let full_path = ensure_sub_path_is_in_location(&location_path, sub_path)
+					.await
+					.map_err(ArchiveError::from)?;
+				ensure_sub_path_is_directory(&location_path, sub_path)
+					.await
+					.map_err(ArchiveError::from)?;
+
+				let sub_iso_file_path =
+					IsolatedFilePathData::new(init.location_id, &location_path, &full_path, true)
+						.map_err(ArchiveError::from)?;
+
+				ensure_file_path_exists(
+					sub_path,
+					&sub_iso_file_path,
+					db,
+					ArchiveError::SubPathNotFound,
+				)
+				.await?;
+
+				(full_path, Some(sub_iso_file_path))
+			}
+			_ => (location_path.clone(), None),
+		};
+
+		let destination_path = match &init.destination {
+			ArchiveDestination::Location {
+				location_id,
+				sub_path,
+			} => {
+				let path = get_location_path_from_location_id(db, *location_id).await?;
+				match sub_path {
+					Some(sub_path) => join_location_relative_path(path, sub_path),
+					None => path,
+				}
+			}
+			ArchiveDestination::Path(path) => path.clone(),
+		};
+
+		if destination_path.starts_with(&source_path) {
+			return Err(ArchiveError::DestinationInsideSource(destination_path.into()).into());
+		}
+
+		let file_paths = db
+			.file_path()
+			.find_many(sd_utils::chain_optional_iter(
+				[
+					file_path::location_id::equals(Some(init.location_id)),
+					not_archived(),
+				],
+				[maybe_sub_iso_file_path
+					.as_ref()
+					.and_then(|sub_iso_file_path| {
+						sub_iso_file_path.materialized_path_for_children().map(
+							|materialized_path| {
+								or![
+									and(filter_existing_file_path_params(sub_iso_file_path)),
+									file_path::materialized_path::starts_with(materialized_path),
+								]
+							},
+						)
+					})],
+			))
+			.order_by(file_path::materialized_path::order(SortOrder::Asc))
+			.exec()
+			.await?;
+
+		if file_paths.is_empty() {
+			return Err(ArchiveError::NothingToArchive(init.location_id).into());
+		}
+
+		let mut steps = Vec::new();
+		let mut entries = Vec::new();
+		let mut directories = Vec::new();
+		let mut part_size = 0;
+
+		for file_path in &file_paths {
+			let is_dir = maybe_missing(file_path.is_dir, "file_path.is_dir")?;
+			let size = if is_dir {
+				0
+			} else {
+				file_path
+					.size_in_bytes_bytes
+					.as_deref()
+					.map(size_in_bytes_from_db)
+					.unwrap_or_default()
+			};
+
+			if !entries.is_empty()
+				&& (part_size + size > PART_MAX_BYTES || entries.len() == PART_MAX_ENTRIES)
+			{
+				steps.push(ArchiverStep::Part {
+					part: steps.len() as i32,
+					entries: std::mem::take(&mut entries),
+				});
+				part_size = 0;
+			}
+
+			let entry = IsolatedFilePathData::try_from(file_path)?.to_string();
+
+			if is_dir && init.remove_originals {
+				directories.push(ArchivedDirectory {
+					file_path_id: file_path.id,
+					part: steps.len() as i32,
+					entry: entry.clone(),
+				});
+			}
+
+			part_size += size;
+			entries.push(ArchiveEntry {
+				file_path_id: file_path.id,
+				is_dir,
+				entry,
+				size,
+			});
+		}
+
+		steps.push(ArchiverStep::Part {
+			part: steps.len() as i32,
+			entries,
+		});
+
+		let pub_id = Uuid::new_v4();
+		let archive_path = destination_path.join(format!("{pub_id}.{ARCHIVE_EXTENSION}"));
+
+		fs::create_dir_all(&archive_path).await.map_err(|e| {
+			FileIOError::from((&archive_path, e, "Failed to create archive directory"))
+		})?;
+
+		let key = if init.encrypt {
+			Some(SecretKey::generate(
+				&mut CryptoRng::new().map_err(ArchiveError::from)?,
+			))
+		} else {
+			None
+		};
+
+		let materialized_path = maybe_sub_iso_file_path
+			.as_ref()
+			.and_then(IsolatedFilePathData::materialized_path_for_children)
+			.unwrap_or_else(|| "/".to_string());
+
+		let name = match (&location.name, &init.sub_path) {
+			(Some(location_name), Some(sub_path)) if sub_path != Path::new("") => {
+				Some(format!("{location_name}/{}", sub_path.display()))
+			}
+			(location_name, _) => location_name.clone(),
+		};
+
+		let archive = db
+			.archive()
+			.create(
+				uuid_to_bytes(&pub_id),
+				archive_path.to_string_lossy().to_string(),
+				materialized_path,
+				vec![
+					archive::name::set(name),
+					archive::key::set(key.as_ref().map(Vec::from)),
+					archive::location::connect(location::id::equals(init.location_id)),
+					archive::date_created::set(Some(Utc::now().into())),
+				],
+			)
+			.select(archive::select!({ id }))
+			.exec()
+			.await?;
+
+		debug!(
+			archive_id = archive.id,
+			path = %archive_path.display(),
+			steps = steps.len(),
+			"Archiving location;",
+		);
+
+		*data = Some(OldLocationArchiverJobData {
+			location_path,
+			archive_id: archive.id,
+			archive_path,
+			key,
+			directories,
+			last_part: steps.len() as i32 - 1,
+		});
+
+		Ok(steps.into())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: &WorkerContext,
+		CurrentStep { step, .. }: CurrentStep<'_, Self::Step>,
+		data: &Self::Data,
+		_: &Self::RunMetadata,
+	) -> Result<JobStepOutput<Self::Step, Self::RunMetadata>, JobError> {
+		let Library { db, .. } = &*ctx.library;
+
+		match step {
+			ArchiverStep::Part { part, entries } => {
+				let part = *part;
+				let temp_path = temp_part_path(&data.archive_path, part);
+				let part_path = data
+					.archive_path
+					.join(part_file_name(part, data.key.is_some()));
+
+				let (written, errors) = spawn_blocking({
+					let location_path = data.location_path.clone();
+					let entries = entries
+						.iter()
+						.map(|entry| (entry.entry.clone(), entry.is_dir))
+						.collect::<Vec<_>>();
+					let temp_path = temp_path.clone();
+
+					move || write_part(&location_path, &entries, &temp_path)
+				})
+				.await??;
+
+				if let Some(key) = &data.key {
+					encrypt_part(key, &temp_path, &part_path).await?;
+					fs::remove_file(&temp_path).await.map_err(|e| {
+						FileIOError::from((&temp_path, e, "Failed to remove unencrypted part"))
+					})?;
+				} else {
+					fs::rename(&temp_path, &part_path).await.map_err(|e| {
+						FileIOError::from((&part_path, e, "Failed to move archive part in place"))
+					})?;
+				}
+
+				trace!(part, entries = written.len(), "Wrote archive part;");
+
+				let run_metadata = LocationArchiverJobRunMetadata {
+					archived_files: written
+						.iter()
+						.filter(|(idx, _)| !entries[*idx].is_dir)
+						.count() as u64,
+					archived_bytes: written.iter().map(|(idx, _)| entries[*idx].size).sum(),
+					removed_files: 0,
+				};
+
+				let more_steps = if self.remove_originals {
+					vec![ArchiverStep::RemoveOriginals {
+						part,
+						originals: written
+							.into_iter()
+							.filter_map(|(idx, state)| {
+								state.map(|state| ArchivedOriginal {
+									file_path_id: entries[idx].file_path_id,
+									entry: entries[idx].entry.clone(),
+									state,
+								})
+							})
+							.collect(),
+					}]
+				} else {
+					vec![]
+				};
+
+				Ok((more_steps, run_metadata, JobRunErrors(errors)).into())
+			}
+
+			ArchiverStep::RemoveOriginals { part, originals } => {
+				// Parts were synced when written, their directory entries must be too before
+				// the only other copy is gone
+				sync_directory(&data.archive_path).await?;
+
+				let (removed, errors) =
+					remove_unchanged_originals(&data.location_path, originals).await;
+
+				let mut archived = removed
+					.iter()
+					.map(|&idx| {
+						let original = &originals[idx];
+						archived_file_path::create_unchecked(
+							data.archive_id,
+							original.file_path_id,
+							*part,
+							original.entry.clone(),
+							vec![],
+						)
+					})
+					.collect::<Vec<_>>();
+
+				// Directories can only be removed once the files of every part are gone
+				if *part == data.last_part {
+					let removed =
+						remove_empty_directories(&data.location_path, &data.directories).await;
+
+					archived.extend(removed.into_iter().map(|idx| {
+						let directory = &data.directories[idx];
+						archived_file_path::create_unchecked(
+							data.archive_id,
+							directory.file_path_id,
+							directory.part,
+							directory.entry.clone(),
+							vec![],
+						)
+					}));
+				}
+
+				// Only the removed originals are left in the archive alone
+				db.archived_file_path()
+					.create_many(archived)
+					.skip_duplicates()
+					.exec()
+					.await?;
+
+				Ok((
+					LocationArchiverJobRunMetadata {
+						removed_files: removed.len() as u64,
+						..Default::default()
+					},
+					JobRunErrors(errors),
+				)
+					.into())
+			}
+		}
+	}
+
+	async fn finalize(
+		&self,
+		ctx: &WorkerContext,
+		data: &Option<Self::Data>,
+		run_metadata: &Self::RunMetadata,
+	) -> JobResult {
+		let init = self;
+		let data = data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		write_manifest(&ctx.library.db, data.archive_id).await?;
+
+		if init.remove_originals
+			&& init
+				.sub_path
+				.as_ref()
+				.map_or(true, |sub_path| sub_path == Path::new(""))
+		{
+			set_location_archived(&ctx.library, init.location_id, true).await?;
+			invalidate_query!(ctx.library, "locations.list");
+		}
+
+		invalidate_query!(ctx.library, "locations.archives");
+		invalidate_query!(ctx.library, "search.paths");
+
+		Ok(Some(json!({
+			"init": init,
+			"archive_id": data.archive_id,
+			"archived_files": run_metadata.archived_files,
+			"archived_bytes": run_metadata.archived_bytes,
+			"removed_files": run_metadata.removed_files,
+		})))
+	}
+}
+
+/// Writes the entries into a tar.gz part, returning the indexes of the written entries along the
+/// state of the written files.
+///
+/// Entries which can't be opened anymore are skipped with an error, but failing in the middle of
+/// an entry aborts the whole part, as the archive would be left corrupted. The part is synced to
+/// disk before returning.
+fn write_part(
+	location_path: &Path,
+	entries: &[(String, bool)],
+	target: &Path,
+) -> Result<(Vec<(usize, Option<OriginalState>)>, Vec<String>), FileIOError> {
+	let file = std::fs::File::create(target)
+		.map_err(|e| FileIOError::from((target, e, "Failed to create archive part")))?;
+
+	let mut builder =
+		tar::Builder::new(GzEncoder::new(BufWriter::new(file), Compression::default()));
+	builder.follow_symlinks(false);
+
+	let mut written = Vec::with_capacity(entries.len());
+	let mut errors = vec![];
+
+	for (idx, (entry, is_dir)) in entries.iter().enumerate() {
+		let full_path = join_location_relative_path(location_path, entry);
+
+		if *is_dir {
+			if let Err(e) = std::fs::metadata(&full_path) {
+				errors.push(FileIOError::from((&full_path, e)).to_string());
+				continue;
+			}
+
+			builder.append_dir(entry, &full_path).map_err(|e| {
+				FileIOError::from((target, e, "Failed to append directory to archive part"))
+			})?;
+
+			written.push((idx, None));
+		} else {
+			let mut file = match std::fs::File::open(&full_path) {
+				Ok(file) => file,
+				Err(e) => {
+					errors.push(
+						FileIOError::from((&full_path, e, "Failed to open file to be archived"))
+							.to_string(),
+					);
+					continue;
+				}
+			};
+
+			let state = match file.metadata() {
+				Ok(metadata) => OriginalState::from(&metadata),
+				Err(e) => {
+					errors.push(FileIOError::from((&full_path, e)).to_string());
+					continue;
+				}
+			};
+
+			builder.append_file(entry, &mut file).map_err(|e| {
+				FileIOError::from((target, e, "Failed to append file to archive part"))
+			})?;
+
+			written.push((idx, Some(state)));
+		}
+	}
+
+	builder
+		.into_inner()
+		.and_then(GzEncoder::finish)
+		.and_then(|writer| writer.into_inner().map_err(io::IntoInnerError::into_error))
+		.and_then(|file| file.sync_all())
+		.map_err(|e| FileIOError::from((target, e, "Failed to finish archive part")))?;
+
+	Ok((written, errors))
+}
+
+/// Removes the originals which didn't change since they were archived, returning the indexes of
+/// the removed ones.
+///
+/// Originals which changed are kept and reported as errors, as the archive doesn't hold their
+/// latest data. Originals which are already gone aren't reported as removed, as they can't be
+/// told apart from files deleted before being archived.
+async fn remove_unchanged_originals(
+	location_path: &Path,
+	originals: &[ArchivedOriginal],
+) -> (Vec<usize>, Vec<String>) {
+	let mut removed = vec![];
+	let mut errors = vec![];
+
+	for (idx, original) in originals.iter().enumerate() {
+		let full_path = join_location_relative_path(location_path, &original.entry);
+
+		match fs::symlink_metadata(&full_path).await {
+			Ok(metadata) if original.state.is_unchanged(&metadata) => {
+				match fs::remove_file(&full_path).await {
+					Ok(()) => removed.push(idx),
+					Err(e) => errors.push(
+						FileIOError::from((&full_path, e, "Failed to remove archived file"))
+							.to_string(),
+					),
+				}
+			}
+			Ok(_) => errors.push(format!(
+				"file changed since it was archived, it wasn't removed: <path='{}'>",
+				full_path.display()
+			)),
+			Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+			Err(e) => errors.push(FileIOError::from((&full_path, e)).to_string()),
+		}
+	}
+
+	(removed, errors)
+}
+
+/// Removes the archived directories which ended up empty, deepest first, returning the indexes of
+/// the removed ones. Directories still holding files which weren't archived or were kept stay.
+async fn remove_empty_directories(
+	location_path: &Path,
+	directories: &[ArchivedDirectory],
+) -> Vec<usize> {
+	let mut order = (0..directories.len()).collect::<Vec<_>>();
+	order.sort_by_key(|&idx| {
+		std::cmp::Reverse(Path::new(&directories[idx].entry).components().count())
+	});
+
+	let mut removed = vec![];
+
+	for idx in order {
+		let full_path = join_location_relative_path(location_path, &directories[idx].entry);
+
+		match fs::remove_dir(&full_path).await {
+			Ok(()) => removed.push(idx),
+			Err(e) => trace!(
+				path = %full_path.display(),
+				?e,
+				"Keeping archived directory which isn't empty;",
+			),
+		}
+	}
+
+	removed
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{super::restore_job::unpack_part, *};
+
+	use std::collections::HashSet;
+
+	use tempfile::tempdir;
+
+	fn create_files(location_path: &Path) {
+		std::fs::create_dir_all(location_path.join("dir/sub")).unwrap();
+		std::fs::write(location_path.join("dir/a.txt"), b"first file").unwrap();
+		std::fs::write(location_path.join("dir/sub/b.txt"), b"second file").unwrap();
+		std::fs::write(location_path.join("c.txt"), b"third file").unwrap();
+	}
+
+	fn entries() -> Vec<(String, bool)> {
+		[
+			("dir", true),
+			("dir/a.txt", false),
+			("dir/sub", true),
+			("dir/sub/b.txt", false),
+			("c.txt", false),
+		]
+		.into_iter()
+		.map(|(entry, is_dir)| (entry.to_string(), is_dir))
+		.collect()
+	}
+
+	fn originals(
+		entries: &[(String, bool)],
+		written: Vec<(usize, Option<OriginalState>)>,
+	) -> Vec<ArchivedOriginal> {
+		written
+			.into_iter()
+			.filter_map(|(idx, state)| {
+				state.map(|state| ArchivedOriginal {
+					file_path_id: idx as file_path::id::Type,
+					entry: entries[idx].0.clone(),
+					state,
+				})
+			})
+			.collect()
+	}
+
+	fn directories(entries: &[(String, bool)]) -> Vec<ArchivedDirectory> {
+		entries
+			.iter()
+			.enumerate()
+			.filter(|(_, (_, is_dir))| *is_dir)
+			.map(|(idx, (entry, _))| ArchivedDirectory {
+				file_path_id: idx as file_path::id::Type,
+				part: 0,
+				entry: entry.clone(),
+			})
+			.collect()
+	}
+
+	#[tokio::test]
+	async fn archive_and_restore_round_trip() {
+		let location = tempdir().unwrap();
+		let archive = tempdir().unwrap();
+		create_files(location.path());
+
+		let entries = entries();
+		let part_path = archive.path().join(part_file_name(0, false));
+
+		let (written, errors) = write_part(location.path(), &entries, &part_path).unwrap();
+		assert!(errors.is_empty());
+		assert_eq!(written.len(), entries.len());
+
+		let originals = originals(&entries, written);
+		let (removed, errors) = remove_unchanged_originals(location.path(), &originals).await;
+		assert!(errors.is_empty());
+		assert_eq!(removed.len(), 3);
+
+		let directories = directories(&entries);
+		let removed = remove_empty_directories(location.path(), &directories).await;
+		assert_eq!(removed.len(), 2);
+		assert!(!location.path().join("dir").exists());
+
+		let wanted = entries
+			.iter()
+			.map(|(entry, _)| entry.clone())
+			.collect::<HashSet<_>>();
+		let (restored, errors) = unpack_part(&part_path, location.path(), &wanted).unwrap();
+		assert!(errors.is_empty());
+		assert_eq!(restored.into_iter().collect::<HashSet<_>>(), wanted);
+
+		assert_eq!(
+			std::fs::read(location.path().join("dir/a.txt")).unwrap(),
+			b"first file"
+		);
+		assert_eq!(
+			std::fs::read(location.path().join("dir/sub/b.txt")).unwrap(),
+			b"second file"
+		);
+		assert_eq!(
+			std::fs::read(location.path().join("c.txt")).unwrap(),
+			b"third file"
+		);
+	}
+
+	#[tokio::test]
+	async fn encrypted_part_round_trip() {
+		let location = tempdir().unwrap();
+		let archive = tempdir().unwrap();
+		create_files(location.path());
+
+		let key = SecretKey::generate(&mut CryptoRng::new().unwrap());
+		let temp_path = temp_part_path(archive.path(), 0);
+		let part_path = archive.path().join(part_file_name(0, true));
+
+		write_part(location.path(), &entries(), &temp_path).unwrap();
+		encrypt_part(&key, &temp_path, &part_path).await.unwrap();
+		std::fs::remove_file(&temp_path).unwrap();
+
+		super::super::decrypt_part(&key, &part_path, &temp_path)
+			.await
+			.unwrap();
+
+		let restored_location = tempdir().unwrap();
+		let wanted = HashSet::from(["c.txt".to_string()]);
+		let (restored, errors) =
+			unpack_part(&temp_path, restored_location.path(), &wanted).unwrap();
+
+		assert!(errors.is_empty());
+		assert_eq!(restored, vec!["c.txt".to_string()]);
+		assert_eq!(
+			std::fs::read(restored_location.path().join("c.txt")).unwrap(),
+			b"third file"
+		);
+	}
+
+	#[tokio::test]
+	async fn modified_originals_are_not_removed() {
+		let location = tempdir().unwrap();
+		let archive = tempdir().unwrap();
+		create_files(location.path());
+
+		let entries = entries();
+		let part_path = archive.path().join(part_file_name(0, false));
+		let (written, _) = write_part(location.path(), &entries, &part_path).unwrap();
+
+		std::fs::write(location.path().join("dir/a.txt"), b"first file, edited").unwrap();
+
+		let originals = originals(&entries, written);
+		let (removed, errors) = remove_unchanged_originals(location.path(), &originals).await;
+
+		assert_eq!(errors.len(), 1);
+		assert_eq!(
+			removed
+				.into_iter()
+				.map(|idx| originals[idx].entry.as_str())
+				.collect::<Vec<_>>(),
+			["dir/sub/b.txt", "c.txt"]
+		);
+		assert_eq!(
+			std::fs::read(location.path().join("dir/a.txt")).unwrap(),
+			b"first file, edited"
+		);
+
+		// The directory holding the kept file stays, its emptied sub directory doesn't
+		let removed = remove_empty_directories(location.path(), &directories(&entries)).await;
+		assert_eq!(removed, vec![1]);
+		assert!(location.path().join("dir").exists());
+	}
+}
@@ -0,0 +1,349 @@
+use crate::{library::Library, location::LocationError};
+
+use sd_core_file_path_helper::FilePathError;
+
+use sd_crypto::{
+	cloud::{decrypt::StreamDecryption, encrypt::StreamEncryption, SecretKey},
+	primitives::StreamNonce,
+	CryptoRng,
+};
+use sd_prisma::{
+	prisma::{archive, archived_file_path, file_path, location, PrismaClient},
+	prisma_sync,
+};
+use sd_sync::{sync_db_entry, OperationFactory};
+use sd_utils::{db::MissingFieldError, error::FileIOError, from_bytes_to_uuid};
+
+use std::{
+	path::{Path, PathBuf},
+	pin::pin,
+};
+
+use futures::StreamExt;
+use prisma_client_rust::QueryError;
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::{
+	fs,
+	io::{AsyncReadExt, AsyncWriteExt},
+};
+use uuid::Uuid;
+
+mod archive_job;
+mod restore_job;
+
+pub use archive_job::{ArchiveDestination, OldLocationArchiverJobInit};
+pub use restore_job::OldArchiveRestorerJobInit;
+
+/// Extension of the directories archives are written to
+const ARCHIVE_EXTENSION: &str = "sdarchive";
+const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// A part is closed once it holds this many bytes or entries. Each part is written by a single
+/// job step, and restoring a file only has to read the part it is in.
+const PART_MAX_BYTES: u64 = 512 * 1024 * 1024;
+const PART_MAX_ENTRIES: usize = 1000;
+
+#[derive(Error, Debug)]
+pub enum ArchiveError {
+	#[error("archive not found: <id='{0}'>")]
+	NotFound(archive::id::Type),
+	#[error("sub path not found: <path='{}'>", .0.display())]
+	SubPathNotFound(Box<Path>),
+	#[error("nothing left to archive in location <id='{0}'>")]
+	NothingToArchive(location::id::Type),
+	#[error("archive destination is inside the archived directory: <path='{}'>", .0.display())]
+	DestinationInsideSource(Box<Path>),
+	#[error("archive still holds the only copy of {0} file paths, restore them first")]
+	StillArchived(i64),
+	#[error("archive key is malformed")]
+	MalformedKey,
+	#[error("archive part {0} is missing from the archive")]
+	MissingPart(i32),
+
+	// Internal errors
+	#[error("failed to encrypt or decrypt archive part: {0}")]
+	Crypto(#[from] sd_crypto::Error),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error(transparent)]
+	Sync(#[from] sd_core_sync::Error),
+	#[error(transparent)]
+	Location(#[from] LocationError),
+	#[error(transparent)]
+	FilePath(#[from] FilePathError),
+	#[error(transparent)]
+	MissingField(#[from] MissingFieldError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+	#[error("failed to write archive manifest: {0}")]
+	Manifest(#[from] serde_json::Error),
+}
+
+impl From<ArchiveError> for rspc::Error {
+	fn from(e: ArchiveError) -> Self {
+		match e {
+			ArchiveError::NotFound(_) | ArchiveError::SubPathNotFound(_) => {
+				Self::with_cause(ErrorCode::NotFound, e.to_string(), e)
+			}
+			ArchiveError::NothingToArchive(_)
+			| ArchiveError::DestinationInsideSource(_)
+			| ArchiveError::StillArchived(_) => Self::with_cause(ErrorCode::BadRequest, e.to_string(), e),
+			ArchiveError::Location(e) => e.into(),
+
+			_ => Self::with_cause(ErrorCode::InternalServerError, e.to_string(), e),
+		}
+	}
+}
+
+/// Written next to the parts, so an archive still tells which files only exist in it without the
+/// library
+#[derive(Serialize, Deserialize, Debug)]
+struct Manifest {
+	pub_id: Uuid,
+	name: Option<String>,
+	location_pub_id: Option<Uuid>,
+	materialized_path: String,
+	encrypted: bool,
+	entries: Vec<ManifestEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ManifestEntry {
+	file_path_pub_id: Uuid,
+	part: i32,
+	entry: String,
+}
+
+/// File paths which only exist in an archive must not be removed when they go missing on disk
+pub(crate) fn not_archived() -> file_path::WhereParam {
+	file_path::archived_in::none(vec![])
+}
+
+pub(crate) async fn is_archived(
+	db: &PrismaClient,
+	file_path_id: file_path::id::Type,
+) -> Result<bool, QueryError> {
+	db.archived_file_path()
+		.count(vec![archived_file_path::file_path_id::equals(file_path_id)])
+		.exec()
+		.await
+		.map(|count| count > 0)
+}
+
+/// Removes an archive from disk and from the library, as long as none of its files still only
+/// exist in it
+pub async fn delete_archive(
+	db: &PrismaClient,
+	archive_id: archive::id::Type,
+) -> Result<(), ArchiveError> {
+	let archive = db
+		.archive()
+		.find_unique(archive::id::equals(archive_id))
+		.exec()
+		.await?
+		.ok_or(ArchiveError::NotFound(archive_id))?;
+
+	let still_archived = db
+		.archived_file_path()
+		.count(vec![archived_file_path::archive_id::equals(archive_id)])
+		.exec()
+		.await?;
+
+	if still_archived > 0 {
+		return Err(ArchiveError::StillArchived(still_archived));
+	}
+
+	match fs::remove_dir_all(&archive.path).await {
+		Ok(()) => {}
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+		Err(e) => {
+			return Err(
+				FileIOError::from((&archive.path, e, "Failed to remove archive directory")).into(),
+			)
+		}
+	}
+
+	db.archive()
+		.delete(archive::id::equals(archive_id))
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+pub(crate) async fn set_location_archived(
+	library: &Library,
+	location_id: location::id::Type,
+	archived: bool,
+) -> Result<(), ArchiveError> {
+	let Library { db, sync, .. } = library;
+
+	let location = db
+		.location()
+		.find_unique(location::id::equals(location_id))
+		.select(location::select!({ pub_id is_archived }))
+		.exec()
+		.await?
+		.ok_or(LocationError::IdNotFound(location_id))?;
+
+	if location.is_archived.unwrap_or_default() == archived {
+		return Ok(());
+	}
+
+	let (sync_param, db_param) = sync_db_entry!(archived, location::is_archived);
+
+	sync.write_op(
+		db,
+		sync.shared_update(
+			prisma_sync::location::SyncId {
+				pub_id: location.pub_id,
+			},
+			[sync_param],
+		),
+		db.location()
+			.update(location::id::equals(location_id), vec![db_param])
+			.select(location::select!({ id })),
+	)
+	.await?;
+
+	Ok(())
+}
+
+async fn write_manifest(
+	db: &PrismaClient,
+	archive_id: archive::id::Type,
+) -> Result<(), ArchiveError> {
+	let archive = db
+		.archive()
+		.find_unique(archive::id::equals(archive_id))
+		.select(archive::select!({
+			pub_id
+			name
+			path
+			materialized_path
+			key
+			location: select { pub_id }
+			file_paths: select { part entry file_path: select { pub_id } }
+		}))
+		.exec()
+		.await?
+		.ok_or(ArchiveError::NotFound(archive_id))?;
+
+	let manifest = Manifest {
+		pub_id: from_bytes_to_uuid(&archive.pub_id),
+		name: archive.name,
+		location_pub_id: archive
+			.location
+			.map(|location| from_bytes_to_uuid(&location.pub_id)),
+		materialized_path: archive.materialized_path,
+		encrypted: archive.key.is_some(),
+		entries: archive
+			.file_paths
+			.into_iter()
+			.map(|archived| ManifestEntry {
+				file_path_pub_id: from_bytes_to_uuid(&archived.file_path.pub_id),
+				part: archived.part,
+				entry: archived.entry,
+			})
+			.collect(),
+	};
+
+	let manifest_path = Path::new(&archive.path).join(MANIFEST_FILE_NAME);
+
+	fs::write(&manifest_path, serde_json::to_vec_pretty(&manifest)?)
+		.await
+		.map_err(|e| {
+			FileIOError::from((manifest_path, e, "Failed to write archive manifest")).into()
+		})
+}
+
+fn part_file_name(part: i32, encrypted: bool) -> String {
+	if encrypted {
+		format!("part-{part:05}.tar.gz.enc")
+	} else {
+		format!("part-{part:05}.tar.gz")
+	}
+}
+
+fn temp_part_path(archive_path: &Path, part: i32) -> PathBuf {
+	archive_path.join(format!("{}.tmp", part_file_name(part, false)))
+}
+
+/// Syncs the entries of a directory to disk, so parts moved into it survive a crash
+async fn sync_directory(path: &Path) -> Result<(), FileIOError> {
+	// Directories can't be opened as files to be synced on Windows
+	#[cfg(unix)]
+	fs::File::open(path)
+		.await
+		.map_err(|e| FileIOError::from((path, e, "Failed to open archive directory")))?
+		.sync_all()
+		.await
+		.map_err(|e| FileIOError::from((path, e, "Failed to sync archive directory")))?;
+
+	#[cfg(not(unix))]
+	let _ = path;
+
+	Ok(())
+}
+
+fn key_from_db(key: Option<&Vec<u8>>) -> Result<Option<SecretKey>, ArchiveError> {
+	key.map(|key| SecretKey::try_from(key.as_slice()).map_err(|_| ArchiveError::MalformedKey))
+		.transpose()
+}
+
+/// Encrypts `source` into `target`, which starts with the stream nonce
+async fn encrypt_part(key: &SecretKey, source: &Path, target: &Path) -> Result<(), ArchiveError> {
+	let mut rng = CryptoRng::new()?;
+
+	let plain_text = fs::File::open(source).await.map_err(|e| {
+		FileIOError::from((source, e, "Failed to open archive part to be encrypted"))
+	})?;
+
+	let mut file = fs::File::create(target)
+		.await
+		.map_err(|e| FileIOError::from((target, e, "Failed to create encrypted archive part")))?;
+
+	let (nonce, stream) = StreamEncryption::encrypt(key, plain_text, &mut rng);
+
+	file.write_all(nonce.as_slice())
+		.await
+		.map_err(|e| FileIOError::from((target, e, "Failed to write archive part nonce")))?;
+
+	let mut stream = pin!(stream);
+	while let Some(res) = stream.next().await {
+		file.write_all(&res?).await.map_err(|e| {
+			FileIOError::from((target, e, "Failed to write encrypted archive part"))
+		})?;
+	}
+
+	file.flush()
+		.await
+		.map_err(|e| FileIOError::from((target, e, "Failed to flush encrypted archive part")))?;
+
+	file.sync_all()
+		.await
+		.map_err(|e| FileIOError::from((target, e, "Failed to sync encrypted archive part")))?;
+
+	Ok(())
+}
+
+async fn decrypt_part(key: &SecretKey, source: &Path, target: &Path) -> Result<(), ArchiveError> {
+	let mut file = fs::File::open(source).await.map_err(|e| {
+		FileIOError::from((source, e, "Failed to open archive part to be decrypted"))
+	})?;
+
+	let mut nonce = StreamNonce::default();
+	file.read_exact(&mut nonce)
+		.await
+		.map_err(|e| FileIOError::from((source, e, "Failed to read archive part nonce")))?;
+
+	let plain_text = fs::File::create(target)
+		.await
+		.map_err(|e| FileIOError::from((target, e, "Failed to create decrypted archive part")))?;
+
+	StreamDecryption::decrypt(key, &nonce, file, plain_text)
+		.await
+		.map_err(Into::into)
+}
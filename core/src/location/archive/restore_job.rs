@@ -0,0 +1,324 @@
+use crate::{
+	invalidate_query,
+	library::Library,
+	old_job::{
+		CurrentStep, JobError, JobInitOutput, JobResult, JobRunErrors, JobRunMetadata,
+		JobStepOutput, StatefulJob, WorkerContext,
+	},
+};
+
+use sd_core_file_path_helper::join_location_relative_path;
+
+use sd_crypto::cloud::SecretKey;
+use sd_prisma::prisma::{archive, archived_file_path, file_path, location};
+use sd_utils::{db::maybe_missing, error::FileIOError};
+
+use std::{
+	collections::{BTreeMap, HashMap, HashSet},
+	io::{self, BufReader},
+	path::{Path, PathBuf},
+};
+
+use flate2::bufread::GzDecoder;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use specta::Type;
+use tokio::{fs, task::spawn_blocking};
+use tracing::trace;
+
+use super::{
+	decrypt_part, key_from_db, part_file_name, set_location_archived, temp_part_path, ArchiveError,
+};
+
+#[derive(Serialize, Deserialize, Hash, Type, Debug)]
+pub struct OldArchiveRestorerJobInit {
+	pub archive_id: archive::id::Type,
+	pub location_id: location::id::Type,
+	/// File paths to restore, directories bring their archived contents along. The whole archive
+	/// is restored when missing.
+	pub file_path_ids: Option<Vec<file_path::id::Type>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OldArchiveRestorerJobData {
+	location_path: PathBuf,
+	archive_path: PathBuf,
+	key: Option<SecretKey>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RestorePart {
+	part: i32,
+	/// Entries to extract from the part, with the file path they belong to
+	entries: HashMap<String, file_path::id::Type>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct ArchiveRestorerJobRunMetadata {
+	restored: u64,
+}
+
+impl JobRunMetadata for ArchiveRestorerJobRunMetadata {
+	fn update(&mut self, new_data: Self) {
+		self.restored += new_data.restored;
+	}
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for OldArchiveRestorerJobInit {
+	type Data = OldArchiveRestorerJobData;
+	type Step = RestorePart;
+	type RunMetadata = ArchiveRestorerJobRunMetadata;
+
+	const NAME: &'static str = "archive_restorer";
+
+	fn target_location(&self) -> location::id::Type {
+		self.location_id
+	}
+
+	async fn init(
+		&self,
+		ctx: &WorkerContext,
+		data: &mut Option<Self::Data>,
+	) -> Result<JobInitOutput<Self::RunMetadata, Self::Step>, JobError> {
+		let init = self;
+		let Library { db, .. } = &*ctx.library;
+
+		let archive = db
+			.archive()
+			.find_unique(archive::id::equals(init.archive_id))
+			.select(archive::select!({ path key location: select { path } }))
+			.exec()
+			.await?
+			.ok_or(ArchiveError::NotFound(init.archive_id))?;
+
+		let location_path = maybe_missing(
+			archive.location.and_then(|location| location.path),
+			"archive.location.path",
+		)
+		.map(PathBuf::from)?;
+
+		let archived = db
+			.archived_file_path()
+			.find_many(vec![archived_file_path::archive_id::equals(
+				init.archive_id,
+			)])
+			.select(archived_file_path::select!({ file_path_id part entry }))
+			.exec()
+			.await?;
+
+		let archived = if let Some(file_path_ids) = &init.file_path_ids {
+			let file_path_ids = file_path_ids.iter().copied().collect::<HashSet<_>>();
+
+			// Entries of the requested directories, so their contents are restored along
+			let directories = archived
+				.iter()
+				.filter(|archived| file_path_ids.contains(&archived.file_path_id))
+				.map(|archived| format!("{}/", archived.entry))
+				.collect::<Vec<_>>();
+
+			archived
+				.into_iter()
+				.filter(|archived| {
+					file_path_ids.contains(&archived.file_path_id)
+						|| directories
+							.iter()
+							.any(|directory| archived.entry.starts_with(directory))
+				})
+				.collect()
+		} else {
+			archived
+		};
+
+		let mut parts = BTreeMap::<_, HashMap<_, _>>::new();
+		for archived in archived {
+			parts
+				.entry(archived.part)
+				.or_default()
+				.insert(archived.entry, archived.file_path_id);
+		}
+
+		*data = Some(OldArchiveRestorerJobData {
+			location_path,
+			archive_path: PathBuf::from(archive.path),
+			key: key_from_db(archive.key.as_ref())?,
+		});
+
+		Ok(parts
+			.into_iter()
+			.map(|(part, entries)| RestorePart { part, entries })
+			.collect::<Vec<_>>()
+			.into())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: &WorkerContext,
+		CurrentStep {
+			step: RestorePart { part, entries },
+			..
+		}: CurrentStep<'_, Self::Step>,
+		data: &Self::Data,
+		_: &Self::RunMetadata,
+	) -> Result<JobStepOutput<Self::Step, Self::RunMetadata>, JobError> {
+		let init = self;
+		let Library { db, .. } = &*ctx.library;
+
+		let part_path = data
+			.archive_path
+			.join(part_file_name(*part, data.key.is_some()));
+
+		if fs::metadata(&part_path).await.is_err() {
+			return Err(ArchiveError::MissingPart(*part).into());
+		}
+
+		let source_path = if let Some(key) = &data.key {
+			let temp_path = temp_part_path(&data.archive_path, *part);
+			decrypt_part(key, &part_path, &temp_path).await?;
+			temp_path
+		} else {
+			part_path
+		};
+
+		let res = spawn_blocking({
+			let location_path = data.location_path.clone();
+			let wanted = entries.keys().cloned().collect::<HashSet<_>>();
+			let source_path = source_path.clone();
+
+			move || unpack_part(&source_path, &location_path, &wanted)
+		})
+		.await;
+
+		if data.key.is_some() {
+			if let Err(e) = fs::remove_file(&source_path).await {
+				trace!(?e, "Failed to remove decrypted archive part;");
+			}
+		}
+
+		let (restored, errors) = res??;
+
+		let restored_file_path_ids = restored
+			.iter()
+			.filter_map(|entry| entries.get(entry).copied())
+			.collect::<Vec<_>>();
+
+		db.archived_file_path()
+			.delete_many(vec![
+				archived_file_path::archive_id::equals(init.archive_id),
+				archived_file_path::file_path_id::in_vec(restored_file_path_ids),
+			])
+			.exec()
+			.await?;
+
+		Ok((
+			ArchiveRestorerJobRunMetadata {
+				restored: restored.len() as u64,
+			},
+			JobRunErrors(errors),
+		)
+			.into())
+	}
+
+	async fn finalize(
+		&self,
+		ctx: &WorkerContext,
+		_: &Option<Self::Data>,
+		run_metadata: &Self::RunMetadata,
+	) -> JobResult {
+		let init = self;
+		let Library { db, .. } = &*ctx.library;
+
+		let still_archived = db
+			.archived_file_path()
+			.count(vec![archived_file_path::file_path::is(vec![
+				file_path::location_id::equals(Some(init.location_id)),
+			])])
+			.exec()
+			.await?;
+
+		if still_archived == 0 {
+			set_location_archived(&ctx.library, init.location_id, false).await?;
+			invalidate_query!(ctx.library, "locations.list");
+		}
+
+		invalidate_query!(ctx.library, "locations.archives");
+		invalidate_query!(ctx.library, "search.paths");
+
+		Ok(Some(json!({
+			"init": init,
+			"restored": run_metadata.restored,
+		})))
+	}
+}
+
+/// Extracts the wanted entries of a tar.gz part into the location, returning the restored ones.
+///
+/// Files which already exist in the location are kept untouched and reported as errors, so
+/// restoring never overwrites newer data.
+pub(super) fn unpack_part(
+	source: &Path,
+	location_path: &Path,
+	wanted: &HashSet<String>,
+) -> Result<(Vec<String>, Vec<String>), FileIOError> {
+	let file = std::fs::File::open(source)
+		.map_err(|e| FileIOError::from((source, e, "Failed to open archive part")))?;
+
+	let mut archive = tar::Archive::new(GzDecoder::new(BufReader::new(file)));
+	archive.set_preserve_mtime(true);
+
+	let mut restored = vec![];
+	let mut errors = vec![];
+
+	for entry in archive
+		.entries()
+		.map_err(|e| FileIOError::from((source, e, "Failed to read archive part entries")))?
+	{
+		let mut entry = entry
+			.map_err(|e| FileIOError::from((source, e, "Failed to read archive part entry")))?;
+
+		let path = entry
+			.path()
+			.map_err(|e| FileIOError::from((source, e, "Failed to read archive entry path")))?
+			.to_string_lossy()
+			.trim_end_matches('/')
+			.to_string();
+
+		if !wanted.contains(&path) {
+			continue;
+		}
+
+		let is_dir = entry.header().entry_type().is_dir();
+		let full_path = join_location_relative_path(location_path, &path);
+
+		match std::fs::symlink_metadata(&full_path) {
+			Ok(metadata) if is_dir && metadata.is_dir() => {
+				restored.push(path);
+				continue;
+			}
+			Ok(_) => {
+				errors.push(format!(
+					"file already exists, it wasn't restored: <path='{}'>",
+					full_path.display()
+				));
+				continue;
+			}
+			Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+			Err(e) => {
+				errors.push(FileIOError::from((&full_path, e)).to_string());
+				continue;
+			}
+		}
+
+		match entry.unpack_in(location_path) {
+			Ok(true) => restored.push(path),
+			Ok(false) => errors.push(format!(
+				"archive entry points outside of the location: <entry='{path}'>"
+			)),
+			Err(e) => errors.push(
+				FileIOError::from((&full_path, e, "Failed to restore archived file")).to_string(),
+			),
+		}
+	}
+
+	Ok((restored, errors))
+}
@@ -0,0 +1,161 @@
+use crate::{invalidate_query, library::Library, volume::util::volume_for_path, Node};
+
+use sd_prisma::{
+	prisma::{location, volume},
+	prisma_sync,
+};
+use sd_sync::{sync_db_entry, OperationFactory};
+use sd_utils::{chain_optional_iter, from_bytes_to_uuid, uuid_to_bytes};
+
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::Serialize;
+use specta::Type;
+use tracing::warn;
+use uuid::Uuid;
+
+use super::LocationError;
+
+/// Whether the files of a location can be reached from this device right now
+#[derive(Serialize, Type, Debug, Clone, Default)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Availability {
+	#[default]
+	Online,
+	/// The location is on this device, but the volume holding it isn't mounted
+	Offline {
+		/// Name of the volume to be plugged back in, if the location was ever seen on one
+		volume_name: Option<String>,
+		date_last_seen: Option<DateTime<FixedOffset>>,
+	},
+	/// The location belongs to another device
+	OtherDevice,
+}
+
+pub async fn locations_availability(
+	node: &Node,
+	library: &Library,
+	location_ids: Vec<location::id::Type>,
+) -> Result<HashMap<location::id::Type, Availability>, LocationError> {
+	let Library { db, sync, .. } = library;
+
+	let device_pub_id = sync.device_pub_id.to_db();
+
+	let locations = db
+		.location()
+		.find_many(vec![location::id::in_vec(location_ids)])
+		.select(location::select!({
+			id
+			pub_id
+			date_last_seen
+			device: select { pub_id }
+			volume: select { name }
+		}))
+		.exec()
+		.await?;
+
+	let mut availability = HashMap::with_capacity(locations.len());
+
+	for location in locations {
+		let value = if !location
+			.device
+			.as_ref()
+			.is_some_and(|device| device.pub_id == device_pub_id)
+		{
+			Availability::OtherDevice
+		} else if node
+			.locations
+			.is_online(&from_bytes_to_uuid(&location.pub_id))
+			.await
+		{
+			Availability::Online
+		} else {
+			Availability::Offline {
+				volume_name: location.volume.and_then(|volume| volume.name),
+				date_last_seen: location.date_last_seen,
+			}
+		};
+
+		availability.insert(location.id, value);
+	}
+
+	Ok(availability)
+}
+
+/// Links the location to the volume it was found on, tracking the volume if needed, and records
+/// when it was seen. Called when a location comes online.
+pub(crate) async fn location_came_online(
+	node: &Node,
+	library: &Arc<Library>,
+	location_id: location::id::Type,
+	location_pub_id: Uuid,
+	location_path: &Path,
+) -> Result<(), LocationError> {
+	let volume_id = match node.volumes.list_system_volumes(Arc::clone(library)).await {
+		Ok(volumes) => match volume_for_path(&volumes, location_path) {
+			Some(volume) if volume.id.is_some() => volume.id,
+			Some(volume) => volume
+				.create(&library.db, library.sync.device_pub_id.to_db())
+				.await
+				.map_err(|e| warn!(?e, "Failed to track the volume of a location;"))
+				.ok()
+				.and_then(|volume| volume.id),
+			None => None,
+		},
+		Err(e) => {
+			warn!(?e, "Failed to list volumes to link a location to;");
+			None
+		}
+	};
+
+	update_date_last_seen(
+		library,
+		location_id,
+		location_pub_id,
+		volume_id.map(|volume_id| location::volume::connect(volume::id::equals(volume_id))),
+	)
+	.await
+}
+
+/// Records the moment a location went missing, as the last time it was seen
+pub(crate) async fn location_went_offline(
+	library: &Library,
+	location_id: location::id::Type,
+	location_pub_id: Uuid,
+) -> Result<(), LocationError> {
+	update_date_last_seen(library, location_id, location_pub_id, None).await
+}
+
+async fn update_date_last_seen(
+	library: &Library,
+	location_id: location::id::Type,
+	location_pub_id: Uuid,
+	volume_param: Option<location::SetParam>,
+) -> Result<(), LocationError> {
+	let Library { db, sync, .. } = library;
+
+	let (sync_param, db_param) = sync_db_entry!(Utc::now(), location::date_last_seen);
+
+	sync.write_op(
+		db,
+		sync.shared_update(
+			prisma_sync::location::SyncId {
+				pub_id: uuid_to_bytes(&location_pub_id),
+			},
+			[sync_param],
+		),
+		db.location()
+			.update(
+				location::id::equals(location_id),
+				// Volumes are tracked per device, so the link isn't synced
+				chain_optional_iter([db_param], [volume_param]),
+			)
+			.select(location::select!({ id })),
+	)
+	.await?;
+
+	invalidate_query!(library, "locations.list");
+
+	Ok(())
+}
@@ -48,7 +48,7 @@ pub enum LocationError {
 	MetadataNotFound(Box<Path>),
 	#[error("location already exists in database <path='{}'>", .0.display())]
 	LocationAlreadyExists(Box<Path>),
-	#[error("nested location currently not supported <path='{}'>", .0.display())]
+	#[error("location overlaps existing locations, must nest or merge with them <path='{}'>", .0.display())]
 	NestedLocation(Box<Path>),
 	#[error(transparent)]
 	NonUtf8Path(#[from] NonUtf8PathError),
@@ -80,6 +80,8 @@ pub enum LocationError {
 	InvalidScanStateValue(i32),
 	#[error(transparent)]
 	Sync(#[from] sd_core_sync::Error),
+	#[error(transparent)]
+	IndexerRules(#[from] sd_core_indexer_rules::Error),
 }
 
 impl From<LocationError> for rspc::Error {
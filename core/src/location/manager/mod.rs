@@ -27,9 +27,12 @@ use tokio::{
 use tracing::{debug, error, instrument, trace};
 use uuid::Uuid;
 
+mod relink;
 mod runner;
 mod watcher;
 
+pub use watcher::WatcherMode;
+
 #[derive(Clone, Copy, Debug)]
 enum ManagementMessageAction {
 	Add,
@@ -0,0 +1,142 @@
+use crate::{library::Library, location::metadata::SpacedriveLocationMetadataFile};
+
+use sd_prisma::prisma::location;
+use sd_utils::db::maybe_missing;
+
+use std::path::{Component, Path, PathBuf};
+
+use tracing::debug;
+use uuid::Uuid;
+
+use super::LocationManagerError;
+
+pub(super) struct RelinkCandidates {
+	pub name: String,
+	/// Paths holding a metadata file for this location
+	pub paths: Vec<PathBuf>,
+}
+
+/// Looks for an offline location under the given mount points, by the metadata file at its root
+pub(super) async fn find_relink_candidates(
+	library: &Library,
+	location_id: location::id::Type,
+	mount_points: &[PathBuf],
+) -> Result<RelinkCandidates, LocationManagerError> {
+	let location = library
+		.db
+		.location()
+		.find_unique(location::id::equals(location_id))
+		.select(location::select!({ pub_id name path volume: select { mount_point } }))
+		.exec()
+		.await?
+		.ok_or(LocationManagerError::LocationNotFound(location_id))?;
+
+	let location_pub_id = Uuid::from_slice(&location.pub_id)?;
+	let location_path = PathBuf::from(maybe_missing(location.path, "location.path")?);
+	let old_mount_point = location
+		.volume
+		.and_then(|volume| volume.mount_point)
+		.map(PathBuf::from);
+
+	let mut paths = vec![];
+
+	for candidate in candidate_paths(&location_path, old_mount_point.as_deref(), mount_points) {
+		match SpacedriveLocationMetadataFile::try_load(&candidate).await {
+			Ok(Some(metadata))
+				if metadata
+					.location_pub_id(library.id)
+					.is_ok_and(|pub_id| pub_id == location_pub_id) =>
+			{
+				paths.push(candidate);
+			}
+			Ok(_) => {}
+			Err(e) => debug!(
+				?e,
+				candidate = %candidate.display(),
+				"Failed to read location metadata file while looking for a moved location;",
+			),
+		}
+	}
+
+	Ok(RelinkCandidates {
+		name: location.name.unwrap_or_default(),
+		paths,
+	})
+}
+
+/// Where a location last seen at `location_path` could be under the given mount points.
+///
+/// Keeps its path relative to the volume it was on when known, otherwise tries every trailing
+/// part of its path, as removable drives are usually mounted under a user or label directory.
+fn candidate_paths(
+	location_path: &Path,
+	old_mount_point: Option<&Path>,
+	mount_points: &[PathBuf],
+) -> Vec<PathBuf> {
+	let relative_paths = match old_mount_point
+		.and_then(|old_mount_point| location_path.strip_prefix(old_mount_point).ok())
+	{
+		Some(relative_path) => vec![relative_path.to_path_buf()],
+		None => {
+			let components = location_path
+				.components()
+				.filter(|component| matches!(component, Component::Normal(_)))
+				.collect::<Vec<_>>();
+
+			(0..=components.len())
+				.map(|skip| components[skip..].iter().collect::<PathBuf>())
+				.collect()
+		}
+	};
+
+	let mut candidates = vec![];
+
+	for mount_point in mount_points {
+		for relative_path in &relative_paths {
+			let candidate = mount_point.join(relative_path);
+			if candidate != location_path && !candidates.contains(&candidate) {
+				candidates.push(candidate);
+			}
+		}
+	}
+
+	candidates
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn keeps_path_relative_to_known_volume() {
+		assert_eq!(
+			candidate_paths(
+				Path::new("/media/alex/BACKUP/Photos"),
+				Some(Path::new("/media/alex/BACKUP")),
+				&[
+					PathBuf::from("/media/alex/BACKUP1"),
+					PathBuf::from("/media/alex/BACKUP"),
+				],
+			),
+			vec![PathBuf::from("/media/alex/BACKUP1/Photos")]
+		);
+	}
+
+	#[test]
+	fn tries_trailing_parts_on_unknown_volume() {
+		assert_eq!(
+			candidate_paths(
+				Path::new("/media/alex/BACKUP/Photos"),
+				None,
+				&[PathBuf::from("/run/media/alex/BACKUP")],
+			),
+			vec![
+				PathBuf::from("/run/media/alex/BACKUP/media/alex/BACKUP/Photos"),
+				PathBuf::from("/run/media/alex/BACKUP/alex/BACKUP/Photos"),
+				PathBuf::from("/run/media/alex/BACKUP/BACKUP/Photos"),
+				PathBuf::from("/run/media/alex/BACKUP/Photos"),
+				PathBuf::from("/run/media/alex/BACKUP"),
+			]
+		);
+	}
+}
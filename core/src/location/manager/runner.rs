@@ -1,9 +1,17 @@
 use crate::{
+	api::notifications::{NotificationData, NotificationKind},
+	invalidate_query,
 	library::{Library, LibraryId},
+	location::{
+		find_location, light_scan_location, location_came_online, location_went_offline,
+		relink_location,
+	},
+	volume::{Volume, VolumeEvent},
 	Node,
 };
 
-use sd_core_prisma_helpers::location_ids_and_path;
+use sd_core_heavy_lifting::JobName;
+use sd_core_prisma_helpers::{location_ids_and_path, location_with_indexer_rules};
 
 use sd_prisma::prisma::location;
 use sd_utils::db::maybe_missing;
@@ -11,27 +19,29 @@ use sd_utils::db::maybe_missing;
 use std::{
 	collections::{HashMap, HashSet},
 	io::ErrorKind,
-	path::PathBuf,
+	path::{Path, PathBuf},
 	pin::pin,
 	sync::Arc,
 	time::Duration,
 };
 
 use async_channel as chan;
-use futures::stream::StreamExt;
+use futures::stream::{self, StreamExt};
 use futures_concurrency::stream::Merge;
 use tokio::{
-	fs,
-	sync::oneshot,
+	fs, spawn,
+	sync::{broadcast::error::RecvError, oneshot},
 	time::{interval, MissedTickBehavior},
 };
 use tokio_stream::wrappers::IntervalStream;
-use tracing::{debug, error, instrument, trace, warn};
+use tracing::{debug, error, info, instrument, trace, warn};
 use uuid::Uuid;
 
 use super::{
-	watcher::LocationWatcher, LocationManagementMessage, LocationManagerError,
-	ManagementMessageAction, WatcherManagementMessage, WatcherManagementMessageAction,
+	relink::{find_relink_candidates, RelinkCandidates},
+	watcher::LocationWatcher,
+	LocationManagementMessage, LocationManagerError, ManagementMessageAction,
+	WatcherManagementMessage, WatcherManagementMessageAction,
 };
 
 type LocationIdAndLibraryId = (location::id::Type, LibraryId);
@@ -43,6 +53,8 @@ struct Runner {
 	locations_watched: HashMap<LocationIdAndLibraryId, LocationWatcher>,
 	locations_unwatched: HashMap<LocationIdAndLibraryId, LocationWatcher>,
 	forced_unwatch: HashSet<LocationIdAndLibraryId>,
+	/// Locations found offline, which get a quick rescan once their volume is back
+	locations_offline: HashMap<LocationIdAndLibraryId, Arc<Library>>,
 }
 impl Runner {
 	async fn new(node: Arc<Node>) -> Self {
@@ -53,6 +65,7 @@ impl Runner {
 			locations_watched: HashMap::new(),
 			locations_unwatched: HashMap::new(),
 			forced_unwatch: HashSet::new(),
+			locations_offline: HashMap::new(),
 		}
 	}
 
@@ -69,24 +82,60 @@ impl Runner {
 		library: Arc<Library>,
 	) -> Result<(), LocationManagerError> {
 		if let Some(location) = get_location(location_id, &library).await? {
+			let poll_interval = match (Uuid::from_slice(&location.pub_id), location.path.as_deref())
+			{
+				(Ok(pub_id), Some(location_path)) => {
+					library
+						.config()
+						.await
+						.location_watchers
+						.get(&pub_id)
+						.copied()
+						.unwrap_or_default()
+						.poll_interval(Path::new(location_path), &library, &self.node)
+						.await
+				}
+				_ => None,
+			};
+
+			// Replacing the previous watcher, in case its mode changed
+			let key = (location_id, library.id);
+			self.locations_watched.remove(&key);
+			self.locations_unwatched.remove(&key);
+
 			check_online(&location, &self.node, &library, &self.device_pub_id_to_db)
 				.await
 				.and_then(|is_online| {
-					LocationWatcher::new(location, Arc::clone(&library), Arc::clone(&self.node))
-						.map(|mut watcher| {
-							if is_online {
-								trace!(%location_id, "Location is online, watching it!;");
-								watcher.watch();
-								self.locations_watched
-									.insert((location_id, library.id), watcher);
-							} else {
-								self.locations_unwatched
-									.insert((location_id, library.id), watcher);
-							}
-
-							self.locations_to_check
-								.insert(location_id, Arc::clone(&library));
-						})
+					LocationWatcher::new(
+						location,
+						Arc::clone(&library),
+						Arc::clone(&self.node),
+						poll_interval,
+					)
+					.map(|mut watcher| {
+						if is_online {
+							trace!(%location_id, "Location is online, watching it!;");
+							watcher.watch();
+							self.locations_watched
+								.insert((location_id, library.id), watcher);
+						} else {
+							self.locations_unwatched
+								.insert((location_id, library.id), watcher);
+							self.locations_offline
+								.insert((location_id, library.id), Arc::clone(&library));
+
+							// It may have been mounted somewhere else
+							relink_offline_location(
+								location_id,
+								Arc::clone(&library),
+								Arc::clone(&self.node),
+								None,
+							);
+						}
+
+						self.locations_to_check
+							.insert(location_id, Arc::clone(&library));
+					})
 				})
 		} else {
 			Err(LocationManagerError::LocationNotFound(location_id))
@@ -122,6 +171,7 @@ impl Runner {
 
 		// Removing location from checker
 		self.locations_to_check.remove(&location_id);
+		self.locations_offline.remove(&key);
 
 		Ok(())
 	}
@@ -134,6 +184,7 @@ impl Runner {
 		reason: &'static str,
 	) {
 		warn!(%reason);
+		self.locations_offline.remove(&(location_id, library_id));
 		if let Some(mut watcher) = self.locations_watched.remove(&(location_id, library_id)) {
 			watcher.unwatch();
 		} else {
@@ -225,6 +276,20 @@ impl Runner {
 		}
 	}
 
+	/// Looks for the offline locations on a volume which was just mounted
+	fn look_for_offline_locations(&self, volume: &Volume) {
+		let mount_points = volume_mount_points(volume);
+
+		for ((location_id, _), library) in &self.locations_offline {
+			relink_offline_location(
+				*location_id,
+				Arc::clone(library),
+				Arc::clone(&self.node),
+				Some(mount_points.clone()),
+			);
+		}
+	}
+
 	async fn ignore_events_for_path(
 		&self,
 		location_id: location::id::Type,
@@ -307,14 +372,29 @@ impl Runner {
 
 		if let Some(location) = get_location(location_id, &library).await? {
 			if self.check_same_device(&location) {
-				if check_online(&location, &self.node, &library, &self.device_pub_id_to_db).await?
-					&& !self.forced_unwatch.contains(&key)
-				{
+				let is_online =
+					check_online(&location, &self.node, &library, &self.device_pub_id_to_db)
+						.await?;
+
+				if is_online && !self.forced_unwatch.contains(&key) {
 					self.watch_location(location, library.id);
 				} else {
 					self.unwatch_location(location, library.id);
 				}
 
+				if !is_online {
+					// A watcher paused for its volume to be ejected is watched again once the
+					// volume is back
+					self.forced_unwatch.remove(&key);
+					self.locations_offline.insert(key, Arc::clone(&library));
+				} else if self.locations_offline.remove(&key).is_some() {
+					rescan_reconnected_location(
+						location_id,
+						Arc::clone(&library),
+						Arc::clone(&self.node),
+					);
+				}
+
 				self.locations_to_check.insert(location_id, library);
 			} else {
 				self.drop_location(
@@ -343,6 +423,7 @@ pub(super) async fn run(
 		LocationManagementMessage(LocationManagementMessage),
 		WatcherManagementMessage(WatcherManagementMessage),
 		CheckLocations,
+		VolumeMounted(Volume),
 		Stop,
 	}
 
@@ -351,12 +432,31 @@ pub(super) async fn run(
 	let mut check_locations_interval = interval(Duration::from_secs(2));
 	check_locations_interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
 
+	let volume_events = stream::unfold(
+		node.volumes.subscribe(),
+		|mut volume_events_rx| async move {
+			loop {
+				match volume_events_rx.recv().await {
+					Ok(event) => return Some((event, volume_events_rx)),
+					Err(RecvError::Lagged(_)) => continue,
+					Err(RecvError::Closed) => return None,
+				}
+			}
+		},
+	);
+
 	let mut runner = Runner::new(node).await;
 
 	let mut msg_stream = pin!((
 		location_management_rx.map(StreamMessage::LocationManagementMessage),
 		watcher_management_rx.map(StreamMessage::WatcherManagementMessage),
 		IntervalStream::new(check_locations_interval).map(|_| StreamMessage::CheckLocations),
+		Box::pin(volume_events.filter_map(|event| async move {
+			match event {
+				VolumeEvent::VolumeAdded(volume) => Some(StreamMessage::VolumeMounted(volume)),
+				_ => None,
+			}
+		})),
 		stop_rx.map(|()| StreamMessage::Stop),
 	)
 		.merge());
@@ -389,6 +489,7 @@ pub(super) async fn run(
 					warn!(?errors, "Errors while checking locations;");
 				}
 			}
+			StreamMessage::VolumeMounted(volume) => runner.look_for_offline_locations(&volume),
 			StreamMessage::Stop => {
 				debug!("Stopping location manager");
 				break;
@@ -425,7 +526,7 @@ async fn check_online(
 		path,
 	}: &location_ids_and_path::Data,
 	node: &Node,
-	library: &Library,
+	library: &Arc<Library>,
 	device_pub_id_to_db: &[u8],
 ) -> Result<bool, LocationManagerError> {
 	let pub_id = Uuid::from_slice(pub_id)?;
@@ -434,12 +535,29 @@ async fn check_online(
 		.as_ref()
 		.is_some_and(|device| device.pub_id == device_pub_id_to_db)
 	{
-		match fs::metadata(maybe_missing(path, "location.path")?).await {
+		let path = maybe_missing(path, "location.path")?;
+
+		match fs::metadata(path).await {
 			Ok(_) => {
+				if !node.locations.is_online(&pub_id).await {
+					if let Err(e) =
+						location_came_online(node, library, *location_id, pub_id, Path::new(path))
+							.await
+					{
+						warn!(?e, "Failed to record location as online;");
+					}
+				}
+
 				node.locations.add_online(pub_id).await;
 				Ok(true)
 			}
 			Err(e) if e.kind() == ErrorKind::NotFound => {
+				if node.locations.is_online(&pub_id).await {
+					if let Err(e) = location_went_offline(library, *location_id, pub_id).await {
+						warn!(?e, "Failed to record location as offline;");
+					}
+				}
+
 				node.locations.remove_online(&pub_id).await;
 				Ok(false)
 			}
@@ -457,3 +575,132 @@ async fn check_online(
 		Err(LocationManagerError::NonLocalLocation(*location_id))
 	}
 }
+
+/// Quick rescans a location whose volume was plugged back in, as its files may have changed
+/// while it was away
+fn rescan_reconnected_location(
+	location_id: location::id::Type,
+	library: Arc<Library>,
+	node: Arc<Node>,
+) {
+	spawn(async move {
+		if node
+			.job_system
+			.check_running_jobs(
+				vec![
+					JobName::Indexer,
+					JobName::FileIdentifier,
+					JobName::MediaProcessor,
+				],
+				location_id,
+			)
+			.await
+		{
+			debug!(%location_id, "Skipping reconnected location rescan, as it's already being scanned;");
+			return;
+		}
+
+		match find_location(&library, location_id)
+			.include(location_with_indexer_rules::include())
+			.exec()
+			.await
+		{
+			Ok(Some(location)) => {
+				debug!(%location_id, "Location is back online, rescanning it;");
+				if let Err(e) = light_scan_location(node, library, location, "").await {
+					error!(?e, %location_id, "Failed to rescan reconnected location;");
+				}
+			}
+			Ok(None) => {}
+			Err(e) => error!(?e, %location_id, "Failed to fetch reconnected location;"),
+		}
+	});
+}
+
+fn volume_mount_points(volume: &Volume) -> Vec<PathBuf> {
+	let mut mount_points = volume.mount_points.clone();
+	if !mount_points.contains(&volume.mount_point) {
+		mount_points.push(volume.mount_point.clone());
+	}
+
+	mount_points
+}
+
+/// Looks for the metadata file of an offline location under the given mount points, or under
+/// every mounted volume when missing. The location is relinked when found at a single place,
+/// otherwise the user is asked to pick the right one.
+fn relink_offline_location(
+	location_id: location::id::Type,
+	library: Arc<Library>,
+	node: Arc<Node>,
+	mount_points: Option<Vec<PathBuf>>,
+) {
+	spawn(async move {
+		let mount_points = match mount_points {
+			Some(mount_points) => mount_points,
+			None => match node.volumes.list_system_volumes(Arc::clone(&library)).await {
+				Ok(volumes) => volumes.iter().flat_map(volume_mount_points).collect(),
+				Err(e) => {
+					warn!(?e, "Failed to list volumes to look for a moved location;");
+					return;
+				}
+			},
+		};
+
+		let RelinkCandidates { name, paths } =
+			match find_relink_candidates(&library, location_id, &mount_points).await {
+				Ok(candidates) => candidates,
+				Err(e) => {
+					warn!(?e, %location_id, "Failed to look for a moved location;");
+					return;
+				}
+			};
+
+		match paths.as_slice() {
+			[] => {}
+			[path] => match relink_location(&library, path).await {
+				Ok(_) => {
+					info!(%location_id, path = %path.display(), "Relinked moved location;");
+					invalidate_query!(library, "locations.list");
+
+					// Replacing the watcher, which still points to the old path
+					if let Err(e) = node.locations.add(location_id, Arc::clone(&library)).await {
+						error!(?e, %location_id, "Failed to watch relinked location;");
+					}
+
+					node.emit_notification(
+						NotificationData {
+							title: "Location relinked".to_string(),
+							content: format!(
+								"\"{name}\" was found at {} and relinked to it",
+								path.display()
+							),
+							kind: NotificationKind::Info,
+						},
+						None,
+					)
+					.await;
+				}
+				Err(e) => error!(?e, %location_id, "Failed to relink moved location;"),
+			},
+			paths => {
+				node.emit_notification(
+					NotificationData {
+						title: "Location found at several places".to_string(),
+						content: format!(
+							"\"{name}\" may have moved to any of {}, relink it to the right one",
+							paths
+								.iter()
+								.map(|path| path.display().to_string())
+								.collect::<Vec<_>>()
+								.join(", ")
+						),
+						kind: NotificationKind::Warning,
+					},
+					None,
+				)
+				.await;
+			}
+		}
+	});
+}
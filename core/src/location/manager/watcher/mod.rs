@@ -1,6 +1,7 @@
-use crate::{library::Library, Node};
+use crate::{library::Library, volume::util::volume_for_path, Node};
 
-use sd_core_indexer_rules::{IndexerRule, IndexerRuler};
+use sd_core_heavy_lifting::indexer::location_indexer_ruler;
+use sd_core_indexer_rules::IndexerRuler;
 use sd_core_prisma_helpers::{location_ids_and_path, location_with_indexer_rules};
 
 use sd_prisma::prisma::{location, PrismaClient};
@@ -19,6 +20,8 @@ use async_channel as chan;
 use futures::StreamExt;
 use futures_concurrency::stream::Merge;
 use notify::{Config, Event, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use specta::Type;
 use tokio::{
 	spawn,
 	task::JoinHandle,
@@ -45,8 +48,10 @@ mod ios;
 #[cfg(target_os = "android")]
 mod android;
 
+mod polling;
 mod utils;
 
+use polling::PollWatcher;
 use utils::reject_event;
 
 #[cfg(target_os = "linux")]
@@ -75,6 +80,51 @@ const ONE_SECOND: Duration = Duration::from_secs(1);
 const THIRTY_SECONDS: Duration = Duration::from_secs(30);
 const HUNDRED_MILLIS: Duration = Duration::from_millis(100);
 
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(60);
+const MIN_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How changes to the files of a location are noticed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum WatcherMode {
+	/// Polling for locations on network and FUSE file systems, native events otherwise
+	#[default]
+	Auto,
+	/// File system events emitted by the OS
+	Native,
+	/// Walking the location at the given interval, looking for changes
+	Polling { interval_secs: u32 },
+}
+
+impl WatcherMode {
+	/// The interval to poll the location at, `None` when native events should be used
+	pub(super) async fn poll_interval(
+		self,
+		location_path: &Path,
+		library: &Arc<Library>,
+		node: &Node,
+	) -> Option<Duration> {
+		match self {
+			Self::Native => None,
+			Self::Polling { interval_secs } => {
+				Some(Duration::from_secs(u64::from(interval_secs)).max(MIN_POLL_INTERVAL))
+			}
+			Self::Auto => {
+				let volumes = node
+					.volumes
+					.list_system_volumes(Arc::clone(library))
+					.await
+					.map_err(|e| warn!(?e, "Failed to list volumes to pick the watcher mode;"))
+					.ok()?;
+
+				volume_for_path(&volumes, location_path)
+					.filter(|volume| volume.lacks_native_events())
+					.map(|_| DEFAULT_POLL_INTERVAL)
+			}
+		}
+	}
+}
+
 trait EventHandler: 'static {
 	fn new(
 		location_id: location::id::Type,
@@ -96,11 +146,17 @@ trait EventHandler: 'static {
 	fn tick(&mut self) -> impl Future<Output = ()> + Send;
 }
 
+#[derive(Debug)]
+enum Backend {
+	Native(RecommendedWatcher),
+	Polling(PollWatcher),
+}
+
 #[derive(Debug)]
 pub(super) struct LocationWatcher {
 	location_id: location::id::Type,
 	location_path: PathBuf,
-	watcher: RecommendedWatcher,
+	backend: Backend,
 	ignore_path_tx: chan::Sender<IgnorePath>,
 	handle: Option<JoinHandle<()>>,
 	stop_tx: chan::Sender<()>,
@@ -124,45 +180,74 @@ impl LocationWatcher {
 		}: location_ids_and_path::Data,
 		library: Arc<Library>,
 		node: Arc<Node>,
+		poll_interval: Option<Duration>,
 	) -> Result<Self, LocationManagerError> {
 		let location_pub_id = Uuid::from_slice(&pub_id)?;
-		let location_path = maybe_missing(maybe_location_path, "location.path")?.into();
+		let location_path: PathBuf = maybe_missing(maybe_location_path, "location.path")?.into();
 
 		let (events_tx, events_rx) = chan::unbounded();
 		let (ignore_path_tx, ignore_path_rx) = chan::bounded(8);
 		let (stop_tx, stop_rx) = chan::bounded(1);
 
-		let watcher = RecommendedWatcher::new(
-			move |result| {
-				if !events_tx.is_closed() {
-					// SAFETY: we are not blocking the thread as this is an unbounded channel
-					if events_tx.send_blocking(result).is_err() {
-						error!(%location_id, "Unable to send watcher event to location manager;");
+		let backend = if let Some(poll_interval) = poll_interval {
+			debug!(?poll_interval, "Polling location for changes;");
+
+			Backend::Polling(PollWatcher::new(
+				location_id,
+				location_path.clone(),
+				Arc::clone(&library),
+				poll_interval,
+				events_tx,
+			))
+		} else {
+			Backend::Native(RecommendedWatcher::new(
+				move |result| {
+					if !events_tx.is_closed() {
+						// SAFETY: we are not blocking the thread as this is an unbounded channel
+						if events_tx.send_blocking(result).is_err() {
+							error!(%location_id, "Unable to send watcher event to location manager;");
+						}
+					} else {
+						error!(%location_id, "Tried to send file system events to a closed channel;");
 					}
-				} else {
-					error!(%location_id, "Tried to send file system events to a closed channel;");
-				}
-			},
-			Config::default(),
-		)?;
+				},
+				Config::default(),
+			)?)
+		};
+		let is_polling = matches!(backend, Backend::Polling(_));
 
 		let handle = spawn({
 			let events_rx = events_rx.clone();
 			let ignore_path_rx = ignore_path_rx.clone();
 			let stop_rx = stop_rx.clone();
 			async move {
-				while let Err(e) = spawn(
-					Self::handle_watch_events(
-						location_id,
-						location_pub_id,
-						Arc::clone(&node),
-						Arc::clone(&library),
-						events_rx.clone(),
-						ignore_path_rx.clone(),
-						stop_rx.clone(),
+				while let Err(e) = if is_polling {
+					spawn(
+						Self::handle_watch_events::<polling::EventHandler>(
+							location_id,
+							location_pub_id,
+							Arc::clone(&node),
+							Arc::clone(&library),
+							events_rx.clone(),
+							ignore_path_rx.clone(),
+							stop_rx.clone(),
+						)
+						.in_current_span(),
+					)
+				} else {
+					spawn(
+						Self::handle_watch_events::<Handler>(
+							location_id,
+							location_pub_id,
+							Arc::clone(&node),
+							Arc::clone(&library),
+							events_rx.clone(),
+							ignore_path_rx.clone(),
+							stop_rx.clone(),
+						)
+						.in_current_span(),
 					)
-					.in_current_span(),
-				)
+				}
 				.await
 				{
 					if e.is_panic() {
@@ -182,14 +267,14 @@ impl LocationWatcher {
 		Ok(Self {
 			location_id,
 			location_path,
-			watcher,
+			backend,
 			ignore_path_tx,
 			handle: Some(handle),
 			stop_tx,
 		})
 	}
 
-	async fn handle_watch_events(
+	async fn handle_watch_events<H: EventHandler + Send>(
 		location_id: location::id::Type,
 		location_pub_id: Uuid,
 		node: Arc<Node>,
@@ -205,7 +290,7 @@ impl LocationWatcher {
 			Stop,
 		}
 
-		let mut event_handler = Handler::new(
+		let mut event_handler = H::new(
 			location_id,
 			uuid_to_bytes(&location_pub_id),
 			Arc::clone(&library),
@@ -249,7 +334,7 @@ impl LocationWatcher {
 
 					last_event_at = Instant::now();
 
-					if let Err(e) = Self::handle_single_event(
+					match Self::handle_single_event(
 						location_pub_id,
 						cached_location_path.as_deref(),
 						event,
@@ -260,7 +345,9 @@ impl LocationWatcher {
 					)
 					.await
 					{
-						error!(?e, "Failed to handle location file system event;");
+						Ok(true) => node.workflows.location_changed(library.id, location_id),
+						Ok(false) => {}
+						Err(e) => error!(?e, "Failed to handle location file system event;"),
 					}
 				}
 
@@ -284,6 +371,7 @@ impl LocationWatcher {
 		}
 	}
 
+	/// Returns if the event was handled, rejected events and events for offline locations aren't
 	#[instrument(skip_all, fields(?event, ?ignore_paths, ?location_path))]
 	async fn handle_single_event(
 		location_pub_id: Uuid,
@@ -293,17 +381,17 @@ impl LocationWatcher {
 		node: &Node,
 		ignore_paths: &HashSet<PathBuf>,
 		indexer_ruler: Option<&IndexerRuler>,
-	) -> Result<(), LocationManagerError> {
+	) -> Result<bool, LocationManagerError> {
 		if reject_event(&event, ignore_paths, location_path, indexer_ruler).await {
-			return Ok(());
+			return Ok(false);
 		}
 
 		if !node.locations.is_online(&location_pub_id).await {
 			warn!("Tried to handle event for offline location");
-			return Ok(());
+			return Ok(false);
 		}
 
-		event_handler.handle_event(event).await
+		event_handler.handle_event(event).await.map(|()| true)
 	}
 
 	#[instrument(
@@ -335,14 +423,19 @@ impl LocationWatcher {
 	pub(super) fn watch(&mut self) {
 		trace!("Start watching location");
 
-		if let Err(e) = self
-			.watcher
-			.watch(self.location_path.as_path(), RecursiveMode::Recursive)
-		{
-			error!(?e, "Unable to watch location;");
-		} else {
-			trace!("Now watching location");
+		match &mut self.backend {
+			Backend::Native(watcher) => {
+				if let Err(e) =
+					watcher.watch(self.location_path.as_path(), RecursiveMode::Recursive)
+				{
+					error!(?e, "Unable to watch location;");
+					return;
+				}
+			}
+			Backend::Polling(watcher) => watcher.watch(),
 		}
+
+		trace!("Now watching location");
 	}
 
 	#[instrument(
@@ -353,7 +446,16 @@ impl LocationWatcher {
 		),
 	)]
 	pub(super) fn unwatch(&mut self) {
-		if let Err(e) = self.watcher.unwatch(self.location_path.as_path()) {
+		let watcher = match &mut self.backend {
+			Backend::Native(watcher) => watcher,
+			Backend::Polling(watcher) => {
+				watcher.unwatch();
+				trace!("Stop polling location");
+				return;
+			}
+		};
+
+		if let Err(e) = watcher.unwatch(self.location_path.as_path()) {
 			/**************************************** TODO: ****************************************
 			 * According to an unit test, this error may occur when a subdirectory is removed	   *
 			 * and we try to unwatch the parent directory then we have to check the implications   *
@@ -393,26 +495,21 @@ async fn get_cached_indexer_ruler_and_location_path(
 	db: &PrismaClient,
 ) -> Result<(), LocationManagerError> {
 	if cached_indexer_ruler.is_none() || last_event_at.elapsed() > THIRTY_SECONDS {
-		if let Some(location_with_indexer_rules::Data {
-			path,
-			indexer_rules,
-			..
-		}) = db
+		if let Some(location) = db
 			.location()
 			.find_unique(location::id::equals(location_id))
 			.include(location_with_indexer_rules::include())
 			.exec()
 			.await?
 		{
+			// Events inside nested locations are left for their own watchers
 			*cached_indexer_ruler = Some(
-				indexer_rules
-					.iter()
-					.map(|rule| IndexerRule::try_from(&rule.indexer_rule))
-					.collect::<Result<Vec<_>, _>>()
-					.map(IndexerRuler::new)?,
+				location_indexer_ruler(&location, db)
+					.await
+					.map_err(sd_core_heavy_lifting::Error::from)?,
 			);
 
-			*location_path = path.map(Into::into);
+			*location_path = location.path.map(Into::into);
 		}
 	}
 
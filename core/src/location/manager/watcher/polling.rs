@@ -0,0 +1,832 @@
+//! Network file systems (NFS, SMB) and most FUSE mounts don't emit native file system events, so
+//! for locations on them we poll at a fixed interval instead.
+//! We only keep the inode and modification time of every directory in the location, as adding,
+//! removing or renaming an entry updates the modification time of its parent directory. Each poll
+//! stats those directories and re-lists only the ones that changed, comparing their entries against
+//! the `file_path`s we have for them on the database. Entries that disappeared and appeared with
+//! the same inode were renamed, and everything else is a creation, update or removal. The
+//! differences are sent as regular `notify` events, so they go through the same ignore paths and
+//! indexer rules checks as the native ones, and are handled by our [`EventHandler`] below.
+//!
+//! As a consequence, a file rewritten in place, which doesn't touch its directory, is only picked
+//! up the next time something else changes in that directory.
+
+use crate::{library::Library, location::manager::LocationManagerError, Node};
+
+use sd_core_file_path_helper::IsolatedFilePathData;
+
+use sd_prisma::prisma::{file_path, location};
+use sd_utils::{
+	db::{inode_from_db, size_in_bytes_from_db},
+	error::FileIOError,
+};
+
+use std::{
+	collections::{HashMap, HashSet},
+	fs::Metadata,
+	io::ErrorKind,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::{Duration, SystemTime},
+};
+
+use async_channel as chan;
+use notify::{
+	event::{CreateKind, DataChange, ModifyKind, RemoveKind, RenameMode},
+	Event, EventKind,
+};
+use tokio::{
+	fs, spawn,
+	task::JoinHandle,
+	time::{interval, Instant, MissedTickBehavior},
+};
+use tracing::{debug, error, instrument, trace, warn};
+
+use super::{
+	utils::{create_dir, recalculate_directories_size, remove, rename, update_file},
+	INode, HUNDRED_MILLIS,
+};
+
+/// Datetimes stored in the database lose a bit of precision, so we can't compare them exactly
+/// against the ones we get from the file system
+const MODIFIED_TOLERANCE: Duration = Duration::from_millis(1);
+
+#[cfg(target_family = "unix")]
+fn inode_from_metadata(metadata: &Metadata) -> INode {
+	use std::os::unix::fs::MetadataExt;
+
+	metadata.ino()
+}
+
+#[cfg(not(target_family = "unix"))]
+fn inode_from_metadata(_: &Metadata) -> INode {
+	0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Entry {
+	/// `0` when the platform doesn't give us inodes, so renames are seen as a remove and a create
+	inode: INode,
+	is_dir: bool,
+	size: u64,
+	modified: Option<SystemTime>,
+}
+
+impl Entry {
+	fn from_metadata(metadata: &Metadata) -> Self {
+		Self {
+			inode: inode_from_metadata(metadata),
+			is_dir: metadata.is_dir(),
+			size: metadata.len(),
+			modified: metadata.modified().ok(),
+		}
+	}
+
+	fn from_db(
+		file_path_to_poll::Data {
+			is_dir,
+			inode,
+			size_in_bytes_bytes,
+			date_modified,
+			..
+		}: &file_path_to_poll::Data,
+	) -> Self {
+		Self {
+			// Must match what `inode_from_metadata` gives us for the same entry
+			inode: inode
+				.as_deref()
+				.filter(|inode| cfg!(target_family = "unix") && inode.len() >= 8)
+				.map_or(0, |inode| inode_from_db(&inode[0..8])),
+			is_dir: is_dir.unwrap_or_default(),
+			size: size_in_bytes_bytes
+				.as_deref()
+				.map_or(0, size_in_bytes_from_db),
+			modified: date_modified.map(SystemTime::from),
+		}
+	}
+
+	fn has_changed(&self, other: &Self) -> bool {
+		let modified_changed = match (self.modified, other.modified) {
+			(Some(modified), Some(other_modified)) => {
+				modified
+					.duration_since(other_modified)
+					.unwrap_or_else(|e| e.duration())
+					> MODIFIED_TOLERANCE
+			}
+			(modified, other_modified) => modified.is_some() != other_modified.is_some(),
+		};
+
+		self.size != other.size || modified_changed
+	}
+}
+
+type Snapshot = HashMap<PathBuf, Entry>;
+
+/// Changes whenever an entry is added to, removed from or renamed inside the directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DirectoryState {
+	inode: INode,
+	modified: Option<SystemTime>,
+}
+
+impl DirectoryState {
+	fn from_metadata(metadata: &Metadata) -> Self {
+		Self {
+			inode: inode_from_metadata(metadata),
+			modified: metadata.modified().ok(),
+		}
+	}
+}
+
+type Directories = HashMap<PathBuf, DirectoryState>;
+
+file_path::select!(file_path_to_poll {
+	is_dir
+	name
+	extension
+	inode
+	size_in_bytes_bytes
+	date_modified
+});
+
+/// Records the state of `root` and of every directory under it, without following symlinks.
+/// Directories that can't be read are skipped
+async fn walk_directories(root: &Path, directories: &mut Directories) -> Result<(), FileIOError> {
+	let metadata = fs::symlink_metadata(root)
+		.await
+		.map_err(|e| FileIOError::from((root, e)))?;
+
+	directories.insert(root.to_path_buf(), DirectoryState::from_metadata(&metadata));
+
+	let mut to_walk = vec![root.to_path_buf()];
+
+	while let Some(dir) = to_walk.pop() {
+		let mut read_dir = match fs::read_dir(&dir).await {
+			Ok(read_dir) => read_dir,
+			Err(e) => {
+				if e.kind() != ErrorKind::NotFound {
+					warn!(?e, dir = %dir.display(), "Failed to read directory while polling;");
+				}
+				continue;
+			}
+		};
+
+		loop {
+			let dir_entry = match read_dir.next_entry().await {
+				Ok(Some(dir_entry)) => dir_entry,
+				Ok(None) => break,
+				Err(e) => {
+					warn!(?e, dir = %dir.display(), "Failed to read directory entry while polling;");
+					break;
+				}
+			};
+
+			let Ok(metadata) = dir_entry.metadata().await else {
+				continue;
+			};
+
+			if metadata.is_dir() {
+				let path = dir_entry.path();
+				directories.insert(path.clone(), DirectoryState::from_metadata(&metadata));
+				to_walk.push(path);
+			}
+		}
+	}
+
+	Ok(())
+}
+
+/// Stats every known directory, forgetting the ones that are gone and returning the new state of
+/// the ones whose entries changed since the last poll
+async fn changed_directories(
+	location_path: &Path,
+	directories: &mut Directories,
+) -> Result<Vec<(PathBuf, DirectoryState)>, FileIOError> {
+	// The location itself being unreachable means the mount is gone, so we don't want to report
+	// everything as removed
+	fs::symlink_metadata(location_path)
+		.await
+		.map_err(|e| FileIOError::from((location_path, e)))?;
+
+	let mut changed = vec![];
+	let mut gone = vec![];
+
+	for (path, state) in directories.iter() {
+		match fs::symlink_metadata(path).await {
+			Ok(metadata) if metadata.is_dir() => {
+				let new_state = DirectoryState::from_metadata(&metadata);
+				if new_state != *state {
+					changed.push((path.clone(), new_state));
+				}
+			}
+			Ok(_) => gone.push(path.clone()),
+			Err(e) if e.kind() == ErrorKind::NotFound => gone.push(path.clone()),
+			Err(e) => {
+				warn!(?e, dir = %path.display(), "Failed to stat directory while polling;");
+			}
+		}
+	}
+
+	// Their parents changed as well, so they get their events from there
+	for path in gone {
+		directories.remove(&path);
+	}
+
+	Ok(changed)
+}
+
+/// Direct children of `dir` as they are on the database and as they are on disk
+async fn list_directory(
+	location_id: location::id::Type,
+	location_path: &Path,
+	dir: &Path,
+	library: &Library,
+) -> Result<(Snapshot, Snapshot), LocationManagerError> {
+	let children_materialized_path =
+		IsolatedFilePathData::new(location_id, location_path, dir, true)?
+			.materialized_path_for_children()
+			.unwrap_or_else(|| "/".to_string());
+
+	let in_db = library
+		.db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(Some(location_id)),
+			file_path::materialized_path::equals(Some(children_materialized_path)),
+		])
+		.select(file_path_to_poll::select())
+		.exec()
+		.await?
+		.into_iter()
+		.filter_map(|file_path| {
+			let name = file_path.name.as_deref()?;
+			let full_name = match file_path.extension.as_deref() {
+				Some(extension) if !extension.is_empty() => format!("{name}.{extension}"),
+				_ => name.to_string(),
+			};
+
+			Some((dir.join(full_name), Entry::from_db(&file_path)))
+		})
+		.collect();
+
+	let mut on_disk = Snapshot::new();
+	let mut read_dir = fs::read_dir(dir)
+		.await
+		.map_err(|e| FileIOError::from((dir, e)))?;
+
+	while let Some(dir_entry) = read_dir
+		.next_entry()
+		.await
+		.map_err(|e| FileIOError::from((dir, e)))?
+	{
+		let Ok(metadata) = dir_entry.metadata().await else {
+			continue;
+		};
+
+		if !metadata.is_symlink() {
+			on_disk.insert(dir_entry.path(), Entry::from_metadata(&metadata));
+		}
+	}
+
+	Ok((in_db, on_disk))
+}
+
+/// Compares the changed directories against the database, updating their state once they were
+/// listed, so the ones that failed are retried on the next poll
+async fn poll_changes(
+	location_id: location::id::Type,
+	location_path: &Path,
+	directories: &mut Directories,
+	library: &Library,
+) -> Result<Vec<Event>, FileIOError> {
+	let mut in_db = Snapshot::new();
+	let mut on_disk = Snapshot::new();
+
+	for (dir, state) in changed_directories(location_path, directories).await? {
+		let (dir_in_db, dir_on_disk) =
+			match list_directory(location_id, location_path, &dir, library).await {
+				Ok(entries) => entries,
+				Err(e) => {
+					debug!(?e, dir = %dir.display(), "Failed to list changed directory;");
+					continue;
+				}
+			};
+
+		for (path, entry) in &dir_on_disk {
+			if entry.is_dir && !directories.contains_key(path) {
+				if let Err(e) = walk_directories(path, directories).await {
+					debug!(?e, "Failed to walk new directory;");
+				}
+			}
+		}
+
+		directories.insert(dir, state);
+		in_db.extend(dir_in_db);
+		on_disk.extend(dir_on_disk);
+	}
+
+	Ok(diff(&in_db, &on_disk))
+}
+
+fn event(kind: EventKind, paths: impl IntoIterator<Item = PathBuf>) -> Event {
+	paths.into_iter().fold(Event::new(kind), Event::add_path)
+}
+
+/// Events turning the `old` snapshot into the `new` one.
+///
+/// Only the top most entry of a created, removed or renamed directory gets an event, as the
+/// utilities handling them already take care of the directory's contents.
+fn diff(old: &Snapshot, new: &Snapshot) -> Vec<Event> {
+	let mut removed = old
+		.keys()
+		.filter(|path| !new.contains_key(*path))
+		.collect::<Vec<_>>();
+	// Parents before their children
+	removed.sort_by_key(|path| path.components().count());
+
+	let mut created = new
+		.keys()
+		.filter(|path| !old.contains_key(*path))
+		.collect::<HashSet<_>>();
+
+	let created_by_inode = created
+		.iter()
+		.filter(|path| new[**path].inode != 0)
+		.map(|path| (new[*path].inode, *path))
+		.collect::<HashMap<_, _>>();
+
+	let mut events = vec![];
+	let mut renamed_dirs = Vec::<(&Path, &Path)>::new();
+	let mut removed_dirs = Vec::<&Path>::new();
+
+	for from in removed {
+		let old_entry = &old[from];
+
+		// Moved along with a renamed parent directory
+		if let Some(to) = renamed_dirs.iter().find_map(|(from_dir, to_dir)| {
+			from.strip_prefix(from_dir)
+				.ok()
+				.map(|relative| to_dir.join(relative))
+				.filter(|to| created.contains(to))
+		}) {
+			if !old_entry.is_dir && old_entry.has_changed(&new[&to]) {
+				events.push(event(
+					EventKind::Modify(ModifyKind::Data(DataChange::Content)),
+					[to.clone()],
+				));
+			}
+			created.remove(&to);
+			continue;
+		}
+
+		if removed_dirs.iter().any(|dir| from.starts_with(dir)) {
+			continue;
+		}
+
+		let renamed_to = Some(old_entry.inode)
+			.filter(|inode| *inode != 0)
+			.and_then(|inode| created_by_inode.get(&inode).copied())
+			.filter(|to| created.contains(to) && new[*to].is_dir == old_entry.is_dir);
+
+		if let Some(to) = renamed_to {
+			created.remove(to);
+
+			events.push(event(
+				EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+				[from.clone(), to.clone()],
+			));
+
+			if old_entry.is_dir {
+				renamed_dirs.push((from.as_path(), to.as_path()));
+			} else if old_entry.has_changed(&new[to]) {
+				events.push(event(
+					EventKind::Modify(ModifyKind::Data(DataChange::Content)),
+					[to.clone()],
+				));
+			}
+		} else {
+			events.push(event(
+				EventKind::Remove(if old_entry.is_dir {
+					RemoveKind::Folder
+				} else {
+					RemoveKind::File
+				}),
+				[from.clone()],
+			));
+
+			if old_entry.is_dir {
+				removed_dirs.push(from.as_path());
+			}
+		}
+	}
+
+	let mut created = created.into_iter().collect::<Vec<_>>();
+	created.sort_by_key(|path| path.components().count());
+
+	let mut created_dirs = Vec::<&Path>::new();
+
+	for path in created {
+		if created_dirs.iter().any(|dir| path.starts_with(dir)) {
+			continue;
+		}
+
+		if new[path].is_dir {
+			events.push(event(EventKind::Create(CreateKind::Folder), [path.clone()]));
+			created_dirs.push(path.as_path());
+		} else {
+			events.push(event(EventKind::Create(CreateKind::File), [path.clone()]));
+		}
+	}
+
+	events.extend(new.iter().filter_map(|(path, new_entry)| {
+		old.get(path)
+			.filter(|old_entry| {
+				!old_entry.is_dir
+					&& !new_entry.is_dir
+					&& (old_entry.has_changed(new_entry) || old_entry.inode != new_entry.inode)
+			})
+			.map(|_| {
+				event(
+					EventKind::Modify(ModifyKind::Data(DataChange::Content)),
+					[path.clone()],
+				)
+			})
+	}));
+
+	events
+}
+
+/// Polls the location while it's being watched, sending the changes to the location watcher
+#[derive(Debug)]
+pub(super) struct PollWatcher {
+	location_id: location::id::Type,
+	location_path: PathBuf,
+	library: Arc<Library>,
+	interval: Duration,
+	events_tx: chan::Sender<notify::Result<Event>>,
+	handle: Option<JoinHandle<()>>,
+}
+
+impl PollWatcher {
+	pub(super) fn new(
+		location_id: location::id::Type,
+		location_path: PathBuf,
+		library: Arc<Library>,
+		interval: Duration,
+		events_tx: chan::Sender<notify::Result<Event>>,
+	) -> Self {
+		Self {
+			location_id,
+			location_path,
+			library,
+			interval,
+			events_tx,
+			handle: None,
+		}
+	}
+
+	pub(super) fn watch(&mut self) {
+		if self.handle.is_some() {
+			return;
+		}
+
+		let location_id = self.location_id;
+		let location_path = self.location_path.clone();
+		let library = Arc::clone(&self.library);
+		let poll_interval = self.interval;
+		let events_tx = self.events_tx.clone();
+
+		self.handle = Some(spawn(async move {
+			let mut interval = interval(poll_interval);
+			interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+			// Empty until the first walk succeeds, which is only the starting point to compare
+			// against
+			let mut directories = Directories::new();
+
+			loop {
+				interval.tick().await;
+
+				if directories.is_empty() {
+					if let Err(e) = walk_directories(&location_path, &mut directories).await {
+						debug!(?e, "Failed to walk location, retrying on the next poll;");
+						directories.clear();
+					}
+					continue;
+				}
+
+				let events =
+					match poll_changes(location_id, &location_path, &mut directories, &library)
+						.await
+					{
+						Ok(events) => events,
+						Err(e) => {
+							debug!(?e, "Failed to poll location, keeping the last state;");
+							continue;
+						}
+					};
+
+				trace!(count = events.len(), "Polled location changes;");
+
+				for event in events {
+					if events_tx.send(Ok(event)).await.is_err() {
+						return;
+					}
+				}
+			}
+		}));
+	}
+
+	pub(super) fn unwatch(&mut self) {
+		if let Some(handle) = self.handle.take() {
+			handle.abort();
+		}
+	}
+}
+
+impl Drop for PollWatcher {
+	fn drop(&mut self) {
+		self.unwatch();
+	}
+}
+
+/// Handles the events of [`PollWatcher`], they are already deduplicated and their renames
+/// already matched, so unlike the platform handlers we don't have to wait for more events
+#[derive(Debug)]
+pub(super) struct EventHandler {
+	location_id: location::id::Type,
+	location_pub_id: location::pub_id::Type,
+	library: Arc<Library>,
+	node: Arc<Node>,
+	last_events_eviction_check: Instant,
+	to_recalculate_size: HashMap<PathBuf, Instant>,
+
+	path_and_instant_buffer: Vec<(PathBuf, Instant)>,
+}
+
+impl EventHandler {
+	fn recalculate_size_of_parent(&mut self, path: &Path) {
+		if let Some(parent) = path.parent() {
+			if parent != Path::new("") {
+				self.to_recalculate_size
+					.insert(parent.to_path_buf(), Instant::now());
+			}
+		}
+	}
+}
+
+impl super::EventHandler for EventHandler {
+	fn new(
+		location_id: location::id::Type,
+		location_pub_id: location::pub_id::Type,
+		library: Arc<Library>,
+		node: Arc<Node>,
+	) -> Self
+	where
+		Self: Sized,
+	{
+		Self {
+			location_id,
+			location_pub_id,
+			library,
+			node,
+			last_events_eviction_check: Instant::now(),
+			to_recalculate_size: HashMap::new(),
+			path_and_instant_buffer: Vec::new(),
+		}
+	}
+
+	#[instrument(
+		skip_all,
+		fields(
+			location_id = %self.location_id,
+			library_id = %self.library.id,
+			waiting_size_count = %self.to_recalculate_size.len(),
+		),
+	)]
+	async fn handle_event(&mut self, event: Event) -> Result<(), LocationManagerError> {
+		trace!("Received polled event");
+
+		let Event {
+			kind, mut paths, ..
+		} = event;
+
+		match kind {
+			EventKind::Create(CreateKind::File)
+			| EventKind::Modify(ModifyKind::Data(DataChange::Content)) => {
+				let path = paths.remove(0);
+
+				update_file(self.location_id, &path, &self.node, &self.library).await?;
+
+				self.recalculate_size_of_parent(&path);
+			}
+
+			EventKind::Create(CreateKind::Folder) => {
+				let path = paths.remove(0);
+
+				create_dir(
+					self.location_id,
+					&path,
+					&fs::metadata(&path)
+						.await
+						.map_err(|e| FileIOError::from((&path, e)))?,
+					&self.node,
+					&self.library,
+				)
+				.await?;
+			}
+
+			EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+				let to_path = paths.remove(1);
+				let from_path = paths.remove(0);
+
+				rename(
+					self.location_id,
+					&to_path,
+					&from_path,
+					fs::metadata(&to_path)
+						.await
+						.map_err(|e| FileIOError::from((&to_path, e)))?,
+					&self.library,
+				)
+				.await?;
+
+				self.recalculate_size_of_parent(&from_path);
+				self.recalculate_size_of_parent(&to_path);
+			}
+
+			EventKind::Remove(_) => {
+				let path = paths.remove(0);
+
+				remove(self.location_id, &path, &self.library).await?;
+
+				self.recalculate_size_of_parent(&path);
+			}
+
+			_ => {
+				trace!("Other event that we don't poll for");
+			}
+		}
+
+		Ok(())
+	}
+
+	async fn tick(&mut self) {
+		if self.last_events_eviction_check.elapsed() > HUNDRED_MILLIS {
+			if !self.to_recalculate_size.is_empty() {
+				if let Err(e) = recalculate_directories_size(
+					&mut self.to_recalculate_size,
+					&mut self.path_and_instant_buffer,
+					self.location_id,
+					self.location_pub_id.clone(),
+					&self.library,
+				)
+				.await
+				{
+					error!(?e, "Failed to recalculate directories size;");
+				}
+			}
+
+			self.last_events_eviction_check = Instant::now();
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn entry(inode: INode, is_dir: bool, size: u64) -> Entry {
+		Entry {
+			inode,
+			is_dir,
+			size,
+			modified: None,
+		}
+	}
+
+	fn kinds_and_paths(mut events: Vec<Event>) -> Vec<(EventKind, Vec<PathBuf>)> {
+		events.sort_by(|a, b| a.paths.cmp(&b.paths));
+		events
+			.into_iter()
+			.map(|event| (event.kind, event.paths))
+			.collect()
+	}
+
+	#[test]
+	fn test_diff() {
+		let old = Snapshot::from([
+			("/l/a".into(), entry(1, true, 0)),
+			("/l/a/x.txt".into(), entry(2, false, 10)),
+			("/l/a/y.txt".into(), entry(3, false, 10)),
+			("/l/b.txt".into(), entry(4, false, 10)),
+			("/l/c".into(), entry(5, true, 0)),
+			("/l/c/z.txt".into(), entry(6, false, 10)),
+			("/l/d.txt".into(), entry(7, false, 10)),
+		]);
+
+		let new = Snapshot::from([
+			// `a` renamed to `e`, with `y.txt` updated
+			("/l/e".into(), entry(1, true, 0)),
+			("/l/e/x.txt".into(), entry(2, false, 10)),
+			("/l/e/y.txt".into(), entry(3, false, 20)),
+			// `b.txt` updated
+			("/l/b.txt".into(), entry(4, false, 20)),
+			// `c` removed and `f` created
+			("/l/f".into(), entry(8, true, 0)),
+			("/l/f/w.txt".into(), entry(9, false, 10)),
+			// `d.txt` renamed to `g.txt`
+			("/l/g.txt".into(), entry(7, false, 10)),
+		]);
+
+		assert_eq!(
+			kinds_and_paths(diff(&old, &new)),
+			vec![
+				(
+					EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+					vec!["/l/a".into(), "/l/e".into()]
+				),
+				(
+					EventKind::Modify(ModifyKind::Data(DataChange::Content)),
+					vec!["/l/b.txt".into()]
+				),
+				(EventKind::Remove(RemoveKind::Folder), vec!["/l/c".into()]),
+				(
+					EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+					vec!["/l/d.txt".into(), "/l/g.txt".into()]
+				),
+				(
+					EventKind::Modify(ModifyKind::Data(DataChange::Content)),
+					vec!["/l/e/y.txt".into()]
+				),
+				(EventKind::Create(CreateKind::Folder), vec!["/l/f".into()]),
+			]
+		);
+
+		assert!(diff(&new, &new).is_empty());
+	}
+
+	#[test]
+	fn database_precision_loss_is_not_a_change() {
+		let on_disk = Entry {
+			modified: Some(SystemTime::UNIX_EPOCH + Duration::from_nanos(1_000_123_456)),
+			..entry(1, false, 10)
+		};
+		let in_db = Entry {
+			modified: Some(SystemTime::UNIX_EPOCH + Duration::from_millis(1_000)),
+			..entry(1, false, 10)
+		};
+
+		assert!(!in_db.has_changed(&on_disk));
+		assert!(in_db.has_changed(&Entry {
+			modified: on_disk
+				.modified
+				.map(|modified| modified + Duration::from_millis(5)),
+			..on_disk
+		}));
+	}
+
+	#[tokio::test]
+	async fn only_directories_with_changed_entries_are_listed() {
+		let root = tempfile::tempdir().unwrap();
+		let root = root.path();
+
+		fs::create_dir_all(root.join("a/b")).await.unwrap();
+		fs::create_dir(root.join("c")).await.unwrap();
+		fs::write(root.join("a/b/x.txt"), b"x").await.unwrap();
+
+		let mut directories = Directories::new();
+		walk_directories(root, &mut directories).await.unwrap();
+
+		assert_eq!(
+			directories.keys().cloned().collect::<HashSet<_>>(),
+			HashSet::from([
+				root.to_path_buf(),
+				root.join("a"),
+				root.join("a/b"),
+				root.join("c")
+			])
+		);
+		assert!(changed_directories(root, &mut directories)
+			.await
+			.unwrap()
+			.is_empty());
+
+		// Directory modification times can be as coarse as the kernel tick
+		tokio::time::sleep(Duration::from_millis(50)).await;
+
+		fs::write(root.join("a/b/y.txt"), b"y").await.unwrap();
+		fs::remove_dir(root.join("c")).await.unwrap();
+
+		let mut changed = changed_directories(root, &mut directories)
+			.await
+			.unwrap()
+			.into_iter()
+			.map(|(path, _)| path)
+			.collect::<Vec<_>>();
+		changed.sort();
+
+		assert_eq!(changed, vec![root.to_path_buf(), root.join("a/b")]);
+		assert!(!directories.contains_key(&root.join("c")));
+	}
+}
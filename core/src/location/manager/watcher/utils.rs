@@ -2,7 +2,7 @@ use crate::{
 	invalidate_query,
 	library::Library,
 	location::{
-		create_file_path, delete_directory, find_location,
+		archive::is_archived, create_file_path, delete_directory, find_location,
 		indexer::reverse_update_directories_sizes, location_with_indexer_rules,
 		manager::LocationManagerError, scan_location_sub_path, update_location_size,
 	},
@@ -1057,6 +1057,11 @@ async fn remove_by_file_path(
 		Err(e) if e.kind() == ErrorKind::NotFound => {
 			let Library { sync, db, .. } = library;
 
+			// Archived files were removed from disk on purpose, they must stay in the library
+			if is_archived(db, file_path.id).await? {
+				return Ok(());
+			}
+
 			let is_dir = maybe_missing(file_path.is_dir, "file_path.is_dir")?;
 
 			// if is doesn't, we can remove it safely from our db
@@ -32,21 +32,33 @@ use std::{
 use chrono::Utc;
 use futures::future::TryFutureExt;
 use normpath::PathExt;
-use prisma_client_rust::{operator::and, or, QueryError};
+use prisma_client_rust::{operator::and, or};
 use serde::{Deserialize, Serialize};
 use specta::Type;
 use tokio::{fs, io, time::Instant};
 use tracing::{debug, error, info, instrument, warn};
 use uuid::Uuid;
 
+pub mod archive;
+mod availability;
 mod error;
 mod manager;
 pub mod metadata;
+mod nested;
 pub mod non_indexed;
+mod promote;
+mod size_tree;
 
+pub use availability::{locations_availability, Availability};
 pub use error::LocationError;
-pub use manager::{LocationManagerError, Locations};
+pub use manager::{LocationManagerError, Locations, WatcherMode};
 use metadata::SpacedriveLocationMetadataFile;
+pub use nested::{overlapping_locations, OverlappingLocations};
+pub use promote::{LocationPromoteArgs, OverlapResolution, Promoted};
+pub use size_tree::{size_tree, SizeTree};
+
+pub(crate) use availability::{location_came_online, location_went_offline};
+use size_tree::snapshot_directory_sizes;
 
 pub type LocationPubId = Uuid;
 
@@ -454,6 +466,11 @@ pub async fn scan_location(
 
 	let job_id = match location_scan_state {
 		ScanState::Pending | ScanState::Completed => {
+			// Keeping the sizes from before the scan, to tell what it changed
+			if let Err(e) = snapshot_directory_sizes(&library.db, location_id).await {
+				warn!(?e, "Failed to snapshot directory sizes before scanning;");
+			}
+
 			node.job_system
 				.dispatch(
 					JobEnqueuer::new(Indexer::new(location, None)?)
@@ -725,10 +742,6 @@ async fn create_location(
 		return Err(LocationError::LocationAlreadyExists(location_path.into()));
 	}
 
-	if check_nested_location(&location_path, db).await? {
-		return Err(LocationError::NestedLocation(location_path.into()));
-	}
-
 	if dry_run {
 		return Ok(None);
 	}
@@ -784,6 +797,15 @@ async fn create_location(
 		.await?
 		.ok_or(LocationError::IdNotFound(location_id))?;
 
+	// A nested location takes over the file paths already indexed by the location containing it,
+	// which leaves them out from now on
+	if let Some(path) = &location.path {
+		if let Some(parent) = overlapping_locations(path, library).await?.parent {
+			nested::hand_over_file_paths(library, &parent, &location::Data::from(&location))
+				.await?;
+		}
+	}
+
 	invalidate_query!(library, "locations.list");
 
 	Ok(Some(CreatedLocationResult {
@@ -804,10 +826,6 @@ pub async fn delete_location(
 	node.locations.remove(location_id, library.clone()).await?;
 	debug!(elapsed_time = ?start.elapsed(), "Removed location from node;");
 
-	let start = Instant::now();
-	delete_directory(library, location_id, None).await?;
-	debug!(elapsed_time = ?start.elapsed(), "Deleted location file paths;");
-
 	let location = library
 		.db
 		.location()
@@ -816,6 +834,19 @@ pub async fn delete_location(
 		.await?
 		.ok_or(LocationError::IdNotFound(location_id))?;
 
+	// The location containing a nested one takes its file paths back, instead of indexing them again
+	if location.instance_id == Some(library.config().await.instance_id) {
+		if let Some(path) = &location.path {
+			if let Some(parent) = overlapping_locations(path, library).await?.parent {
+				nested::hand_over_file_paths(library, &location, &parent).await?;
+			}
+		}
+	}
+
+	let start = Instant::now();
+	delete_directory(library, location_id, None).await?;
+	debug!(elapsed_time = ?start.elapsed(), "Deleted location file paths;");
+
 	let start = Instant::now();
 	// TODO: This should really be queued to the proper node so it will always run
 	// TODO: Deal with whether a location is online or not
@@ -885,16 +916,20 @@ pub async fn delete_directory(
 
 	let children_params = sd_utils::chain_optional_iter(
 		[file_path::location_id::equals(Some(location_id))],
-		[parent_iso_file_path.and_then(|parent| {
-			parent
-				.materialized_path_for_children()
-				.map(|materialized_path| {
-					or![
-						and(filter_existing_file_path_params(parent)),
-						file_path::materialized_path::starts_with(materialized_path),
-					]
-				})
-		})],
+		[
+			parent_iso_file_path.and_then(|parent| {
+				parent
+					.materialized_path_for_children()
+					.map(|materialized_path| {
+						or![
+							and(filter_existing_file_path_params(parent)),
+							file_path::materialized_path::starts_with(materialized_path),
+						]
+					})
+			}),
+			// Archived file paths are only gone from disk, they're removed along with the location
+			parent_iso_file_path.map(|_| archive::not_archived()),
+		],
 	);
 
 	let pub_ids = library
@@ -939,63 +974,6 @@ pub async fn delete_directory(
 	Ok(())
 }
 
-#[instrument(skip_all, err)]
-async fn check_nested_location(
-	location_path: impl AsRef<Path>,
-	db: &PrismaClient,
-) -> Result<bool, QueryError> {
-	let location_path = location_path.as_ref();
-
-	let (parents_count, potential_children) = db
-		._batch((
-			db.location().count(vec![location::path::in_vec(
-				location_path
-					.ancestors()
-					.skip(1) // skip the actual location_path, we only want the parents
-					.map(|p| {
-						p.to_str()
-							.map(str::to_string)
-							.expect("Found non-UTF-8 path")
-					})
-					.collect(),
-			)]),
-			db.location().find_many(vec![location::path::starts_with(
-				location_path
-					.to_str()
-					.map(str::to_string)
-					.expect("Found non-UTF-8 path"),
-			)]),
-		))
-		.await?;
-
-	let comps = location_path.components().collect::<Vec<_>>();
-	let is_a_child_location = potential_children.into_iter().any(|v| {
-		let Some(location_path) = v.path else {
-			warn!(
-				location_id = %v.id,
-				"Missing location path on location at check nested location",
-			);
-			return false;
-		};
-		let comps2 = PathBuf::from(location_path);
-		let comps2 = comps2.components().collect::<Vec<_>>();
-
-		if comps.len() > comps2.len() {
-			return false;
-		}
-
-		for (a, b) in comps.iter().zip(comps2.iter()) {
-			if a != b {
-				return false;
-			}
-		}
-
-		true
-	});
-
-	Ok(parents_count > 0 || is_a_child_location)
-}
-
 #[instrument(skip_all, err)]
 pub async fn update_location_size(
 	location_id: location::id::Type,
@@ -0,0 +1,398 @@
+use crate::library::Library;
+
+use sd_core_file_path_helper::{join_location_relative_path, IsolatedFilePathData};
+use sd_core_indexer_rules::{IndexerRule, IndexerRuler, MetadataForIndexerRules, RulerDecision};
+
+use sd_prisma::{
+	prisma::{device, file_path, indexer_rule, indexer_rules_in_location, location},
+	prisma_sync,
+};
+use sd_sync::*;
+use sd_utils::db::maybe_missing;
+
+use std::{
+	borrow::Cow,
+	path::{Component, Path},
+};
+
+use itertools::{Either, Itertools};
+use prisma_client_rust::QueryError;
+use serde::Serialize;
+use specta::Type;
+use tracing::{debug, instrument};
+
+use super::LocationError;
+
+/// Amount of file paths moved between locations in each sync batch
+const HAND_OVER_BATCH_SIZE: usize = 1000;
+
+/// Existing locations of this device containing, or contained in, a path
+#[derive(Serialize, Type, Debug, Default)]
+pub struct OverlappingLocations {
+	/// The closest location containing the path
+	pub parent: Option<location::Data>,
+	/// Every location inside the path, the path itself included
+	pub children: Vec<location::Data>,
+}
+
+impl OverlappingLocations {
+	pub fn is_empty(&self) -> bool {
+		self.parent.is_none() && self.children.is_empty()
+	}
+}
+
+/// Finds the locations of this device overlapping the path
+pub async fn overlapping_locations(
+	path: impl AsRef<Path>,
+	library: &Library,
+) -> Result<OverlappingLocations, QueryError> {
+	let Library { db, sync, .. } = library;
+	let path = path.as_ref();
+
+	let Some(path_str) = path.to_str() else {
+		return Ok(OverlappingLocations::default());
+	};
+
+	let on_this_device =
+		|| location::device::is(vec![device::pub_id::equals(sync.device_pub_id.to_db())]);
+
+	let (parents, potential_children) = db
+		._batch((
+			db.location().find_many(vec![
+				on_this_device(),
+				location::path::in_vec(
+					path.ancestors()
+						.skip(1) // skip the path itself, we only want its parents
+						.filter_map(|ancestor| ancestor.to_str().map(str::to_string))
+						.collect(),
+				),
+			]),
+			db.location().find_many(vec![
+				on_this_device(),
+				location::path::starts_with(path_str.to_string()),
+			]),
+		))
+		.await?;
+
+	Ok(OverlappingLocations {
+		parent: parents.into_iter().max_by_key(|parent| {
+			parent
+				.path
+				.as_deref()
+				.map_or(0, |parent_path| Path::new(parent_path).components().count())
+		}),
+		children: potential_children
+			.into_iter()
+			.filter(|child| {
+				child
+					.path
+					.as_deref()
+					.is_some_and(|child_path| Path::new(child_path).starts_with(path))
+			})
+			.collect(),
+	})
+}
+
+/// Moves the file paths of one location which belong to the other over to it, keeping their
+/// objects and media data, so nothing is indexed again.
+///
+/// When `to` is nested in `from`, the file paths inside `to` are moved, as `from` leaves out the
+/// contents of its nested locations. When `from` is nested in `to`, all of them are handed back.
+/// File paths rejected by the indexer rules of `to` are removed instead, as neither location
+/// indexes them anymore. Each batch is committed on its own, so the database isn't locked for the
+/// whole hand over. A failure leaves the batches already committed in `to`, and handing over again
+/// picks up the file paths left in `from`.
+#[instrument(skip_all, fields(from = %from.id, to = %to.id), err)]
+pub(super) async fn hand_over_file_paths(
+	Library { db, sync, .. }: &Library,
+	from: &location::Data,
+	to: &location::Data,
+) -> Result<usize, LocationError> {
+	let from_path = Path::new(maybe_missing(&from.path, "location.path")?);
+	let to_path = Path::new(maybe_missing(&to.path, "location.path")?);
+
+	let (old_prefix, new_prefix) = if let Ok(relative_path) = to_path.strip_prefix(from_path) {
+		(materialized_prefix(relative_path), "/".to_string())
+	} else if let Ok(relative_path) = from_path.strip_prefix(to_path) {
+		("/".to_string(), materialized_prefix(relative_path))
+	} else {
+		return Ok(0);
+	};
+
+	// Only the rules of `to` itself, as the location containing a nested one rejects its contents
+	// until it's deleted
+	let indexer_ruler = IndexerRuler::new(
+		db.indexer_rule()
+			.find_many(vec![indexer_rule::locations::some(vec![
+				indexer_rules_in_location::location_id::equals(to.id),
+			])])
+			.exec()
+			.await?
+			.iter()
+			.map(IndexerRule::try_from)
+			.collect::<Result<Vec<_>, _>>()?,
+	);
+
+	let file_paths = db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(Some(from.id)),
+			file_path::materialized_path::starts_with(old_prefix.clone()),
+		])
+		.select(file_path::select!({ id pub_id materialized_path is_dir name extension }))
+		.exec()
+		.await?
+		.into_iter()
+		.filter_map(|file_path| {
+			file_path
+				.materialized_path
+				// The database matches prefixes ignoring case
+				.filter(|materialized_path| materialized_path.starts_with(&old_prefix))
+				.map(|materialized_path| HandedOverFilePath {
+					id: file_path.id,
+					pub_id: file_path.pub_id,
+					materialized_path: format!(
+						"{new_prefix}{}",
+						&materialized_path[old_prefix.len()..]
+					),
+					is_dir: file_path.is_dir.unwrap_or_default(),
+					name: file_path.name.unwrap_or_default(),
+					extension: file_path.extension.unwrap_or_default(),
+				})
+		})
+		.collect::<Vec<_>>();
+
+	let (accepted, rejected) = split_by_rules(&indexer_ruler, to.id, to_path, file_paths).await?;
+
+	let total_file_paths = accepted.len();
+	let total_rejected = rejected.len();
+
+	for chunk in &accepted.into_iter().chunks(HAND_OVER_BATCH_SIZE) {
+		let (sync_params, db_params) = chunk
+			.map(|handed_over| {
+				let (sync_params, db_params) = [
+					(
+						sync_entry!(
+							prisma_sync::location::SyncId {
+								pub_id: to.pub_id.clone()
+							},
+							file_path::location
+						),
+						file_path::location::connect(location::id::equals(to.id)),
+					),
+					sync_db_entry!(handed_over.materialized_path, file_path::materialized_path),
+				]
+				.into_iter()
+				.unzip::<_, _, Vec<_>, Vec<_>>();
+
+				(
+					sync.shared_update(
+						prisma_sync::file_path::SyncId {
+							pub_id: handed_over.pub_id,
+						},
+						sync_params,
+					),
+					db.file_path()
+						.update(file_path::id::equals(handed_over.id), db_params)
+						.select(file_path::select!({ id })),
+				)
+			})
+			.unzip::<_, _, Vec<_>, Vec<_>>();
+
+		if !sync_params.is_empty() && !db_params.is_empty() {
+			sync.write_ops(db, (sync_params, db_params)).await?;
+		}
+	}
+
+	for chunk in &rejected.into_iter().chunks(HAND_OVER_BATCH_SIZE) {
+		let (sync_params, ids) = chunk
+			.map(|handed_over| {
+				(
+					sync.shared_delete(prisma_sync::file_path::SyncId {
+						pub_id: handed_over.pub_id,
+					}),
+					handed_over.id,
+				)
+			})
+			.unzip::<_, _, Vec<_>, Vec<_>>();
+
+		if !sync_params.is_empty() {
+			sync.write_ops(
+				db,
+				(
+					sync_params,
+					db.file_path().delete_many(vec![file_path::id::in_vec(ids)]),
+				),
+			)
+			.await?;
+		}
+	}
+
+	debug!(
+		%total_file_paths,
+		%total_rejected,
+		"Handed over file paths between nested locations;",
+	);
+
+	Ok(total_file_paths)
+}
+
+/// A file path being handed over, with its materialized path in the receiving location
+#[derive(Debug)]
+struct HandedOverFilePath {
+	id: file_path::id::Type,
+	pub_id: Vec<u8>,
+	materialized_path: String,
+	is_dir: bool,
+	name: String,
+	extension: String,
+}
+
+impl MetadataForIndexerRules for HandedOverFilePath {
+	fn is_dir(&self) -> bool {
+		self.is_dir
+	}
+}
+
+/// Splits the file paths between the ones accepted by the receiving location's rules and the ones
+/// rejected, either by the rules or for being inside a rejected directory, which the indexer
+/// wouldn't walk
+async fn split_by_rules(
+	indexer_ruler: &IndexerRuler,
+	location_id: location::id::Type,
+	to_path: &Path,
+	file_paths: Vec<HandedOverFilePath>,
+) -> Result<(Vec<HandedOverFilePath>, Vec<HandedOverFilePath>), LocationError> {
+	let mut rejected_directories = Vec::new();
+	let mut decisions = Vec::with_capacity(file_paths.len());
+
+	for file_path in file_paths {
+		let iso_file_path = IsolatedFilePathData::from_db_data(
+			location_id,
+			file_path.is_dir,
+			Cow::Borrowed(&file_path.materialized_path),
+			Cow::Borrowed(&file_path.name),
+			Cow::Borrowed(&file_path.extension),
+		);
+
+		let decision = indexer_ruler
+			.evaluate_path(
+				join_location_relative_path(to_path, &iso_file_path),
+				&file_path,
+			)
+			.await?;
+
+		if decision == RulerDecision::Reject {
+			if let Some(children_prefix) = iso_file_path.materialized_path_for_children() {
+				rejected_directories.push(children_prefix);
+			}
+		}
+
+		decisions.push((file_path, decision));
+	}
+
+	Ok(decisions
+		.into_iter()
+		.partition_map(|(file_path, decision)| {
+			if decision == RulerDecision::Reject
+				|| rejected_directories
+					.iter()
+					.any(|prefix| file_path.materialized_path.starts_with(prefix))
+			{
+				Either::Right(file_path)
+			} else {
+				Either::Left(file_path)
+			}
+		}))
+}
+
+/// Materialized path of the children of a directory, relative to the location root
+fn materialized_prefix(relative_path: &Path) -> String {
+	relative_path
+		.components()
+		.filter_map(|component| match component {
+			Component::Normal(name) => name.to_str(),
+			_ => None,
+		})
+		.fold(String::from("/"), |mut prefix, name| {
+			prefix.push_str(name);
+			prefix.push('/');
+			prefix
+		})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use sd_core_indexer_rules::RulePerKind;
+
+	use chrono::Utc;
+
+	#[test]
+	fn materialized_prefix_of_nested_directories() {
+		assert_eq!(materialized_prefix(Path::new("")), "/");
+		assert_eq!(materialized_prefix(Path::new("Projects")), "/Projects/");
+		assert_eq!(
+			materialized_prefix(Path::new("Projects/spacedrive")),
+			"/Projects/spacedrive/"
+		);
+	}
+
+	#[tokio::test]
+	async fn rejected_file_paths_are_left_out_with_their_contents() {
+		let file_path =
+			|id, materialized_path: &str, is_dir, name: &str, extension: &str| HandedOverFilePath {
+				id,
+				pub_id: vec![],
+				materialized_path: materialized_path.to_string(),
+				is_dir,
+				name: name.to_string(),
+				extension: extension.to_string(),
+			};
+
+		let indexer_ruler = IndexerRuler::new(vec![IndexerRule {
+			id: None,
+			name: "No node_modules or logs".to_string(),
+			default: false,
+			rules: vec![RulePerKind::new_reject_files_by_globs_str([
+				"**/node_modules",
+				"**/*.log",
+			])
+			.unwrap()],
+			date_created: Utc::now(),
+			date_modified: Utc::now(),
+		}]);
+
+		let (accepted, rejected) = split_by_rules(
+			&indexer_ruler,
+			1,
+			Path::new("/home/code"),
+			vec![
+				file_path(1, "/", true, "spacedrive", ""),
+				file_path(2, "/spacedrive/", true, "node_modules", ""),
+				file_path(3, "/spacedrive/node_modules/react/", false, "index", "js"),
+				file_path(4, "/spacedrive/", false, "build", "log"),
+				file_path(5, "/spacedrive/", false, "Cargo", "toml"),
+				file_path(6, "/spacedrive/node_modules_backup/", false, "index", "js"),
+			],
+		)
+		.await
+		.unwrap();
+
+		assert_eq!(
+			accepted
+				.iter()
+				.map(|file_path| file_path.id)
+				.collect::<Vec<_>>(),
+			[1, 5, 6]
+		);
+		assert_eq!(
+			rejected
+				.iter()
+				.map(|file_path| file_path.id)
+				.collect::<Vec<_>>(),
+			[2, 3, 4]
+		);
+	}
+}
@@ -12,17 +12,19 @@ use sd_core_indexer_rules::{
 	seed::{NO_HIDDEN, NO_SYSTEM_FILES},
 	IndexerRule, IndexerRuler, RulerDecision,
 };
+use sd_core_prisma_helpers::CasId;
 
 use sd_file_ext::{extensions::Extension, kind::ObjectKind};
 use sd_prisma::prisma::location;
 use sd_utils::{chain_optional_iter, error::FileIOError};
 
 use std::{
-	collections::HashMap,
+	collections::{HashMap, VecDeque},
 	io::ErrorKind,
 	ops::Deref,
 	path::{Path, PathBuf},
-	sync::Arc,
+	sync::{Arc, LazyLock, Mutex, PoisonError},
+	time::{Duration, Instant, SystemTime},
 };
 
 use chrono::{DateTime, Utc};
@@ -49,6 +51,9 @@ pub enum NonIndexedLocationError {
 	#[error("database error: {0}")]
 	Database(#[from] prisma_client_rust::QueryError),
 
+	#[error(transparent)]
+	IndexerRules(#[from] sd_core_indexer_rules::Error),
+
 	#[error("error joining tokio task: {0}")]
 	TaskJoinError(#[from] JoinError),
 
@@ -68,6 +73,7 @@ impl From<NonIndexedLocationError> for rspc::Error {
 			NonIndexedLocationError::NotFound(_) => {
 				rspc::Error::with_cause(ErrorCode::NotFound, e.to_string(), e)
 			}
+			NonIndexedLocationError::IndexerRules(e) => e.into(),
 			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, e.to_string(), e),
 		}
 	}
@@ -96,10 +102,137 @@ pub struct NonIndexedPathItem {
 	pub hidden: bool,
 }
 
+/// Recursive walks stop after finding this many entries, or after visiting this many entries
+/// when few of them match the filters
+const MAX_RECURSIVE_ENTRIES: usize = 10_000;
+const MAX_RECURSIVE_VISITED: usize = 200_000;
+
+/// How long the kind and cas id of visited files are kept around so revisiting a large directory
+/// doesn't have to read all of its files again
+const CACHE_TTL: Duration = Duration::from_secs(5 * 60);
+/// A directory's modification date only changes with its direct children, so its size is only
+/// kept for a short while, as changes deeper inside it go unnoticed
+const DIRECTORY_SIZES_CACHE_TTL: Duration = Duration::from_secs(30);
+const CACHE_MAX_ENTRIES: usize = 100_000;
+
+static FILES_CACHE: LazyLock<Mutex<Cache<(ObjectKind, Option<CasId<'static>>)>>> =
+	LazyLock::new(|| Mutex::new(Cache::new(CACHE_TTL)));
+static DIRECTORY_SIZES_CACHE: LazyLock<Mutex<Cache<u64>>> =
+	LazyLock::new(|| Mutex::new(Cache::new(DIRECTORY_SIZES_CACHE_TTL)));
+
+/// Values computed for paths, valid while the path keeps the modification date it had when
+/// they were computed, up to the cache's time to live
+struct Cache<T> {
+	entries: HashMap<PathBuf, CacheEntry<T>>,
+	ttl: Duration,
+}
+
+struct CacheEntry<T> {
+	date_modified: SystemTime,
+	cached_at: Instant,
+	value: T,
+}
+
+impl<T> Cache<T> {
+	fn new(ttl: Duration) -> Self {
+		Self {
+			entries: HashMap::new(),
+			ttl,
+		}
+	}
+}
+
+impl<T: Clone> Cache<T> {
+	fn get(&self, path: &Path, date_modified: SystemTime) -> Option<T> {
+		self.entries
+			.get(path)
+			.filter(|entry| {
+				entry.date_modified == date_modified && entry.cached_at.elapsed() < self.ttl
+			})
+			.map(|entry| entry.value.clone())
+	}
+
+	fn insert(&mut self, path: PathBuf, date_modified: SystemTime, value: T) {
+		if self.entries.len() >= CACHE_MAX_ENTRIES {
+			let ttl = self.ttl;
+			self.entries
+				.retain(|_, entry| entry.cached_at.elapsed() < ttl);
+
+			if self.entries.len() >= CACHE_MAX_ENTRIES {
+				self.entries.clear();
+			}
+		}
+
+		self.entries.insert(
+			path,
+			CacheEntry {
+				date_modified,
+				cached_at: Instant::now(),
+				value,
+			},
+		);
+	}
+}
+
+/// Cas ids of the files under `path` seen while recently browsing it, along with the files'
+/// modification dates, to be reused when indexing it
+pub(crate) fn cached_cas_ids(path: &Path) -> Vec<(PathBuf, SystemTime, CasId<'static>)> {
+	FILES_CACHE
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+		.entries
+		.iter()
+		.filter(|(file_path, entry)| {
+			file_path.starts_with(path) && entry.cached_at.elapsed() < CACHE_TTL
+		})
+		.filter_map(|(file_path, entry)| {
+			entry
+				.value
+				.1
+				.clone()
+				.map(|cas_id| (file_path.clone(), entry.date_modified, cas_id))
+		})
+		.collect()
+}
+
+/// How a non indexed path is walked
+#[derive(Debug, Default)]
+pub struct WalkOptions {
+	pub with_hidden_files: bool,
+	/// Look for entries in all sub directories too, instead of only listing the path
+	pub recursive: bool,
+	/// Applied on top of the rules rejecting system and hidden files
+	pub indexer_rules: Vec<IndexerRule>,
+	/// Only entries whose name contains this, ignoring case
+	pub name: Option<String>,
+	/// Only files with one of these extensions, ignoring case
+	pub extensions: Vec<String>,
+	/// Sum the size of the contents of directories, instead of using the size reported for them
+	pub with_directory_sizes: bool,
+}
+
+impl WalkOptions {
+	fn matches(&self, entry: &Entry) -> bool {
+		self.name.as_ref().map_or(true, |name| {
+			entry.name.to_lowercase().contains(&name.to_lowercase())
+		}) && (self.extensions.is_empty()
+			|| (!entry.metadata.is_dir()
+				&& entry
+					.path
+					.extension()
+					.and_then(|extension| extension.to_str())
+					.is_some_and(|extension| {
+						self.extensions
+							.iter()
+							.any(|wanted| wanted.eq_ignore_ascii_case(extension))
+					})))
+	}
+}
+
 // #[instrument(name = "non_indexed::walk", skip(sort_fn))]
 pub async fn walk(
 	path: PathBuf,
-	with_hidden_files: bool,
+	options: WalkOptions,
 	node: Arc<Node>,
 	library: Arc<Library>,
 	sort_fn: impl FnOnce(&mut Vec<Entry>) + Send,
@@ -107,7 +240,13 @@ pub async fn walk(
 	impl Stream<Item = Result<ExplorerItem, Either<rspc::Error, NonIndexedLocationError>>> + Send,
 	NonIndexedLocationError,
 > {
-	let mut entries = get_all_entries(path.clone()).await?;
+	let (mut entries, errors) = collect_entries(path.clone(), &options).await?;
+
+	if options.with_directory_sizes {
+		for entry in entries.iter_mut().filter(|entry| entry.metadata.is_dir()) {
+			entry.size_in_bytes = directory_size(entry.path.clone(), &entry.metadata).await?;
+		}
+	}
 
 	{
 		let span = span!(Level::INFO, "sort_fn");
@@ -122,10 +261,10 @@ pub async fn walk(
 	// We wanna process and let the caller use the stream.
 	let task = spawn(async move {
 		let path = &path;
-		let indexer_ruler = IndexerRuler::new(chain_optional_iter(
-			[IndexerRule::from(NO_SYSTEM_FILES.deref())],
-			[(!with_hidden_files).then(|| IndexerRule::from(NO_HIDDEN.deref()))],
-		));
+
+		for e in errors {
+			tx.send(Err(Either::Left(e))).await?;
+		}
 
 		let mut thumbnails_to_generate = vec![];
 		// Generating thumbnails for PDFs is kinda slow, so we're leaving them for last in the batch
@@ -133,7 +272,9 @@ pub async fn walk(
 		let mut directories = vec![];
 
 		for entry in entries.into_iter() {
-			let (entry_path, name) = match normalize_path(entry.path) {
+			let size_in_bytes = entry.size_in_bytes;
+
+			let (entry_path, name) = match normalize_path(&entry.path) {
 				Ok(v) => v,
 				Err(e) => {
 					tx.send(Err(Either::Left(
@@ -144,24 +285,8 @@ pub async fn walk(
 				}
 			};
 
-			match indexer_ruler
-				.evaluate_path(&entry_path, &entry.metadata)
-				.await
-			{
-				Ok(RulerDecision::Accept) => { /* Everything is awesome! */ }
-
-				Ok(RulerDecision::Reject) => {
-					continue;
-				}
-
-				Err(e) => {
-					tx.send(Err(Either::Left(e.into()))).await?;
-					continue;
-				}
-			}
-
 			if entry.metadata.is_dir() {
-				directories.push((entry_path, name, entry.metadata));
+				directories.push((entry_path, name, entry.metadata, size_in_bytes));
 			} else {
 				let path = Path::new(&entry_path);
 
@@ -178,58 +303,85 @@ pub async fn walk(
 					.and_then(|s| s.to_str().map(str::to_string))
 					.unwrap_or_default();
 
-				let kind = Extension::resolve_conflicting(&path, false)
-					.await
-					.map(Into::into)
-					.unwrap_or(ObjectKind::Unknown);
-
-				let should_generate_thumbnail = {
-					#[cfg(feature = "ffmpeg")]
-					{
-						matches!(
-							kind,
-							ObjectKind::Image | ObjectKind::Video | ObjectKind::Document
-						)
-					}
+				let date_modified = entry.metadata.modified().ok();
 
-					#[cfg(not(feature = "ffmpeg"))]
-					{
-						matches!(kind, ObjectKind::Image | ObjectKind::Document)
-					}
-				};
+				let cached = date_modified.and_then(|date_modified| {
+					FILES_CACHE
+						.lock()
+						.unwrap_or_else(PoisonError::into_inner)
+						.get(path, date_modified)
+				});
+
+				let (kind, cas_id) = if let Some(cached) = cached {
+					cached
+				} else {
+					let kind = Extension::resolve_conflicting(&path, false)
+						.await
+						.map(Into::into)
+						.unwrap_or(ObjectKind::Unknown);
+
+					let should_generate_thumbnail = {
+						#[cfg(feature = "ffmpeg")]
+						{
+							matches!(
+								kind,
+								ObjectKind::Image | ObjectKind::Video | ObjectKind::Document
+							)
+						}
+
+						#[cfg(not(feature = "ffmpeg"))]
+						{
+							matches!(kind, ObjectKind::Image | ObjectKind::Document)
+						}
+					};
 
-				let (thumbnail_key, has_created_thumbnail) = if should_generate_thumbnail {
-					if let Ok(cas_id) =
-						generate_cas_id(&path, entry.metadata.len())
-							.await
-							.map_err(|e| {
+					let cas_id = if should_generate_thumbnail {
+						match generate_cas_id(&path, entry.metadata.len()).await {
+							Ok(cas_id) => Some(cas_id),
+							Err(e) => {
 								tx.send(Err(Either::Left(
 									NonIndexedLocationError::from((path, e)).into(),
 								)))
-							}) {
-						if kind == ObjectKind::Document {
-							document_thumbnails_to_generate.push(GenerateThumbnailArgs::new(
-								extension.clone(),
-								cas_id.clone(),
-								path.to_path_buf(),
-							));
-						} else {
-							thumbnails_to_generate.push(GenerateThumbnailArgs::new(
-								extension.clone(),
-								cas_id.clone(),
-								path.to_path_buf(),
-							));
+								.await?;
+								None
+							}
 						}
+					} else {
+						None
+					};
+
+					if let Some(date_modified) = date_modified {
+						FILES_CACHE
+							.lock()
+							.unwrap_or_else(PoisonError::into_inner)
+							.insert(path.to_path_buf(), date_modified, (kind, cas_id.clone()));
+					}
+
+					(kind, cas_id)
+				};
 
-						let thumb_exists = node
-							.ephemeral_thumbnail_exists(&cas_id)
-							.await
-							.map_err(NonIndexedLocationError::from)?;
+				let (thumbnail_key, has_created_thumbnail) = if let Some(cas_id) = cas_id {
+					let thumb_exists = node
+						.ephemeral_thumbnail_exists(&cas_id)
+						.await
+						.map_err(NonIndexedLocationError::from)?;
 
-						(Some(ThumbKey::new_ephemeral(cas_id)), thumb_exists)
-					} else {
-						(None, false)
+					// Thumbnails are only generated once, revisiting the directory reuses them
+					if !thumb_exists {
+						let args = GenerateThumbnailArgs::new(
+							extension.clone(),
+							cas_id.clone(),
+							path.to_path_buf(),
+						);
+
+						if kind == ObjectKind::Document {
+							document_thumbnails_to_generate.push(args);
+						} else {
+							thumbnails_to_generate.push(args);
+						}
 					}
+
+					(Some(ThumbKey::new_ephemeral(cas_id)), thumb_exists)
 				} else {
 					(None, false)
 				};
@@ -245,7 +397,7 @@ pub async fn walk(
 						is_dir: false,
 						date_created: entry.metadata.created_or_now().into(),
 						date_modified: entry.metadata.modified_or_now().into(),
-						size_in_bytes_bytes: entry.metadata.len().to_be_bytes().to_vec(),
+						size_in_bytes_bytes: size_in_bytes.to_be_bytes().to_vec(),
 					},
 					has_created_thumbnail,
 				}))
@@ -291,7 +443,7 @@ pub async fn walk(
 			.find_many(vec![location::path::in_vec(
 				directories
 					.iter()
-					.map(|(path, _, _)| path.clone())
+					.map(|(path, _, _, _)| path.clone())
 					.collect(),
 			)])
 			.exec()
@@ -305,7 +457,7 @@ pub async fn walk(
 			})
 			.collect::<HashMap<_, _>>();
 
-		for (directory, name, metadata) in directories {
+		for (directory, name, metadata, size_in_bytes) in directories {
 			if let Some(location) = locations.remove(&directory) {
 				tx.send(Ok(ExplorerItem::Location { item: location }))
 					.await?;
@@ -321,7 +473,7 @@ pub async fn walk(
 						is_dir: true,
 						date_created: metadata.created_or_now().into(),
 						date_modified: metadata.modified_or_now().into(),
-						size_in_bytes_bytes: metadata.len().to_be_bytes().to_vec(),
+						size_in_bytes_bytes: size_in_bytes.to_be_bytes().to_vec(),
 					},
 					has_created_thumbnail: false,
 				}))
@@ -349,8 +501,8 @@ pub async fn walk(
 pub struct Entry {
 	path: PathBuf,
 	name: String,
-	// size_in_bytes: u64,
-	// date_created:
+	/// Size of the contents for directories, when asked for
+	size_in_bytes: u64,
 	metadata: std::fs::Metadata,
 }
 
@@ -360,7 +512,7 @@ impl Entry {
 	}
 
 	pub fn size_in_bytes(&self) -> u64 {
-		self.metadata.len()
+		self.size_in_bytes
 	}
 
 	pub fn date_created(&self) -> DateTime<Utc> {
@@ -391,6 +543,8 @@ pub async fn get_all_entries(path: PathBuf) -> Result<Vec<Entry>, NonIndexedLoca
 		for entry in dir {
 			let entry = entry.map_err(|e| (path, e))?;
 
+			let metadata = entry.metadata().map_err(|e| (path, e))?;
+
 			// We must not keep `entry` around as we will quickly hit the OS limit on open file descriptors
 			entries.push(Entry {
 				path: entry.path(),
@@ -404,7 +558,8 @@ pub async fn get_all_entries(path: PathBuf) -> Result<Vec<Entry>, NonIndexedLoca
 						)
 					})?
 					.to_string(),
-				metadata: entry.metadata().map_err(|e| (path, e))?,
+				size_in_bytes: metadata.len(),
+				metadata,
 			});
 		}
 
@@ -412,3 +567,298 @@ pub async fn get_all_entries(path: PathBuf) -> Result<Vec<Entry>, NonIndexedLoca
 	})
 	.await?
 }
+
+/// Lists the entries of `path` accepted by the rules and matching the filters, descending into
+/// all accepted sub directories when walking recursively. Sub directories which can't be read
+/// are reported along the entries, instead of failing the whole walk.
+async fn collect_entries(
+	path: PathBuf,
+	options: &WalkOptions,
+) -> Result<(Vec<Entry>, Vec<rspc::Error>), NonIndexedLocationError> {
+	collect_entries_up_to(path, options, MAX_RECURSIVE_ENTRIES, MAX_RECURSIVE_VISITED).await
+}
+
+/// [`collect_entries`], a recursive walk stopping once it collected `max_entries` or visited
+/// `max_visited` entries
+async fn collect_entries_up_to(
+	path: PathBuf,
+	options: &WalkOptions,
+	max_entries: usize,
+	max_visited: usize,
+) -> Result<(Vec<Entry>, Vec<rspc::Error>), NonIndexedLocationError> {
+	let indexer_ruler = IndexerRuler::new(
+		chain_optional_iter(
+			[IndexerRule::from(NO_SYSTEM_FILES.deref())],
+			[(!options.with_hidden_files).then(|| IndexerRule::from(NO_HIDDEN.deref()))],
+		)
+		.into_iter()
+		.chain(options.indexer_rules.iter().cloned())
+		.collect(),
+	);
+
+	let mut collected = vec![];
+	let mut errors = vec![];
+	let mut visited = 0;
+	let mut to_walk = VecDeque::from([get_all_entries(path).await?]);
+
+	while let Some(entries) = to_walk.pop_front() {
+		for entry in entries {
+			visited += 1;
+			if options.recursive && visited > max_visited {
+				debug!("Recursive walk reached its maximum amount of visited entries");
+				return Ok((collected, errors));
+			}
+
+			match indexer_ruler
+				.evaluate_path(&entry.path, &entry.metadata)
+				.await
+			{
+				Ok(RulerDecision::Accept) => { /* Everything is awesome! */ }
+
+				Ok(RulerDecision::Reject) => {
+					continue;
+				}
+
+				Err(e) => {
+					errors.push(e.into());
+					continue;
+				}
+			}
+
+			if options.recursive && entry.metadata.is_dir() {
+				match get_all_entries(entry.path.clone()).await {
+					Ok(entries) => to_walk.push_back(entries),
+					Err(e) => errors.push(e.into()),
+				}
+			}
+
+			if options.matches(&entry) {
+				collected.push(entry);
+
+				if options.recursive && collected.len() >= max_entries {
+					debug!("Recursive walk reached its maximum amount of entries");
+					return Ok((collected, errors));
+				}
+			}
+		}
+	}
+
+	Ok((collected, errors))
+}
+
+/// Sums the size of the files inside a directory, skipping whatever can't be read
+async fn directory_size(
+	path: PathBuf,
+	metadata: &std::fs::Metadata,
+) -> Result<u64, NonIndexedLocationError> {
+	let date_modified = metadata.modified().ok();
+
+	if let Some(size) = date_modified.and_then(|date_modified| {
+		DIRECTORY_SIZES_CACHE
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.get(&path, date_modified)
+	}) {
+		return Ok(size);
+	}
+
+	let (path, size) = tokio::task::spawn_blocking(move || {
+		let mut size = 0;
+		let mut to_walk = vec![path.clone()];
+
+		while let Some(directory) = to_walk.pop() {
+			let Ok(dir) = std::fs::read_dir(&directory) else {
+				continue;
+			};
+
+			for entry in dir.flatten() {
+				// Symlinks aren't followed, so nothing is counted twice
+				match entry.metadata() {
+					Ok(metadata) if metadata.is_dir() => to_walk.push(entry.path()),
+					Ok(metadata) => size += metadata.len(),
+					Err(_) => {}
+				}
+			}
+		}
+
+		(path, size)
+	})
+	.await?;
+
+	if let Some(date_modified) = date_modified {
+		DIRECTORY_SIZES_CACHE
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.insert(path, date_modified, size);
+	}
+
+	Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use sd_core_indexer_rules::RulePerKind;
+
+	use tempfile::{Builder, TempDir};
+
+	/// Temporary directories are hidden by default, which would reject everything inside them
+	fn temp_root() -> TempDir {
+		Builder::new().prefix("non-indexed").tempdir().unwrap()
+	}
+
+	fn create_tree(root: &Path) {
+		std::fs::create_dir_all(root.join("photos/2024")).unwrap();
+		std::fs::create_dir_all(root.join("logs")).unwrap();
+		std::fs::write(root.join("photos/cat.jpg"), b"cat").unwrap();
+		std::fs::write(root.join("photos/2024/dog.JPG"), b"dog").unwrap();
+		std::fs::write(root.join("photos/2024/notes.txt"), b"notes").unwrap();
+		std::fs::write(root.join("logs/app.log"), b"log").unwrap();
+		std::fs::write(root.join(".hidden.jpg"), b"hidden").unwrap();
+	}
+
+	fn names(entries: &[Entry]) -> Vec<&str> {
+		let mut names = entries.iter().map(Entry::name).collect::<Vec<_>>();
+		names.sort_unstable();
+		names
+	}
+
+	#[tokio::test]
+	async fn recursive_walks_find_nested_matches() {
+		let root = temp_root();
+		create_tree(root.path());
+
+		let (entries, errors) = collect_entries(
+			root.path().to_path_buf(),
+			&WalkOptions {
+				recursive: true,
+				extensions: vec!["jpg".to_string()],
+				..Default::default()
+			},
+		)
+		.await
+		.unwrap();
+
+		assert!(errors.is_empty());
+		assert_eq!(names(&entries), ["cat.jpg", "dog.JPG"]);
+
+		let (entries, _) = collect_entries(
+			root.path().to_path_buf(),
+			&WalkOptions {
+				name: Some("DOG".to_string()),
+				..Default::default()
+			},
+		)
+		.await
+		.unwrap();
+
+		// Without recursion only the path itself is listed
+		assert!(entries.is_empty());
+	}
+
+	#[tokio::test]
+	async fn rules_filter_the_walk() {
+		let root = temp_root();
+		create_tree(root.path());
+
+		let (entries, _) = collect_entries(root.path().to_path_buf(), &WalkOptions::default())
+			.await
+			.unwrap();
+		assert_eq!(names(&entries), ["logs", "photos"]);
+
+		let (entries, _) = collect_entries(
+			root.path().to_path_buf(),
+			&WalkOptions {
+				with_hidden_files: true,
+				..Default::default()
+			},
+		)
+		.await
+		.unwrap();
+		assert_eq!(names(&entries), [".hidden.jpg", "logs", "photos"]);
+
+		let (entries, _) = collect_entries(
+			root.path().to_path_buf(),
+			&WalkOptions {
+				recursive: true,
+				indexer_rules: vec![IndexerRule {
+					id: None,
+					name: "No logs".to_string(),
+					default: false,
+					rules: vec![RulePerKind::new_reject_files_by_globs_str(["**/*.log"]).unwrap()],
+					date_created: Utc::now(),
+					date_modified: Utc::now(),
+				}],
+				..Default::default()
+			},
+		)
+		.await
+		.unwrap();
+		assert_eq!(
+			names(&entries),
+			["2024", "cat.jpg", "dog.JPG", "logs", "notes.txt", "photos"]
+		);
+	}
+
+	#[tokio::test]
+	async fn recursive_walks_are_bounded() {
+		let root = temp_root();
+		create_tree(root.path());
+
+		let options = WalkOptions {
+			recursive: true,
+			..Default::default()
+		};
+
+		let (entries, _) = collect_entries_up_to(root.path().to_path_buf(), &options, 3, 100)
+			.await
+			.unwrap();
+		assert_eq!(entries.len(), 3);
+
+		// Nothing matches, but the walk still stops after visiting a few entries
+		let (entries, _) = collect_entries_up_to(
+			root.path().to_path_buf(),
+			&WalkOptions {
+				name: Some("missing".to_string()),
+				..options
+			},
+			100,
+			2,
+		)
+		.await
+		.unwrap();
+		assert!(entries.is_empty());
+	}
+
+	#[test]
+	fn cache_entries_expire_and_follow_modification_dates() {
+		let path = PathBuf::from("/some/directory");
+		let date_modified = SystemTime::now();
+
+		let mut cache = Cache::new(Duration::from_secs(60));
+		cache.insert(path.clone(), date_modified, 42);
+
+		assert_eq!(cache.get(&path, date_modified), Some(42));
+		assert_eq!(
+			cache.get(&path, date_modified + Duration::from_secs(1)),
+			None
+		);
+		assert_eq!(cache.get(Path::new("/other"), date_modified), None);
+
+		let mut cache = Cache::new(Duration::ZERO);
+		cache.insert(path.clone(), date_modified, 42);
+		assert_eq!(cache.get(&path, date_modified), None);
+	}
+
+	#[tokio::test]
+	async fn directory_sizes_count_nested_files() {
+		let root = temp_root();
+		create_tree(root.path());
+
+		let photos = root.path().join("photos");
+		let metadata = std::fs::metadata(&photos).unwrap();
+
+		assert_eq!(directory_size(photos, &metadata).await.unwrap(), 11);
+	}
+}
@@ -0,0 +1,329 @@
+use crate::{library::Library, Node};
+
+use sd_core_heavy_lifting::{file_identifier, media_processor::ThumbnailKind};
+use sd_core_prisma_helpers::{location_with_indexer_rules, CasId};
+
+use sd_prisma::prisma::location;
+
+use std::{
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+
+use serde::Deserialize;
+use specta::Type;
+use tokio::{fs, io};
+use tracing::{debug, instrument, warn};
+use uuid::Uuid;
+
+use super::{
+	delete_location, find_location, nested::overlapping_locations, non_indexed, normalize_path,
+	LocationCreateArgs, LocationError,
+};
+
+/// What to do with the locations overlapping a path being promoted
+#[derive(Type, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum OverlapResolution {
+	/// Keep the existing locations, nesting the new one with them
+	Nest,
+	/// Index the path as part of the location containing it, or fold the locations inside it into
+	/// the new one
+	Merge,
+}
+
+/// `LocationPromoteArgs` turns a path browsed ephemerally into an indexed location, reusing the
+/// cas ids and thumbnails generated while browsing it.
+///
+/// Cas ids are handed to the file identifier, which uses them for the files that weren't modified
+/// since they were browsed. Browsing doesn't extract media data, so the media processor still
+/// extracts it, but it skips generating the reused thumbnails.
+#[derive(Type, Deserialize, Debug)]
+pub struct LocationPromoteArgs {
+	pub path: PathBuf,
+	pub indexer_rules_ids: Vec<i32>,
+	/// Required when [`overlapping_locations`] finds any for the path
+	pub overlap: Option<OverlapResolution>,
+}
+
+#[derive(Debug)]
+pub enum Promoted {
+	/// A new location was created at the path
+	Created(location_with_indexer_rules::Data),
+	/// The path was merged into the location containing it, which must index it
+	Merged {
+		location: location_with_indexer_rules::Data,
+		sub_path: PathBuf,
+	},
+}
+
+impl LocationPromoteArgs {
+	#[instrument(skip(node, library), fields(library_id = %library.id), err)]
+	pub async fn promote(
+		self,
+		node: &Node,
+		library: &Arc<Library>,
+	) -> Result<Promoted, LocationError> {
+		let (path, _) = normalize_path(&self.path)
+			.map_err(|_| LocationError::DirectoryNotFound(self.path.clone().into_boxed_path()))?;
+		let path = PathBuf::from(path);
+
+		let overlapping = overlapping_locations(&path, library).await?;
+
+		let promoted = match resolve(
+			&path,
+			overlapping.parent.as_ref().map(|parent| parent.id),
+			&overlapping
+				.children
+				.iter()
+				.map(|child| (child.id, child.path.as_deref()))
+				.collect::<Vec<_>>(),
+			self.overlap,
+		)? {
+			Resolution::Create => {
+				Promoted::Created(create(node, library, &path, self.indexer_rules_ids).await?)
+			}
+
+			Resolution::MergeIntoParent(parent_id) => Promoted::Merged {
+				location: find_location(library, parent_id)
+					.include(location_with_indexer_rules::include())
+					.exec()
+					.await?
+					.ok_or(LocationError::IdNotFound(parent_id))?,
+				sub_path: path.clone(),
+			},
+
+			Resolution::CreateAndMergeChildren(children) => {
+				let location = create(node, library, &path, self.indexer_rules_ids).await?;
+
+				// Deleting them hands their file paths back to the new location
+				for child_id in children {
+					debug!(location_id = %child_id, "Deleting location merged into promoted path;");
+					delete_location(node, library, child_id).await?;
+				}
+
+				Promoted::Created(location)
+			}
+		};
+
+		let cached_cas_ids = non_indexed::cached_cas_ids(&path);
+
+		let reused = copy_ephemeral_thumbnails(
+			&node.config.data_directory(),
+			library.id,
+			cached_cas_ids.iter().map(|(_, _, cas_id)| cas_id),
+		)
+		.await;
+		debug!(
+			cas_ids = cached_cas_ids.len(),
+			%reused,
+			"Reusing ephemeral data for promoted path;",
+		);
+
+		// Registered before the location is scanned, so they're there when its files are identified
+		file_identifier::register_known_cas_ids(cached_cas_ids);
+
+		Ok(promoted)
+	}
+}
+
+/// How a promoted path becomes a location, given the locations overlapping it
+#[derive(Debug, PartialEq, Eq)]
+enum Resolution {
+	Create,
+	/// Index the path as part of this location
+	MergeIntoParent(location::id::Type),
+	/// Create the location, then delete these ones so their file paths are handed back to it
+	CreateAndMergeChildren(Vec<location::id::Type>),
+}
+
+fn resolve(
+	path: &Path,
+	parent_id: Option<location::id::Type>,
+	children: &[(location::id::Type, Option<&str>)],
+	overlap: Option<OverlapResolution>,
+) -> Result<Resolution, LocationError> {
+	if children
+		.iter()
+		.any(|(_, child_path)| child_path.map(Path::new) == Some(path))
+	{
+		return Err(LocationError::LocationAlreadyExists(path.into()));
+	}
+
+	if parent_id.is_none() && children.is_empty() {
+		return Ok(Resolution::Create);
+	}
+
+	match (parent_id, overlap) {
+		(_, None) => Err(LocationError::NestedLocation(path.into())),
+		(_, Some(OverlapResolution::Nest)) => Ok(Resolution::Create),
+		(Some(parent_id), Some(OverlapResolution::Merge)) => {
+			Ok(Resolution::MergeIntoParent(parent_id))
+		}
+		(None, Some(OverlapResolution::Merge)) => Ok(Resolution::CreateAndMergeChildren(
+			children.iter().map(|(child_id, _)| *child_id).collect(),
+		)),
+	}
+}
+
+async fn create(
+	node: &Node,
+	library: &Arc<Library>,
+	path: &Path,
+	indexer_rules_ids: Vec<i32>,
+) -> Result<location_with_indexer_rules::Data, LocationError> {
+	LocationCreateArgs {
+		path: path.to_path_buf(),
+		dry_run: false,
+		indexer_rules_ids,
+	}
+	.create(node, library)
+	.await
+	.map(|location| location.expect("Locations are always created when not on a dry run"))
+}
+
+/// Copies the thumbnails generated while browsing into the library's thumbnails, so the media
+/// processor skips generating them again
+async fn copy_ephemeral_thumbnails(
+	data_directory: &Path,
+	library_id: Uuid,
+	cas_ids: impl IntoIterator<Item = &CasId<'static>>,
+) -> usize {
+	let mut reused = 0;
+
+	for cas_id in cas_ids {
+		let ephemeral_thumb = ThumbnailKind::Ephemeral.compute_path(data_directory, cas_id);
+		let indexed_thumb = ThumbnailKind::Indexed(library_id).compute_path(data_directory, cas_id);
+
+		if fs::metadata(&indexed_thumb).await.is_ok() {
+			continue;
+		}
+
+		let copied = async {
+			if let Some(shard_directory) = indexed_thumb.parent() {
+				fs::create_dir_all(shard_directory).await?;
+			}
+
+			fs::copy(&ephemeral_thumb, &indexed_thumb).await
+		}
+		.await;
+
+		match copied {
+			Ok(_) => reused += 1,
+			// Not generated yet, the media processor will take care of it
+			Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+			Err(e) => warn!(
+				?e,
+				thumbnail = %ephemeral_thumb.display(),
+				"Failed to reuse ephemeral thumbnail;",
+			),
+		}
+	}
+
+	reused
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use tempfile::tempdir;
+
+	#[test]
+	fn paths_without_overlapping_locations_are_created() {
+		assert_eq!(
+			resolve(Path::new("/photos"), None, &[], None).unwrap(),
+			Resolution::Create
+		);
+		assert_eq!(
+			resolve(
+				Path::new("/photos"),
+				None,
+				&[],
+				Some(OverlapResolution::Merge)
+			)
+			.unwrap(),
+			Resolution::Create
+		);
+	}
+
+	#[test]
+	fn overlapping_locations_need_a_resolution() {
+		let path = Path::new("/home/photos");
+
+		assert!(matches!(
+			resolve(path, Some(1), &[], None),
+			Err(LocationError::NestedLocation(_))
+		));
+		assert!(matches!(
+			resolve(
+				path,
+				Some(1),
+				&[(2, Some("/home/photos"))],
+				Some(OverlapResolution::Nest)
+			),
+			Err(LocationError::LocationAlreadyExists(_))
+		));
+
+		assert_eq!(
+			resolve(path, Some(1), &[], Some(OverlapResolution::Nest)).unwrap(),
+			Resolution::Create
+		);
+		assert_eq!(
+			resolve(path, Some(1), &[], Some(OverlapResolution::Merge)).unwrap(),
+			Resolution::MergeIntoParent(1)
+		);
+	}
+
+	#[test]
+	fn merging_folds_the_children_into_the_new_location() {
+		let path = Path::new("/home");
+		let children = [(2, Some("/home/photos")), (3, Some("/home/music/albums"))];
+
+		assert_eq!(
+			resolve(path, None, &children, Some(OverlapResolution::Merge)).unwrap(),
+			Resolution::CreateAndMergeChildren(vec![2, 3])
+		);
+		assert_eq!(
+			resolve(path, None, &children, Some(OverlapResolution::Nest)).unwrap(),
+			Resolution::Create
+		);
+	}
+
+	#[tokio::test]
+	async fn ephemeral_thumbnails_are_copied_to_the_library() {
+		let data_directory = tempdir().unwrap();
+		let library_id = Uuid::new_v4();
+
+		let generated = CasId::from("0123456789abcdef".to_string());
+		let already_indexed = CasId::from("fedcba9876543210".to_string());
+		let missing = CasId::from("00000000ffffffff".to_string());
+
+		for (kind, cas_id) in [
+			(ThumbnailKind::Ephemeral, &generated),
+			(ThumbnailKind::Ephemeral, &already_indexed),
+			(ThumbnailKind::Indexed(library_id), &already_indexed),
+		] {
+			let thumb = kind.compute_path(data_directory.path(), cas_id);
+			fs::create_dir_all(thumb.parent().unwrap()).await.unwrap();
+			fs::write(&thumb, cas_id.as_str()).await.unwrap();
+		}
+
+		let reused = copy_ephemeral_thumbnails(
+			data_directory.path(),
+			library_id,
+			[&generated, &already_indexed, &missing],
+		)
+		.await;
+		assert_eq!(reused, 1);
+
+		assert_eq!(
+			fs::read_to_string(
+				ThumbnailKind::Indexed(library_id).compute_path(data_directory.path(), &generated)
+			)
+			.await
+			.unwrap(),
+			generated.as_str()
+		);
+	}
+}
@@ -0,0 +1,740 @@
+use sd_core_file_path_helper::{
+	filter_existing_file_path_params, FilePathError, IsolatedFilePathData,
+};
+
+use sd_file_ext::kind::ObjectKind;
+use sd_prisma::prisma::{directory_size_snapshot, file_path, location, PrismaClient, SortOrder};
+use sd_utils::db::{maybe_missing, size_in_bytes_from_db};
+
+use std::{cmp::Reverse, collections::HashMap, path::Path};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use prisma_client_rust::QueryError;
+use serde::Serialize;
+use serde_with::{serde_as, DisplayFromStr};
+use specta::Type;
+use strum::IntoEnumIterator;
+
+use super::LocationError;
+
+/// Disk usage of a directory, or of the whole location, built from the indexed sizes alone
+#[derive(Serialize, Type, Debug)]
+pub struct SizeTree {
+	pub root: SizeTreeNode,
+	/// Directories whose size changed the most since the last scan started
+	pub changes: Vec<SizeChange>,
+	/// When the sizes being compared against were taken, `None` if the location was never scanned
+	pub snapshot_date: Option<DateTime<FixedOffset>>,
+}
+
+#[serde_as]
+#[derive(Serialize, Type, Debug)]
+pub struct SizeTreeNode {
+	/// `None` for the location root
+	pub file_path_id: Option<file_path::id::Type>,
+	pub name: String,
+	pub is_dir: bool,
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub size_in_bytes: u64,
+	/// Size of the files inside the directory by their kind, largest first
+	pub kinds: Vec<KindSize>,
+	/// Largest children first, empty for files and past the requested depth
+	pub children: Vec<SizeTreeNode>,
+}
+
+#[serde_as]
+#[derive(Serialize, Type, Debug)]
+pub struct KindSize {
+	pub kind: ObjectKind,
+	pub count: u64,
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub size_in_bytes: u64,
+}
+
+#[serde_as]
+#[derive(Serialize, Type, Debug)]
+pub struct SizeChange {
+	/// Materialized path of the directory's children
+	pub path: String,
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub previous_size_in_bytes: u64,
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub size_in_bytes: u64,
+}
+
+impl SizeChange {
+	fn delta(&self) -> u64 {
+		self.size_in_bytes.abs_diff(self.previous_size_in_bytes)
+	}
+}
+
+/// Files are read in batches of this size to account their kinds, as every file under the root
+/// counts towards the directories holding it, no matter how deep it is
+const KINDS_BATCH_SIZE: i64 = 10_000;
+/// Directories whose files are fetched by a single query
+const PARENTS_PER_QUERY: usize = 500;
+
+file_path::select!(directory_for_size_tree {
+	id
+	materialized_path
+	name
+	size_in_bytes_bytes
+});
+
+file_path::select!(file_for_size_tree {
+	id
+	materialized_path
+	name
+	extension
+	size_in_bytes_bytes
+});
+
+file_path::select!(file_kind_for_size_tree {
+	id
+	materialized_path
+	size_in_bytes_bytes
+	object: select { kind }
+});
+
+struct Entry {
+	file_path_id: file_path::id::Type,
+	name: String,
+	is_dir: bool,
+	size: u64,
+	/// Materialized path of the entry's children, only for directories
+	children_path: Option<String>,
+}
+
+#[derive(Default)]
+struct Directory {
+	children: Vec<usize>,
+	/// Count and size of the files inside the directory, at any depth, by kind
+	kinds: HashMap<i32, (u64, u64)>,
+}
+
+/// Gathers the entries of the tree, only keeping the ones within the requested depth
+struct TreeBuilder {
+	root_path: String,
+	depth: usize,
+	entries: Vec<Entry>,
+	directories: HashMap<String, Directory>,
+	/// Sizes of every directory under the root, by the materialized path of their children
+	current_sizes: HashMap<String, u64>,
+}
+
+impl TreeBuilder {
+	fn new(root_path: String, depth: u8) -> Self {
+		Self {
+			root_path,
+			depth: usize::from(depth),
+			entries: vec![],
+			directories: HashMap::new(),
+			current_sizes: HashMap::new(),
+		}
+	}
+
+	/// Levels below the root, 0 for its direct children
+	fn level(&self, materialized_path: &str) -> usize {
+		materialized_path[self.root_path.len()..]
+			.matches('/')
+			.count()
+	}
+
+	/// Materialized paths of the directories whose children are within the depth
+	fn parents_within_depth(&self) -> Vec<String> {
+		if self.depth == 0 {
+			return vec![];
+		}
+
+		self.entries
+			.iter()
+			.filter_map(|entry| entry.children_path.clone())
+			.filter(|children_path| self.level(children_path) < self.depth)
+			.chain([self.root_path.clone()])
+			.collect()
+	}
+
+	fn push_entry(&mut self, materialized_path: String, entry: Entry) {
+		self.directories
+			.entry(materialized_path)
+			.or_default()
+			.children
+			.push(self.entries.len());
+		self.entries.push(entry);
+	}
+
+	fn add_directory(
+		&mut self,
+		file_path_id: file_path::id::Type,
+		materialized_path: String,
+		name: String,
+		size: u64,
+	) {
+		let children_path = format!("{materialized_path}{name}/");
+		self.current_sizes.insert(children_path.clone(), size);
+
+		if self.level(&materialized_path) < self.depth {
+			self.push_entry(
+				materialized_path,
+				Entry {
+					file_path_id,
+					name,
+					is_dir: true,
+					size,
+					children_path: Some(children_path),
+				},
+			);
+		}
+	}
+
+	fn add_file(
+		&mut self,
+		file_path_id: file_path::id::Type,
+		materialized_path: String,
+		name: String,
+		extension: String,
+		size: u64,
+	) {
+		if self.level(&materialized_path) < self.depth {
+			self.push_entry(
+				materialized_path,
+				Entry {
+					file_path_id,
+					name: if extension.is_empty() {
+						name
+					} else {
+						format!("{name}.{extension}")
+					},
+					is_dir: false,
+					size,
+					children_path: None,
+				},
+			);
+		}
+	}
+
+	/// Accounts the file on every directory of the tree holding it
+	fn add_file_kind(&mut self, materialized_path: &str, kind: i32, size: u64) {
+		for (i, _) in materialized_path
+			.match_indices('/')
+			.filter(|(i, _)| *i >= self.root_path.len() - 1)
+			.take(self.depth + 1)
+		{
+			let (count, total) = self
+				.directories
+				.entry(materialized_path[..=i].to_string())
+				.or_default()
+				.kinds
+				.entry(kind)
+				.or_default();
+			*count += 1;
+			*total += size;
+		}
+	}
+
+	fn build(mut self, root: &Entry, limit: usize) -> SizeTreeNode {
+		let is_location_root = self.root_path == "/";
+		build_node(
+			root,
+			&self.root_path,
+			is_location_root,
+			&self.entries,
+			&mut self.directories,
+			limit,
+		)
+	}
+}
+
+/// Builds the size tree of `sub_path` down to `depth` levels, keeping the `limit` largest
+/// children of each directory and the `limit` biggest changes
+pub async fn size_tree(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+	sub_path: Option<&Path>,
+	depth: u8,
+	limit: usize,
+) -> Result<SizeTree, LocationError> {
+	let location = db
+		.location()
+		.find_unique(location::id::equals(location_id))
+		.select(location::select!({ name path size_in_bytes }))
+		.exec()
+		.await?
+		.ok_or(LocationError::IdNotFound(location_id))?;
+
+	let location_path = maybe_missing(location.path, "location.path")?;
+
+	let (root_path, root) = match sub_path
+		.filter(|sub_path| *sub_path != Path::new("") && *sub_path != Path::new("/"))
+	{
+		Some(sub_path) => {
+			let full_path =
+				Path::new(&location_path).join(sub_path.strip_prefix("/").unwrap_or(sub_path));
+			let iso_file_path =
+				IsolatedFilePathData::new(location_id, &location_path, &full_path, true)?;
+
+			let directory = db
+				.file_path()
+				.find_first(filter_existing_file_path_params(&iso_file_path))
+				.select(file_path::select!({ id name size_in_bytes_bytes }))
+				.exec()
+				.await?
+				.ok_or_else(|| FilePathError::NotFound(full_path.into_boxed_path()))?;
+
+			(
+				iso_file_path
+					.materialized_path_for_children()
+					.unwrap_or_else(|| "/".to_string()),
+				Entry {
+					file_path_id: directory.id,
+					name: directory.name.unwrap_or_default(),
+					is_dir: true,
+					size: size_from_db(directory.size_in_bytes_bytes),
+					children_path: None,
+				},
+			)
+		}
+		None => (
+			"/".to_string(),
+			Entry {
+				file_path_id: 0,
+				name: location.name.unwrap_or_default(),
+				is_dir: true,
+				size: size_from_db(location.size_in_bytes),
+				children_path: None,
+			},
+		),
+	};
+
+	let mut builder = TreeBuilder::new(root_path.clone(), depth);
+
+	// Every directory is needed to tell which sizes changed, but they are a fraction of the files
+	for directory in db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(Some(location_id)),
+			file_path::materialized_path::starts_with(root_path.clone()),
+			file_path::is_dir::equals(Some(true)),
+		])
+		.select(directory_for_size_tree::select())
+		.exec()
+		.await?
+	{
+		builder.add_directory(
+			directory.id,
+			maybe_missing(directory.materialized_path, "file_path.materialized_path")?,
+			directory.name.unwrap_or_default(),
+			size_from_db(directory.size_in_bytes_bytes),
+		);
+	}
+
+	// Only files within the depth are part of the tree
+	for parents in builder.parents_within_depth().chunks(PARENTS_PER_QUERY) {
+		for file in db
+			.file_path()
+			.find_many(vec![
+				file_path::location_id::equals(Some(location_id)),
+				file_path::materialized_path::in_vec(parents.to_vec()),
+				file_path::is_dir::equals(Some(false)),
+			])
+			.select(file_for_size_tree::select())
+			.exec()
+			.await?
+		{
+			builder.add_file(
+				file.id,
+				maybe_missing(file.materialized_path, "file_path.materialized_path")?,
+				file.name.unwrap_or_default(),
+				file.extension.unwrap_or_default(),
+				size_from_db(file.size_in_bytes_bytes),
+			);
+		}
+	}
+
+	// Sizes are stored as bytes, so kinds can't be summed by the database and the files under
+	// the root are read in batches instead
+	let mut last_id = file_path::id::Type::MIN;
+	loop {
+		let files = db
+			.file_path()
+			.find_many(vec![
+				file_path::location_id::equals(Some(location_id)),
+				file_path::materialized_path::starts_with(root_path.clone()),
+				file_path::is_dir::equals(Some(false)),
+				file_path::id::gt(last_id),
+			])
+			.order_by(file_path::id::order(SortOrder::Asc))
+			.take(KINDS_BATCH_SIZE)
+			.select(file_kind_for_size_tree::select())
+			.exec()
+			.await?;
+
+		let Some(last) = files.last() else {
+			break;
+		};
+		last_id = last.id;
+
+		for file in files {
+			builder.add_file_kind(
+				&maybe_missing(file.materialized_path, "file_path.materialized_path")?,
+				file.object
+					.and_then(|object| object.kind)
+					.unwrap_or(ObjectKind::Unknown as i32),
+				size_from_db(file.size_in_bytes_bytes),
+			);
+		}
+	}
+
+	let snapshot = db
+		.directory_size_snapshot()
+		.find_many(vec![
+			directory_size_snapshot::location_id::equals(location_id),
+			directory_size_snapshot::path::starts_with(root_path.clone()),
+		])
+		.exec()
+		.await?;
+
+	let snapshot_date = snapshot.first().map(|snapshot| snapshot.date_created);
+
+	let changes = if snapshot_date.is_some() {
+		let mut current_sizes = std::mem::take(&mut builder.current_sizes);
+		current_sizes.insert(root_path, root.size);
+
+		size_changes(
+			current_sizes,
+			snapshot
+				.into_iter()
+				.map(|snapshot| {
+					(
+						snapshot.path,
+						size_in_bytes_from_db(&snapshot.size_in_bytes),
+					)
+				})
+				.collect(),
+			limit,
+		)
+	} else {
+		vec![]
+	};
+
+	Ok(SizeTree {
+		root: builder.build(&root, limit),
+		changes,
+		snapshot_date,
+	})
+}
+
+fn size_from_db(size_in_bytes_bytes: Option<Vec<u8>>) -> u64 {
+	size_in_bytes_bytes
+		.as_deref()
+		.map(size_in_bytes_from_db)
+		.unwrap_or_default()
+}
+
+/// The `limit` directories whose size changed the most, directories missing from either side
+/// being counted as empty there
+fn size_changes(
+	current_sizes: HashMap<String, u64>,
+	mut previous_sizes: HashMap<String, u64>,
+	limit: usize,
+) -> Vec<SizeChange> {
+	let mut changes = current_sizes
+		.into_iter()
+		.map(|(path, size_in_bytes)| SizeChange {
+			previous_size_in_bytes: previous_sizes.remove(&path).unwrap_or_default(),
+			path,
+			size_in_bytes,
+		})
+		.collect::<Vec<_>>();
+
+	// Directories removed since the snapshot
+	changes.extend(
+		previous_sizes
+			.into_iter()
+			.map(|(path, previous_size_in_bytes)| SizeChange {
+				path,
+				previous_size_in_bytes,
+				size_in_bytes: 0,
+			}),
+	);
+
+	changes.retain(|change| change.delta() > 0);
+	changes.sort_unstable_by_key(|change| Reverse(change.delta()));
+	changes.truncate(limit);
+	changes
+}
+
+fn build_node(
+	entry: &Entry,
+	children_path: &str,
+	is_location_root: bool,
+	entries: &[Entry],
+	directories: &mut HashMap<String, Directory>,
+	limit: usize,
+) -> SizeTreeNode {
+	let Directory { children, kinds } = directories.remove(children_path).unwrap_or_default();
+
+	let mut children = children
+		.into_iter()
+		.map(|i| &entries[i])
+		.collect::<Vec<_>>();
+	children.sort_unstable_by_key(|child| Reverse(child.size));
+	children.truncate(limit);
+
+	let mut kinds = kinds
+		.into_iter()
+		.map(|(kind, (count, size_in_bytes))| KindSize {
+			kind: ObjectKind::iter()
+				.find(|object_kind| *object_kind as i32 == kind)
+				.unwrap_or(ObjectKind::Unknown),
+			count,
+			size_in_bytes,
+		})
+		.collect::<Vec<_>>();
+	kinds.sort_unstable_by_key(|kind| Reverse(kind.size_in_bytes));
+
+	SizeTreeNode {
+		file_path_id: (!is_location_root).then_some(entry.file_path_id),
+		name: entry.name.clone(),
+		is_dir: entry.is_dir,
+		size_in_bytes: entry.size,
+		kinds,
+		children: children
+			.into_iter()
+			.map(|child| match &child.children_path {
+				Some(children_path) => {
+					build_node(child, children_path, false, entries, directories, limit)
+				}
+				None => SizeTreeNode {
+					file_path_id: Some(child.file_path_id),
+					name: child.name.clone(),
+					is_dir: child.is_dir,
+					size_in_bytes: child.size,
+					kinds: vec![],
+					children: vec![],
+				},
+			})
+			.collect(),
+	}
+}
+
+/// Keeps the current directory sizes of a location, so the next size tree can tell what changed
+pub(crate) async fn snapshot_directory_sizes(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+) -> Result<(), QueryError> {
+	let date_created: DateTime<FixedOffset> = Utc::now().into();
+
+	let location_size = db
+		.location()
+		.find_unique(location::id::equals(location_id))
+		.select(location::select!({ size_in_bytes }))
+		.exec()
+		.await?
+		.and_then(|location| location.size_in_bytes);
+
+	let directories = db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(Some(location_id)),
+			file_path::is_dir::equals(Some(true)),
+		])
+		.select(file_path::select!({ materialized_path name size_in_bytes_bytes }))
+		.exec()
+		.await?;
+
+	let snapshots = directories
+		.into_iter()
+		.filter_map(|directory| {
+			Some((
+				format!("{}{}/", directory.materialized_path?, directory.name?),
+				directory.size_in_bytes_bytes?,
+			))
+		})
+		.chain(location_size.map(|size| ("/".to_string(), size)))
+		.map(|(path, size_in_bytes)| {
+			directory_size_snapshot::create_unchecked(
+				location_id,
+				path,
+				size_in_bytes,
+				date_created,
+				vec![],
+			)
+		})
+		.collect::<Vec<_>>();
+
+	db._batch((
+		db.directory_size_snapshot().delete_many(vec![
+			directory_size_snapshot::location_id::equals(location_id),
+		]),
+		db.directory_size_snapshot().create_many(snapshots),
+	))
+	.await?;
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn root() -> Entry {
+		Entry {
+			file_path_id: 0,
+			name: "location".to_string(),
+			is_dir: true,
+			size: 160,
+			children_path: None,
+		}
+	}
+
+	fn builder(root_path: &str, depth: u8) -> TreeBuilder {
+		let mut builder = TreeBuilder::new(root_path.to_string(), depth);
+
+		builder.add_directory(1, "/".to_string(), "a".to_string(), 100);
+		builder.add_directory(2, "/a/".to_string(), "b".to_string(), 50);
+		builder.add_file(3, "/".to_string(), "x".to_string(), "txt".to_string(), 10);
+		builder.add_file(4, "/a/".to_string(), "y".to_string(), "".to_string(), 50);
+		builder.add_file(
+			5,
+			"/a/b/".to_string(),
+			"z".to_string(),
+			"jpg".to_string(),
+			50,
+		);
+
+		builder.add_file_kind("/", ObjectKind::Text as i32, 10);
+		builder.add_file_kind("/a/", ObjectKind::Unknown as i32, 50);
+		builder.add_file_kind("/a/b/", ObjectKind::Image as i32, 50);
+
+		builder
+	}
+
+	#[test]
+	fn tree_only_holds_entries_within_depth() {
+		let builder = builder("/", 1);
+		assert_eq!(builder.parents_within_depth(), vec!["/".to_string()]);
+
+		let tree = builder.build(&root(), 10);
+		assert_eq!(tree.file_path_id, None);
+		assert_eq!(
+			tree.children
+				.iter()
+				.map(|child| (
+					child.name.as_str(),
+					child.size_in_bytes,
+					child.children.len()
+				))
+				.collect::<Vec<_>>(),
+			[("a", 100, 0), ("x.txt", 10, 0)]
+		);
+
+		// Kinds account for files at any depth, not only the ones in the tree
+		assert_eq!(
+			tree.kinds
+				.iter()
+				.map(|kind| (kind.count, kind.size_in_bytes))
+				.collect::<Vec<_>>(),
+			[(1, 50), (1, 50), (1, 10)]
+		);
+		assert_eq!(tree.children[0].kinds.len(), 2);
+	}
+
+	#[test]
+	fn deeper_trees_keep_the_largest_children() {
+		let builder = builder("/", 2);
+		let mut parents = builder.parents_within_depth();
+		parents.sort();
+		assert_eq!(parents, ["/", "/a/"]);
+
+		let tree = builder.build(&root(), 1);
+		assert_eq!(tree.children.len(), 1);
+
+		let a = &tree.children[0];
+		assert_eq!((a.name.as_str(), a.file_path_id), ("a", Some(1)));
+		assert_eq!(a.children.len(), 1);
+		assert_eq!(a.children[0].size_in_bytes, 50);
+	}
+
+	#[test]
+	fn sub_path_trees_start_at_the_directory() {
+		let mut builder = TreeBuilder::new("/a/".to_string(), 1);
+		builder.add_directory(2, "/a/".to_string(), "b".to_string(), 75);
+		builder.add_file(4, "/a/".to_string(), "y".to_string(), "".to_string(), 20);
+		builder.add_file(
+			5,
+			"/a/b/".to_string(),
+			"z".to_string(),
+			"jpg".to_string(),
+			50,
+		);
+		builder.add_file(
+			6,
+			"/a/b/".to_string(),
+			"w".to_string(),
+			"jpg".to_string(),
+			25,
+		);
+
+		builder.add_file_kind("/a/", ObjectKind::Unknown as i32, 20);
+		builder.add_file_kind("/a/b/", ObjectKind::Image as i32, 50);
+		builder.add_file_kind("/a/b/", ObjectKind::Image as i32, 25);
+
+		let tree = builder.build(
+			&Entry {
+				file_path_id: 1,
+				..root()
+			},
+			10,
+		);
+		assert_eq!(tree.file_path_id, Some(1));
+		assert_eq!(
+			tree.children
+				.iter()
+				.map(|child| (child.name.as_str(), child.children.len()))
+				.collect::<Vec<_>>(),
+			[("b", 0), ("y", 0)]
+		);
+
+		let image = tree
+			.kinds
+			.iter()
+			.find(|kind| kind.kind == ObjectKind::Image)
+			.unwrap();
+		assert_eq!((image.count, image.size_in_bytes), (2, 75));
+		assert_eq!(tree.children[0].kinds.len(), 1);
+	}
+
+	#[test]
+	fn changes_are_the_biggest_size_differences() {
+		let current = HashMap::from([
+			("/".to_string(), 100),
+			("/a/".to_string(), 60),
+			("/b/".to_string(), 5),
+		]);
+		let previous = HashMap::from([
+			("/".to_string(), 80),
+			("/a/".to_string(), 60),
+			("/c/".to_string(), 30),
+		]);
+
+		let changes = size_changes(current.clone(), previous.clone(), 10);
+		assert_eq!(
+			changes
+				.iter()
+				.map(|change| (
+					change.path.as_str(),
+					change.previous_size_in_bytes,
+					change.size_in_bytes
+				))
+				.collect::<Vec<_>>(),
+			[("/c/", 30, 0), ("/", 80, 100), ("/b/", 0, 5)]
+		);
+
+		assert_eq!(size_changes(current, previous, 2).len(), 2);
+	}
+}
@@ -6,15 +6,16 @@ use crate::{
 
 use sd_cloud_schema::devices::DeviceOS;
 use sd_core_sync::DevicePubId;
-use sd_old_p2p::Identity;
+use sd_old_p2p::{BandwidthLimits, Identity, Priority, RemoteIdentity};
 use sd_utils::error::FileIOError;
 
 use std::{
-	collections::HashSet,
+	collections::{BTreeMap, HashMap, HashSet},
 	path::{Path, PathBuf},
 	sync::Arc,
 };
 
+use chrono::{DateTime, Utc};
 use int_enum::IntEnum;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Map, Value};
@@ -74,6 +75,10 @@ fn skip_if_false(value: &bool) -> bool {
 	!*value
 }
 
+fn default_true() -> bool {
+	true
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Type)]
 pub struct NodeConfigP2P {
 	#[serde(default)]
@@ -99,6 +104,155 @@ pub struct NodeConfigP2P {
 	/// which is why we use `String` not `SocketAddr`
 	#[serde(default)]
 	pub manual_peers: HashSet<String>,
+	/// Spacedrops from these peers are accepted without asking, saved into the given directory
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub spacedrop_auto_accept: Option<SpacedropAutoAccept>,
+	/// Peers paired with this node, with what each of them is allowed to do
+	#[serde(default)]
+	pub trusted_peers: HashMap<RemoteIdentity, TrustedPeer>,
+	/// Whether peers that aren't trusted can send Spacedrop requests, they always have to be accepted
+	#[serde(default = "default_true")]
+	pub allow_untrusted_spacedrop: bool,
+	/// Upload and download limits of P2P traffic
+	#[serde(default)]
+	pub bandwidth: BandwidthConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct SpacedropAutoAccept {
+	pub peers: HashSet<RemoteIdentity>,
+	pub directory: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct TrustedPeer {
+	/// Name of the peer when it was paired
+	pub name: String,
+	pub permissions: PeerPermissions,
+	pub paired_at: DateTime<Utc>,
+}
+
+/// What a peer is allowed to do on this node
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct PeerPermissions {
+	pub spacedrop: bool,
+	/// Browsing this node's locations, remote rspc also requires `enable_remote_access`
+	pub remote_browsing: bool,
+	/// Fetching files of the libraries both nodes are part of
+	pub library_files: bool,
+}
+
+impl PeerPermissions {
+	/// Granted to newly paired peers
+	pub const PAIRED: Self = Self {
+		spacedrop: true,
+		remote_browsing: false,
+		library_files: true,
+	};
+}
+
+/// Limits in KiB per second, `None` being unlimited
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct RateLimit {
+	pub upload_kib_per_sec: Option<u32>,
+	pub download_kib_per_sec: Option<u32>,
+}
+
+impl RateLimit {
+	/// Zero limits are rejected, `None` must be used to lift a limit
+	pub fn is_valid(&self) -> bool {
+		self.upload_kib_per_sec != Some(0) && self.download_kib_per_sec != Some(0)
+	}
+}
+
+impl From<RateLimit> for BandwidthLimits {
+	/// Zero limits saved by older versions are treated as unlimited
+	fn from(limit: RateLimit) -> Self {
+		let to_bytes =
+			|kib: Option<u32>| kib.filter(|kib| *kib > 0).map(|kib| u64::from(kib) * 1024);
+
+		Self {
+			upload: to_bytes(limit.upload_kib_per_sec),
+			download: to_bytes(limit.download_kib_per_sec),
+		}
+	}
+}
+
+/// Limits replacing the global ones during part of the day
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct BandwidthSchedule {
+	/// Minutes after midnight in local time, the schedule wraps around midnight when `start` is
+	/// after `end`
+	pub start: u16,
+	pub end: u16,
+	pub limits: RateLimit,
+}
+
+impl BandwidthSchedule {
+	const MINUTES_PER_DAY: u16 = 24 * 60;
+
+	pub fn is_valid(&self) -> bool {
+		self.start < Self::MINUTES_PER_DAY && self.end < Self::MINUTES_PER_DAY
+	}
+
+	fn is_active(&self, minute: u16) -> bool {
+		if self.start <= self.end {
+			self.start <= minute && minute < self.end
+		} else {
+			minute >= self.start || minute < self.end
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type)]
+pub struct TrafficPriorities {
+	pub sync: Priority,
+	pub file_fetch: Priority,
+	pub spacedrop: Priority,
+}
+
+impl Default for TrafficPriorities {
+	fn default() -> Self {
+		Self {
+			sync: Priority::High,
+			file_fetch: Priority::Normal,
+			spacedrop: Priority::Low,
+		}
+	}
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize, Type)]
+pub struct BandwidthConfig {
+	/// Shared by all peers
+	#[serde(default)]
+	pub limits: RateLimit,
+	/// The first schedule active at the current time replaces `limits`
+	#[serde(default)]
+	pub schedules: Vec<BandwidthSchedule>,
+	/// Applied to each peer on top of the global limits
+	#[serde(default)]
+	pub peer_limits: HashMap<RemoteIdentity, RateLimit>,
+	#[serde(default)]
+	pub priorities: TrafficPriorities,
+}
+
+impl BandwidthConfig {
+	pub fn limits_are_valid(&self) -> bool {
+		self.limits.is_valid()
+			&& self
+				.schedules
+				.iter()
+				.all(|schedule| schedule.limits.is_valid())
+			&& self.peer_limits.values().all(RateLimit::is_valid)
+	}
+
+	/// The global limits at the given minute after midnight
+	pub fn active_limits(&self, minute: u16) -> RateLimit {
+		self.schedules
+			.iter()
+			.find(|schedule| schedule.is_active(minute))
+			.map_or(self.limits, |schedule| schedule.limits)
+	}
 }
 
 impl Default for NodeConfigP2P {
@@ -111,6 +265,10 @@ impl Default for NodeConfigP2P {
 			disable_relay: true,
 			enable_remote_access: false,
 			manual_peers: Default::default(),
+			spacedrop_auto_accept: None,
+			trusted_peers: HashMap::new(),
+			allow_untrusted_spacedrop: true,
+			bandwidth: BandwidthConfig::default(),
 		}
 	}
 }
@@ -175,7 +333,77 @@ mod identity_serde {
 #[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq, Type)]
 pub struct NodePreferences {
 	// pub thumbnailer: ThumbnailerPreferences,
-	// TODO(fogodev): introduce preferences to choose how many worker the task system should have
+	#[serde(default)]
+	pub task_system: TaskSystemPreferences,
+	#[serde(default)]
+	pub job_history: JobHistoryPreferences,
+	#[serde(default)]
+	pub capacity_alerts: CapacityAlertPreferences,
+}
+
+/// When to warn about volumes running out of space, each rule is disabled when not set
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Type)]
+#[serde(default)]
+pub struct CapacityAlertPreferences {
+	/// Warn when a volume is projected to fill up within this amount of days at its current growth
+	pub fill_within_days: Option<u32>,
+	/// Warn when less than this percentage of a volume is available
+	pub min_available_percent: Option<u8>,
+}
+
+impl Default for CapacityAlertPreferences {
+	fn default() -> Self {
+		Self {
+			fill_within_days: Some(7),
+			min_available_percent: Some(5),
+		}
+	}
+}
+
+/// Retention rules for finished job reports, which are kept forever if no rule is set
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq, Type)]
+pub struct JobHistoryPreferences {
+	/// Delete finished job reports older than this amount of days
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub max_age_days: Option<u32>,
+	/// Keep at most this amount of finished job reports per library, deleting the oldest ones
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub max_reports: Option<u32>,
+}
+
+/// How much of the machine the task system is allowed to use
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq, Type)]
+pub struct TaskSystemPreferences {
+	/// Amount of tasks running at the same time, the task system default is used if not set
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub workers_count: Option<u32>,
+	/// Maximum amount of running tasks for each kind of heavy work, unlimited if not set
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub concurrency_limits: BTreeMap<ConcurrencyGroup, u32>,
+	/// Shrink the workers count while the machine is busy or running on battery
+	#[serde(default, skip_serializing_if = "skip_if_false")]
+	pub auto_throttle: bool,
+}
+
+/// Kinds of heavy tasks that can have their concurrency limited
+#[derive(
+	Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, Type,
+)]
+pub enum ConcurrencyGroup {
+	/// Video thumbnails and media data extraction
+	#[serde(rename = "ffmpeg")]
+	FFmpeg,
+}
+
+impl ConcurrencyGroup {
+	pub const ALL: [Self; 1] = [Self::FFmpeg];
+
+	/// The concurrency group declared by the tasks of this kind on the task system
+	pub const fn task_group(self) -> &'static str {
+		match self {
+			Self::FFmpeg => sd_core_heavy_lifting::media_processor::FFMPEG_TASKS_GROUP,
+		}
+	}
 }
 
 #[derive(
@@ -522,6 +750,11 @@ impl Manager {
 		self.data_directory_path.clone()
 	}
 
+	/// preferences_watcher returns a receiver notified every time the node preferences are updated.
+	pub(crate) fn preferences_watcher(&self) -> watch::Receiver<NodePreferences> {
+		self.preferences_watcher_tx.subscribe()
+	}
+
 	/// write allows the user to update the configuration. This is done in a closure while a Mutex lock is held so that the user can't cause a race condition if the config were to be updated in multiple parts of the app at the same time.
 	pub(crate) async fn write<F: FnOnce(&mut NodeConfig)>(
 		&self,
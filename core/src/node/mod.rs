@@ -1,6 +1,9 @@
 pub mod config;
 mod hardware;
 mod platform;
+pub(crate) mod power;
+mod throttle;
 
 pub use hardware::*;
 pub use platform::*;
+pub(crate) use throttle::TaskSystemThrottle;
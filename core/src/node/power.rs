@@ -0,0 +1,147 @@
+//! Best effort probes for the machine's power source and load, used to decide if background
+//! work should run right now.
+
+use std::time::Duration;
+
+use sysinfo::{CpuExt, ProcessExt, System, SystemExt};
+use tokio::task::spawn_blocking;
+use tracing::warn;
+
+/// Checks if the machine is plugged in. Machines without any battery, and platforms where we
+/// can't tell, are assumed to be on AC power.
+#[allow(unreachable_code)]
+pub async fn is_on_ac_power() -> bool {
+	#[cfg(target_os = "linux")]
+	return linux::is_on_ac_power().await;
+
+	#[cfg(target_os = "macos")]
+	return macos::is_on_ac_power().await;
+
+	#[cfg(target_os = "windows")]
+	return windows::is_on_ac_power();
+
+	true
+}
+
+/// Global CPU usage of the machine, from 0 to 100, sampled over a short period of time
+pub async fn cpu_usage() -> f32 {
+	sample_cpu_usage(false).await
+}
+
+/// Like [`cpu_usage`], but leaving out our own process, so our background work doesn't count as
+/// the machine being busy
+pub async fn cpu_usage_by_other_processes() -> f32 {
+	sample_cpu_usage(true).await
+}
+
+async fn sample_cpu_usage(exclude_own_process: bool) -> f32 {
+	spawn_blocking(move || {
+		let mut sys = System::new();
+		let own_pid = exclude_own_process
+			.then(sysinfo::get_current_pid)
+			.and_then(Result::ok);
+
+		// The first refresh only sets the baseline, usage is computed between refreshes
+		sys.refresh_cpu();
+		if let Some(pid) = own_pid {
+			sys.refresh_process(pid);
+		}
+		std::thread::sleep(System::MINIMUM_CPU_UPDATE_INTERVAL.max(Duration::from_millis(250)));
+		sys.refresh_cpu();
+
+		let own_usage = own_pid
+			.filter(|pid| sys.refresh_process(*pid))
+			.and_then(|pid| sys.process(pid))
+			.map_or(0.0, ProcessExt::cpu_usage);
+
+		// A process usage is relative to a single core, while the global one is for all of them
+		#[allow(clippy::cast_precision_loss)]
+		let cores_count = sys.cpus().len().max(1) as f32;
+
+		(sys.global_cpu_info().cpu_usage() - own_usage / cores_count).max(0.0)
+	})
+	.await
+	.unwrap_or_else(|e| {
+		warn!(?e, "Failed to sample CPU usage;");
+		0.0
+	})
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+	use tokio::fs;
+
+	const POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+
+	pub async fn is_on_ac_power() -> bool {
+		let Ok(mut entries) = fs::read_dir(POWER_SUPPLY_DIR).await else {
+			return true;
+		};
+
+		let mut found_battery = false;
+
+		while let Ok(Some(entry)) = entries.next_entry().await {
+			let path = entry.path();
+
+			let Ok(supply_type) = fs::read_to_string(path.join("type")).await else {
+				continue;
+			};
+
+			match supply_type.trim() {
+				"Mains" | "USB" => {
+					if fs::read_to_string(path.join("online"))
+						.await
+						.is_ok_and(|online| online.trim() == "1")
+					{
+						return true;
+					}
+				}
+				"Battery" => {
+					found_battery = true;
+					if fs::read_to_string(path.join("status"))
+						.await
+						.is_ok_and(|status| status.trim() != "Discharging")
+					{
+						return true;
+					}
+				}
+				_ => {}
+			}
+		}
+
+		!found_battery
+	}
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+	use tokio::process::Command;
+
+	pub async fn is_on_ac_power() -> bool {
+		Command::new("pmset")
+			.args(["-g", "batt"])
+			.output()
+			.await
+			.map_or(true, |output| {
+				String::from_utf8_lossy(&output.stdout).contains("'AC Power'")
+			})
+	}
+}
+
+#[cfg(target_os = "windows")]
+mod windows {
+	use ::windows::Win32::System::Power::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+
+	const AC_LINE_OFFLINE: u8 = 0;
+
+	pub fn is_on_ac_power() -> bool {
+		let mut status = SYSTEM_POWER_STATUS::default();
+
+		// SAFETY: `status` is a valid pointer to a `SYSTEM_POWER_STATUS` for the call duration
+		if unsafe { GetSystemPowerStatus(&mut status) }.is_err() {
+			return true;
+		}
+
+		status.ACLineStatus != AC_LINE_OFFLINE
+	}
+}
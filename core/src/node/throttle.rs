@@ -0,0 +1,136 @@
+//! Keeps the task system in sync with the node preferences, shrinking its workers while the
+//! machine is busy or running on battery if the user enabled automatic throttling.
+
+use crate::{invalidate_query, Node};
+
+use std::{pin::pin, sync::Arc, time::Duration};
+
+use futures::{stream, StreamExt};
+use futures_concurrency::stream::Merge;
+use tokio::{
+	spawn,
+	time::{interval, MissedTickBehavior},
+};
+use tokio_stream::wrappers::IntervalStream;
+use tracing::{debug, error, info, trace};
+
+use super::{
+	config::{ConcurrencyGroup, TaskSystemPreferences},
+	power,
+};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// CPU usage of other processes, in percent, above which we start throttling. Our own usage is
+/// left out, otherwise a high workers count would throttle itself and then unthrottle as soon as
+/// it stops using the CPU.
+const HIGH_CPU_USAGE_THRESHOLD: f32 = 85.0;
+
+/// CPU usage of other processes, in percent, below which we stop throttling. It's lower than the
+/// threshold to start, so we don't keep flipping between states when the usage is around it.
+const NORMAL_CPU_USAGE_THRESHOLD: f32 = 60.0;
+
+/// Amount of workers kept while throttled, tasks still make progress but barely noticeable
+const THROTTLED_WORKERS_COUNT: usize = 1;
+
+pub struct TaskSystemThrottle;
+
+impl TaskSystemThrottle {
+	pub fn start(node: Arc<Node>) {
+		spawn(async move {
+			while let Err(e) = spawn(run(Arc::clone(&node))).await {
+				if e.is_panic() {
+					error!(?e, "Task system throttle panicked;");
+				} else {
+					trace!("Task system throttle received shutdown signal and will exit...");
+					break;
+				}
+				trace!("Restarting task system throttle processing task...");
+			}
+
+			debug!("Task system throttle gracefully shutdown");
+		});
+	}
+}
+
+async fn run(node: Arc<Node>) {
+	enum StreamMessage {
+		PreferencesChanged,
+		Tick,
+	}
+
+	let mut ticker = interval(CHECK_INTERVAL);
+	ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+	let mut msg_stream = pin!((
+		stream::unfold(node.config.preferences_watcher(), |mut rx| async move {
+			rx.changed()
+				.await
+				.ok()
+				.map(|()| (StreamMessage::PreferencesChanged, rx))
+		}),
+		IntervalStream::new(ticker).map(|_| StreamMessage::Tick),
+	)
+		.merge());
+
+	let mut throttled = false;
+
+	while let Some(msg) = msg_stream.next().await {
+		let preferences = node.config.get().await.preferences.task_system;
+
+		if matches!(msg, StreamMessage::PreferencesChanged) {
+			trace!(?preferences, "Task system preferences changed;");
+		}
+
+		apply_concurrency_limits(&node, &preferences);
+
+		let was_throttled = throttled;
+		throttled = preferences.auto_throttle && should_throttle(was_throttled).await;
+
+		if throttled != was_throttled {
+			info!(%throttled, "Task system automatic throttling changed;");
+		}
+
+		let workers_count = if throttled {
+			THROTTLED_WORKERS_COUNT
+		} else {
+			preferences.workers_count.map_or_else(
+				|| node.task_system.default_workers_count(),
+				|count| count as usize,
+			)
+		};
+
+		if workers_count != node.task_system.workers_count() {
+			node.task_system.set_workers_count(workers_count);
+			invalidate_query!(node; node, "nodes.taskSystem");
+		}
+	}
+}
+
+fn apply_concurrency_limits(node: &Node, preferences: &TaskSystemPreferences) {
+	for group in ConcurrencyGroup::ALL {
+		let limit = preferences
+			.concurrency_limits
+			.get(&group)
+			.map(|limit| *limit as usize);
+
+		if node.task_system.concurrency_limit(group.task_group()) != limit {
+			node.task_system
+				.set_concurrency_limit(group.task_group(), limit);
+		}
+	}
+}
+
+async fn should_throttle(throttled: bool) -> bool {
+	if !power::is_on_ac_power().await {
+		return true;
+	}
+
+	let cpu_usage = power::cpu_usage_by_other_processes().await;
+
+	if throttled {
+		cpu_usage >= NORMAL_CPU_USAGE_THRESHOLD
+	} else {
+		cpu_usage >= HIGH_CPU_USAGE_THRESHOLD
+	}
+}
@@ -1,6 +1,9 @@
 use crate::library::Library;
 
-use sd_prisma::{prisma::tag, prisma_sync};
+use sd_prisma::{
+	prisma::{device, object, tag, tag_on_object},
+	prisma_sync,
+};
 use sd_sync::*;
 
 use chrono::Utc;
@@ -45,3 +48,54 @@ impl TagCreateArgs {
 		.await
 	}
 }
+
+/// Assigns a tag to objects, objects that already have this tag are skipped
+pub async fn assign_to_objects(
+	Library { db, sync, .. }: &Library,
+	(tag_id, tag_pub_id): (tag::id::Type, &tag::pub_id::Type),
+	device_id: device::id::Type,
+	objects: impl IntoIterator<Item = (object::id::Type, object::pub_id::Type)> + Send,
+) -> Result<(), sd_core_sync::Error> {
+	let (sync_ops, db_creates) = objects
+		.into_iter()
+		.map(|(id, pub_id)| {
+			(
+				sync.relation_create(
+					prisma_sync::tag_on_object::SyncId {
+						tag: prisma_sync::tag::SyncId {
+							pub_id: tag_pub_id.clone(),
+						},
+						object: prisma_sync::object::SyncId { pub_id },
+					},
+					[sync_entry!(
+						prisma_sync::device::SyncId {
+							pub_id: sync.device_pub_id.to_db(),
+						},
+						tag_on_object::device
+					)],
+				),
+				tag_on_object::CreateUnchecked {
+					tag_id,
+					object_id: id,
+					_params: vec![
+						tag_on_object::date_created::set(Some(Utc::now().into())),
+						tag_on_object::device_id::set(Some(device_id)),
+					],
+				},
+			)
+		})
+		.unzip::<_, _, Vec<_>, Vec<_>>();
+
+	if !sync_ops.is_empty() && !db_creates.is_empty() {
+		sync.write_ops(
+			db,
+			(
+				sync_ops,
+				db.tag_on_object().create_many(db_creates).skip_duplicates(),
+			),
+		)
+		.await?;
+	}
+
+	Ok(())
+}
@@ -1,5 +1,5 @@
 use crate::{
-	location::{/*indexer::IndexerError,*/ LocationError},
+	location::{/*indexer::IndexerError,*/ archive::ArchiveError, LocationError},
 	object::{
 		fs::error::FileSystemJobsError, /*media::old_media_processor::MediaProcessorError,*/
 		/*old_file_identifier::FileIdentifierJobError,*/ validation::ValidatorError,
@@ -62,6 +62,8 @@ pub enum JobError {
 	Validator(#[from] ValidatorError),
 	#[error(transparent)]
 	FileSystemJobsError(#[from] FileSystemJobsError),
+	#[error(transparent)]
+	Archive(#[from] ArchiveError),
 	// #[error(transparent)]
 	// CryptoError(#[from] CryptoError),
 
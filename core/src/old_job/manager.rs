@@ -1,5 +1,6 @@
 use crate::{
 	library::Library,
+	location::archive::{OldArchiveRestorerJobInit, OldLocationArchiverJobInit},
 	object::{
 		fs::{
 			old_copy::OldFileCopierJobInit, old_cut::OldFileCutterJobInit,
@@ -11,7 +12,7 @@ use crate::{
 	Node,
 };
 
-use sd_prisma::prisma::job;
+use sd_prisma::prisma::{job, location};
 
 use std::{
 	collections::{HashMap, HashSet, VecDeque},
@@ -298,7 +299,7 @@ impl OldJobs {
 				Err(JobError::UnknownJobName(_, job_name))
 					if matches!(
 						job_name.as_str(),
-						"indexer" | "file_identifier" | "media_processor"
+						"indexer" | "file_identifier" | "media_processor" | "image_converter"
 					) =>
 				{
 					debug!(%job_name, "Moved to new job system");
@@ -356,6 +357,27 @@ impl OldJobs {
 			.collect()
 	}
 
+	/// Running jobs of the library targeting one of the locations
+	pub async fn running_on_locations(
+		&self,
+		library_id: Uuid,
+		location_ids: &HashSet<location::id::Type>,
+	) -> Vec<Uuid> {
+		self.running_workers
+			.read()
+			.await
+			.values()
+			.filter(|worker| worker.library_id == library_id && !worker.is_paused())
+			.map(Worker::report)
+			.filter(|report| {
+				report
+					.location_id
+					.is_some_and(|location_id| location_ids.contains(&location_id))
+			})
+			.map(|report| report.id)
+			.collect()
+	}
+
 	/// Check if the manager currently has some active workers.
 	pub async fn has_active_workers(&self, library_id: Uuid) -> bool {
 		self.running_workers
@@ -411,6 +433,8 @@ fn initialize_resumable_job(
 			OldFileCopierJobInit,
 			OldFileDeleterJobInit,
 			OldFileEraserJobInit,
+			OldLocationArchiverJobInit,
+			OldArchiveRestorerJobInit,
 		]
 	)
 }
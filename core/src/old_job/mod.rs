@@ -163,7 +163,10 @@ impl<SJob: StatefulJob> OldJob<SJob> {
 		Box::new(OldJob::<SJob> {
 			id,
 			hash: <SJob as StatefulJob>::hash(&init),
-			report: Some(JobReportBuilder::new(id, SJob::NAME.to_string()).build()),
+			report: Some(OldJobReport {
+				location_id: Some(init.target_location()),
+				..JobReportBuilder::new(id, SJob::NAME.to_string()).build()
+			}),
 			state: Some(JobState {
 				init,
 				data: None,
@@ -196,6 +199,20 @@ impl<SJob: StatefulJob> OldJob<SJob> {
 		}))
 	}
 
+	pub fn id(&self) -> Uuid {
+		self.id
+	}
+
+	/// Groups this job under an already existing parent report, like a workflow run
+	pub fn with_parent(mut self: Box<Self>, parent_id: Uuid, action: String) -> Box<Self> {
+		if let Some(report) = self.report.as_mut() {
+			report.parent_id = Some(parent_id);
+			report.action = Some(action);
+		}
+
+		self
+	}
+
 	pub async fn spawn(
 		self,
 		node: &Arc<Node>,
@@ -1,5 +1,6 @@
 use crate::{
 	library::Library,
+	location::archive::{OldArchiveRestorerJobInit, OldLocationArchiverJobInit},
 	object::{
 		fs::{
 			old_copy::OldFileCopierJobInit, old_cut::OldFileCutterJobInit,
@@ -11,7 +12,7 @@ use crate::{
 
 use sd_core_prisma_helpers::job_without_data;
 
-use sd_prisma::prisma::job;
+use sd_prisma::prisma::{job, location};
 use sd_utils::db::{maybe_missing, MissingFieldError};
 
 use std::fmt::{Display, Formatter};
@@ -50,6 +51,8 @@ pub struct OldJobReport {
 	pub completed_at: Option<DateTime<Utc>>,
 
 	pub parent_id: Option<Uuid>,
+	#[serde(default)]
+	pub location_id: Option<location::id::Type>,
 
 	pub status: JobStatus,
 	pub task_count: i32,
@@ -74,6 +77,7 @@ impl From<OldJobReport> for sd_core_heavy_lifting::job_system::report::Report {
 			started_at,
 			completed_at,
 			parent_id,
+			location_id,
 			status,
 			task_count,
 			completed_task_count,
@@ -86,19 +90,56 @@ impl From<OldJobReport> for sd_core_heavy_lifting::job_system::report::Report {
 		use sd_core_heavy_lifting::{job_system::report::ReportOutputMetadata, JobName};
 
 		let mut new_metadata = Vec::new();
+		// Old jobs created before their location was recorded on database have it only in their
+		// init data
+		let mut metadata_location_id = None;
 
 		if let Some(metadata) = metadata {
 			if let Some(metadata) = metadata.as_object() {
 				if let Some(metadata) = metadata.get("output") {
 					if let Some(metadata) = metadata.as_object() {
 						if let Some(metadata) = metadata.get("init") {
-							if let Ok(OldFileCopierJobInit {
+							// Checked first, as the restorer init would also parse as the deleter one
+							if let Ok(OldLocationArchiverJobInit {
+								location_id: archiver_location_id,
+								sub_path,
+								..
+							}) = serde_json::from_value::<OldLocationArchiverJobInit>(
+								metadata.clone(),
+							) {
+								new_metadata.push(
+									ReportOutputMetadata::Archiver {
+										location_id: *metadata_location_id
+											.insert(archiver_location_id),
+										sub_path,
+									}
+									.into(),
+								);
+							} else if let Ok(OldArchiveRestorerJobInit {
+								archive_id,
+								location_id: restorer_location_id,
+								file_path_ids,
+							}) = serde_json::from_value::<OldArchiveRestorerJobInit>(
+								metadata.clone(),
+							) {
+								new_metadata.push(
+									ReportOutputMetadata::ArchiveRestorer {
+										location_id: *metadata_location_id
+											.insert(restorer_location_id),
+										archive_id,
+										file_path_ids,
+									}
+									.into(),
+								);
+							} else if let Ok(OldFileCopierJobInit {
 								source_location_id,
 								target_location_id,
 								sources_file_path_ids,
 								target_location_relative_directory_path,
-							}) = serde_json::from_value::<OldFileCopierJobInit>(metadata.clone())
+							}) =
+								serde_json::from_value::<OldFileCopierJobInit>(metadata.clone())
 							{
+								metadata_location_id = Some(source_location_id);
 								new_metadata.push(
 									ReportOutputMetadata::Copier {
 										source_location_id,
@@ -116,6 +157,7 @@ impl From<OldJobReport> for sd_core_heavy_lifting::job_system::report::Report {
 							}) =
 								serde_json::from_value::<OldFileCutterJobInit>(metadata.clone())
 							{
+								metadata_location_id = Some(source_location_id);
 								new_metadata.push(
 									ReportOutputMetadata::Mover {
 										source_location_id,
@@ -126,20 +168,21 @@ impl From<OldJobReport> for sd_core_heavy_lifting::job_system::report::Report {
 									.into(),
 								);
 							} else if let Ok(OldFileDeleterJobInit {
-								location_id,
+								location_id: deleter_location_id,
 								file_path_ids,
 							}) =
 								serde_json::from_value::<OldFileDeleterJobInit>(metadata.clone())
 							{
 								new_metadata.push(
 									ReportOutputMetadata::Deleter {
-										location_id,
+										location_id: *metadata_location_id
+											.insert(deleter_location_id),
 										file_path_ids,
 									}
 									.into(),
 								);
 							} else if let Ok(OldFileEraserJobInit {
-								location_id,
+								location_id: eraser_location_id,
 								file_path_ids,
 								passes,
 							}) =
@@ -147,7 +190,8 @@ impl From<OldJobReport> for sd_core_heavy_lifting::job_system::report::Report {
 							{
 								new_metadata.push(
 									ReportOutputMetadata::Eraser {
-										location_id,
+										location_id: *metadata_location_id
+											.insert(eraser_location_id),
 										file_path_ids,
 										passes: passes as u32,
 									}
@@ -159,7 +203,7 @@ impl From<OldJobReport> for sd_core_heavy_lifting::job_system::report::Report {
 								) {
 								new_metadata.push(
 									ReportOutputMetadata::FileValidator {
-										location_id: location.id,
+										location_id: *metadata_location_id.insert(location.id),
 										sub_path,
 									}
 									.into(),
@@ -179,6 +223,8 @@ impl From<OldJobReport> for sd_core_heavy_lifting::job_system::report::Report {
 				"file_deleter" => JobName::Delete,
 				"file_eraser" => JobName::Erase,
 				"object_validator" => JobName::FileValidator,
+				"location_archiver" => JobName::Archive,
+				"archive_restorer" => JobName::RestoreArchive,
 
 				// Already implemented in the new job system
 				"indexer" => JobName::Indexer,
@@ -195,6 +241,7 @@ impl From<OldJobReport> for sd_core_heavy_lifting::job_system::report::Report {
 			started_at,
 			completed_at,
 			parent_id,
+			location_id: location_id.or(metadata_location_id),
 			status: status.into(),
 			task_count,
 			completed_task_count,
@@ -242,6 +289,7 @@ impl TryFrom<job::Data> for OldJobReport {
 			parent_id: data
 				.parent_id
 				.map(|id| Uuid::from_slice(&id).expect("corrupted database")),
+			location_id: data.location_id,
 			status: JobStatus::try_from(maybe_missing(data.status, "job.status")?)
 				.expect("corrupted database"),
 			task_count: data.task_count.unwrap_or(0),
@@ -284,6 +332,7 @@ impl TryFrom<job_without_data::Data> for OldJobReport {
 			parent_id: data
 				.parent_id
 				.map(|id| Uuid::from_slice(&id).expect("corrupted database")),
+			location_id: data.location_id,
 			status: JobStatus::try_from(maybe_missing(data.status, "job.status")?)
 				.expect("corrupted database"),
 			task_count: data.task_count.unwrap_or(0),
@@ -313,6 +362,7 @@ impl OldJobReport {
 			data: None,
 			metadata: None,
 			parent_id: None,
+			location_id: None,
 			completed_task_count: 0,
 			info: String::new(),
 			phase: String::new(),
@@ -360,6 +410,7 @@ impl OldJobReport {
 						job::task_count::set(Some(1)),
 						job::info::set(Some(self.info.clone())),
 						job::completed_task_count::set(Some(0)),
+						job::location_id::set(self.location_id),
 					],
 					[self
 						.parent_id
@@ -484,6 +535,7 @@ impl JobReportBuilder {
 			data: None,
 			metadata: self.metadata,
 			parent_id: self.parent_id,
+			location_id: None,
 			completed_task_count: 0,
 			info: String::new(),
 			phase: String::new(),
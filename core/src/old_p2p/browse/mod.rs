@@ -0,0 +1,448 @@
+//! Browsing the locations of another node of the same library, without syncing its files.
+//! Directories can be listed, or searched with the same arguments as `search.paths`.
+//!
+//! Requests go through the library tunnel, so only nodes of the library can browse it, and the
+//! browsed node only answers nodes with the remote browsing permission. Thumbnails fetched from
+//! the remote node are cached locally, so they're still shown while it's offline.
+
+use crate::{
+	api::search::{find_file_paths, FilePathSearchArgs},
+	library::Library,
+	Node,
+};
+
+use sd_core_heavy_lifting::media_processor::{
+	get_shard_hex, get_thumbnails_directory, ThumbnailKind, WEBP_EXTENSION,
+};
+use sd_core_prisma_helpers::CasId;
+use sd_old_p2p::{RemoteIdentity, TrafficClass};
+use sd_old_p2p_tunnel::Tunnel;
+use sd_prisma::prisma::{file_path, location, SortOrder};
+use sd_utils::{db::size_in_bytes_from_db, error::FileIOError};
+
+use std::{
+	io,
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+
+use tokio::{
+	fs,
+	io::{AsyncRead, AsyncWrite, AsyncWriteExt},
+};
+use tracing::{debug, instrument};
+use uuid::Uuid;
+
+use super::{Header, P2PManager};
+
+mod proto;
+
+pub use proto::*;
+
+/// Maximum amount of file paths sent for each request
+const MAX_PATHS_PER_REQUEST: u32 = 1000;
+
+/// Directory within the thumbnails directory where thumbnails of remote nodes are cached
+const REMOTE_THUMBNAILS_DIR: &str = "remote";
+
+async fn open_session(
+	p2p: &P2PManager,
+	library: &Library,
+	node_identity: RemoteIdentity,
+) -> Result<Tunnel, BrowseError> {
+	let peer = p2p
+		.get_instance(&library.id, node_identity)
+		.filter(|peer| peer.can_connect())
+		.ok_or(BrowseError::Offline)?;
+
+	let mut stream = peer.new_stream().await?;
+	stream.set_traffic_class(TrafficClass::FileFetch);
+	stream.write_all(&Header::Browse.to_bytes()).await?;
+
+	Tunnel::initiator(stream, &library.identity)
+		.await
+		.map_err(Into::into)
+}
+
+async fn request(
+	tunnel: &mut Tunnel,
+	request: &BrowseRequest,
+) -> Result<BrowseResponse, BrowseError> {
+	tunnel.write_all(&request.to_bytes()?).await?;
+	tunnel.flush().await?;
+
+	match BrowseResponse::from_stream(tunnel).await? {
+		BrowseResponse::Error(e) => Err(BrowseError::Remote(e)),
+		response => Ok(response),
+	}
+}
+
+/// Sends a single request, closing the session right after
+async fn request_once(
+	p2p: &P2PManager,
+	library: &Library,
+	node_identity: RemoteIdentity,
+	req: &BrowseRequest,
+) -> Result<BrowseResponse, BrowseError> {
+	let mut tunnel = open_session(p2p, library, node_identity).await?;
+	let response = request(&mut tunnel, req).await?;
+
+	tunnel.write_all(&BrowseRequest::Done.to_bytes()?).await?;
+	tunnel.flush().await?;
+
+	Ok(response)
+}
+
+/// Locations stored on the remote node
+#[instrument(skip(p2p, library), fields(library_id = %library.id), err)]
+pub async fn locations(
+	p2p: &P2PManager,
+	library: &Library,
+	node_identity: RemoteIdentity,
+) -> Result<Vec<RemoteLocation>, BrowseError> {
+	match request_once(p2p, library, node_identity, &BrowseRequest::Locations).await? {
+		BrowseResponse::Locations(locations) => Ok(locations),
+		_ => Err(BrowseError::UnexpectedResponse),
+	}
+}
+
+/// Contents of a directory of a location stored on the remote node, directories first
+#[instrument(skip(p2p, library), fields(library_id = %library.id), err)]
+pub async fn paths(
+	p2p: &P2PManager,
+	library: &Library,
+	node_identity: RemoteIdentity,
+	location_pub_id: Uuid,
+	materialized_path: String,
+	skip: u32,
+	take: u32,
+) -> Result<Vec<RemoteFilePath>, BrowseError> {
+	match request_once(
+		p2p,
+		library,
+		node_identity,
+		&BrowseRequest::Paths {
+			location_pub_id,
+			materialized_path,
+			skip,
+			take,
+		},
+	)
+	.await?
+	{
+		BrowseResponse::Paths(paths) => Ok(paths),
+		_ => Err(BrowseError::UnexpectedResponse),
+	}
+}
+
+/// File paths of the remote node's locations matching a `search.paths` query
+#[instrument(skip(p2p, library), fields(library_id = %library.id), err)]
+pub async fn search_paths(
+	p2p: &P2PManager,
+	library: &Library,
+	node_identity: RemoteIdentity,
+	args: FilePathSearchArgs,
+) -> Result<Vec<RemoteFilePath>, BrowseError> {
+	match request_once(
+		p2p,
+		library,
+		node_identity,
+		&BrowseRequest::SearchPaths(args),
+	)
+	.await?
+	{
+		BrowseResponse::SearchPaths(paths) => Ok(paths),
+		_ => Err(BrowseError::UnexpectedResponse),
+	}
+}
+
+/// The cas_id comes from the frontend or a remote node, so it must not escape the thumbnails
+/// directory
+fn validate_cas_id(cas_id: &str) -> Result<(), BrowseError> {
+	if cas_id.len() < 3 || !cas_id.chars().all(|c| c.is_ascii_alphanumeric()) {
+		return Err(BrowseError::InvalidCasId(cas_id.to_string()));
+	}
+
+	Ok(())
+}
+
+/// Where a thumbnail of a remote node is cached
+pub fn cached_thumbnail_path(
+	data_directory: impl AsRef<Path>,
+	library_id: Uuid,
+	cas_id: &str,
+) -> Result<PathBuf, BrowseError> {
+	validate_cas_id(cas_id)?;
+
+	let cas_id = CasId::from(cas_id);
+
+	let mut path = get_thumbnails_directory(data_directory);
+	path.push(REMOTE_THUMBNAILS_DIR);
+	path.push(library_id.to_string());
+	path.push(get_shard_hex(&cas_id));
+	path.push(cas_id.as_str());
+	path.set_extension(WEBP_EXTENSION);
+
+	Ok(path)
+}
+
+/// Path of the cached thumbnail, fetching it from the remote node if it isn't cached yet.
+/// `None` if the remote node doesn't have a thumbnail for this cas_id.
+#[instrument(skip(p2p, library, data_directory), fields(library_id = %library.id), err)]
+pub async fn thumbnail(
+	p2p: &P2PManager,
+	library: &Library,
+	data_directory: impl AsRef<Path>,
+	node_identity: RemoteIdentity,
+	cas_id: String,
+) -> Result<Option<PathBuf>, BrowseError> {
+	let path = cached_thumbnail_path(data_directory, library.id, &cas_id)?;
+
+	if fs::metadata(&path).await.is_ok() {
+		return Ok(Some(path));
+	}
+
+	let BrowseResponse::Thumbnail(thumbnail) = request_once(
+		p2p,
+		library,
+		node_identity,
+		&BrowseRequest::Thumbnail { cas_id },
+	)
+	.await?
+	else {
+		return Err(BrowseError::UnexpectedResponse);
+	};
+
+	let Some(thumbnail) = thumbnail else {
+		return Ok(None);
+	};
+
+	if let Some(parent) = path.parent() {
+		fs::create_dir_all(parent)
+			.await
+			.map_err(|e| FileIOError::from((parent, e)))?;
+	}
+
+	fs::write(&path, thumbnail)
+		.await
+		.map_err(|e| FileIOError::from((&path, e)))?;
+
+	debug!(path = %path.display(), "Cached thumbnail of remote node;");
+
+	Ok(Some(path))
+}
+
+/// Removes the cached thumbnails of the library
+pub async fn clear_thumbnails_cache(
+	data_directory: impl AsRef<Path>,
+	library_id: Uuid,
+) -> Result<(), BrowseError> {
+	let path = get_thumbnails_directory(data_directory)
+		.join(REMOTE_THUMBNAILS_DIR)
+		.join(library_id.to_string());
+
+	match fs::remove_dir_all(&path).await {
+		Ok(()) => Ok(()),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+		Err(e) => Err(FileIOError::from((path, e)).into()),
+	}
+}
+
+async fn local_locations(library: &Library) -> Result<Vec<RemoteLocation>, BrowseError> {
+	Ok(library
+		.db
+		.location()
+		.find_many(vec![location::instance_id::equals(Some(
+			library.config().await.instance_id,
+		))])
+		.select(location::select!({ pub_id name path }))
+		.exec()
+		.await?
+		.into_iter()
+		.filter_map(|location| {
+			Some(RemoteLocation {
+				pub_id: Uuid::from_slice(&location.pub_id).ok()?,
+				name: location.name,
+				path: location.path,
+			})
+		})
+		.collect())
+}
+
+/// Converts any selection of a file path with the fields sent to browsing nodes, leaving
+/// `has_thumbnail` to [`with_thumbnails`]
+macro_rules! remote_file_path {
+	($file_path:expr) => {{
+		let file_path = $file_path;
+
+		Uuid::from_slice(&file_path.pub_id)
+			.ok()
+			.map(|pub_id| RemoteFilePath {
+				pub_id,
+				is_dir: file_path.is_dir.unwrap_or(false),
+				materialized_path: file_path.materialized_path.unwrap_or_default(),
+				name: file_path.name.unwrap_or_default(),
+				extension: file_path.extension.unwrap_or_default(),
+				size_in_bytes: file_path
+					.size_in_bytes_bytes
+					.filter(|bytes| bytes.len() == 8)
+					.map_or(0, |bytes| size_in_bytes_from_db(&bytes)),
+				cas_id: file_path.cas_id,
+				has_thumbnail: false,
+				date_modified: file_path.date_modified,
+			})
+	}};
+}
+
+async fn local_paths(
+	node: &Node,
+	library: &Library,
+	location_pub_id: Uuid,
+	materialized_path: String,
+	skip: u32,
+	take: u32,
+) -> Result<Vec<RemoteFilePath>, BrowseError> {
+	let file_paths = library
+		.db
+		.file_path()
+		.find_many(vec![
+			file_path::location::is(vec![
+				location::pub_id::equals(location_pub_id.as_bytes().to_vec()),
+				location::instance_id::equals(Some(library.config().await.instance_id)),
+			]),
+			file_path::materialized_path::equals(Some(materialized_path)),
+		])
+		.order_by(file_path::is_dir::order(SortOrder::Desc))
+		.order_by(file_path::name::order(SortOrder::Asc))
+		.skip(i64::from(skip))
+		.take(i64::from(take.clamp(1, MAX_PATHS_PER_REQUEST)))
+		.select(file_path::select!({
+			pub_id
+			is_dir
+			materialized_path
+			name
+			extension
+			size_in_bytes_bytes
+			cas_id
+			date_modified
+		}))
+		.exec()
+		.await?;
+
+	Ok(with_thumbnails(
+		node,
+		library,
+		file_paths
+			.into_iter()
+			.filter_map(|file_path| remote_file_path!(file_path))
+			.collect(),
+	)
+	.await)
+}
+
+async fn local_search_paths(
+	node: &Node,
+	library: &Library,
+	mut args: FilePathSearchArgs,
+) -> Result<Vec<RemoteFilePath>, BrowseError> {
+	// The whole result goes in a single message, so it's always paginated
+	args.take = Some(args.take.unwrap_or(u8::MAX));
+
+	let file_paths = find_file_paths(
+		&library.db,
+		args,
+		// Other nodes' file paths may be synced here, but only ours can be browsed
+		vec![file_path::location::is(vec![
+			location::instance_id::equals(Some(library.config().await.instance_id)),
+		])],
+	)
+	.await
+	.map_err(BrowseError::Search)?;
+
+	Ok(with_thumbnails(
+		node,
+		library,
+		file_paths
+			.into_iter()
+			.filter_map(|file_path| remote_file_path!(file_path))
+			.collect(),
+	)
+	.await)
+}
+
+async fn with_thumbnails(
+	node: &Node,
+	library: &Library,
+	mut paths: Vec<RemoteFilePath>,
+) -> Vec<RemoteFilePath> {
+	for path in &mut paths {
+		if let Some(cas_id) = &path.cas_id {
+			path.has_thumbnail = library
+				.thumbnail_exists(node, &CasId::from(cas_id))
+				.await
+				.unwrap_or(false);
+		}
+	}
+
+	paths
+}
+
+async fn local_thumbnail(
+	node: &Node,
+	library: &Library,
+	cas_id: &str,
+) -> Result<Option<Vec<u8>>, BrowseError> {
+	validate_cas_id(cas_id)?;
+
+	let path = ThumbnailKind::Indexed(library.id)
+		.compute_path(node.config.data_directory(), &CasId::from(cas_id));
+
+	match fs::read(&path).await {
+		Ok(thumbnail) => Ok(Some(thumbnail)),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+		Err(e) => Err(FileIOError::from((path, e)).into()),
+	}
+}
+
+/// Answers the browsing requests of the peer until it's done
+#[instrument(skip_all, fields(library_id = %library.id), err)]
+pub async fn responder(
+	stream: &mut (impl AsyncRead + AsyncWrite + Unpin),
+	node: &Node,
+	library: Arc<Library>,
+) -> Result<(), BrowseError> {
+	loop {
+		let result = match BrowseRequest::from_stream(stream).await? {
+			BrowseRequest::Locations => local_locations(&library)
+				.await
+				.map(BrowseResponse::Locations),
+			BrowseRequest::Paths {
+				location_pub_id,
+				materialized_path,
+				skip,
+				take,
+			} => local_paths(
+				node,
+				&library,
+				location_pub_id,
+				materialized_path,
+				skip,
+				take,
+			)
+			.await
+			.map(BrowseResponse::Paths),
+			BrowseRequest::SearchPaths(args) => local_search_paths(node, &library, args)
+				.await
+				.map(BrowseResponse::SearchPaths),
+			BrowseRequest::Thumbnail { cas_id } => local_thumbnail(node, &library, &cas_id)
+				.await
+				.map(BrowseResponse::Thumbnail),
+			BrowseRequest::Done => return Ok(()),
+		};
+
+		let response = result.unwrap_or_else(|e| BrowseResponse::Error(e.to_string()));
+
+		stream.write_all(&response.to_bytes()?).await?;
+		stream.flush().await?;
+	}
+}
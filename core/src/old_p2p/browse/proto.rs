@@ -0,0 +1,213 @@
+use crate::api::search::FilePathSearchArgs;
+
+use sd_old_p2p::NewStreamError;
+use sd_old_p2p_proto::{decode, encode};
+use sd_old_p2p_tunnel::TunnelError;
+use sd_utils::error::FileIOError;
+
+use chrono::{DateTime, FixedOffset};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use specta::Type;
+use thiserror::Error;
+use tokio::io::AsyncRead;
+use uuid::Uuid;
+
+#[derive(Debug, Error)]
+pub enum BrowseError {
+	#[error("failed to open stream to peer: {0}")]
+	NewStream(#[from] NewStreamError),
+	#[error("failed to establish library tunnel: {0}")]
+	Tunnel(#[from] TunnelError),
+	#[error("io error: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("failed to decode browse message: {0}")]
+	Decode(#[from] decode::Error),
+	#[error("failed to serialize browse message: {0}")]
+	Serialization(#[from] rmp_serde::encode::Error),
+	#[error("failed to deserialize browse message: {0}")]
+	Deserialization(#[from] rmp_serde::decode::Error),
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+	#[error("node is offline or doesn't have this library")]
+	Offline,
+	#[error("invalid cas_id: {0}")]
+	InvalidCasId(String),
+	#[error("error from remote node: {0}")]
+	Remote(String),
+	#[error("unexpected response from peer")]
+	UnexpectedResponse,
+	#[error("failed to search file paths: {0}")]
+	Search(rspc::Error),
+}
+
+impl From<BrowseError> for rspc::Error {
+	fn from(e: BrowseError) -> Self {
+		let code = match &e {
+			BrowseError::Offline => rspc::ErrorCode::PreconditionFailed,
+			BrowseError::InvalidCasId(_) | BrowseError::Remote(_) | BrowseError::Search(_) => {
+				rspc::ErrorCode::BadRequest
+			}
+			_ => rspc::ErrorCode::InternalServerError,
+		};
+
+		Self::with_cause(code, e.to_string(), e)
+	}
+}
+
+/// A location of the remote node
+#[derive(Serialize, Deserialize, Type, Debug, Clone, PartialEq, Eq)]
+pub struct RemoteLocation {
+	pub pub_id: Uuid,
+	pub name: Option<String>,
+	pub path: Option<String>,
+}
+
+/// A file path of the remote node, as it's in its database
+#[serde_as]
+#[derive(Serialize, Deserialize, Type, Debug, Clone, PartialEq, Eq)]
+pub struct RemoteFilePath {
+	pub pub_id: Uuid,
+	pub is_dir: bool,
+	pub materialized_path: String,
+	pub name: String,
+	pub extension: String,
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub size_in_bytes: u64,
+	pub cas_id: Option<String>,
+	pub has_thumbnail: bool,
+	pub date_modified: Option<DateTime<FixedOffset>>,
+}
+
+/// Sent by the node browsing, each one answered with the [`BrowseResponse`] variant of the same
+/// name or with [`BrowseResponse::Error`]
+#[derive(Serialize, Deserialize, Debug)]
+pub enum BrowseRequest {
+	Locations,
+	Paths {
+		location_pub_id: Uuid,
+		materialized_path: String,
+		skip: u32,
+		take: u32,
+	},
+	/// Same as `search.paths`, over the locations of the remote node
+	SearchPaths(FilePathSearchArgs),
+	Thumbnail {
+		cas_id: String,
+	},
+	Done,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum BrowseResponse {
+	Locations(Vec<RemoteLocation>),
+	Paths(Vec<RemoteFilePath>),
+	SearchPaths(Vec<RemoteFilePath>),
+	/// WebP bytes of the thumbnail, `None` if the remote node doesn't have one
+	Thumbnail(Option<Vec<u8>>),
+	Error(String),
+}
+
+impl BrowseRequest {
+	pub async fn from_stream(stream: &mut (impl AsyncRead + Unpin)) -> Result<Self, BrowseError> {
+		decode_message(stream).await
+	}
+
+	pub fn to_bytes(&self) -> Result<Vec<u8>, BrowseError> {
+		encode_message(self)
+	}
+}
+
+impl BrowseResponse {
+	pub async fn from_stream(stream: &mut (impl AsyncRead + Unpin)) -> Result<Self, BrowseError> {
+		decode_message(stream).await
+	}
+
+	pub fn to_bytes(&self) -> Result<Vec<u8>, BrowseError> {
+		encode_message(self)
+	}
+}
+
+async fn decode_message<T: DeserializeOwned>(
+	stream: &mut (impl AsyncRead + Unpin),
+) -> Result<T, BrowseError> {
+	rmp_serde::from_slice(&decode::buf(stream).await?).map_err(Into::into)
+}
+
+fn encode_message(message: &impl Serialize) -> Result<Vec<u8>, BrowseError> {
+	let mut buf = vec![];
+	encode::buf(&mut buf, &rmp_serde::to_vec_named(message)?);
+	Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn test_types() {
+		{
+			let original = BrowseRequest::Paths {
+				location_pub_id: Uuid::new_v4(),
+				materialized_path: "/Photos/".to_string(),
+				skip: 100,
+				take: 100,
+			};
+
+			let mut cursor = std::io::Cursor::new(original.to_bytes().unwrap());
+			let result = BrowseRequest::from_stream(&mut cursor).await.unwrap();
+			assert_eq!(original.to_bytes().unwrap(), result.to_bytes().unwrap());
+		}
+
+		{
+			let original = BrowseRequest::SearchPaths(
+				serde_json::from_value(serde_json::json!({
+					"take": 50,
+					"orderAndPagination": { "orderOnly": { "field": "name", "value": "Asc" } },
+					"filters": [{ "filePath": { "hidden": false } }],
+				}))
+				.unwrap(),
+			);
+
+			let mut cursor = std::io::Cursor::new(original.to_bytes().unwrap());
+			let result = BrowseRequest::from_stream(&mut cursor).await.unwrap();
+			assert!(matches!(
+				&result,
+				BrowseRequest::SearchPaths(args) if args.take == Some(50) && args.group_directories
+			));
+			assert_eq!(original.to_bytes().unwrap(), result.to_bytes().unwrap());
+		}
+
+		{
+			let original = vec![RemoteFilePath {
+				pub_id: Uuid::new_v4(),
+				is_dir: false,
+				materialized_path: "/Photos/".to_string(),
+				name: "Spacedrive".to_string(),
+				extension: "png".to_string(),
+				size_in_bytes: u64::MAX,
+				cas_id: Some("0123456789abcdef".to_string()),
+				has_thumbnail: true,
+				date_modified: None,
+			}];
+
+			let mut cursor =
+				std::io::Cursor::new(BrowseResponse::Paths(original.clone()).to_bytes().unwrap());
+			match BrowseResponse::from_stream(&mut cursor).await.unwrap() {
+				BrowseResponse::Paths(result) => assert_eq!(original, result),
+				response => unreachable!("unexpected response: {response:?}"),
+			}
+		}
+
+		{
+			let original = BrowseRequest::Done;
+
+			let mut cursor = std::io::Cursor::new(original.to_bytes().unwrap());
+			let result = BrowseRequest::from_stream(&mut cursor).await.unwrap();
+			assert!(matches!(result, BrowseRequest::Done));
+		}
+	}
+}
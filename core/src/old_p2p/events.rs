@@ -1,4 +1,4 @@
-use std::{collections::HashSet, net::SocketAddr, sync::Arc};
+use std::{collections::HashSet, net::SocketAddr, path::PathBuf, sync::Arc};
 
 use sd_old_p2p::{
 	flume::bounded, hooks::QuicHandle, HookEvent, PeerConnectionCandidate, RemoteIdentity, P2P,
@@ -56,6 +56,13 @@ pub enum P2PEvent {
 		peer_name: String,
 		files: Vec<String>,
 	},
+	// Sent by a trusted peer, so it's being saved without asking
+	SpacedropAutoAccepted {
+		id: Uuid,
+		identity: RemoteIdentity,
+		directory: PathBuf,
+		files: Vec<String>,
+	},
 	SpacedropProgress {
 		id: Uuid,
 		percent: u8,
@@ -66,6 +73,11 @@ pub enum P2PEvent {
 	SpacedropRejected {
 		id: Uuid,
 	},
+	// Paired with a peer, both nodes now trust each other
+	PeerPaired {
+		identity: RemoteIdentity,
+		name: String,
+	},
 }
 
 /// A P2P hook which listens for events and sends them over a channel which can be connected to the frontend.
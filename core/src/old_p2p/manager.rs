@@ -1,11 +1,15 @@
 use crate::{
+	invalidate_query,
 	node::{
-		config::{self, P2PDiscoveryState},
+		config::{self, BandwidthConfig, P2PDiscoveryState},
 		HardwareModel,
 	},
 	old_p2p::{
-		libraries::libraries_hook, operations, sync::SyncMessage, Header, OperatingSystem,
-		SPACEDRIVE_APP_ID,
+		browse,
+		libraries::libraries_hook,
+		operations,
+		sync::{self, SyncMessage},
+		Header, OperatingSystem, SPACEDRIVE_APP_ID,
 	},
 	Node,
 };
@@ -15,9 +19,11 @@ use axum::routing::IntoMakeService;
 use sd_old_p2p::{
 	flume::{bounded, Receiver},
 	hooks::{Libp2pPeerId, Mdns, QuicHandle, QuicTransport, RelayServerEntry},
-	Peer, RemoteIdentity, UnicastStream, P2P,
+	Peer, RemoteIdentity, TrafficClass, UnicastStream, P2P,
 };
 use sd_old_p2p_tunnel::Tunnel;
+
+use chrono::{Local, Timelike};
 use serde::Serialize;
 use serde_json::json;
 use specta::Type;
@@ -28,9 +34,12 @@ use std::{
 	time::Duration,
 };
 use tower_service::Service;
-use tracing::error;
+use tracing::{debug, error};
 
-use tokio::sync::{oneshot, Notify};
+use tokio::{
+	io::AsyncWriteExt,
+	sync::{oneshot, Notify},
+};
 use tracing::info;
 use uuid::Uuid;
 
@@ -64,6 +73,10 @@ pub struct P2PManager {
 	pub(crate) events: P2PEvents,
 	pub(super) spacedrop_pairing_reqs: Arc<Mutex<HashMap<Uuid, oneshot::Sender<Option<String>>>>>,
 	pub(super) spacedrop_cancellations: Arc<Mutex<HashMap<Uuid, Arc<AtomicBool>>>>,
+	/// Sync sessions running for each library and peer, flagged if new operations were written
+	/// during the session so it runs again when done
+	pub(super) sync_sessions: Mutex<HashMap<(Uuid, RemoteIdentity), bool>>,
+	pub(super) pending_pairing: Mutex<Option<operations::pairing::PendingPairing>>,
 	pub(crate) node_config: Arc<config::Manager>,
 	pub listeners: Mutex<Listeners>,
 	relay_config: Mutex<Vec<RelayServerEntry>>,
@@ -84,7 +97,7 @@ impl P2PManager {
 		let (tx, rx) = bounded(25);
 		let p2p = P2P::new(SPACEDRIVE_APP_ID, node_config.get().await.identity, tx);
 		let (quic, lp2p_peer_id) = QuicTransport::spawn(p2p.clone()).map_err(|e| e.to_string())?;
-		libraries_hook(p2p.clone(), quic.handle(), libraries);
+		libraries_hook(p2p.clone(), quic.handle(), libraries.clone());
 		let this = Arc::new(Self {
 			p2p: p2p.clone(),
 			lp2p_peer_id,
@@ -94,11 +107,14 @@ impl P2PManager {
 			quic_transport: quic,
 			spacedrop_pairing_reqs: Default::default(),
 			spacedrop_cancellations: Default::default(),
+			sync_sessions: Default::default(),
+			pending_pairing: Default::default(),
 			node_config,
 			listeners: Default::default(),
 			relay_config: Default::default(),
 			trigger_relay_config_update: Default::default(),
 		});
+		sync::peers_hook(&this, libraries);
 		this.on_node_config_change().await;
 
 		info!(
@@ -111,6 +127,19 @@ impl P2PManager {
 		Ok((this.clone(), |node: Arc<Node>, router| {
 			tokio::spawn(start(this.clone(), node.clone(), rx, router));
 
+			// TODO: Cleanup this thread on p2p shutdown.
+			tokio::spawn({
+				let this = this.clone();
+				async move {
+					// The active bandwidth schedule is checked every minute
+					let mut interval = tokio::time::interval(Duration::from_secs(60));
+					loop {
+						interval.tick().await;
+						this.apply_bandwidth_config(&this.node_config.get().await.p2p.bandwidth);
+					}
+				}
+			});
+
 			// TODO: Cleanup this thread on p2p shutdown.
 			tokio::spawn(async move {
 				let client = reqwest::Client::new();
@@ -194,6 +223,27 @@ impl P2PManager {
 		self.p2p.metadata().clone()
 	}
 
+	/// Applies the bandwidth limits of the config, using the schedule active right now
+	pub(crate) fn apply_bandwidth_config(&self, config: &BandwidthConfig) {
+		let now = Local::now();
+		// Both are less than a day worth of minutes, so they fit in a `u16`
+		#[allow(clippy::cast_possible_truncation)]
+		let minute = (now.hour() * 60 + now.minute()) as u16;
+
+		let bandwidth = self.p2p.bandwidth();
+		bandwidth.set_limits(config.active_limits(minute).into());
+		bandwidth.set_peer_limits(
+			config
+				.peer_limits
+				.iter()
+				.map(|(identity, limits)| (*identity, (*limits).into()))
+				.collect(),
+		);
+		bandwidth.set_priority(TrafficClass::Sync, config.priorities.sync);
+		bandwidth.set_priority(TrafficClass::FileFetch, config.priorities.file_fetch);
+		bandwidth.set_priority(TrafficClass::Spacedrop, config.priorities.spacedrop);
+	}
+
 	// TODO: Remove this and add a subscription system to `config::Manager`
 	pub async fn on_node_config_change(&self) {
 		self.trigger_relay_config_update.notify_waiters();
@@ -261,6 +311,8 @@ impl P2PManager {
 			}
 		};
 
+		self.apply_bandwidth_config(&config.p2p.bandwidth);
+
 		self.quic_transport
 			.set_manual_peer_addrs(config.p2p.manual_peers);
 
@@ -379,9 +431,18 @@ async fn start(
 				return;
 			};
 
+			stream.set_traffic_class(header.traffic_class());
+
 			match header {
 				Header::Ping => operations::ping::receiver(stream).await,
 				Header::Spacedrop(req) => {
+					let remote = stream.remote_identity();
+					if !this.peer_permissions(&remote).await.spacedrop {
+						debug!(%remote, "Rejecting Spacedrop from peer without permission;");
+						stream.write_all(&[0]).await.ok();
+						return;
+					}
+
 					let Err(()) = operations::spacedrop::receiver(&this, req, stream).await else {
 						return;
 					};
@@ -416,16 +477,29 @@ async fn start(
 
 					match msg {
 						SyncMessage::NewOperations => {
-							let Err(()) = super::sync::responder(&mut tunnel, library).await else {
+							let Err(e) = sync::responder(&mut tunnel, library).await else {
+								return;
+							};
+
+							error!(?e, "Failed to handle sync responder request;");
+						}
+						SyncMessage::Inspect => {
+							let Err(e) = sync::inspect::responder(&mut tunnel, library).await
+							else {
 								return;
 							};
 
-							error!("Failed to handle sync responder request");
+							error!(?e, "Failed to handle sync inspect request;");
 						}
 					};
 				}
 				Header::RspcRemote => {
 					let remote = stream.remote_identity();
+					if !this.peer_permissions(&remote).await.remote_browsing {
+						debug!(%remote, "Rejecting rspc request from peer without permission;");
+						return;
+					}
+
 					let Err(e) = operations::rspc::receiver(stream, &mut service, &node).await
 					else {
 						return;
@@ -438,6 +512,11 @@ async fn start(
 					range,
 				} => {
 					let remote = stream.remote_identity();
+					if !this.peer_permissions(&remote).await.library_files {
+						debug!(%remote, "Rejecting library file request from peer without permission;");
+						return;
+					}
+
 					let Err(e) =
 						operations::library::receiver(stream, file_path_id, range, &node).await
 					else {
@@ -451,6 +530,41 @@ async fn start(
 						"Failed to handling library file request;",
 					);
 				}
+				Header::Browse => {
+					let remote = stream.remote_identity();
+					if !this.peer_permissions(&remote).await.remote_browsing {
+						debug!(%remote, "Rejecting browse request from peer without permission;");
+						return;
+					}
+
+					let Ok(mut tunnel) = Tunnel::responder(stream).await.map_err(|e| {
+						error!(?e, "Failed `Tunnel::responder`;");
+					}) else {
+						return;
+					};
+
+					let Some(library) = node
+						.libraries
+						.get_library_for_instance(&tunnel.library_remote_identity())
+						.await
+					else {
+						error!(remote_identity = %tunnel.library_remote_identity(), "Failed to get library;");
+						return;
+					};
+
+					if let Err(e) = browse::responder(&mut tunnel, &node, library).await {
+						error!(%remote, ?e, "Failed to handle browse request;");
+					}
+				}
+				Header::Pair => {
+					let remote = stream.remote_identity();
+					let Err(e) = operations::pairing::receiver(&this, stream).await else {
+						invalidate_query!(node; node, "p2p.trustedPeers");
+						return;
+					};
+
+					error!(%remote, ?e, "Failed to handle pairing request;");
+				}
 			};
 		});
 	}
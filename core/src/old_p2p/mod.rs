@@ -1,6 +1,7 @@
 #![warn(clippy::all, clippy::unwrap_used, clippy::panic)]
 #![allow(clippy::unnecessary_cast)] // Yeah they aren't necessary on this arch, but they are on others
 
+pub mod browse;
 mod events;
 pub(super) mod libraries;
 mod manager;
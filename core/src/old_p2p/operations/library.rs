@@ -31,15 +31,12 @@ pub async fn request_file(
 	let peer = p2p.peers().get(&identity).ok_or("Peer offline")?.clone();
 	let mut stream = peer.new_stream().await?;
 
-	stream
-		.write_all(
-			&Header::LibraryFile {
-				file_path_id,
-				range: range.clone(),
-			}
-			.to_bytes(),
-		)
-		.await?;
+	let header = Header::LibraryFile {
+		file_path_id,
+		range: range.clone(),
+	};
+	stream.set_traffic_class(header.traffic_class());
+	stream.write_all(&header.to_bytes()).await?;
 
 	let mut stream = sd_old_p2p_tunnel::Tunnel::initiator(stream, library_identity).await?;
 
@@ -0,0 +1,235 @@
+//! Pairing of two nodes, so they trust each other.
+//!
+//! One node shows a short-lived code along with its identity, as text or as a QR code. The other
+//! node connects to that identity and sends the code. Connections already authenticate both
+//! identities, so the code only proves the same user has access to both nodes. A code can only be
+//! tried once, so it can't be brute forced.
+
+use std::{
+	sync::{Arc, PoisonError},
+	time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use sd_old_p2p::{NewStreamError, RemoteIdentity, UnicastStream};
+use sd_old_p2p_proto::{decode, encode};
+use serde::Serialize;
+use specta::Type;
+use thiserror::Error;
+use tokio::{
+	io::{AsyncReadExt, AsyncWriteExt},
+	time::Instant,
+};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::{
+	node::config::{NodeConfigError, PeerPermissions, TrustedPeer},
+	old_p2p::{Header, P2PEvent, P2PManager},
+};
+
+/// How long a pairing code can be used for
+const PAIRING_CODE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Error)]
+pub enum PairingError {
+	#[error("peer not found, has it been discovered?")]
+	PeerNotFound,
+	#[error("error creating stream: {0}")]
+	FailedNewStream(#[from] NewStreamError),
+	#[error("io error: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("error decoding message: {0}")]
+	Decode(#[from] decode::Error),
+	#[error("the pairing code was rejected")]
+	Rejected,
+	#[error("error saving trusted peer: {0}")]
+	Config(#[from] NodeConfigError),
+}
+
+impl From<PairingError> for rspc::Error {
+	fn from(e: PairingError) -> Self {
+		let code = match &e {
+			PairingError::PeerNotFound => rspc::ErrorCode::NotFound,
+			PairingError::Rejected => rspc::ErrorCode::Forbidden,
+			_ => rspc::ErrorCode::InternalServerError,
+		};
+
+		Self::with_cause(code, e.to_string(), e)
+	}
+}
+
+/// Shown to the user, so they can enter it on the other node
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct PairingCode {
+	pub identity: RemoteIdentity,
+	pub code: String,
+	pub expires_at: DateTime<Utc>,
+}
+
+pub(crate) struct PendingPairing {
+	code: String,
+	expires_at: Instant,
+}
+
+fn new_code() -> String {
+	// The bits of v4 UUIDs are random, so the code can't be predicted
+	format!("{:08}", Uuid::new_v4().as_u128() % 100_000_000)
+}
+
+/// Compares the hashes of both codes, as their comparison takes constant time
+fn is_same_code(a: &str, b: &str) -> bool {
+	blake3::hash(a.as_bytes()) == blake3::hash(b.as_bytes())
+}
+
+impl P2PManager {
+	/// Creates a new pairing code, replacing the previous one
+	pub fn start_pairing(&self) -> PairingCode {
+		let code = new_code();
+
+		*self
+			.pending_pairing
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner) = Some(PendingPairing {
+			code: code.clone(),
+			expires_at: Instant::now() + PAIRING_CODE_TIMEOUT,
+		});
+
+		PairingCode {
+			identity: self.p2p.remote_identity(),
+			code,
+			expires_at: Utc::now() + PAIRING_CODE_TIMEOUT,
+		}
+	}
+
+	pub fn cancel_pairing(&self) {
+		self.pending_pairing
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.take();
+	}
+
+	/// What the peer is allowed to do on this node
+	pub(crate) async fn peer_permissions(&self, identity: &RemoteIdentity) -> PeerPermissions {
+		let config = self.node_config.get().await.p2p;
+
+		config
+			.trusted_peers
+			.get(identity)
+			.map(|peer| peer.permissions)
+			.unwrap_or(PeerPermissions {
+				spacedrop: config.allow_untrusted_spacedrop,
+				remote_browsing: false,
+				// The tunnel already checks the peer is part of the library
+				library_files: true,
+			})
+	}
+
+	/// Trusts the peer, keeping its permissions if it was already trusted
+	async fn trust_peer(&self, identity: RemoteIdentity, name: String) -> Result<(), PairingError> {
+		self.node_config
+			.write(|config| {
+				let permissions = config
+					.p2p
+					.trusted_peers
+					.get(&identity)
+					.map_or(PeerPermissions::PAIRED, |peer| peer.permissions);
+
+				config.p2p.trusted_peers.insert(
+					identity,
+					TrustedPeer {
+						name,
+						permissions,
+						paired_at: Utc::now(),
+					},
+				);
+			})
+			.await?;
+
+		Ok(())
+	}
+}
+
+/// Pairs with the node showing the code, both nodes trust each other when it succeeds
+pub async fn pair(
+	p2p: &Arc<P2PManager>,
+	identity: RemoteIdentity,
+	code: String,
+) -> Result<(), PairingError> {
+	let peer = p2p
+		.p2p
+		.peers()
+		.get(&identity)
+		.ok_or(PairingError::PeerNotFound)?
+		.clone();
+
+	let mut stream = peer.new_stream().await?;
+
+	let mut buf = Header::Pair.to_bytes();
+	encode::string(&mut buf, &code);
+	encode::string(&mut buf, &p2p.node_config.get().await.name);
+	stream.write_all(&buf).await?;
+	stream.flush().await?;
+
+	if stream.read_u8().await? != 1 {
+		debug!(peer = %identity, "Pairing code was rejected by;");
+		return Err(PairingError::Rejected);
+	}
+
+	let name = decode::string(&mut stream).await?;
+
+	p2p.trust_peer(identity, name.clone()).await?;
+
+	info!(peer = %identity, %name, "Paired with;");
+
+	p2p.events
+		.send(P2PEvent::PeerPaired { identity, name })
+		.ok();
+
+	Ok(())
+}
+
+pub(crate) async fn receiver(
+	this: &Arc<P2PManager>,
+	mut stream: UnicastStream,
+) -> Result<(), PairingError> {
+	let identity = stream.remote_identity();
+
+	let code = decode::string(&mut stream).await?;
+	let name = decode::string(&mut stream).await?;
+
+	// Taking the pending code, so it can't be tried again
+	let pending = this
+		.pending_pairing
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+		.take();
+
+	let accepted = pending.is_some_and(|pending| {
+		pending.expires_at > Instant::now() && is_same_code(&pending.code, &code)
+	});
+
+	if !accepted {
+		warn!(peer = %identity, "Rejected pairing request with an invalid code from;");
+
+		stream.write_u8(0).await?;
+		stream.flush().await?;
+
+		return Err(PairingError::Rejected);
+	}
+
+	this.trust_peer(identity, name.clone()).await?;
+
+	let mut buf = vec![1];
+	encode::string(&mut buf, &this.node_config.get().await.name);
+	stream.write_all(&buf).await?;
+	stream.flush().await?;
+
+	info!(peer = %identity, %name, "Paired with;");
+
+	this.events
+		.send(P2PEvent::PeerPaired { identity, name })
+		.ok();
+
+	Ok(())
+}
@@ -3,7 +3,7 @@ use std::{error::Error, sync::Arc};
 use axum::{extract::Request, http, Router};
 use hyper::{body::Incoming, client::conn::http1::handshake, server::conn::http1, Response};
 use hyper_util::rt::TokioIo;
-use sd_old_p2p::{RemoteIdentity, UnicastStream, P2P};
+use sd_old_p2p::{RemoteIdentity, TrafficClass, UnicastStream, P2P};
 use tokio::io::AsyncWriteExt;
 use tower_service::Service;
 use tracing::debug;
@@ -23,6 +23,7 @@ pub async fn remote_rspc(
 		.clone();
 	let mut stream = peer.new_stream().await?;
 
+	stream.set_traffic_class(TrafficClass::FileFetch);
 	stream.write_all(&Header::RspcRemote.to_bytes()).await?;
 
 	let (mut sender, conn) = handshake(TokioIo::new(stream)).await?;
@@ -45,10 +46,9 @@ pub(crate) async fn receiver(
 		"Received http request from;",
 	);
 
-	// TODO: Authentication
-	#[allow(clippy::todo)]
+	// Permissions of the peer are checked before, this is the switch for every peer
 	if !node.config.get().await.p2p.enable_remote_access {
-		todo!("No way buddy!");
+		return Err("Remote access is disabled".into());
 	}
 
 	let hyper_service =
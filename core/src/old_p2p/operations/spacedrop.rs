@@ -1,6 +1,6 @@
 use std::{
 	borrow::Cow,
-	path::PathBuf,
+	path::{Component, Path, PathBuf},
 	sync::{
 		atomic::{AtomicBool, Ordering},
 		Arc, PoisonError,
@@ -9,13 +9,12 @@ use std::{
 };
 
 use crate::old_p2p::{Header, P2PEvent, P2PManager};
-use futures::future::join_all;
-use sd_old_p2p::{RemoteIdentity, UnicastStream};
+use sd_old_p2p::{RemoteIdentity, TrafficClass, UnicastStream};
 use sd_old_p2p_block::{BlockSize, Range, SpaceblockRequest, SpaceblockRequests, Transfer};
 use thiserror::Error;
 use tokio::{
-	fs::{create_dir_all, File},
-	io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter},
+	fs::{self, create_dir_all, File},
+	io::{self, AsyncReadExt, AsyncWriteExt, BufReader, BufWriter},
 	sync::oneshot,
 	time::{sleep, Instant},
 };
@@ -29,6 +28,8 @@ pub(crate) const SPACEDROP_TIMEOUT: Duration = Duration::from_secs(60);
 pub enum SpacedropError {
 	#[error("paths argument is an empty vector")]
 	EmptyPath,
+	#[error("identities argument is an empty vector")]
+	NoPeers,
 	#[error("error connecting to peer")]
 	FailedPeerConnection,
 	#[error("error creating stream: {0}")]
@@ -37,142 +38,239 @@ pub enum SpacedropError {
 	FailedFileOpen(#[from] std::io::Error),
 }
 
-pub async fn spacedrop(
-	p2p: Arc<P2PManager>,
-	identity: RemoteIdentity,
+/// Lists the files and directories being sent, walking directories so their structure is preserved.
+///
+/// Each entry is named by its path relative to the parent of the selected path. Directories get
+/// their own entries, so empty ones are created on the receiver too. Symlinks inside directories
+/// are skipped, so we never walk in circles.
+async fn collect_entries(
 	paths: Vec<PathBuf>,
-) -> Result<Uuid, SpacedropError> {
-	if paths.is_empty() {
-		return Err(SpacedropError::EmptyPath);
-	}
+) -> Result<Vec<(PathBuf, SpaceblockRequest)>, std::io::Error> {
+	let mut entries = vec![];
 
-	let (files, requests): (Vec<_>, Vec<_>) = join_all(paths.into_iter().map(|path| async move {
-		let file = File::open(&path).await?;
-		let metadata = file.metadata().await?;
+	for path in paths {
+		let metadata = fs::metadata(&path).await?;
 		let name = path
 			.file_name()
 			.map(|v| v.to_string_lossy())
 			.unwrap_or(Cow::Borrowed(""))
 			.to_string();
 
-		Ok((
-			(path, file),
-			SpaceblockRequest {
-				name,
-				size: metadata.len(),
-				range: Range::Full,
-			},
-		))
-	}))
-	.await
-	.into_iter()
-	.collect::<Result<Vec<_>, std::io::Error>>()
-	.map_err(SpacedropError::FailedFileOpen)?
-	.into_iter()
-	.unzip();
+		if !metadata.is_dir() {
+			entries.push((
+				path,
+				SpaceblockRequest {
+					name,
+					size: metadata.len(),
+					range: Range::Full,
+				},
+			));
+			continue;
+		}
+
+		let mut to_walk = vec![(path, name)];
+
+		while let Some((dir_path, dir_name)) = to_walk.pop() {
+			entries.push((dir_path.clone(), SpaceblockRequest::directory(&dir_name)));
+
+			let mut read_dir = fs::read_dir(&dir_path).await?;
+			let mut children = vec![];
+
+			while let Some(entry) = read_dir.next_entry().await? {
+				let file_type = entry.file_type().await?;
+				let name = format!("{dir_name}/{}", entry.file_name().to_string_lossy());
+
+				if file_type.is_dir() {
+					children.push((entry.path(), name));
+				} else if file_type.is_file() {
+					entries.push((
+						entry.path(),
+						SpaceblockRequest {
+							name,
+							size: entry.metadata().await?.len(),
+							range: Range::Full,
+						},
+					));
+				}
+			}
+
+			to_walk.extend(children);
+		}
+	}
+
+	Ok(entries)
+}
+
+/// Sends files and directories to each peer concurrently, returning the Spacedrop id used for
+/// each peer, so their progress can be followed separately.
+pub async fn spacedrop(
+	p2p: Arc<P2PManager>,
+	identities: Vec<RemoteIdentity>,
+	paths: Vec<PathBuf>,
+) -> Result<Vec<(RemoteIdentity, Uuid)>, SpacedropError> {
+	if paths.is_empty() {
+		return Err(SpacedropError::EmptyPath);
+	}
+
+	if identities.is_empty() {
+		return Err(SpacedropError::NoPeers);
+	}
 
+	let (paths, requests): (Vec<_>, Vec<_>) = collect_entries(paths)
+		.await
+		.map_err(SpacedropError::FailedFileOpen)?
+		.into_iter()
+		.unzip();
+
+	let paths = Arc::new(paths);
 	let total_length: u64 = requests.iter().map(|req| req.size).sum();
 
-	let id = Uuid::new_v4();
-	debug!(spacedrop_id = %id, peer = %identity, "Starting Spacedrop;");
-	let peer = p2p
-		.p2p
-		.peers()
-		.get(&identity)
-		.ok_or_else(|| {
-			debug!(spacedrop_id = %id, peer = %identity, "Failed to find connection method;");
-			SpacedropError::FailedPeerConnection
-		})?
-		.clone();
-
-	let mut stream = peer.new_stream().await.map_err(|e| {
-		debug!(spacedrop_id = %id, peer = %identity, ?e, "Failed to connect");
-		SpacedropError::FailedNewStream(e)
-	})?;
+	// Connecting to every peer before sending anything, so a drop is either sent to all or to none
+	let mut streams = Vec::with_capacity(identities.len());
+	for identity in identities {
+		let id = Uuid::new_v4();
+		debug!(spacedrop_id = %id, peer = %identity, "Starting Spacedrop;");
+		let peer = p2p
+			.p2p
+			.peers()
+			.get(&identity)
+			.ok_or_else(|| {
+				debug!(spacedrop_id = %id, peer = %identity, "Failed to find connection method;");
+				SpacedropError::FailedPeerConnection
+			})?
+			.clone();
+
+		let mut stream = peer.new_stream().await.map_err(|e| {
+			debug!(spacedrop_id = %id, peer = %identity, ?e, "Failed to connect");
+			SpacedropError::FailedNewStream(e)
+		})?;
+		stream.set_traffic_class(TrafficClass::Spacedrop);
+
+		streams.push((identity, id, stream));
+	}
 
-	tokio::spawn(async move {
-		debug!(spacedrop_id = %id, "Connected, sending header");
-		let header = Header::Spacedrop(SpaceblockRequests {
-			id,
-			block_size: BlockSize::from_file_size(total_length),
-			requests,
-		});
-		if let Err(e) = stream.write_all(&header.to_bytes()).await {
-			debug!(spacedrop_id = %id, ?e, "Failed to send header");
+	Ok(streams
+		.into_iter()
+		.map(|(identity, id, stream)| {
+			tokio::spawn(send(
+				Arc::clone(&p2p),
+				identity,
+				stream,
+				SpaceblockRequests {
+					id,
+					block_size: BlockSize::from_file_size(total_length),
+					requests: requests.clone(),
+				},
+				Arc::clone(&paths),
+			));
+
+			(identity, id)
+		})
+		.collect())
+}
+
+async fn send(
+	p2p: Arc<P2PManager>,
+	identity: RemoteIdentity,
+	mut stream: UnicastStream,
+	requests: SpaceblockRequests,
+	paths: Arc<Vec<PathBuf>>,
+) {
+	let id = requests.id;
+
+	debug!(spacedrop_id = %id, "Connected, sending header");
+	let header = Header::Spacedrop(requests);
+	if let Err(e) = stream.write_all(&header.to_bytes()).await {
+		debug!(spacedrop_id = %id, ?e, "Failed to send header");
+		return;
+	}
+	let Header::Spacedrop(requests) = header else {
+		unreachable!();
+	};
+
+	debug!(spacedrop_id = %id, "Waiting for response");
+	let result = tokio::select! {
+	  result = stream.read_u8() => result,
+	  // Add 5 seconds incase the user responded on the deadline and slow network
+	   _ = sleep(SPACEDROP_TIMEOUT + Duration::from_secs(5)) => {
+			debug!(spacedrop_id = %id, "Timed out, cancelling");
+			p2p.events.send(P2PEvent::SpacedropTimedOut { id }).ok();
+			return;
+		},
+	};
+
+	match result {
+		Ok(0) => {
+			debug!(spacedrop_id = %id, peer = %identity, "Spacedrop was rejected from;");
+			p2p.events.send(P2PEvent::SpacedropRejected { id }).ok();
 			return;
 		}
-		let Header::Spacedrop(requests) = header else {
-			unreachable!();
-		};
+		Ok(1) => {} // Okay
+		// A misbehaving peer or a dropped stream only fails the drop to this peer
+		Ok(response) => {
+			warn!(spacedrop_id = %id, peer = %identity, %response, "Invalid Spacedrop response;");
+			p2p.events.send(P2PEvent::SpacedropRejected { id }).ok();
+			return;
+		}
+		Err(e) => {
+			warn!(spacedrop_id = %id, peer = %identity, ?e, "Failed to read Spacedrop response;");
+			p2p.events.send(P2PEvent::SpacedropRejected { id }).ok();
+			return;
+		}
+	}
 
-		debug!(spacedrop_id = %id, "Waiting for response");
-		let result = tokio::select! {
-		  result = stream.read_u8() => result,
-		  // Add 5 seconds incase the user responded on the deadline and slow network
-		   _ = sleep(SPACEDROP_TIMEOUT + Duration::from_secs(5)) => {
-				debug!(spacedrop_id = %id, "Timed out, cancelling");
-				p2p.events.send(P2PEvent::SpacedropTimedOut { id }).ok();
-				return;
-			},
-		};
+	let cancelled = Arc::new(AtomicBool::new(false));
+	p2p.spacedrop_cancellations
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+		.insert(id, cancelled.clone());
 
-		match result {
-			Ok(0) => {
-				debug!(spacedrop_id = %id, peer = %identity, "Spacedrop was rejected from;");
-				p2p.events.send(P2PEvent::SpacedropRejected { id }).ok();
-				return;
-			}
-			Ok(1) => {}                 // Okay
-			Ok(_) => todo!(),           // TODO: Proper error
-			Err(e) => todo!("{:?}", e), // TODO: Proper error
-		}
+	debug!(spacedrop_id = %id, "Starting transfer");
+	let i = Instant::now();
 
-		let cancelled = Arc::new(AtomicBool::new(false));
-		p2p.spacedrop_cancellations
-			.lock()
-			.unwrap_or_else(PoisonError::into_inner)
-			.insert(id, cancelled.clone());
-
-		debug!(spacedrop_id = %id, "Starting transfer");
-		let i = Instant::now();
-
-		let mut transfer = Transfer::new(
-			&requests,
-			|percent| {
-				p2p.events
-					.send(P2PEvent::SpacedropProgress { id, percent })
-					.ok();
-			},
-			&cancelled,
+	let mut transfer = Transfer::new(
+		&requests,
+		|percent| {
+			p2p.events
+				.send(P2PEvent::SpacedropProgress { id, percent })
+				.ok();
+		},
+		&cancelled,
+	);
+
+	for (file_id, (path, request)) in paths.iter().zip(&requests.requests).enumerate() {
+		debug!(
+			spacedrop_id = %id,
+			%file_id,
+			path = %path.display(),
+			"Transmitting;",
 		);
 
-		for (file_id, (path, file)) in files.into_iter().enumerate() {
+		let res = if request.is_directory() {
+			transfer.send(&mut stream, io::empty()).await
+		} else {
+			// Files are only opened when sent, so huge directories don't exhaust file descriptors
+			match File::open(path).await {
+				Ok(file) => transfer.send(&mut stream, BufReader::new(file)).await,
+				Err(e) => Err(e),
+			}
+		};
+
+		if let Err(e) = res {
 			debug!(
 				spacedrop_id = %id,
 				%file_id,
-				path = %path.display(),
-				"Transmitting;",
-			);
-
-			let file = BufReader::new(file);
-			if let Err(e) = transfer.send(&mut stream, file).await {
-				debug!(
-					spacedrop_id = %id,
-					%file_id,
-					?e,
-					"Failed to send file;");
-				// TODO: Error to frontend
-				// p2p.events
-				// 	.send(P2PEvent::SpacedropFailed { id, file_id })
-				// 	.ok();
-				return;
-			}
+				?e,
+				"Failed to send file;");
+			// TODO: Error to frontend
+			// p2p.events
+			// 	.send(P2PEvent::SpacedropFailed { id, file_id })
+			// 	.ok();
+			return;
 		}
+	}
 
-		debug!(spacedrop_id = %id, elapsed_time = ?i.elapsed(), "Finished;");
-	});
-
-	Ok(id)
+	debug!(spacedrop_id = %id, elapsed_time = ?i.elapsed(), "Finished;");
 }
 
 // TODO: Move these off the manager
@@ -219,21 +317,288 @@ impl P2PManager {
 	}
 }
 
+/// Resolves where an entry is saved, rejecting names that could escape the destination directory
+fn entry_path(destination: &Path, name: &str) -> Option<PathBuf> {
+	let mut path = destination.to_path_buf();
+
+	for part in name.split('/').filter(|part| !part.is_empty()) {
+		match Path::new(part).components().collect::<Vec<_>>().as_slice() {
+			[Component::Normal(part)] => path.push(part),
+			_ => return None,
+		}
+	}
+
+	(path != destination).then_some(path)
+}
+
+/// Where the entries of an accepted Spacedrop are saved
+enum Destination {
+	/// Picked by the user, which is the file itself when a single file is received
+	Chosen(PathBuf),
+	/// The auto accept directory, entries are always saved inside it and never replace existing
+	/// files, as nobody confirmed where they go
+	AutoAccept(PathBuf),
+}
+
+impl Destination {
+	fn path(&self) -> &Path {
+		match self {
+			Self::Chosen(path) | Self::AutoAccept(path) => path,
+		}
+	}
+}
+
+/// Creates a file which didn't exist yet, adding a numeric suffix to the name when it's taken,
+/// like `photo (1).jpg`
+async fn create_unique_file(path: &Path) -> io::Result<(PathBuf, File)> {
+	let stem = path
+		.file_stem()
+		.map(|stem| stem.to_string_lossy().to_string())
+		.unwrap_or_default();
+	let extension = path
+		.extension()
+		.map(|extension| extension.to_string_lossy().to_string());
+
+	let mut candidate = path.to_path_buf();
+
+	for i in 1..u32::MAX {
+		match fs::OpenOptions::new()
+			.write(true)
+			.create_new(true)
+			.open(&candidate)
+			.await
+		{
+			Ok(file) => return Ok((candidate, file)),
+			Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+				candidate = path.with_file_name(extension.as_ref().map_or_else(
+					|| format!("{stem} ({i})"),
+					|extension| format!("{stem} ({i}).{extension}"),
+				));
+			}
+			Err(e) => return Err(e),
+		}
+	}
+
+	Err(io::Error::new(
+		io::ErrorKind::AlreadyExists,
+		"no free file name left",
+	))
+}
+
+async fn receive_entries(
+	this: &Arc<P2PManager>,
+	req: &SpaceblockRequests,
+	stream: &mut UnicastStream,
+	destination: Destination,
+	cancelled: &AtomicBool,
+) -> Result<(), ()> {
+	let id = req.id;
+
+	let (destination, auto_accepted) = match destination {
+		Destination::Chosen(destination) => (destination, false),
+		Destination::AutoAccept(directory) => (directory, true),
+	};
+
+	// When transferring more than a single file, the destination provided by the user is a directory
+	let is_single_file = !auto_accepted
+		&& req.requests.len() == 1
+		&& !req.requests[0].is_directory()
+		&& !req.requests[0].name.trim_end_matches('/').contains('/');
+
+	let mut transfer = Transfer::new(
+		req,
+		|percent| {
+			this.events
+				.send(P2PEvent::SpacedropProgress { id, percent })
+				.ok();
+		},
+		cancelled,
+	);
+
+	for request in &req.requests {
+		let file_name = &request.name;
+
+		let path = if is_single_file {
+			Some(destination.clone())
+		} else {
+			entry_path(&destination, file_name)
+		};
+
+		let Some(path) = path else {
+			warn!(spacedrop_id = %id, %file_name, "Skipping entry with an invalid name;");
+
+			transfer.receive(stream, io::sink()).await.map_err(|e| {
+				error!(spacedrop_id = %id, %file_name, ?e, "Error skipping entry;");
+			})?;
+			continue;
+		};
+
+		debug!(
+			spacedrop_id = %id,
+			%file_name,
+			saving_to = %path.display(),
+			"Accepting;",
+		);
+
+		if request.is_directory() {
+			create_dir_all(&path).await.map_err(|e| {
+				error!(
+					spacedrop_id = %id,
+					directory = %path.display(),
+					?e,
+					"Error creating directory;");
+
+				// TODO: Send error to the frontend
+
+				// TODO: Send error to remote peer
+			})?;
+
+			transfer.receive(stream, io::sink()).await.map_err(|e| {
+				error!(spacedrop_id = %id, %file_name, ?e, "Error receiving directory;");
+			})?;
+			continue;
+		}
+
+		if let Some(parent) = path.parent() {
+			create_dir_all(&parent).await.map_err(|e| {
+				error!(
+					spacedrop_id = %id,
+					parent = %parent.display(),
+					?e,
+					"Error creating parent directory;");
+
+				// TODO: Send error to the frontend
+
+				// TODO: Send error to remote peer
+			})?;
+		}
+
+		let created = if auto_accepted {
+			create_unique_file(&path).await
+		} else {
+			File::create(&path).await.map(|file| (path.clone(), file))
+		};
+
+		let (path, f) = created.map_err(|e| {
+			error!(
+				spacedrop_id = %id,
+				creating_file_at = %path.display(),
+				?e,
+				"Error creating file;",
+			);
+
+			// TODO: Send error to the frontend
+
+			// TODO: Send error to remote peer
+		})?;
+
+		if auto_accepted {
+			debug!(spacedrop_id = %id, %file_name, saved_as = %path.display(), "Auto accepted file;");
+		}
+
+		let mut f = BufWriter::new(f);
+		if let Err(e) = transfer.receive(stream, &mut f).await {
+			error!(
+				spacedrop_id = %id,
+				%file_name,
+				?e,
+				"Error receiving file;");
+
+			// TODO: Send error to frontend
+
+			break;
+		}
+
+		f.flush().await.map_err(|e| {
+			error!(spacedrop_id = %id, %file_name, ?e, "Error flushing file;");
+		})?;
+	}
+
+	Ok(())
+}
+
+async fn accept(
+	this: &Arc<P2PManager>,
+	req: &SpaceblockRequests,
+	stream: &mut UnicastStream,
+	destination: Destination,
+) -> Result<(), ()> {
+	let id = req.id;
+
+	info!(spacedrop_id = %id, saving_to = %destination.path().display(), "Accepted;");
+
+	let cancelled = Arc::new(AtomicBool::new(false));
+	this.spacedrop_cancellations
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+		.insert(id, cancelled.clone());
+
+	stream.write_all(&[1]).await.map_err(|e| {
+		error!(spacedrop_id = %id, ?e, "Error sending continuation bit;");
+
+		// TODO: Send error to the frontend
+
+		// TODO: make sure the other peer times out or we retry???
+	})?;
+
+	let res = receive_entries(this, req, stream, destination, &cancelled).await;
+
+	this.spacedrop_cancellations
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+		.remove(&id);
+
+	res?;
+
+	info!(spacedrop_id = %id, "Completed;");
+
+	Ok(())
+}
+
 pub(crate) async fn receiver(
 	this: &Arc<P2PManager>,
 	req: SpaceblockRequests,
 	mut stream: UnicastStream,
 ) -> Result<(), ()> {
 	let id = req.id;
-	let (tx, rx) = oneshot::channel();
+	let identity = stream.remote_identity();
+	let files = req
+		.requests
+		.iter()
+		.map(|req| req.name.clone())
+		.collect::<Vec<_>>();
 
 	info!(
 		spacedrop_id = %id,
 		files_count = req.requests.len(),
-		peer = %stream.remote_identity(),
+		peer = %identity,
 		block_size = ?req.block_size,
 		"Receiving spacedrop files;",
 	);
+
+	// Trusted peers can skip the prompt, saving straight to the configured directory
+	if let Some(auto_accept) = this.node_config.get().await.p2p.spacedrop_auto_accept {
+		if auto_accept.peers.contains(&identity) {
+			this.events
+				.send(P2PEvent::SpacedropAutoAccepted {
+					id,
+					identity,
+					directory: auto_accept.directory.clone(),
+					files,
+				})
+				.ok();
+
+			return accept(
+				this,
+				&req,
+				&mut stream,
+				Destination::AutoAccept(auto_accept.directory),
+			)
+			.await;
+		}
+	}
+
+	let (tx, rx) = oneshot::channel();
 	this.spacedrop_pairing_reqs
 		.lock()
 		.unwrap_or_else(PoisonError::into_inner)
@@ -243,7 +608,7 @@ pub(crate) async fn receiver(
 		.events
 		.send(P2PEvent::SpacedropRequest {
 			id,
-			identity: stream.remote_identity(),
+			identity,
 			peer_name: "Unknown".into(),
 			// TODO: A better solution to this
 			// manager
@@ -253,11 +618,7 @@ pub(crate) async fn receiver(
 			// 	.find(|p| p.peer_id == event.peer_id)
 			// 	.map(|p| p.metadata.name)
 			// 	.unwrap_or_else(|| "Unknown".to_string()),
-			files: req
-				.requests
-				.iter()
-				.map(|req| req.name.clone())
-				.collect::<Vec<_>>(),
+			files,
 		})
 		.is_err()
 	{
@@ -281,85 +642,13 @@ pub(crate) async fn receiver(
 		file_path = rx => {
 			match file_path {
 				Ok(Some(file_path)) => {
-					info!(spacedrop_id = %id, saving_to = %file_path, "Accepted;");
-
-					let cancelled = Arc::new(AtomicBool::new(false));
-					this.spacedrop_cancellations
-						.lock()
-						.unwrap_or_else(PoisonError::into_inner)
-						.insert(id, cancelled.clone());
-
-					stream.write_all(&[1]).await.map_err(|e| {
-						error!(spacedrop_id = %id, ?e, "Error sending continuation bit;");
-
-						// TODO: Send error to the frontend
-
-						// TODO: make sure the other peer times out or we retry???
-					})?;
-
-					let names = req.requests.iter().map(|req| req.name.clone()).collect::<Vec<_>>();
-					let mut transfer = Transfer::new(&req, |percent| {
-						this.events.send(P2PEvent::SpacedropProgress { id, percent }).ok();
-					}, &cancelled);
-
-					let file_path = PathBuf::from(file_path);
-					let names_len = names.len();
-					for file_name in names {
-						 // When transferring more than 1 file we wanna join the incoming file name to the directory provided by the user
-						 let mut path = file_path.clone();
-						 if names_len != 1 {
-							// We know the `file_path` will be a directory so we can just push the file name to it
-							path.push(&file_name);
-						}
-
-						debug!(
-							spacedrop_id = %id,
-							%file_name,
-							saving_to = %path.display(),
-							"Accepting;",
-						);
-
-						if let Some(parent) = path.parent() {
-						  create_dir_all(&parent).await.map_err(|e| {
-								error!(
-									spacedrop_id = %id,
-									parent = %parent.display(),
-									?e,
-									"Error creating parent directory;");
-
-								// TODO: Send error to the frontend
-
-								// TODO: Send error to remote peer
-							})?;
-						}
-
-						let f = File::create(&path).await.map_err(|e| {
-							error!(
-								spacedrop_id = %id,
-								creating_file_at = %path.display(),
-								?e,
-								"Error creating file;",
-							);
-
-							// TODO: Send error to the frontend
-
-							// TODO: Send error to remote peer
-						})?;
-						let f = BufWriter::new(f);
-						if let Err(e) = transfer.receive(&mut stream, f).await {
-							error!(
-								spacedrop_id = %id,
-								%file_name,
-								?e,
-								"Error receiving file;");
-
-							// TODO: Send error to frontend
-
-							break;
-						}
-					}
-
-					info!(spacedrop_id = %id, "Completed;");
+					accept(
+						this,
+						&req,
+						&mut stream,
+						Destination::Chosen(PathBuf::from(file_path)),
+					)
+					.await?;
 				}
 				Ok(None) => {
 					info!(spacedrop_id = %id, "Rejected;");
@@ -380,3 +669,40 @@ pub(crate) async fn receiver(
 
 	Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use tempfile::tempdir;
+
+	#[test]
+	fn entries_never_escape_the_destination() {
+		let destination = Path::new("/downloads");
+
+		assert_eq!(
+			entry_path(destination, "Shoot/Raw/1.cr3"),
+			Some(destination.join("Shoot").join("Raw").join("1.cr3"))
+		);
+		assert_eq!(
+			entry_path(destination, "Shoot/"),
+			Some(destination.join("Shoot"))
+		);
+		assert_eq!(entry_path(destination, "../etc/passwd"), None);
+		assert_eq!(entry_path(destination, ""), None);
+	}
+
+	#[tokio::test]
+	async fn auto_accepted_files_never_replace_existing_ones() {
+		let directory = tempdir().unwrap();
+		let path = directory.path().join("photo.jpg");
+		fs::write(&path, b"existing").await.unwrap();
+
+		let (first, _) = create_unique_file(&path).await.unwrap();
+		let (second, _) = create_unique_file(&path).await.unwrap();
+
+		assert_eq!(first, directory.path().join("photo (1).jpg"));
+		assert_eq!(second, directory.path().join("photo (2).jpg"));
+		assert_eq!(fs::read(&path).await.unwrap(), b"existing");
+	}
+}
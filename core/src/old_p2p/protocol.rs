@@ -1,4 +1,5 @@
-use sd_old_p2p_block::{Range, SpaceblockRequests, SpaceblockRequestsError};
+use sd_old_p2p::TrafficClass;
+use sd_old_p2p_block::{Range, RequestsLen, SpaceblockRequests, SpaceblockRequestsError};
 use sd_old_p2p_proto::{decode, encode};
 use thiserror::Error;
 use tokio::io::{AsyncRead, AsyncReadExt};
@@ -9,7 +10,8 @@ use uuid::Uuid;
 pub enum Header {
 	/// Basic pin protocol for demonstrating the P2P system
 	Ping,
-	/// Spacedrop file sending
+	/// Spacedrop file sending. Up to 255 entries are sent with the original header, bigger
+	/// transfers use their own, as older peers only read the amount of entries from a single byte.
 	Spacedrop(SpaceblockRequests),
 	/// Used for sending sync messages between nodes.
 	Sync,
@@ -21,6 +23,11 @@ pub enum Header {
 		file_path_id: Uuid,
 		range: Range,
 	},
+	/// Pairing with a code exchanged out of band, so both nodes trust each other
+	Pair,
+	/// Browsing the locations of a library on the remote node
+	// We don't include a library ID here as it's taken care of by `sd_p2p_tunnel::Tunnel`.
+	Browse,
 }
 
 #[derive(Debug, Error)]
@@ -40,6 +47,16 @@ pub enum HeaderError {
 }
 
 impl Header {
+	/// What the stream is used for, so it's throttled with the right priority
+	pub fn traffic_class(&self) -> TrafficClass {
+		match self {
+			Self::Sync => TrafficClass::Sync,
+			Self::LibraryFile { .. } | Self::RspcRemote | Self::Browse => TrafficClass::FileFetch,
+			Self::Spacedrop(_) => TrafficClass::Spacedrop,
+			Self::Ping | Self::Pair => TrafficClass::Other,
+		}
+	}
+
 	pub async fn from_stream(stream: &mut (impl AsyncRead + Unpin)) -> Result<Self, HeaderError> {
 		let discriminator = stream
 			.read_u8()
@@ -48,7 +65,7 @@ impl Header {
 
 		match discriminator {
 			0 => Ok(Self::Spacedrop(
-				SpaceblockRequests::from_stream(stream).await?,
+				SpaceblockRequests::from_stream(stream, RequestsLen::U8).await?,
 			)),
 			1 => Ok(Self::Ping),
 			3 => Ok(Self::Sync),
@@ -77,6 +94,11 @@ impl Header {
 					d => return Err(HeaderError::LibraryDiscriminatorInvalid(d)),
 				},
 			}),
+			7 => Ok(Self::Pair),
+			8 => Ok(Self::Browse),
+			9 => Ok(Self::Spacedrop(
+				SpaceblockRequests::from_stream(stream, RequestsLen::U32).await?,
+			)),
 			d => Err(HeaderError::DiscriminatorInvalid(d)),
 		}
 	}
@@ -84,8 +106,12 @@ impl Header {
 	pub fn to_bytes(&self) -> Vec<u8> {
 		match self {
 			Self::Spacedrop(transfer_request) => {
-				let mut bytes = vec![0];
-				bytes.extend_from_slice(&transfer_request.to_bytes());
+				let len_encoding = RequestsLen::for_requests(&transfer_request.requests);
+				let mut bytes = vec![match len_encoding {
+					RequestsLen::U8 => 0,
+					RequestsLen::U32 => 9,
+				}];
+				bytes.extend_from_slice(&transfer_request.to_bytes(len_encoding));
 				bytes
 			}
 			Self::Ping => vec![1],
@@ -100,6 +126,8 @@ impl Header {
 				buf.extend_from_slice(&range.to_bytes());
 				buf
 			}
+			Self::Pair => vec![7],
+			Self::Browse => vec![8],
 		}
 	}
 }
@@ -0,0 +1,202 @@
+//! Comparison of the sync state of two devices of the same library, to find diverging records and
+//! pull the operations of those records from the other device.
+
+use crate::library::Library;
+
+use sd_core_sync::{
+	inspect::{
+		diverging_records, ingest_record_operations, model_checksums, record_hashes,
+		record_operations, RecordDivergence,
+	},
+	ModelId,
+};
+use sd_old_p2p::{RemoteIdentity, TrafficClass};
+use sd_old_p2p_tunnel::Tunnel;
+
+use std::{collections::HashMap, sync::Arc};
+
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tracing::{debug, instrument};
+
+use super::{
+	super::{Header, P2PManager},
+	InspectRequest, InspectResponse, SyncMessage, SyncProtocolError,
+};
+
+async fn open_session(
+	p2p: &P2PManager,
+	library: &Library,
+	remote_identity: RemoteIdentity,
+) -> Result<Tunnel, SyncProtocolError> {
+	let peer = p2p
+		.get_instance(&library.id, remote_identity)
+		.ok_or(SyncProtocolError::PeerNotFound)?;
+
+	let mut stream = peer.new_stream().await?;
+	stream.set_traffic_class(TrafficClass::Sync);
+	stream.write_all(&Header::Sync.to_bytes()).await?;
+
+	let mut tunnel = Tunnel::initiator(stream, &library.identity).await?;
+	tunnel.write_all(&SyncMessage::Inspect.to_bytes()).await?;
+
+	Ok(tunnel)
+}
+
+async fn request(
+	tunnel: &mut Tunnel,
+	request: &InspectRequest,
+) -> Result<InspectResponse, SyncProtocolError> {
+	tunnel.write_all(&request.to_bytes()?).await?;
+	tunnel.flush().await?;
+
+	InspectResponse::from_stream(tunnel).await
+}
+
+async fn finish(mut tunnel: Tunnel) -> Result<(), SyncProtocolError> {
+	tunnel.write_all(&InspectRequest::Done.to_bytes()?).await?;
+	tunnel.flush().await?;
+
+	Ok(())
+}
+
+/// Compares every synced model with the peer, narrowing down to the records that diverge
+#[instrument(skip(p2p, library), fields(library_id = %library.id), err)]
+pub async fn compare(
+	p2p: &P2PManager,
+	library: &Library,
+	remote_identity: RemoteIdentity,
+) -> Result<Vec<RecordDivergence>, SyncProtocolError> {
+	let mut tunnel = open_session(p2p, library, remote_identity).await?;
+
+	let InspectResponse::Checksums(remote_checksums) =
+		request(&mut tunnel, &InspectRequest::Checksums).await?
+	else {
+		return Err(SyncProtocolError::UnexpectedResponse);
+	};
+
+	let local_checksums = model_checksums(&library.sync).await?;
+
+	let mut divergences = vec![];
+
+	for local in local_checksums {
+		let Some(remote) = remote_checksums
+			.iter()
+			.find(|remote| remote.model_id == local.model_id)
+		else {
+			continue;
+		};
+
+		if local.checksum == remote.checksum {
+			continue;
+		}
+
+		let buckets = local.diverging_buckets(remote);
+
+		debug!(
+			model_id = local.model_id,
+			local_records = local.records_count,
+			remote_records = remote.records_count,
+			diverging_buckets = buckets.len(),
+			"Model diverges from peer;",
+		);
+
+		let InspectResponse::RecordHashes(remote_hashes) = request(
+			&mut tunnel,
+			&InspectRequest::RecordHashes {
+				model_id: local.model_id,
+				buckets: buckets.clone(),
+			},
+		)
+		.await?
+		else {
+			return Err(SyncProtocolError::UnexpectedResponse);
+		};
+
+		let local_hashes = record_hashes(&library.sync, local.model_id, &buckets).await?;
+
+		divergences.extend(diverging_records(
+			local.model_id,
+			local_hashes,
+			remote_hashes,
+		));
+	}
+
+	finish(tunnel).await?;
+
+	Ok(divergences)
+}
+
+/// Pulls every operation the peer has for the given records, ingesting the ones we're missing.
+/// Returns how many operations were ingested.
+#[instrument(skip(p2p, library, records), fields(library_id = %library.id, records_count = records.len()), err)]
+pub async fn resync_records(
+	p2p: &P2PManager,
+	library: &Library,
+	remote_identity: RemoteIdentity,
+	records: Vec<(ModelId, Vec<u8>)>,
+) -> Result<usize, SyncProtocolError> {
+	let mut record_ids_per_model = HashMap::<_, Vec<_>>::new();
+	for (model_id, record_id) in records {
+		record_ids_per_model
+			.entry(model_id)
+			.or_default()
+			.push(record_id);
+	}
+
+	let mut tunnel = open_session(p2p, library, remote_identity).await?;
+
+	let mut ops = vec![];
+
+	for (model_id, record_ids) in record_ids_per_model {
+		let InspectResponse::RecordOperations(model_ops) = request(
+			&mut tunnel,
+			&InspectRequest::RecordOperations {
+				model_id,
+				record_ids,
+			},
+		)
+		.await?
+		else {
+			return Err(SyncProtocolError::UnexpectedResponse);
+		};
+
+		ops.extend(model_ops);
+	}
+
+	finish(tunnel).await?;
+
+	// Ingesting all models at once, as the sync manager ingests them in dependency order
+	let ingested = ingest_record_operations(&library.sync, ops).await?;
+
+	debug!(ingested, "Re-synced records from peer;");
+
+	Ok(ingested)
+}
+
+/// Answers the inspection requests of the peer until it's done
+#[instrument(skip_all, fields(library_id = %library.id), err)]
+pub async fn responder(
+	stream: &mut (impl AsyncRead + AsyncWrite + Unpin),
+	library: Arc<Library>,
+) -> Result<(), SyncProtocolError> {
+	loop {
+		let response = match InspectRequest::from_stream(stream).await? {
+			InspectRequest::Checksums => {
+				InspectResponse::Checksums(model_checksums(&library.sync).await?)
+			}
+			InspectRequest::RecordHashes { model_id, buckets } => InspectResponse::RecordHashes(
+				record_hashes(&library.sync, model_id, &buckets).await?,
+			),
+			InspectRequest::RecordOperations {
+				model_id,
+				record_ids,
+			} => InspectResponse::RecordOperations(
+				record_operations(&library.sync, model_id, record_ids).await?,
+			),
+			InspectRequest::Done => return Ok(()),
+		};
+
+		stream.write_all(&response.to_bytes()?).await?;
+		stream.flush().await?;
+	}
+}
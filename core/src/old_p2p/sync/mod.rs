@@ -1,262 +1,316 @@
-#![allow(clippy::panic, clippy::unwrap_used)] // TODO: Finish this
+//! Direct sync between nodes of the same library over P2P, without going through the cloud.
+//!
+//! Whenever we write new operations, we alert all peers of the library (the originator side).
+//! Each peer (the responder side) then pulls the operations it's missing in batches, sending the
+//! latest timestamp it has for each device, and ingests them the same way cloud operations are.
+
+use crate::library::{Libraries, Library};
+
+use sd_core_sync::{
+	cloud_crdt_op_db, CompressedCRDTOperationsPerModelPerDevice, SyncManager, NTP64,
+};
+use sd_old_p2p::{flume::bounded, HookEvent, Peer, TrafficClass};
+use sd_old_p2p_tunnel::Tunnel;
+
+use std::{
+	collections::{hash_map::Entry, HashMap},
+	pin::pin,
+	sync::{atomic::Ordering, Arc, PoisonError},
+};
+
+use futures::StreamExt;
+use tokio::{
+	io::{AsyncRead, AsyncWrite, AsyncWriteExt},
+	spawn,
+};
+use tracing::{debug, error, instrument, trace};
+use uuid::Uuid;
+
+use super::{Header, P2PManager};
+
+pub mod inspect;
+mod proto;
+
+pub use proto::*;
+
+/// Maximum amount of operations sent for each request, so the responder can ingest them in
+/// batches without holding everything in memory
+const OPS_PER_REQUEST: u32 = 1000;
+
+/// Alerts every peer of this library that we have new operations, so they pull them from us.
+///
+/// Alerts are coalesced per peer: if the peer is still pulling from a previous alert, it will be
+/// alerted again once it's done, so a burst of writes doesn't open a stream for each operation.
+pub fn originator(library: &Arc<Library>, p2p: &Arc<P2PManager>) {
+	for (remote_identity, peer) in p2p.get_library_instances(&library.id) {
+		if !peer.can_connect() {
+			trace!(%remote_identity, "Skipping sync alert to unreachable peer;");
+			continue;
+		}
+
+		alert_peer(p2p, library, peer);
+	}
+}
 
-use crate::library::Library;
+fn alert_peer(p2p: &Arc<P2PManager>, library: &Arc<Library>, peer: Arc<Peer>) {
+	let key = (library.id, peer.identity());
+
+	match p2p
+		.sync_sessions
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+		.entry(key)
+	{
+		Entry::Occupied(mut entry) => {
+			// A session is running, it will be restarted when done to pick up the new operations
+			*entry.get_mut() = true;
+			return;
+		}
+		Entry::Vacant(entry) => {
+			entry.insert(false);
+		}
+	}
 
-// use sd_p2p_proto::{decode, encode};
-// use sd_sync::CompressedCRDTOperationsPerModelPerDevice;
+	let p2p = Arc::clone(p2p);
+	let library = Arc::clone(library);
+
+	spawn(async move {
+		loop {
+			if let Err(e) = serve_peer(&library, &peer).await {
+				error!(
+					library_id = %library.id,
+					remote_identity = %peer.identity(),
+					?e,
+					"Failed to sync library with peer;",
+				);
+			}
+
+			let mut sessions = p2p
+				.sync_sessions
+				.lock()
+				.unwrap_or_else(PoisonError::into_inner);
+
+			if sessions.get(&key).copied().unwrap_or_default() {
+				sessions.insert(key, false);
+			} else {
+				sessions.remove(&key);
+				break;
+			}
+		}
+	});
+}
 
-use std::sync::Arc;
+/// Originator side, serving our operations until the peer is up to date
+#[instrument(skip_all, fields(library_id = %library.id, remote_identity = %peer.identity()), err)]
+async fn serve_peer(library: &Library, peer: &Peer) -> Result<(), SyncProtocolError> {
+	debug!("Alerting peer of new sync operations for library;");
 
-use tokio::io::{AsyncRead, AsyncWrite};
+	let mut stream = peer.new_stream().await?;
+	stream.set_traffic_class(TrafficClass::Sync);
+	stream.write_all(&Header::Sync.to_bytes()).await?;
 
-use super::P2PManager;
+	let mut tunnel = Tunnel::initiator(stream, &library.identity).await?;
+	tunnel
+		.write_all(&SyncMessage::NewOperations.to_bytes())
+		.await?;
+	tunnel.flush().await?;
 
-mod proto;
-pub use proto::*;
+	let mut sent = 0;
 
-pub use originator::run as originator;
-mod originator {
-	// use crate::p2p::Header;
-
-	use super::*;
-	// use responder::tx as rx;
-	use sd_core_sync::SyncManager;
-	// use sd_p2p_tunnel::Tunnel;
-
-	pub mod tx {
-
-		// use super::*;
-
-		// #[derive(Debug)]
-		// pub struct Operations(pub CompressedCRDTOperationsPerModelPerDevice);
-
-		// impl Operations {
-		// 	// TODO: Per field errors for better error handling
-		// 	pub async fn from_stream(
-		// 		stream: &mut (impl AsyncRead + Unpin),
-		// 	) -> std::io::Result<Self> {
-		// 		Ok(Self(
-		// 			rmp_serde::from_slice(&decode::buf(stream).await.unwrap()).unwrap(),
-		// 		))
-		// 	}
-
-		// 	pub fn to_bytes(&self) -> Vec<u8> {
-		// 		let Self(args) = self;
-		// 		let mut buf = vec![];
-
-		// 		// TODO: Error handling
-		// 		encode::buf(&mut buf, &rmp_serde::to_vec_named(&args).unwrap());
-		// 		buf
-		// 	}
-		// }
-
-		// #[cfg(test)]
-		// #[tokio::test]
-		// async fn test() {
-		// 	use sd_sync::CRDTOperation;
-		// 	use uuid::Uuid;
-
-		// 	{
-		// 		let original = Operations(CompressedCRDTOperationsPerModelPerDevice::new(vec![]));
-
-		// 		let mut cursor = std::io::Cursor::new(original.to_bytes());
-		// 		let result = Operations::from_stream(&mut cursor).await.unwrap();
-		// 		assert_eq!(original, result);
-		// 	}
-
-		// 	{
-		// 		let original = Operations(CompressedCRDTOperationsPerModelPerDevice::new(vec![
-		// 			CRDTOperation {
-		// 				device_pub_id: Uuid::new_v4(),
-		// 				timestamp: sync::NTP64(0),
-		// 				record_id: rmpv::Value::Nil,
-		// 				model_id: 0,
-		// 				data: sd_sync::CRDTOperationData::create(),
-		// 			},
-		// 		]));
-
-		// 		let mut cursor = std::io::Cursor::new(original.to_bytes());
-		// 		let result = Operations::from_stream(&mut cursor).await.unwrap();
-		// 		assert_eq!(original, result);
-		// 	}
-		// }
+	while let Request::GetOperations(args) = Request::from_stream(&mut tunnel).await? {
+		let ops = get_ops(&library.sync, args).await?;
+		sent += ops.len();
+
+		tunnel
+			.write_all(&Operations(CompressedCRDTOperationsPerModelPerDevice::new(ops)).to_bytes()?)
+			.await?;
+		tunnel.flush().await?;
 	}
 
-	// #[instrument(skip(sync, p2p))]
-	/// REMEMBER: This only syncs one direction!
-	pub async fn run(_library: Arc<Library>, _sync: &SyncManager, _p2p: &Arc<super::P2PManager>) {
-		// for (remote_identity, peer) in p2p.get_library_instances(&library.id) {
-		// 	if !peer.is_connected() {
-		// 		continue;
-		// 	};
-
-		// 	let sync = sync.clone();
-
-		// 	let library = library.clone();
-		// 	tokio::spawn(async move {
-		// 		debug!(
-		// 			?remote_identity,
-		// 			%library.id,
-		// 			"Alerting peer of new sync events for library;"
-		// 		);
-
-		// 		let mut stream = peer.new_stream().await.unwrap();
-
-		// 		stream.write_all(&Header::Sync.to_bytes()).await.unwrap();
-
-		// 		let mut tunnel = Tunnel::initiator(stream, &library.identity).await.unwrap();
-
-		// 		tunnel
-		// 			.write_all(&SyncMessage::NewOperations.to_bytes())
-		// 			.await
-		// 			.unwrap();
-		// 		tunnel.flush().await.unwrap();
-
-		// 		while let Ok(rx::MainRequest::GetOperations(GetOpsArgs {
-		// 			timestamp_per_device,
-		// 			count,
-		// 		})) = rx::MainRequest::from_stream(&mut tunnel).await
-		// 		{
-		// 			tunnel
-		// 				.write_all(
-		// 					&tx::Operations(CompressedCRDTOperationsPerModelPerDevice::new(
-		// 						sync.get_ops(count, timestamp_per_device).await.unwrap(),
-		// 					))
-		// 					.to_bytes(),
-		// 				)
-		// 				.await
-		// 				.unwrap();
-		// 			tunnel.flush().await.unwrap();
-		// 		}
-		// 	});
-		// }
+	debug!(sent, "Peer finished pulling sync operations;");
+
+	Ok(())
+}
+
+/// Fetches the operations newer than the peer's timestamps, device by device
+async fn get_ops(
+	sync: &SyncManager,
+	GetOpsArgs {
+		timestamp_per_device,
+		count,
+	}: GetOpsArgs,
+) -> Result<Vec<sd_core_sync::CRDTOperation>, SyncProtocolError> {
+	let count = count.clamp(1, OPS_PER_REQUEST) as usize;
+	let remote_timestamps = timestamp_per_device.into_iter().collect::<HashMap<_, _>>();
+
+	let outdated_devices = sync
+		.timestamp_per_device
+		.read()
+		.await
+		.iter()
+		.filter(|(device_pub_id, timestamp)| {
+			remote_timestamps
+				.get(*device_pub_id)
+				.map_or(true, |remote_timestamp| *timestamp > remote_timestamp)
+		})
+		.map(|(device_pub_id, _)| device_pub_id.clone())
+		.collect::<Vec<_>>();
+
+	let mut ops = Vec::with_capacity(count);
+
+	for device_pub_id in outdated_devices {
+		let remaining = count - ops.len();
+		if remaining == 0 {
+			break;
+		}
+
+		let initial_timestamp = remote_timestamps
+			.get(&device_pub_id)
+			.copied()
+			.unwrap_or(NTP64(0));
+
+		// Limited to `OPS_PER_REQUEST`, so it always fits
+		let chunk_size = u32::try_from(remaining).unwrap_or(OPS_PER_REQUEST);
+
+		if let Some(chunk) =
+			pin!(sync.stream_device_ops(&device_pub_id, chunk_size, initial_timestamp))
+				.next()
+				.await
+		{
+			ops.extend(chunk?);
+		}
 	}
+
+	Ok(ops)
 }
 
-pub use responder::run as responder;
-mod responder {
-
-	use super::*;
-	// use futures::StreamExt;
-
-	// pub mod tx {
-	// 	use serde::{Deserialize, Serialize};
-
-	// 	use super::*;
-
-	// 	#[derive(Serialize, Deserialize, PartialEq, Debug)]
-	// 	pub enum MainRequest {
-	// 		GetOperations(GetOpsArgs),
-	// 		Done,
-	// 	}
-
-	// 	impl MainRequest {
-	// 		// TODO: Per field errors for better error handling
-	// 		pub async fn from_stream(
-	// 			stream: &mut (impl AsyncRead + Unpin),
-	// 		) -> std::io::Result<Self> {
-	// 			Ok(
-	// 				// TODO: Error handling
-	// 				rmp_serde::from_slice(&decode::buf(stream).await.unwrap()).unwrap(),
-	// 			)
-	// 		}
-
-	// 		pub fn to_bytes(&self) -> Vec<u8> {
-	// 			let mut buf = vec![];
-	// 			// TODO: Error handling
-	// 			encode::buf(&mut buf, &rmp_serde::to_vec_named(&self).unwrap());
-	// 			buf
-	// 		}
-	// 	}
-
-	// 	#[cfg(test)]
-	// 	#[tokio::test]
-	// 	async fn test() {
-	// 		{
-	// 			let original = MainRequest::GetOperations(GetOpsArgs {
-	// 				timestamp_per_device: vec![],
-	// 				count: 0,
-	// 			});
-
-	// 			let mut cursor = std::io::Cursor::new(original.to_bytes());
-	// 			let result = MainRequest::from_stream(&mut cursor).await.unwrap();
-	// 			assert_eq!(original, result);
-	// 		}
-
-	// 		{
-	// 			let original = MainRequest::Done;
-
-	// 			let mut cursor = std::io::Cursor::new(original.to_bytes());
-	// 			let result = MainRequest::from_stream(&mut cursor).await.unwrap();
-	// 			assert_eq!(original, result);
-	// 		}
-	// 	}
-	// }
-
-	pub async fn run(
-		_stream: &mut (impl AsyncRead + AsyncWrite + Unpin),
-		_library: Arc<Library>,
-	) -> Result<(), ()> {
-		// use sync::ingest::*;
-
-		// let ingest = &library.sync.ingest;
-
-		// ingest.event_tx.send(Event::Notification).await.unwrap();
-
-		// let mut rx = pin!(ingest.req_rx.clone());
-
-		// while let Some(req) = rx.next().await {
-		// 	const OPS_PER_REQUEST: u32 = 1000;
-
-		// 	let timestamps = match req {
-		// 		Request::FinishedIngesting => break,
-		// 		Request::Messages { timestamps, .. } => timestamps,
-		// 	};
-
-		// 	debug!(?timestamps, "Getting ops for timestamps;");
-
-		// 	stream
-		// 		.write_all(
-		// 			&tx::MainRequest::GetOperations(sync::GetOpsArgs {
-		// 				timestamp_per_device: timestamps,
-		// 				count: OPS_PER_REQUEST,
-		// 			})
-		// 			.to_bytes(),
-		// 		)
-		// 		.await
-		// 		.unwrap();
-		// 	stream.flush().await.unwrap();
-
-		// 	let rx::Operations(ops) = rx::Operations::from_stream(stream).await.unwrap();
-
-		// 	let (wait_tx, wait_rx) = tokio::sync::oneshot::channel::<()>();
-
-		// 	// FIXME: If there are exactly a multiple of OPS_PER_REQUEST operations,
-		// 	// then this will bug, as we sent `has_more` as true, but we don't have
-		// 	// more operations to send.
-
-		// 	ingest
-		// 		.event_tx
-		// 		.send(Event::Messages(MessagesEvent {
-		// 			device_pub_id: library.sync.device_pub_id.clone(),
-		// 			has_more: ops.len() == OPS_PER_REQUEST as usize,
-		// 			messages: ops,
-		// 			wait_tx: Some(wait_tx),
-		// 		}))
-		// 		.await
-		// 		.expect("TODO: Handle ingest channel closed, so we don't loose ops");
-
-		// 	wait_rx.await.unwrap()
-		// }
-
-		// debug!("Sync responder done");
-
-		// stream
-		// 	.write_all(&tx::MainRequest::Done.to_bytes())
-		// 	.await
-		// 	.unwrap();
-		// stream.flush().await.unwrap();
-
-		Ok(())
+/// Responder side, pulling operations from the originator until we're up to date
+#[instrument(skip_all, fields(library_id = %library.id), err)]
+pub async fn responder(
+	stream: &mut (impl AsyncRead + AsyncWrite + Unpin),
+	library: Arc<Library>,
+) -> Result<(), SyncProtocolError> {
+	let sync = &library.sync;
+
+	// Operations are only ingested by libraries with sync enabled, as they're the ones
+	// generating operations for their own changes
+	if library
+		.config()
+		.await
+		.generate_sync_operations
+		.load(Ordering::Relaxed)
+	{
+		let mut timestamps = sync.timestamp_per_device.read().await.clone();
+		let mut received = 0;
+
+		loop {
+			stream
+				.write_all(
+					&Request::GetOperations(GetOpsArgs {
+						timestamp_per_device: timestamps
+							.iter()
+							.map(|(device_pub_id, timestamp)| (device_pub_id.clone(), *timestamp))
+							.collect(),
+						count: OPS_PER_REQUEST,
+					})
+					.to_bytes()?,
+				)
+				.await?;
+			stream.flush().await?;
+
+			let Operations(compressed_ops) = Operations::from_stream(stream).await?;
+			if compressed_ops.is_empty() {
+				break;
+			}
+
+			let ops = compressed_ops.into_ops();
+			let ops_count = ops.len();
+			received += ops_count;
+
+			// Tracking timestamps ourselves, as operations older than what we have for a record
+			// are discarded on ingestion and wouldn't move the sync manager's timestamps forward
+			for op in &ops {
+				let timestamp = timestamps
+					.entry(op.device_pub_id.into())
+					.or_insert(NTP64(0));
+
+				if op.timestamp > *timestamp {
+					*timestamp = op.timestamp;
+				}
+			}
+
+			sync.db
+				._batch(
+					ops.iter()
+						.map(|op| cloud_crdt_op_db(op).map(|op| op.to_query(&sync.db)))
+						.collect::<Result<Vec<_>, _>>()?,
+				)
+				.await?;
+
+			sync.active.store(true, Ordering::Relaxed);
+			sync.active_notify.notify_waiters();
+
+			let res = sync.ingest_ops().await;
+
+			sync.active.store(false, Ordering::Relaxed);
+			sync.active_notify.notify_waiters();
+
+			res?;
+
+			if ops_count < OPS_PER_REQUEST as usize {
+				break;
+			}
+		}
+
+		debug!(received, "Finished pulling sync operations from peer;");
+	} else {
+		debug!("Ignoring sync operations as sync is disabled for this library;");
 	}
+
+	stream.write_all(&Request::Done.to_bytes()?).await?;
+	stream.flush().await?;
+
+	Ok(())
+}
+
+/// Alerts peers as soon as they connect, so a node that was offline catches up on the
+/// operations written in the meantime without waiting for a new write
+pub(super) fn peers_hook(p2p: &Arc<P2PManager>, libraries: Arc<Libraries>) {
+	let (tx, rx) = bounded(15);
+	let _ = p2p.p2p.register_hook("sd-sync", tx);
+
+	let p2p = Arc::clone(p2p);
+
+	spawn(async move {
+		while let Ok(event) = rx.recv_async().await {
+			let peer = match event {
+				HookEvent::PeerConnectedWith(_, peer) | HookEvent::PeerDiscoveredBy(_, peer) => {
+					peer
+				}
+				HookEvent::Shutdown { _guard } => break,
+				_ => continue,
+			};
+
+			let library_ids = peer
+				.metadata()
+				.keys()
+				.filter_map(|key| Uuid::parse_str(key).ok())
+				.collect::<Vec<_>>();
+
+			for library_id in library_ids {
+				let Some(library) = libraries.get_library(&library_id).await else {
+					continue;
+				};
+
+				if library
+					.config()
+					.await
+					.generate_sync_operations
+					.load(Ordering::Relaxed)
+				{
+					alert_peer(&p2p, &library, Arc::clone(&peer));
+				}
+			}
+		}
+	});
 }
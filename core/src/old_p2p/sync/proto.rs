@@ -1,10 +1,58 @@
-use sd_old_p2p_proto::decode;
+use sd_core_sync::{
+	inspect::{ModelChecksum, RecordHash},
+	CRDTOperation, CompressedCRDTOperationsPerModelPerDevice, DevicePubId, ModelId, NTP64,
+};
+use sd_old_p2p::NewStreamError;
+use sd_old_p2p_proto::{decode, encode};
+use sd_old_p2p_tunnel::TunnelError;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
 use tokio::io::{AsyncRead, AsyncReadExt};
 
+#[derive(Debug, Error)]
+pub enum SyncProtocolError {
+	#[error("failed to open stream to peer: {0}")]
+	NewStream(#[from] NewStreamError),
+	#[error("failed to establish library tunnel: {0}")]
+	Tunnel(#[from] TunnelError),
+	#[error("io error: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("failed to decode sync message: {0}")]
+	Decode(#[from] decode::Error),
+	#[error("failed to serialize sync message: {0}")]
+	Serialization(#[from] rmp_serde::encode::Error),
+	#[error("failed to deserialize sync message: {0}")]
+	Deserialization(#[from] rmp_serde::decode::Error),
+	#[error(transparent)]
+	Sync(#[from] sd_core_sync::Error),
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error("peer isn't connected or doesn't have this library")]
+	PeerNotFound,
+	#[error("unexpected response from peer")]
+	UnexpectedResponse,
+}
+
+impl From<SyncProtocolError> for rspc::Error {
+	fn from(e: SyncProtocolError) -> Self {
+		match e {
+			SyncProtocolError::PeerNotFound => {
+				Self::with_cause(rspc::ErrorCode::NotFound, e.to_string(), e)
+			}
+			SyncProtocolError::Sync(e) => e.into(),
+			_ => Self::with_cause(rspc::ErrorCode::InternalServerError, e.to_string(), e),
+		}
+	}
+}
+
 // will probs have more variants in future
 #[derive(Debug, PartialEq, Eq)]
 pub enum SyncMessage {
+	/// The originator has new operations, the responder must pull them with [`Request`]s
 	NewOperations,
+	/// The originator wants to inspect the responder's sync state with [`InspectRequest`]s
+	Inspect,
 }
 
 impl SyncMessage {
@@ -12,6 +60,7 @@ impl SyncMessage {
 	pub async fn from_stream(stream: &mut (impl AsyncRead + Unpin)) -> Result<Self, decode::Error> {
 		match stream.read_u8().await? {
 			b'N' => Ok(Self::NewOperations),
+			b'I' => Ok(Self::Inspect),
 			header => Err(decode::Error::IoError(std::io::Error::new(
 				std::io::ErrorKind::InvalidData,
 				format!("Invalid sync message header: {}", (header as char)),
@@ -19,25 +68,180 @@ impl SyncMessage {
 		}
 	}
 
-	// pub fn to_bytes(&self) -> Vec<u8> {
-	// 	match self {
-	// 		Self::NewOperations => vec![b'N'],
-	// 	}
-	// }
+	pub fn to_bytes(&self) -> Vec<u8> {
+		match self {
+			Self::NewOperations => vec![b'N'],
+			Self::Inspect => vec![b'I'],
+		}
+	}
+}
+
+/// The latest timestamp the responder has for each device, so the originator only sends newer
+/// operations. Devices missing from the list are sent from the start.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+pub struct GetOpsArgs {
+	pub timestamp_per_device: Vec<(DevicePubId, NTP64)>,
+	pub count: u32,
+}
+
+/// Sent by the responder to the originator
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+pub enum Request {
+	GetOperations(GetOpsArgs),
+	Done,
+}
+
+impl Request {
+	pub async fn from_stream(
+		stream: &mut (impl AsyncRead + Unpin),
+	) -> Result<Self, SyncProtocolError> {
+		rmp_serde::from_slice(&decode::buf(stream).await?).map_err(Into::into)
+	}
+
+	pub fn to_bytes(&self) -> Result<Vec<u8>, SyncProtocolError> {
+		let mut buf = vec![];
+		encode::buf(&mut buf, &rmp_serde::to_vec_named(self)?);
+		Ok(buf)
+	}
+}
+
+/// Sent by the originator as the response for each [`Request::GetOperations`], an empty
+/// list means the responder is up to date
+#[derive(Debug)]
+pub struct Operations(pub CompressedCRDTOperationsPerModelPerDevice);
+
+impl Operations {
+	pub async fn from_stream(
+		stream: &mut (impl AsyncRead + Unpin),
+	) -> Result<Self, SyncProtocolError> {
+		Ok(Self(rmp_serde::from_slice(&decode::buf(stream).await?)?))
+	}
+
+	pub fn to_bytes(&self) -> Result<Vec<u8>, SyncProtocolError> {
+		let Self(ops) = self;
+		let mut buf = vec![];
+		encode::buf(&mut buf, &rmp_serde::to_vec_named(ops)?);
+		Ok(buf)
+	}
+}
+
+/// Sent by the originator of an [`SyncMessage::Inspect`] session, each one answered with the
+/// [`InspectResponse`] variant of the same name
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+pub enum InspectRequest {
+	Checksums,
+	RecordHashes {
+		model_id: ModelId,
+		buckets: Vec<u8>,
+	},
+	RecordOperations {
+		model_id: ModelId,
+		record_ids: Vec<Vec<u8>>,
+	},
+	Done,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum InspectResponse {
+	Checksums(Vec<ModelChecksum>),
+	RecordHashes(Vec<(Vec<u8>, RecordHash)>),
+	RecordOperations(Vec<CRDTOperation>),
+}
+
+impl InspectRequest {
+	pub async fn from_stream(
+		stream: &mut (impl AsyncRead + Unpin),
+	) -> Result<Self, SyncProtocolError> {
+		decode_message(stream).await
+	}
+
+	pub fn to_bytes(&self) -> Result<Vec<u8>, SyncProtocolError> {
+		encode_message(self)
+	}
+}
+
+impl InspectResponse {
+	pub async fn from_stream(
+		stream: &mut (impl AsyncRead + Unpin),
+	) -> Result<Self, SyncProtocolError> {
+		decode_message(stream).await
+	}
+
+	pub fn to_bytes(&self) -> Result<Vec<u8>, SyncProtocolError> {
+		encode_message(self)
+	}
 }
 
-// #[cfg(test)]
-// mod tests {
-// 	use super::*;
+async fn decode_message<T: DeserializeOwned>(
+	stream: &mut (impl AsyncRead + Unpin),
+) -> Result<T, SyncProtocolError> {
+	rmp_serde::from_slice(&decode::buf(stream).await?).map_err(Into::into)
+}
+
+fn encode_message(message: &impl Serialize) -> Result<Vec<u8>, SyncProtocolError> {
+	let mut buf = vec![];
+	encode::buf(&mut buf, &rmp_serde::to_vec_named(message)?);
+	Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn test_types() {
+		{
+			let original = SyncMessage::NewOperations;
+
+			let mut cursor = std::io::Cursor::new(original.to_bytes());
+			let result = SyncMessage::from_stream(&mut cursor).await.unwrap();
+			assert_eq!(original, result);
+		}
+
+		{
+			let original = Request::GetOperations(GetOpsArgs {
+				timestamp_per_device: vec![(DevicePubId::new(), NTP64(42))],
+				count: 1000,
+			});
+
+			let mut cursor = std::io::Cursor::new(original.to_bytes().unwrap());
+			let result = Request::from_stream(&mut cursor).await.unwrap();
+			assert_eq!(original, result);
+		}
+
+		{
+			let original = SyncMessage::Inspect;
 
-// 	#[tokio::test]
-// 	async fn test_types() {
-// 		{
-// 			let original = SyncMessage::NewOperations;
+			let mut cursor = std::io::Cursor::new(original.to_bytes());
+			let result = SyncMessage::from_stream(&mut cursor).await.unwrap();
+			assert_eq!(original, result);
+		}
+
+		{
+			let original = InspectRequest::RecordHashes {
+				model_id: 1,
+				buckets: vec![0, 42, 255],
+			};
+
+			let mut cursor = std::io::Cursor::new(original.to_bytes().unwrap());
+			let result = InspectRequest::from_stream(&mut cursor).await.unwrap();
+			assert_eq!(original, result);
+		}
 
-// 			let mut cursor = std::io::Cursor::new(original.to_bytes());
-// 			let result = SyncMessage::from_stream(&mut cursor).await.unwrap();
-// 			assert_eq!(original, result);
-// 		}
-// 	}
-// }
+		{
+			let original = Request::Done;
+
+			let mut cursor = std::io::Cursor::new(original.to_bytes().unwrap());
+			let result = Request::from_stream(&mut cursor).await.unwrap();
+			assert_eq!(original, result);
+		}
+
+		{
+			let original = Operations(CompressedCRDTOperationsPerModelPerDevice::new(vec![]));
+
+			let mut cursor = std::io::Cursor::new(original.to_bytes().unwrap());
+			let Operations(result) = Operations::from_stream(&mut cursor).await.unwrap();
+			assert!(result.is_empty());
+		}
+	}
+}
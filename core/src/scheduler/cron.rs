@@ -0,0 +1,289 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, Timelike, Utc};
+use thiserror::Error;
+
+/// How far in the future we look for a matching minute before giving up, enough to cover
+/// expressions like `0 0 29 2 *` which only match on leap years
+const MAX_LOOKAHEAD_DAYS: i64 = 366 * 8;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CronError {
+	#[error("expected 5 fields (minute hour day-of-month month day-of-week), found {0}")]
+	WrongFieldCount(usize),
+	#[error("invalid value '{value}' on {field} field")]
+	InvalidValue { field: &'static str, value: String },
+	#[error("value {value} is out of range on {field} field, expected {min}-{max}")]
+	OutOfRange {
+		field: &'static str,
+		value: u32,
+		min: u32,
+		max: u32,
+	},
+	#[error("step must be greater than zero on {0} field")]
+	ZeroStep(&'static str),
+}
+
+/// A set of allowed values for a single cron field, as a bitmask
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Field(u64);
+
+impl Field {
+	fn contains(self, value: u32) -> bool {
+		self.0 & (1 << value) != 0
+	}
+
+	fn parse(raw: &str, name: &'static str, min: u32, max: u32) -> Result<Self, CronError> {
+		let invalid = |value: &str| CronError::InvalidValue {
+			field: name,
+			value: value.to_string(),
+		};
+
+		let parse_value = |value: &str| {
+			let parsed = value.parse::<u32>().map_err(|_| invalid(value))?;
+			if (min..=max).contains(&parsed) {
+				Ok(parsed)
+			} else {
+				Err(CronError::OutOfRange {
+					field: name,
+					value: parsed,
+					min,
+					max,
+				})
+			}
+		};
+
+		let mut mask = 0;
+
+		for part in raw.split(',') {
+			let (range, step) = match part.split_once('/') {
+				Some((range, step)) => {
+					let step = step.parse::<u32>().map_err(|_| invalid(part))?;
+					if step == 0 {
+						return Err(CronError::ZeroStep(name));
+					}
+					(range, step)
+				}
+				None => (part, 1),
+			};
+
+			let (start, end) = if range == "*" {
+				(min, max)
+			} else if let Some((start, end)) = range.split_once('-') {
+				let (start, end) = (parse_value(start)?, parse_value(end)?);
+				if start > end {
+					return Err(invalid(part));
+				}
+				(start, end)
+			} else {
+				let value = parse_value(range)?;
+				// `5/15` means "starting at 5, every 15"
+				(value, if step > 1 { max } else { value })
+			};
+
+			for value in (start..=end).step_by(step as usize) {
+				mask |= 1 << value;
+			}
+		}
+
+		Ok(Self(mask))
+	}
+}
+
+/// A standard 5 fields cron expression, evaluated on the node's local time.
+///
+/// Supports `*`, lists (`1,15`), ranges (`1-5`), steps (`*/10`, `0-30/5`) and the
+/// `@hourly`, `@daily`, `@weekly`, `@monthly` and `@yearly` shorthands.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronExpression {
+	source: String,
+	minutes: Field,
+	hours: Field,
+	days_of_month: Field,
+	months: Field,
+	days_of_week: Field,
+	/// Following cron semantics, if both day fields are restricted, matching any of them is enough
+	days_of_month_restricted: bool,
+	days_of_week_restricted: bool,
+}
+
+impl FromStr for CronExpression {
+	type Err = CronError;
+
+	fn from_str(source: &str) -> Result<Self, Self::Err> {
+		let expanded = match source.trim() {
+			"@hourly" => "0 * * * *",
+			"@daily" | "@midnight" => "0 0 * * *",
+			"@weekly" => "0 0 * * 0",
+			"@monthly" => "0 0 1 * *",
+			"@yearly" | "@annually" => "0 0 1 1 *",
+			other => other,
+		};
+
+		let fields = expanded.split_whitespace().collect::<Vec<_>>();
+		let [minutes, hours, days_of_month, months, days_of_week] = fields[..] else {
+			return Err(CronError::WrongFieldCount(fields.len()));
+		};
+
+		let mut parsed_days_of_week = Field::parse(days_of_week, "day-of-week", 0, 7)?;
+		// Both 0 and 7 are sunday
+		if parsed_days_of_week.contains(7) {
+			parsed_days_of_week.0 |= 1;
+		}
+
+		Ok(Self {
+			source: source.trim().to_string(),
+			minutes: Field::parse(minutes, "minute", 0, 59)?,
+			hours: Field::parse(hours, "hour", 0, 23)?,
+			days_of_month: Field::parse(days_of_month, "day-of-month", 1, 31)?,
+			months: Field::parse(months, "month", 1, 12)?,
+			days_of_week: parsed_days_of_week,
+			days_of_month_restricted: days_of_month != "*",
+			days_of_week_restricted: days_of_week != "*",
+		})
+	}
+}
+
+impl fmt::Display for CronExpression {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(&self.source)
+	}
+}
+
+impl CronExpression {
+	fn matches_day(&self, date: NaiveDate) -> bool {
+		let day_of_month = self.days_of_month.contains(date.day());
+		let day_of_week = self
+			.days_of_week
+			.contains(date.weekday().num_days_from_sunday());
+
+		match (self.days_of_month_restricted, self.days_of_week_restricted) {
+			(true, true) => day_of_month || day_of_week,
+			(true, false) => day_of_month,
+			(false, true) => day_of_week,
+			(false, false) => true,
+		}
+	}
+
+	/// The first instant strictly after `after` matching this expression, or `None` if the
+	/// expression never matches, like `0 0 31 2 *`
+	#[must_use]
+	pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+		let local = after.with_timezone(&Local).naive_local();
+		let mut candidate =
+			local.date().and_hms_opt(local.hour(), local.minute(), 0)? + Duration::minutes(1);
+		let limit = candidate + Duration::days(MAX_LOOKAHEAD_DAYS);
+
+		while candidate < limit {
+			if !self.months.contains(candidate.month()) {
+				candidate = first_day_of_next_month(candidate)?;
+				continue;
+			}
+
+			if !self.matches_day(candidate.date()) {
+				candidate = (candidate.date() + Duration::days(1)).and_hms_opt(0, 0, 0)?;
+				continue;
+			}
+
+			if !self.hours.contains(candidate.hour()) {
+				candidate =
+					candidate.date().and_hms_opt(candidate.hour(), 0, 0)? + Duration::hours(1);
+				continue;
+			}
+
+			if self.minutes.contains(candidate.minute()) {
+				// Local times skipped by daylight saving time transitions don't exist, so we move on
+				if let Some(next) = candidate
+					.and_local_timezone(Local)
+					.earliest()
+					.map(|next| next.with_timezone(&Utc))
+					.filter(|next| *next > after)
+				{
+					return Some(next);
+				}
+			}
+
+			candidate += Duration::minutes(1);
+		}
+
+		None
+	}
+}
+
+fn first_day_of_next_month(date_time: NaiveDateTime) -> Option<NaiveDateTime> {
+	let (year, month) = if date_time.month() == 12 {
+		(date_time.year() + 1, 1)
+	} else {
+		(date_time.year(), date_time.month() + 1)
+	};
+
+	NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn local(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+		NaiveDate::from_ymd_opt(year, month, day)
+			.and_then(|date| date.and_hms_opt(hour, minute, 0))
+			.and_then(|date_time| date_time.and_local_timezone(Local).earliest())
+			.map(|date_time| date_time.with_timezone(&Utc))
+			.expect("valid local date time")
+	}
+
+	#[test]
+	fn parses_fields() {
+		assert!("*/15 2 * * 1-5".parse::<CronExpression>().is_ok());
+		assert!("@daily".parse::<CronExpression>().is_ok());
+
+		assert_eq!(
+			"* * *".parse::<CronExpression>(),
+			Err(CronError::WrongFieldCount(3))
+		);
+		assert!(matches!(
+			"60 * * * *".parse::<CronExpression>(),
+			Err(CronError::OutOfRange { value: 60, .. })
+		));
+		assert_eq!(
+			"*/0 * * * *".parse::<CronExpression>(),
+			Err(CronError::ZeroStep("minute"))
+		);
+	}
+
+	#[test]
+	fn next_run_on_daily_schedule() {
+		let cron = "30 3 * * *".parse::<CronExpression>().unwrap();
+
+		assert_eq!(
+			cron.next_after(local(2024, 5, 10, 1, 0)),
+			Some(local(2024, 5, 10, 3, 30))
+		);
+		assert_eq!(
+			cron.next_after(local(2024, 5, 10, 3, 30)),
+			Some(local(2024, 5, 11, 3, 30))
+		);
+	}
+
+	#[test]
+	fn next_run_on_weekly_and_monthly_schedules() {
+		// 2024-05-10 is a friday
+		let sundays = "0 12 * * 7".parse::<CronExpression>().unwrap();
+		assert_eq!(
+			sundays.next_after(local(2024, 5, 10, 0, 0)),
+			Some(local(2024, 5, 12, 12, 0))
+		);
+
+		let monthly = "@monthly".parse::<CronExpression>().unwrap();
+		assert_eq!(
+			monthly.next_after(local(2024, 12, 15, 0, 0)),
+			Some(local(2025, 1, 1, 0, 0))
+		);
+	}
+
+	#[test]
+	fn impossible_dates_never_match() {
+		let cron = "0 0 31 2 *".parse::<CronExpression>().unwrap();
+		assert_eq!(cron.next_after(local(2024, 1, 1, 0, 0)), None);
+	}
+}
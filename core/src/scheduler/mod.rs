@@ -0,0 +1,451 @@
+use crate::{
+	library::Library, location::LocationError, old_job::JobManagerError, util::MaybeUndefined,
+	workflow::WorkflowError, Node,
+};
+
+use sd_core_heavy_lifting::JobId;
+
+use sd_prisma::prisma::{job_schedule, location, workflow, SortOrder};
+use sd_utils::{from_bytes_to_uuid, uuid_to_bytes};
+
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use strum::{Display, EnumString};
+use thiserror::Error;
+use tokio::spawn;
+use tracing::{debug, error, trace};
+use uuid::Uuid;
+
+mod cron;
+mod runner;
+
+pub use cron::{CronError, CronExpression};
+pub use runner::run_now;
+
+/// Shortest interval accepted for interval based schedules
+const MIN_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Error, Debug)]
+pub enum JobSchedulerError {
+	#[error("schedule not found <id='{0}'>")]
+	NotFound(job_schedule::id::Type),
+	#[error("invalid cron expression '{expression}': {source}")]
+	InvalidCron {
+		expression: String,
+		source: CronError,
+	},
+	#[error("cron expression never matches any date: '{0}'")]
+	CronNeverMatches(String),
+	#[error("schedule interval must be at least {} seconds, received {0}", MIN_INTERVAL.as_secs())]
+	IntervalTooShort(u32),
+	#[error("scheduled jobs of kind '{0}' require a location")]
+	MissingLocation(ScheduledJobKind),
+	#[error("scheduled workflows require a workflow")]
+	MissingWorkflow,
+	#[error("unknown scheduled job kind: '{0}'")]
+	UnknownKind(String),
+	#[error("schedule has neither a cron expression nor an interval <id='{0}'>")]
+	MissingTrigger(job_schedule::id::Type),
+
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error(transparent)]
+	Location(#[from] LocationError),
+	#[error(transparent)]
+	JobSystem(#[from] sd_core_heavy_lifting::Error),
+	#[error(transparent)]
+	OldJobManager(#[from] JobManagerError),
+	#[error(transparent)]
+	Workflow(#[from] WorkflowError),
+}
+
+impl From<JobSchedulerError> for rspc::Error {
+	fn from(e: JobSchedulerError) -> Self {
+		match e {
+			JobSchedulerError::NotFound(_) => {
+				Self::with_cause(ErrorCode::NotFound, e.to_string(), e)
+			}
+
+			JobSchedulerError::InvalidCron { .. }
+			| JobSchedulerError::CronNeverMatches(_)
+			| JobSchedulerError::IntervalTooShort(_)
+			| JobSchedulerError::MissingLocation(_)
+			| JobSchedulerError::MissingWorkflow => {
+				Self::with_cause(ErrorCode::BadRequest, e.to_string(), e)
+			}
+
+			JobSchedulerError::Location(e) => e.into(),
+			JobSchedulerError::JobSystem(e) => e.into(),
+			JobSchedulerError::OldJobManager(e) => e.into(),
+			JobSchedulerError::Workflow(e) => e.into(),
+
+			_ => Self::with_cause(ErrorCode::InternalServerError, e.to_string(), e),
+		}
+	}
+}
+
+/// The jobs that can be scheduled to run periodically
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq, Display, EnumString)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ScheduledJobKind {
+	/// Reindex the whole location, followed by file identification and media processing
+	FullRescan,
+	/// Compute integrity checksums for files in the location
+	Validation,
+	/// Regenerate all thumbnails in the location
+	ThumbnailRegeneration,
+	/// Backup the whole library, doesn't require a location
+	Backup,
+	/// Run a user defined workflow, which may work on many locations
+	Workflow,
+}
+
+impl ScheduledJobKind {
+	#[must_use]
+	pub const fn requires_location(self) -> bool {
+		!matches!(self, Self::Backup | Self::Workflow)
+	}
+}
+
+/// When a schedule must run
+#[derive(Debug, Clone, Serialize, Deserialize, Type, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", tag = "type", content = "value")]
+pub enum Trigger {
+	/// Runs every given amount of seconds, counting from the previous run
+	Interval(u32),
+	/// Standard 5 fields cron expression, evaluated on the node's local time
+	Cron(String),
+}
+
+impl Trigger {
+	fn from_db(data: &job_schedule::Data) -> Result<Self, JobSchedulerError> {
+		match (&data.cron, data.interval_secs) {
+			(Some(cron), _) => Ok(Self::Cron(cron.clone())),
+			#[allow(clippy::cast_sign_loss)]
+			(None, Some(interval_secs)) => Ok(Self::Interval(interval_secs.max(0) as u32)),
+			(None, None) => Err(JobSchedulerError::MissingTrigger(data.id)),
+		}
+	}
+
+	fn to_db(&self) -> (Option<String>, Option<i32>) {
+		match self {
+			Self::Cron(cron) => (Some(cron.trim().to_string()), None),
+			Self::Interval(secs) => (None, Some(i32::try_from(*secs).unwrap_or(i32::MAX))),
+		}
+	}
+
+	/// The next time this trigger fires after `after`, also validating the trigger
+	pub fn next_run(&self, after: DateTime<Utc>) -> Result<DateTime<Utc>, JobSchedulerError> {
+		match self {
+			Self::Interval(secs) => {
+				if u64::from(*secs) < MIN_INTERVAL.as_secs() {
+					return Err(JobSchedulerError::IntervalTooShort(*secs));
+				}
+
+				Ok(after + chrono::Duration::seconds(i64::from(*secs)))
+			}
+
+			Self::Cron(expression) => expression
+				.parse::<CronExpression>()
+				.map_err(|source| JobSchedulerError::InvalidCron {
+					expression: expression.clone(),
+					source,
+				})?
+				.next_after(after)
+				.ok_or_else(|| JobSchedulerError::CronNeverMatches(expression.clone())),
+		}
+	}
+}
+
+/// A job schedule as shown to the user, with its next run time
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct JobSchedule {
+	pub id: job_schedule::id::Type,
+	pub pub_id: Uuid,
+	pub name: Option<String>,
+	pub kind: ScheduledJobKind,
+	pub trigger: Trigger,
+	pub location_id: Option<location::id::Type>,
+	pub workflow_id: Option<workflow::id::Type>,
+	pub only_on_ac_power: bool,
+	pub only_when_idle: bool,
+	pub catch_up_missed: bool,
+	pub enabled: bool,
+	pub next_run: Option<DateTime<Utc>>,
+	pub last_run: Option<DateTime<Utc>>,
+	/// Id of the last dispatched job, or of the workflow run report for workflows. Backups don't
+	/// run as jobs so they don't have one
+	pub last_job_id: Option<JobId>,
+}
+
+impl TryFrom<job_schedule::Data> for JobSchedule {
+	type Error = JobSchedulerError;
+
+	fn try_from(data: job_schedule::Data) -> Result<Self, Self::Error> {
+		let trigger = Trigger::from_db(&data)?;
+
+		Ok(Self {
+			id: data.id,
+			pub_id: from_bytes_to_uuid(&data.pub_id),
+			kind: data
+				.kind
+				.parse()
+				.map_err(|_| JobSchedulerError::UnknownKind(data.kind.clone()))?,
+			name: data.name,
+			trigger,
+			location_id: data.location_id,
+			workflow_id: data.workflow_id,
+			only_on_ac_power: data.only_on_ac_power,
+			only_when_idle: data.only_when_idle,
+			catch_up_missed: data.catch_up_missed,
+			enabled: data.enabled,
+			next_run: data.next_run.map(Into::into),
+			last_run: data.last_run.map(Into::into),
+			last_job_id: data.last_job_id.as_deref().map(from_bytes_to_uuid),
+		})
+	}
+}
+
+#[derive(Debug, Clone, Deserialize, Type)]
+pub struct JobScheduleCreateArgs {
+	pub name: Option<String>,
+	pub kind: ScheduledJobKind,
+	pub trigger: Trigger,
+	pub location_id: Option<location::id::Type>,
+	pub workflow_id: Option<workflow::id::Type>,
+	pub only_on_ac_power: bool,
+	pub only_when_idle: bool,
+	pub catch_up_missed: bool,
+}
+
+impl JobScheduleCreateArgs {
+	pub async fn create(self, library: &Library) -> Result<JobSchedule, JobSchedulerError> {
+		let Self {
+			name,
+			kind,
+			trigger,
+			location_id,
+			workflow_id,
+			only_on_ac_power,
+			only_when_idle,
+			catch_up_missed,
+		} = self;
+
+		let workflow_id = match (kind, workflow_id) {
+			(ScheduledJobKind::Workflow, None) => return Err(JobSchedulerError::MissingWorkflow),
+			(ScheduledJobKind::Workflow, Some(workflow_id)) => {
+				library
+					.db
+					.workflow()
+					.find_unique(workflow::id::equals(workflow_id))
+					.select(workflow::select!({ id }))
+					.exec()
+					.await?
+					.ok_or(WorkflowError::NotFound(workflow_id))?;
+
+				Some(workflow_id)
+			}
+			_ => None,
+		};
+
+		let location_id = match (kind.requires_location(), location_id) {
+			(true, None) => return Err(JobSchedulerError::MissingLocation(kind)),
+			(true, Some(location_id)) => {
+				library
+					.db
+					.location()
+					.find_unique(location::id::equals(location_id))
+					.select(location::select!({ id }))
+					.exec()
+					.await?
+					.ok_or(LocationError::IdNotFound(location_id))?;
+
+				Some(location_id)
+			}
+			// Location is meaningless for library wide jobs
+			(false, _) => None,
+		};
+
+		let now = Utc::now();
+		let next_run = trigger.next_run(now)?;
+		let (cron, interval_secs) = trigger.to_db();
+
+		let mut params = vec![
+			job_schedule::name::set(name),
+			job_schedule::cron::set(cron),
+			job_schedule::interval_secs::set(interval_secs),
+			job_schedule::only_on_ac_power::set(only_on_ac_power),
+			job_schedule::only_when_idle::set(only_when_idle),
+			job_schedule::catch_up_missed::set(catch_up_missed),
+			job_schedule::next_run::set(Some(next_run.into())),
+			job_schedule::date_created::set(Some(now.into())),
+		];
+
+		if let Some(location_id) = location_id {
+			params.push(job_schedule::location::connect(location::id::equals(
+				location_id,
+			)));
+		}
+
+		if let Some(workflow_id) = workflow_id {
+			params.push(job_schedule::workflow::connect(workflow::id::equals(
+				workflow_id,
+			)));
+		}
+
+		let schedule = library
+			.db
+			.job_schedule()
+			.create(uuid_to_bytes(&Uuid::now_v7()), kind.to_string(), params)
+			.exec()
+			.await?;
+
+		debug!(schedule_id = schedule.id, %kind, %next_run, "Created job schedule;");
+
+		schedule.try_into()
+	}
+}
+
+#[derive(Debug, Clone, Deserialize, Type)]
+pub struct JobScheduleUpdateArgs {
+	pub id: job_schedule::id::Type,
+	pub name: MaybeUndefined<String>,
+	pub trigger: Option<Trigger>,
+	pub only_on_ac_power: Option<bool>,
+	pub only_when_idle: Option<bool>,
+	pub catch_up_missed: Option<bool>,
+	pub enabled: Option<bool>,
+}
+
+impl JobScheduleUpdateArgs {
+	pub async fn update(self, library: &Library) -> Result<JobSchedule, JobSchedulerError> {
+		let Self {
+			id,
+			name,
+			trigger,
+			only_on_ac_power,
+			only_when_idle,
+			catch_up_missed,
+			enabled,
+		} = self;
+
+		let current = library
+			.db
+			.job_schedule()
+			.find_unique(job_schedule::id::equals(id))
+			.exec()
+			.await?
+			.ok_or(JobSchedulerError::NotFound(id))?;
+
+		let mut params = [
+			Option::<Option<String>>::from(name).map(job_schedule::name::set),
+			only_on_ac_power.map(job_schedule::only_on_ac_power::set),
+			only_when_idle.map(job_schedule::only_when_idle::set),
+			catch_up_missed.map(job_schedule::catch_up_missed::set),
+			enabled.map(job_schedule::enabled::set),
+		]
+		.into_iter()
+		.flatten()
+		.collect::<Vec<_>>();
+
+		// Changing the trigger or re-enabling the schedule restarts its countdown from now,
+		// otherwise a re-enabled schedule would run right away to catch up
+		let re_enabled = enabled == Some(true) && !current.enabled;
+		if trigger.is_some() || re_enabled {
+			let trigger = match trigger {
+				Some(trigger) => trigger,
+				None => Trigger::from_db(&current)?,
+			};
+
+			let (cron, interval_secs) = trigger.to_db();
+			params.extend([
+				job_schedule::next_run::set(Some(trigger.next_run(Utc::now())?.into())),
+				job_schedule::cron::set(cron),
+				job_schedule::interval_secs::set(interval_secs),
+			]);
+		}
+
+		library
+			.db
+			.job_schedule()
+			.update(job_schedule::id::equals(id), params)
+			.exec()
+			.await?
+			.try_into()
+	}
+}
+
+/// Background actor that dispatches scheduled jobs when they're due, for all libraries
+pub struct JobScheduler;
+
+impl JobScheduler {
+	pub fn start(node: Arc<Node>) {
+		spawn(async move {
+			let started_at = Utc::now();
+
+			while let Err(e) = spawn(runner::run(Arc::clone(&node), started_at)).await {
+				if e.is_panic() {
+					error!(?e, "Job scheduler panicked;");
+				} else {
+					trace!("Job scheduler received shutdown signal and will exit...");
+					break;
+				}
+				trace!("Restarting job scheduler processing task...");
+			}
+
+			debug!("Job scheduler gracefully shutdown");
+		});
+	}
+}
+
+pub async fn list(library: &Library) -> Result<Vec<JobSchedule>, JobSchedulerError> {
+	library
+		.db
+		.job_schedule()
+		.find_many(vec![])
+		.order_by(job_schedule::next_run::order(SortOrder::Asc))
+		.exec()
+		.await?
+		.into_iter()
+		.map(JobSchedule::try_from)
+		.collect()
+}
+
+pub async fn delete(
+	library: &Library,
+	id: job_schedule::id::Type,
+) -> Result<(), JobSchedulerError> {
+	library
+		.db
+		.job_schedule()
+		.delete_many(vec![job_schedule::id::equals(id)])
+		.exec()
+		.await
+		.map_err(Into::into)
+		.and_then(|count| {
+			if count == 0 {
+				Err(JobSchedulerError::NotFound(id))
+			} else {
+				Ok(())
+			}
+		})
+}
+
+pub(crate) async fn find(
+	library: &Library,
+	id: job_schedule::id::Type,
+) -> Result<JobSchedule, JobSchedulerError> {
+	library
+		.db
+		.job_schedule()
+		.find_unique(job_schedule::id::equals(id))
+		.exec()
+		.await?
+		.ok_or(JobSchedulerError::NotFound(id))
+		.and_then(TryInto::try_into)
+}
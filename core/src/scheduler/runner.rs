@@ -0,0 +1,294 @@
+use crate::{
+	api::backups::start_backup,
+	context::NodeContext,
+	invalidate_query,
+	library::Library,
+	location::{find_location, scan_location, LocationError, ScanState},
+	node::power,
+	object::validation::old_validator_job::OldObjectValidatorJobInit,
+	old_job::OldJob,
+	workflow::RunTrigger,
+	Node,
+};
+
+use sd_core_heavy_lifting::{media_processor::job::MediaProcessor, JobId};
+use sd_core_prisma_helpers::location_with_indexer_rules;
+
+use sd_prisma::prisma::{job_schedule, location};
+use sd_utils::uuid_to_bytes;
+
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::{debug, error, info, instrument, trace, warn};
+
+use super::{JobSchedule, JobSchedulerError, ScheduledJobKind};
+
+const TICK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Global CPU usage, in percent, below which the machine is considered idle
+const IDLE_CPU_USAGE_THRESHOLD: f32 = 25.0;
+
+pub(super) async fn run(node: Arc<Node>, started_at: DateTime<Utc>) {
+	let mut ticker = interval(TICK_INTERVAL);
+	ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+	loop {
+		ticker.tick().await;
+
+		for library in node.libraries.get_all().await {
+			if let Err(e) = run_due_schedules(&node, &library, started_at).await {
+				error!(library_id = %library.id, ?e, "Failed to run due job schedules;");
+			}
+		}
+	}
+}
+
+/// Conditions are only probed once per tick, and only if a due schedule requires them
+#[derive(Debug, Default)]
+struct ConditionsProbe {
+	on_ac_power: Option<bool>,
+	low_cpu_usage: Option<bool>,
+}
+
+impl ConditionsProbe {
+	async fn on_ac_power(&mut self) -> bool {
+		if let Some(on_ac_power) = self.on_ac_power {
+			return on_ac_power;
+		}
+
+		*self.on_ac_power.insert(power::is_on_ac_power().await)
+	}
+
+	async fn is_idle(&mut self, node: &Arc<Node>, library: &Arc<Library>) -> bool {
+		// Jobs may have been dispatched since the last check, so this part is never cached
+		let has_active_jobs = node
+			.job_system
+			.has_active_jobs(NodeContext {
+				node: Arc::clone(node),
+				library: Arc::clone(library),
+			})
+			.await || node.old_jobs.has_active_workers(library.id).await;
+
+		if has_active_jobs {
+			return false;
+		}
+
+		if let Some(low_cpu_usage) = self.low_cpu_usage {
+			return low_cpu_usage;
+		}
+
+		*self
+			.low_cpu_usage
+			.insert(power::cpu_usage().await < IDLE_CPU_USAGE_THRESHOLD)
+	}
+}
+
+#[instrument(skip_all, fields(library_id = %library.id), err)]
+async fn run_due_schedules(
+	node: &Arc<Node>,
+	library: &Arc<Library>,
+	started_at: DateTime<Utc>,
+) -> Result<(), JobSchedulerError> {
+	let now = Utc::now();
+
+	let due_schedules = library
+		.db
+		.job_schedule()
+		.find_many(vec![
+			job_schedule::enabled::equals(true),
+			job_schedule::next_run::lte(now.into()),
+		])
+		.exec()
+		.await?;
+
+	if due_schedules.is_empty() {
+		return Ok(());
+	}
+
+	let mut probe = ConditionsProbe::default();
+
+	for schedule in due_schedules {
+		let schedule_id = schedule.id;
+		let schedule = match JobSchedule::try_from(schedule) {
+			Ok(schedule) => schedule,
+			Err(e) => {
+				error!(%schedule_id, ?e, "Corrupted job schedule on database;");
+				continue;
+			}
+		};
+
+		// Runs that were due before we started were missed while the node was offline
+		if !schedule.catch_up_missed
+			&& schedule
+				.next_run
+				.is_some_and(|next_run| next_run < started_at)
+		{
+			debug!(%schedule_id, "Skipping job schedule run missed while offline;");
+			reschedule(library, &schedule, now, None).await?;
+			continue;
+		}
+
+		// Unmet conditions keep the schedule due, so it runs as soon as they're met
+		if schedule.only_on_ac_power && !probe.on_ac_power().await {
+			trace!(%schedule_id, "Postponing job schedule as we're running on battery;");
+			continue;
+		}
+
+		if schedule.only_when_idle && !probe.is_idle(node, library).await {
+			trace!(%schedule_id, "Postponing job schedule as the system is busy;");
+			continue;
+		}
+
+		match dispatch(node, library, &schedule).await {
+			Ok(maybe_job_id) => {
+				info!(%schedule_id, kind = %schedule.kind, "Dispatched scheduled job;");
+				reschedule(library, &schedule, now, Some(maybe_job_id)).await?;
+			}
+			Err(e) => {
+				// Not retrying on the next tick, otherwise a broken schedule would be dispatched forever
+				warn!(%schedule_id, ?e, "Failed to dispatch scheduled job;");
+				reschedule(library, &schedule, now, None).await?;
+			}
+		}
+	}
+
+	invalidate_query!(library, "jobs.schedules");
+
+	Ok(())
+}
+
+/// Moves the schedule to its next run, also recording the last run if it did run
+async fn reschedule(
+	library: &Library,
+	schedule: &JobSchedule,
+	now: DateTime<Utc>,
+	ran: Option<Option<JobId>>,
+) -> Result<(), JobSchedulerError> {
+	let mut params = vec![job_schedule::next_run::set(Some(
+		schedule.trigger.next_run(now)?.into(),
+	))];
+
+	if let Some(maybe_job_id) = ran {
+		params.extend([
+			job_schedule::last_run::set(Some(now.into())),
+			job_schedule::last_job_id::set(maybe_job_id.as_ref().map(uuid_to_bytes)),
+		]);
+	}
+
+	library
+		.db
+		.job_schedule()
+		.update(job_schedule::id::equals(schedule.id), params)
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+async fn dispatch(
+	node: &Arc<Node>,
+	library: &Arc<Library>,
+	schedule: &JobSchedule,
+) -> Result<Option<JobId>, JobSchedulerError> {
+	let location_id = || {
+		schedule
+			.location_id
+			.ok_or(JobSchedulerError::MissingLocation(schedule.kind))
+	};
+
+	match schedule.kind {
+		ScheduledJobKind::FullRescan => {
+			let location_id = location_id()?;
+			let location = find_location(library, location_id)
+				.include(location_with_indexer_rules::include())
+				.exec()
+				.await?
+				.ok_or(LocationError::IdNotFound(location_id))?;
+
+			let location_scan_state = ScanState::try_from(location.scan_state)?;
+
+			scan_location(node, library, location, location_scan_state)
+				.await
+				.map_err(Into::into)
+		}
+
+		ScheduledJobKind::Validation => {
+			let location = find_location_data(library, location_id()?).await?;
+
+			OldJob::new(OldObjectValidatorJobInit {
+				location,
+				sub_path: None,
+			})
+			.spawn(node, library)
+			.await?;
+
+			Ok(None)
+		}
+
+		ScheduledJobKind::ThumbnailRegeneration => {
+			let location_id = location_id()?;
+			let location = find_location_data(library, location_id).await?;
+
+			node.job_system
+				.dispatch(
+					MediaProcessor::new(location, None, true)
+						.map_err(sd_core_heavy_lifting::Error::from)?,
+					location_id,
+					NodeContext {
+						node: Arc::clone(node),
+						library: Arc::clone(library),
+					},
+				)
+				.await
+				.map(Some)
+				.map_err(|e| sd_core_heavy_lifting::Error::from(e).into())
+		}
+
+		ScheduledJobKind::Backup => {
+			// Backups don't run on the job system, so there is no job id to keep
+			start_backup(Arc::clone(node), Arc::clone(library)).await;
+
+			Ok(None)
+		}
+
+		ScheduledJobKind::Workflow => node
+			.workflows
+			.run(
+				node,
+				library,
+				schedule
+					.workflow_id
+					.ok_or(JobSchedulerError::MissingWorkflow)?,
+				RunTrigger::Schedule,
+			)
+			.await
+			.map(Some)
+			.map_err(Into::into),
+	}
+}
+
+async fn find_location_data(
+	library: &Library,
+	location_id: location::id::Type,
+) -> Result<location::Data, JobSchedulerError> {
+	find_location(library, location_id)
+		.exec()
+		.await?
+		.ok_or_else(|| LocationError::IdNotFound(location_id).into())
+}
+
+/// Dispatches the scheduled job right away, without checking its conditions, and restarts the
+/// schedule's countdown
+pub async fn run_now(
+	node: &Arc<Node>,
+	library: &Arc<Library>,
+	schedule: &JobSchedule,
+) -> Result<Option<JobId>, JobSchedulerError> {
+	let maybe_job_id = dispatch(node, library, schedule).await?;
+
+	reschedule(library, schedule, Utc::now(), Some(maybe_job_id)).await?;
+
+	Ok(maybe_job_id)
+}
@@ -1,6 +1,6 @@
 use super::{
 	error::VolumeError,
-	speed::SpeedTest,
+	speed::{SpeedTest, SpeedTestConfig, SpeedTestResult},
 	types::{Volume, VolumeEvent, VolumeOptions},
 	volumes::Volumes,
 	watcher::VolumeWatcher,
@@ -13,7 +13,7 @@ use crate::{
 };
 use async_channel as chan;
 use sd_core_sync::DevicePubId;
-use sd_prisma::prisma::volume;
+use sd_prisma::prisma::{device, volume};
 use std::{sync::Arc, time::Duration};
 use tokio::sync::{broadcast, oneshot, Mutex, RwLock};
 use tracing::{debug, error, info, trace, warn};
@@ -47,7 +47,8 @@ pub enum VolumeManagerMessage {
 	SpeedTest {
 		fingerprint: VolumeFingerprint,
 		library: Arc<Library>,
-		ack: oneshot::Sender<Result<(), VolumeError>>,
+		config: Option<SpeedTestConfig>,
+		ack: oneshot::Sender<Result<SpeedTestResult, VolumeError>>,
 	},
 	ListSystemVolumes {
 		library: Arc<Library>,
@@ -276,7 +277,6 @@ impl VolumeManagerActor {
 		&mut self,
 		library: Arc<Library>,
 	) -> Result<(), VolumeError> {
-		use sd_prisma::prisma::device;
 		let device_id = DevicePubId::from(self.ctx.device_id.clone());
 		let state = self.state.clone();
 		let state = state.write().await;
@@ -340,7 +340,8 @@ impl VolumeManagerActor {
 				let _ = ack.send(result);
 			}
 			VolumeManagerMessage::ListLibraryVolumes { library, ack } => {
-				todo!();
+				let result = self.handle_list_library_volumes(library).await;
+				let _ = ack.send(result);
 			}
 			VolumeManagerMessage::TrackVolume {
 				fingerprint,
@@ -354,7 +355,10 @@ impl VolumeManagerActor {
 				fingerprint,
 				library,
 				ack,
-			} => todo!(),
+			} => {
+				let result = self.handle_untrack_volume(library, fingerprint).await;
+				let _ = ack.send(result);
+			}
 			VolumeManagerMessage::UpdateVolume { volume, ack } => todo!(),
 			VolumeManagerMessage::MountVolume { fingerprint, ack } => todo!(),
 			VolumeManagerMessage::UnmountVolume { fingerprint, ack } => {
@@ -367,7 +371,11 @@ impl VolumeManagerActor {
 				fingerprint,
 				ack,
 				library,
-			} => todo!(),
+				config,
+			} => {
+				self.handle_speed_test(library, fingerprint, config, ack)
+					.await;
+			}
 		}
 		Ok(())
 	}
@@ -394,56 +402,77 @@ impl VolumeManagerActor {
 		self.state.read().await.volume_exists(&fingerprint).await
 	}
 
-	// async fn handle_list_library_volumes(
-	// 	&self,
-	// 	library: Arc<Library>,
-	// ) -> Result<Vec<Volume>, VolumeError> {
-	// 	let device_pub_id = self.ctx.device_id.clone();
-	// 	let mut result_volumes = Vec::new();
-
-	// 	// Get currently mounted volumes on this system
-	// 	let system_volumes = self.handle_list_system_volumes(library.clone()).await?;
-
-	// 	// Get all volumes from the library database
-	// 	let db_volumes = library
-	// 		.db
-	// 		.volume()
-	// 		.find_many(vec![])
-	// 		.exec()
-	// 		.await?
-	// 		.into_iter()
-	// 		.map(Volume::from)
-	// 		.collect::<Vec<_>>();
-
-	// 	// Create fingerprint maps - create references to avoid moving
-	// 	let system_map: HashMap<Vec<u8>, &Volume> = system_volumes
-	// 		.iter()
-	// 		.map(|v| (v.generate_fingerprint(device_pub_id.clone().into()), v))
-	// 		.collect();
-
-	// 	// First add all currently mounted volumes, merged with DB data if available
-	// 	for volume in &system_volumes {
-	// 		let fingerprint = volume.generate_fingerprint(device_pub_id.clone().into());
-	// 		if let Some(db_volume) = db_volumes
-	// 			.iter()
-	// 			.find(|v| v.generate_fingerprint(device_pub_id.clone().into()) == fingerprint)
-	// 		{
-	// 			result_volumes.push(Volume::merge_with_db_volume(&volume, db_volume));
-	// 		} else {
-	// 			result_volumes.push(volume.clone());
-	// 		}
-	// 	}
-
-	// 	// Then add any database volumes that aren't currently mounted
-	// 	for db_volume in db_volumes {
-	// 		let fingerprint = db_volume.generate_fingerprint(device_pub_id.clone().into());
-	// 		if !system_map.contains_key(&fingerprint) {
-	// 			result_volumes.push(db_volume);
-	// 		}
-	// 	}
-
-	// 	Ok(result_volumes)
-	// }
+	/// Lists the system volumes merged with the library's records of them, followed by the volumes
+	/// the library tracks which aren't mounted right now
+	async fn handle_list_library_volumes(
+		&self,
+		library: Arc<Library>,
+	) -> Result<Vec<Volume>, VolumeError> {
+		let device_pub_id = DevicePubId::from(self.ctx.device_id.clone());
+
+		let mut volumes = self
+			.handle_list_system_volumes(Arc::clone(&library))
+			.await?;
+
+		let db_device_id = library
+			.db
+			.device()
+			.find_unique(device::pub_id::equals(device_pub_id.to_db()))
+			.select(device::select!({ id }))
+			.exec()
+			.await?
+			.map(|device| device.id);
+
+		let db_volumes = library
+			.db
+			.volume()
+			.find_many(vec![])
+			.exec()
+			.await?
+			.into_iter()
+			.map(Volume::from)
+			.collect::<Vec<_>>();
+
+		for mut db_volume in db_volumes {
+			if volumes
+				.iter()
+				.any(|volume| volume.id.is_some() && volume.id == db_volume.id)
+			{
+				continue;
+			}
+
+			if db_volume.device_id.is_some() && db_volume.device_id == db_device_id {
+				// A volume of this device missing from the system ones isn't plugged in
+				db_volume.is_mounted = false;
+				db_volume.fingerprint = Some(VolumeFingerprint::new(&device_pub_id, &db_volume));
+			}
+
+			volumes.push(db_volume);
+		}
+
+		Ok(volumes)
+	}
+
+	/// The library's record of a volume of this device
+	async fn find_db_volume(
+		&self,
+		library: &Library,
+		fingerprint: &VolumeFingerprint,
+	) -> Result<Option<Volume>, VolumeError> {
+		let device_pub_id = DevicePubId::from(self.ctx.device_id.clone());
+
+		Ok(library
+			.db
+			.volume()
+			.find_many(vec![volume::device::is(vec![device::pub_id::equals(
+				device_pub_id.to_db(),
+			)])])
+			.exec()
+			.await?
+			.into_iter()
+			.map(Volume::from)
+			.find(|volume| VolumeFingerprint::new(&device_pub_id, volume) == *fingerprint))
+	}
 
 	/// When tracking a volume, we associate it with the current device in the database
 	async fn handle_track_volume(
@@ -451,31 +480,107 @@ impl VolumeManagerActor {
 		library: Arc<Library>,
 		fingerprint: VolumeFingerprint,
 	) -> Result<(), VolumeError> {
+		if let Some(db_volume) = self.find_db_volume(&library, &fingerprint).await? {
+			return Err(VolumeError::VolumeExists(db_volume.mount_point));
+		}
+
 		let state = self.state.write().await;
 		let device_pub_id = self.ctx.device_id.clone();
 
 		// Find the volume in our current system volumes
 		let mut registry = state.registry.write().await;
-		let mut volume = match registry.get_volume_mut(&fingerprint) {
+		let volume = match registry.get_volume_mut(&fingerprint) {
 			Some(v) => v.clone(),
 			None => return Err(VolumeError::InvalidFingerprint(fingerprint.clone())),
 		};
 
 		// Create in database with current device association
-		volume.create(&library.db, device_pub_id.into()).await?;
+		let created = volume.create(&library.db, device_pub_id.into()).await?;
 
-		// Spawn a background task to perform the speed test
+		// Spawn a background task to perform the speed test and save its results
 		let event_tx = self.event_tx.clone();
-		let mut volume = volume.clone();
+		let mut volume = Volume::merge_with_db(&volume, &created);
 		tokio::spawn(async move {
-			if let Err(e) = volume.speed_test(None, Some(&event_tx)).await {
-				error!(?e, "Failed to perform speed test for volume");
+			match volume.speed_test(None, Some(&event_tx)).await {
+				Ok(_) => {
+					if let Err(e) = volume.update(&library.db).await {
+						error!(?e, "Failed to save speed test results for volume");
+					}
+				}
+				Err(e) => error!(?e, "Failed to perform speed test for volume"),
 			}
 		});
 
 		Ok(())
 	}
 
+	/// Removes the library's record of a volume, the locations on it are kept but unlinked from it
+	async fn handle_untrack_volume(
+		&mut self,
+		library: Arc<Library>,
+		fingerprint: VolumeFingerprint,
+	) -> Result<(), VolumeError> {
+		let id = self
+			.find_db_volume(&library, &fingerprint)
+			.await?
+			.and_then(|volume| volume.id)
+			.ok_or(VolumeError::NotInDatabase)?;
+
+		library
+			.db
+			.volume()
+			.delete(volume::id::equals(id))
+			.exec()
+			.await?;
+
+		let state = self.state.read().await;
+		let mut registry = state.registry.write().await;
+		if let Some(volume) = registry.get_volume_mut(&fingerprint) {
+			volume.untrack();
+		}
+
+		Ok(())
+	}
+
+	/// Runs a speed test in the background, so other messages aren't held up by it, saving the
+	/// results when the library tracks the volume
+	async fn handle_speed_test(
+		&self,
+		library: Arc<Library>,
+		fingerprint: VolumeFingerprint,
+		config: Option<SpeedTestConfig>,
+		ack: oneshot::Sender<Result<SpeedTestResult, VolumeError>>,
+	) {
+		let Some(mut volume) = self.state.read().await.get_volume(&fingerprint).await else {
+			let _ = ack.send(Err(VolumeError::NotFound(fingerprint)));
+			return;
+		};
+
+		let db_volume = match self.find_db_volume(&library, &fingerprint).await {
+			Ok(db_volume) => db_volume,
+			Err(e) => {
+				let _ = ack.send(Err(e));
+				return;
+			}
+		};
+
+		let event_tx = self.event_tx.clone();
+		tokio::spawn(async move {
+			let result = match volume.speed_test(config, Some(&event_tx)).await {
+				Ok(result) => match db_volume {
+					Some(db_volume) => Volume::merge_with_db(&volume, &db_volume)
+						.update(&library.db)
+						.await
+						.map(|()| result),
+					None => Ok(result),
+				},
+				Err(e) => Err(e),
+			};
+
+			let _ = ack.send(result);
+		});
+	}
+
 	async fn handle_unmount_volume(
 		&mut self,
 		fingerprint: VolumeFingerprint,
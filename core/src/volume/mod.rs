@@ -10,6 +10,7 @@ mod os;
 mod speed;
 mod state;
 mod types;
+mod usage;
 mod volumes;
 mod watcher;
 use crate::library::LibraryManagerEvent;
@@ -18,10 +19,12 @@ use crate::util::mpscrr;
 pub use {
 	actor::VolumeManagerActor,
 	error::VolumeError,
+	speed::{SpeedTestConfig, SpeedTestResult},
 	state::VolumeManagerState,
 	types::{
 		DiskType, FileSystem, MountType, Volume, VolumeEvent, VolumeFingerprint, VolumeOptions,
 	},
+	usage::{with_library_usage, LibraryVolume, LibraryVolumeUsage},
 	volumes::Volumes,
 };
 
@@ -82,4 +85,22 @@ pub(crate) mod util {
 			None
 		}
 	}
+
+	/// The volume with the deepest mount point containing the path
+	pub(crate) fn volume_for_path<'a>(volumes: &'a [Volume], path: &Path) -> Option<&'a Volume> {
+		volumes
+			.iter()
+			.filter_map(|volume| {
+				volume
+					.mount_points
+					.iter()
+					.chain([&volume.mount_point])
+					.filter(|mount_point| path.starts_with(mount_point))
+					.map(|mount_point| mount_point.components().count())
+					.max()
+					.map(|depth| (depth, volume))
+			})
+			.max_by_key(|(depth, _)| *depth)
+			.map(|(_, volume)| volume)
+	}
 }
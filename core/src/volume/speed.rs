@@ -1,5 +1,7 @@
 use super::error::VolumeError;
 use super::types::{MountType, Volume, VolumeEvent};
+use serde::{Deserialize, Serialize};
+use specta::Type;
 use std::path::PathBuf;
 use std::time::Instant;
 use tokio::{
@@ -11,7 +13,7 @@ use tokio::{
 use tracing::{debug, error, instrument, trace};
 
 /// Configuration for speed tests
-#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize, Type, Debug, Clone)]
 pub struct SpeedTestConfig {
 	/// Size of the test file in megabytes
 	pub file_size_mb: usize,
@@ -32,7 +34,7 @@ impl Default for SpeedTestConfig {
 }
 
 /// Result of a speed test
-#[derive(Debug, Clone)]
+#[derive(Serialize, Type, Debug, Clone)]
 pub struct SpeedTestResult {
 	/// Write speed in MB/s
 	pub write_speed: f64,
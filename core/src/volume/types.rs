@@ -238,6 +238,12 @@ impl Volume {
 		self.mount_points.iter().any(|mp| path.starts_with(mp))
 	}
 
+	/// Whether the OS doesn't emit file system events for changes on this volume,
+	/// like on network shares and most FUSE mounts
+	pub fn lacks_native_events(&self) -> bool {
+		self.mount_type == MountType::Network || self.file_system.is_network_or_fuse()
+	}
+
 	/// Merge system detected volume with database volume, preferring system values for hardware info
 	pub fn merge_with_db(system_volume: &Volume, db_volume: &Volume) -> Volume {
 		Volume {
@@ -268,6 +274,14 @@ impl Volume {
 		self.pub_id.is_some()
 	}
 
+	/// Forgets the library's record of the volume, its fingerprint is left untouched so the
+	/// volume can be tracked again
+	pub fn untrack(&mut self) {
+		self.id = None;
+		self.pub_id = None;
+		self.device_id = None;
+	}
+
 	/// Creates a new volume record in the database
 	pub async fn create(
 		&self,
@@ -400,6 +414,23 @@ impl FileSystem {
 			other => FileSystem::Other(other.to_string()),
 		}
 	}
+
+	/// Network file systems and FUSE mounts (like SSHFS), changes to them made by other machines or
+	/// by the FUSE daemon itself aren't seen by inotify, FSEvents or ReadDirectoryChangesW
+	pub fn is_network_or_fuse(&self) -> bool {
+		const NETWORK_FILE_SYSTEMS: [&str; 11] = [
+			"NFS", "NFS4", "CIFS", "SMB", "SMB2", "SMBFS", "AFPFS", "WEBDAV", "DAVFS", "SSHFS",
+			"9P",
+		];
+
+		match self {
+			Self::Other(fs) => {
+				let fs = fs.to_uppercase();
+				NETWORK_FILE_SYSTEMS.contains(&fs.as_str()) || fs.starts_with("FUSE")
+			}
+			_ => false,
+		}
+	}
 }
 
 /// Represents how the volume is mounted in the system
@@ -472,3 +503,65 @@ impl<'de> Deserialize<'de> for VolumeFingerprint {
 			.map_err(serde::de::Error::custom)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn volume(mount_point: &str) -> Volume {
+		Volume::new(
+			"disk".to_string(),
+			MountType::External,
+			PathBuf::from(mount_point),
+			vec![],
+			DiskType::SSD,
+			FileSystem::APFS,
+			1000,
+			500,
+			false,
+		)
+	}
+
+	#[test]
+	fn untracked_volumes_keep_their_fingerprint() {
+		let device_pub_id = DevicePubId::new();
+		let system_volume = volume("/Volumes/disk");
+		let fingerprint = VolumeFingerprint::new(&device_pub_id, &system_volume);
+
+		let db_volume = Volume {
+			id: Some(1),
+			pub_id: Some(vec![1, 2, 3]),
+			device_id: Some(4),
+			..volume("/Volumes/disk")
+		};
+
+		let mut tracked = Volume::merge_with_db(&system_volume, &db_volume);
+		assert!(tracked.is_volume_tracked());
+		assert_eq!(
+			VolumeFingerprint::new(&device_pub_id, &tracked),
+			fingerprint
+		);
+
+		tracked.untrack();
+		assert!(!tracked.is_volume_tracked());
+		assert_eq!((tracked.id, tracked.device_id), (None, None));
+		assert_eq!(
+			VolumeFingerprint::new(&device_pub_id, &tracked),
+			fingerprint
+		);
+	}
+
+	#[test]
+	fn fingerprints_tell_volumes_and_devices_apart() {
+		let device_pub_id = DevicePubId::new();
+
+		assert_ne!(
+			VolumeFingerprint::new(&device_pub_id, &volume("/Volumes/disk")),
+			VolumeFingerprint::new(&device_pub_id, &volume("/Volumes/other"))
+		);
+		assert_ne!(
+			VolumeFingerprint::new(&device_pub_id, &volume("/Volumes/disk")),
+			VolumeFingerprint::new(&DevicePubId::new(), &volume("/Volumes/disk"))
+		);
+	}
+}
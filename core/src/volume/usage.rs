@@ -0,0 +1,173 @@
+use super::{error::VolumeError, types::Volume, util::volume_for_path};
+use crate::library::Library;
+use sd_prisma::prisma::{device, location, volume};
+use sd_utils::db::size_in_bytes_from_db;
+use serde::Serialize;
+use serde_with::{serde_as, DisplayFromStr};
+use specta::Type;
+use std::path::Path;
+
+/// How much of a volume the locations of a library take
+#[serde_as]
+#[derive(Serialize, Type, Debug, Clone, Default)]
+pub struct LibraryVolumeUsage {
+	pub location_count: u32,
+	/// Indexed size of those locations
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub size_in_bytes: u64,
+}
+
+#[derive(Serialize, Type, Debug, Clone)]
+pub struct LibraryVolume {
+	#[serde(flatten)]
+	pub volume: Volume,
+	pub usage: LibraryVolumeUsage,
+}
+
+/// Accounts the library's locations of this device to the volumes holding them. A location is
+/// matched to a mounted volume by its path, otherwise to the volume it was last seen on.
+pub async fn with_library_usage(
+	library: &Library,
+	volumes: Vec<Volume>,
+) -> Result<Vec<LibraryVolume>, VolumeError> {
+	let locations = library
+		.db
+		.location()
+		.find_many(vec![location::device::is(vec![device::pub_id::equals(
+			library.sync.device_pub_id.to_db(),
+		)])])
+		.select(location::select!({ path size_in_bytes volume_id }))
+		.exec()
+		.await?;
+
+	let usages = library_usage(
+		&volumes,
+		locations.into_iter().map(|location| {
+			(
+				location.path,
+				location
+					.size_in_bytes
+					.as_deref()
+					.map(size_in_bytes_from_db)
+					.unwrap_or_default(),
+				location.volume_id,
+			)
+		}),
+	);
+
+	Ok(volumes
+		.into_iter()
+		.zip(usages)
+		.map(|(volume, usage)| LibraryVolume { volume, usage })
+		.collect())
+}
+
+/// Usage of each volume by locations given as their path, indexed size and last seen volume
+fn library_usage(
+	volumes: &[Volume],
+	locations: impl IntoIterator<Item = (Option<String>, u64, Option<volume::id::Type>)>,
+) -> Vec<LibraryVolumeUsage> {
+	let mut usages = vec![LibraryVolumeUsage::default(); volumes.len()];
+
+	for (path, size_in_bytes, volume_id) in locations {
+		let index = path
+			.as_deref()
+			.and_then(|path| volume_for_path(volumes, Path::new(path)))
+			.filter(|volume| volume.is_mounted)
+			.and_then(|volume| volumes.iter().position(|v| std::ptr::eq(v, volume)))
+			.or_else(|| {
+				volume_id.and_then(|volume_id| {
+					volumes
+						.iter()
+						.position(|volume| volume.id == Some(volume_id))
+				})
+			});
+
+		if let Some(usage) = index.map(|index| &mut usages[index]) {
+			usage.location_count += 1;
+			usage.size_in_bytes += size_in_bytes;
+		}
+	}
+
+	usages
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{
+		super::types::{DiskType, FileSystem, MountType},
+		*,
+	};
+
+	use std::path::PathBuf;
+
+	fn volume(id: Option<i32>, mount_point: &str, is_mounted: bool) -> Volume {
+		Volume {
+			id,
+			is_mounted,
+			..Volume::new(
+				mount_point.to_string(),
+				MountType::External,
+				PathBuf::from(mount_point),
+				vec![],
+				DiskType::Unknown,
+				FileSystem::EXT4,
+				1000,
+				500,
+				false,
+			)
+		}
+	}
+
+	#[test]
+	fn locations_are_accounted_to_the_deepest_mounted_volume() {
+		let volumes = [
+			volume(Some(1), "/", true),
+			volume(Some(2), "/mnt/disk", true),
+		];
+
+		let usages = library_usage(
+			&volumes,
+			[
+				(Some("/home/user".to_string()), 10, Some(1)),
+				(Some("/mnt/disk/photos".to_string()), 20, Some(1)),
+				(Some("/mnt/disk/music".to_string()), 30, None),
+			],
+		);
+
+		assert_eq!(
+			usages
+				.iter()
+				.map(|usage| (usage.location_count, usage.size_in_bytes))
+				.collect::<Vec<_>>(),
+			[(1, 10), (2, 50)]
+		);
+	}
+
+	#[test]
+	fn locations_of_unmounted_volumes_use_their_last_volume() {
+		let volumes = [
+			volume(Some(1), "/", true),
+			volume(Some(2), "/mnt/disk", false),
+			volume(None, "/mnt/other", true),
+		];
+
+		let usages = library_usage(
+			&volumes,
+			[
+				(Some("/mnt/disk/photos".to_string()), 20, Some(2)),
+				(None, 5, Some(2)),
+				(None, 7, Some(3)),
+			],
+		);
+
+		assert_eq!(
+			usages
+				.iter()
+				.map(|usage| (usage.location_count, usage.size_in_bytes))
+				.collect::<Vec<_>>(),
+			[(0, 0), (2, 25), (0, 0)]
+		);
+	}
+}
@@ -2,6 +2,7 @@
 use super::{
 	actor::VolumeManagerMessage,
 	error::VolumeError,
+	speed::{SpeedTestConfig, SpeedTestResult},
 	types::{Volume, VolumeEvent, VolumeFingerprint},
 };
 use crate::library::Library;
@@ -110,6 +111,30 @@ impl Volumes {
 		rx.await.map_err(|_| VolumeError::Cancelled)?
 	}
 
+	/// Runs a speed test on a volume, saving its results when the library tracks the volume
+	#[instrument(skip(self, library))]
+	pub async fn speed_test(
+		&self,
+		fingerprint: VolumeFingerprint,
+		library: Arc<Library>,
+		config: Option<SpeedTestConfig>,
+	) -> Result<SpeedTestResult, VolumeError> {
+		let (tx, rx) = oneshot::channel();
+		let msg = VolumeManagerMessage::SpeedTest {
+			fingerprint,
+			library,
+			config,
+			ack: tx,
+		};
+
+		self.message_tx
+			.send(msg)
+			.await
+			.map_err(|_| VolumeError::Cancelled)?;
+
+		rx.await.map_err(|_| VolumeError::Cancelled)?
+	}
+
 	pub async fn unmount_volume(&self, fingerprint: VolumeFingerprint) -> Result<(), VolumeError> {
 		let (tx, rx) = oneshot::channel();
 		let msg = VolumeManagerMessage::UnmountVolume {
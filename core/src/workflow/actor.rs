@@ -0,0 +1,360 @@
+use crate::{invalidate_query, library::Library, Node};
+
+use sd_core_heavy_lifting::{job_system::report::Status, JobId, JobName, Report};
+
+use sd_prisma::prisma::{location, workflow};
+
+use std::{
+	collections::{HashMap, HashSet},
+	pin::pin,
+	sync::Arc,
+	time::Duration,
+};
+
+use async_channel as chan;
+use chrono::Utc;
+use futures::StreamExt;
+use futures_concurrency::stream::Merge;
+use strum::Display;
+use tokio::{
+	spawn,
+	sync::Mutex,
+	time::{interval, Instant, MissedTickBehavior},
+};
+use tokio_stream::wrappers::IntervalStream;
+use tracing::{debug, error, info, trace, warn};
+use uuid::Uuid;
+
+use super::{execution_order, runner, Workflow, WorkflowError};
+
+/// How long a watched location must go without changes before its workflows run, so a big copy
+/// into the location triggers a single run
+const CHANGES_SETTLE_TIME: Duration = Duration::from_secs(30);
+
+/// Workflows aren't triggered again for this long after a run finished, as they usually change the
+/// location they watch and would otherwise trigger themselves in a loop. Changes arriving while a
+/// run is in progress or cooling down aren't lost, the workflow runs once the cooldown ends.
+const RUN_COOLDOWN: Duration = Duration::from_secs(60);
+
+const TICK_INTERVAL: Duration = Duration::from_secs(5);
+
+const CHANGES_CHANNEL_CAPACITY: usize = 1024;
+
+/// What started a workflow run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum RunTrigger {
+	Manual,
+	Schedule,
+	LocationChange,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum RunState {
+	Running,
+	Finished(Instant),
+}
+
+impl RunState {
+	fn in_cooldown(&self) -> bool {
+		match self {
+			Self::Running => true,
+			Self::Finished(finished_at) => finished_at.elapsed() < RUN_COOLDOWN,
+		}
+	}
+}
+
+type Runs = Arc<Mutex<HashMap<(Uuid, workflow::id::Type), RunState>>>;
+
+/// Handle to start workflow runs, held by the [`Node`]
+pub struct Workflows {
+	runs: Runs,
+	changes_tx: chan::Sender<(Uuid, location::id::Type)>,
+}
+
+/// Background actor running workflows when the location they watch changes
+pub struct WorkflowsActor {
+	runs: Runs,
+	changes_rx: chan::Receiver<(Uuid, location::id::Type)>,
+}
+
+impl Workflows {
+	#[must_use]
+	pub fn new() -> (Self, WorkflowsActor) {
+		let runs = Runs::default();
+		let (changes_tx, changes_rx) = chan::bounded(CHANGES_CHANNEL_CAPACITY);
+
+		(
+			Self {
+				runs: Arc::clone(&runs),
+				changes_tx,
+			},
+			WorkflowsActor { runs, changes_rx },
+		)
+	}
+
+	/// Starts a new run of the workflow, returning the id of the run's report. Steps are executed
+	/// in the background, each one as a child job of this report.
+	///
+	/// Disabled workflows can still be run manually, they only stop being triggered automatically.
+	pub async fn run(
+		&self,
+		node: &Arc<Node>,
+		library: &Arc<Library>,
+		workflow_id: workflow::id::Type,
+		trigger: RunTrigger,
+	) -> Result<JobId, WorkflowError> {
+		let workflow = super::find(library, workflow_id).await?;
+
+		if !workflow.enabled && trigger != RunTrigger::Manual {
+			return Err(WorkflowError::Disabled(workflow_id));
+		}
+
+		start_run(&self.runs, node, library, workflow, trigger).await
+	}
+
+	/// Notifies that the location's watcher handled a change, workflows watching this location
+	/// will run once changes settle down
+	pub fn location_changed(&self, library_id: Uuid, location_id: location::id::Type) {
+		// A full channel only happens on huge bursts of changes, the location is already pending
+		if let Err(e) = self.changes_tx.try_send((library_id, location_id)) {
+			trace!(?e, "Dropped location change notification for workflows;");
+		}
+	}
+}
+
+async fn start_run(
+	runs: &Runs,
+	node: &Arc<Node>,
+	library: &Arc<Library>,
+	workflow: Workflow,
+	trigger: RunTrigger,
+) -> Result<JobId, WorkflowError> {
+	let order = execution_order(&workflow.steps)?;
+	let key = (library.id, workflow.id);
+
+	// Holding the lock while creating the report, so the same workflow can't be started twice
+	let mut runs_guard = runs.lock().await;
+
+	if let Some(RunState::Running) = runs_guard.get(&key) {
+		return Err(WorkflowError::AlreadyRunning(workflow.id));
+	}
+
+	let now = Utc::now();
+
+	let mut run_report = Report::new(JobId::now_v7(), JobName::Workflow);
+	run_report.action = Some("workflow".to_string());
+	run_report.status = Status::Running;
+	run_report.started_at = Some(now);
+	run_report.info = workflow.name.clone().unwrap_or_default();
+	run_report.create(&library.db, now).await?;
+
+	let run_id = run_report.id;
+	runs_guard.insert(key, RunState::Running);
+	drop(runs_guard);
+
+	info!(workflow_id = workflow.id, %run_id, %trigger, "Starting workflow run;");
+
+	invalidate_query!(library, "jobs.reports");
+
+	spawn({
+		let runs = Arc::clone(runs);
+		let node = Arc::clone(node);
+		let library = Arc::clone(library);
+
+		async move {
+			// A panicking run must not leave the workflow marked as running forever
+			if let Err(e) = spawn(runner::execute(node, library, workflow, order, run_report)).await
+			{
+				error!(%run_id, ?e, "Workflow run panicked;");
+			}
+
+			runs.lock()
+				.await
+				.insert(key, RunState::Finished(Instant::now()));
+		}
+	});
+
+	Ok(run_id)
+}
+
+impl WorkflowsActor {
+	pub fn start(self, node: Arc<Node>) {
+		spawn(async move {
+			let Self { runs, changes_rx } = self;
+
+			while let Err(e) = spawn(run_on_changes(
+				Arc::clone(&node),
+				Arc::clone(&runs),
+				changes_rx.clone(),
+			))
+			.await
+			{
+				if e.is_panic() {
+					error!(?e, "Workflows actor panicked;");
+				} else {
+					trace!("Workflows actor received shutdown signal and will exit...");
+					break;
+				}
+				trace!("Restarting workflows actor processing task...");
+			}
+
+			debug!("Workflows actor gracefully shutdown");
+		});
+	}
+}
+
+async fn run_on_changes(
+	node: Arc<Node>,
+	runs: Runs,
+	changes_rx: chan::Receiver<(Uuid, location::id::Type)>,
+) {
+	enum StreamMessage {
+		LocationChanged((Uuid, location::id::Type)),
+		Tick,
+	}
+
+	let mut ticker = interval(TICK_INTERVAL);
+	ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+	let mut msg_stream = pin!((
+		changes_rx.map(StreamMessage::LocationChanged),
+		IntervalStream::new(ticker).map(|_| StreamMessage::Tick),
+	)
+		.merge());
+
+	// Last change time for each changed location, waiting to settle down
+	let mut pending_locations = HashMap::new();
+	// Workflows whose location changed while they were running or cooling down
+	let mut deferred_workflows = HashSet::new();
+
+	while let Some(msg) = msg_stream.next().await {
+		match msg {
+			StreamMessage::LocationChanged(key) => {
+				pending_locations.insert(key, Instant::now());
+			}
+
+			StreamMessage::Tick => {
+				let settled = pending_locations
+					.iter()
+					.filter(|(_, last_change)| last_change.elapsed() >= CHANGES_SETTLE_TIME)
+					.map(|(key, _)| *key)
+					.collect::<Vec<_>>();
+
+				for key @ (library_id, location_id) in settled {
+					pending_locations.remove(&key);
+
+					let Some(library) = node.libraries.get_library(&library_id).await else {
+						continue;
+					};
+
+					match run_watching_workflows(&node, &runs, &library, location_id).await {
+						Ok(deferred) => deferred_workflows.extend(
+							deferred
+								.into_iter()
+								.map(|workflow_id| (library_id, workflow_id)),
+						),
+						Err(e) => {
+							error!(%library_id, %location_id, ?e, "Failed to run workflows on location changes;");
+						}
+					}
+				}
+
+				let cooled_down = {
+					let runs = runs.lock().await;
+					deferred_workflows
+						.iter()
+						.filter(|key| !runs.get(*key).is_some_and(RunState::in_cooldown))
+						.copied()
+						.collect::<Vec<_>>()
+				};
+
+				for key @ (library_id, workflow_id) in cooled_down {
+					deferred_workflows.remove(&key);
+
+					let Some(library) = node.libraries.get_library(&library_id).await else {
+						continue;
+					};
+
+					match run_deferred_workflow(&node, &runs, &library, workflow_id).await {
+						// Started manually in the meantime, so we wait for this run instead
+						Err(WorkflowError::AlreadyRunning(_)) => {
+							deferred_workflows.insert(key);
+						}
+						Err(e) => {
+							warn!(%library_id, %workflow_id, ?e, "Failed to start deferred workflow run;");
+						}
+						Ok(()) => {}
+					}
+				}
+			}
+		}
+	}
+}
+
+/// Runs the workflows watching the location, returning the ones which must wait for their cooldown
+async fn run_watching_workflows(
+	node: &Arc<Node>,
+	runs: &Runs,
+	library: &Arc<Library>,
+	location_id: location::id::Type,
+) -> Result<Vec<workflow::id::Type>, WorkflowError> {
+	let workflows = library
+		.db
+		.workflow()
+		.find_many(vec![
+			workflow::enabled::equals(true),
+			workflow::watch_location_id::equals(Some(location_id)),
+		])
+		.exec()
+		.await?;
+
+	let mut deferred = vec![];
+
+	for workflow in workflows {
+		let workflow_id = workflow.id;
+
+		if runs
+			.lock()
+			.await
+			.get(&(library.id, workflow_id))
+			.is_some_and(RunState::in_cooldown)
+		{
+			trace!(%workflow_id, "Deferring location changes for recently run workflow;");
+			deferred.push(workflow_id);
+			continue;
+		}
+
+		let result = match Workflow::try_from(workflow) {
+			Ok(workflow) => start_run(runs, node, library, workflow, RunTrigger::LocationChange)
+				.await
+				.map(|_| ()),
+			Err(e) => Err(e),
+		};
+
+		// One broken workflow must not keep the others watching the same location from running
+		if let Err(e) = result {
+			warn!(%workflow_id, ?e, "Failed to start workflow run on location changes;");
+		}
+	}
+
+	Ok(deferred)
+}
+
+/// Runs a workflow whose location changed during its cooldown, unless it stopped watching it since
+async fn run_deferred_workflow(
+	node: &Arc<Node>,
+	runs: &Runs,
+	library: &Arc<Library>,
+	workflow_id: workflow::id::Type,
+) -> Result<(), WorkflowError> {
+	let workflow = super::find(library, workflow_id).await?;
+
+	if !workflow.enabled || workflow.watch_location_id.is_none() {
+		return Ok(());
+	}
+
+	start_run(runs, node, library, workflow, RunTrigger::LocationChange)
+		.await
+		.map(|_| ())
+}
@@ -0,0 +1,147 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use thiserror::Error;
+
+use super::WorkflowStep;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum GraphError {
+	#[error("workflow must have at least one step")]
+	Empty,
+	#[error("duplicated step key: '{0}'")]
+	DuplicatedKey(String),
+	#[error("step '{step}' depends on unknown step '{dependency}'")]
+	UnknownDependency { step: String, dependency: String },
+	#[error("step '{0}' depends on itself")]
+	SelfDependency(String),
+	#[error("workflow steps have a dependency cycle between: {}", .0.join(", "))]
+	Cycle(Vec<String>),
+}
+
+/// Validates the steps dependency graph and returns the steps indexes in an order where every
+/// step comes after all its dependencies. Steps without dependencies between them keep the order
+/// in which the user declared them.
+pub fn execution_order(steps: &[WorkflowStep]) -> Result<Vec<usize>, GraphError> {
+	if steps.is_empty() {
+		return Err(GraphError::Empty);
+	}
+
+	let mut index_by_key = HashMap::with_capacity(steps.len());
+	for (idx, step) in steps.iter().enumerate() {
+		if index_by_key.insert(step.key.as_str(), idx).is_some() {
+			return Err(GraphError::DuplicatedKey(step.key.clone()));
+		}
+	}
+
+	let mut pending_dependencies = vec![0usize; steps.len()];
+	let mut dependents = vec![Vec::new(); steps.len()];
+
+	for (idx, step) in steps.iter().enumerate() {
+		// Repeated dependencies are harmless, so we just count them once
+		for dependency in step.depends_on.iter().collect::<HashSet<_>>() {
+			if *dependency == step.key {
+				return Err(GraphError::SelfDependency(step.key.clone()));
+			}
+
+			let dependency_idx = *index_by_key.get(dependency.as_str()).ok_or_else(|| {
+				GraphError::UnknownDependency {
+					step: step.key.clone(),
+					dependency: dependency.clone(),
+				}
+			})?;
+
+			pending_dependencies[idx] += 1;
+			dependents[dependency_idx].push(idx);
+		}
+	}
+
+	let mut ready = pending_dependencies
+		.iter()
+		.enumerate()
+		.filter_map(|(idx, pending)| (*pending == 0).then_some(idx))
+		.collect::<VecDeque<_>>();
+
+	let mut order = Vec::with_capacity(steps.len());
+
+	while let Some(idx) = ready.pop_front() {
+		order.push(idx);
+
+		for &dependent_idx in &dependents[idx] {
+			pending_dependencies[dependent_idx] -= 1;
+			if pending_dependencies[dependent_idx] == 0 {
+				ready.push_back(dependent_idx);
+			}
+		}
+	}
+
+	if order.len() == steps.len() {
+		Ok(order)
+	} else {
+		Err(GraphError::Cycle(
+			pending_dependencies
+				.iter()
+				.zip(steps)
+				.filter(|(pending, _)| **pending > 0)
+				.map(|(_, step)| step.key.clone())
+				.collect(),
+		))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::workflow::StepAction;
+
+	fn step(key: &str, depends_on: &[&str]) -> WorkflowStep {
+		WorkflowStep {
+			key: key.to_string(),
+			action: StepAction::Index {
+				location_id: 1,
+				sub_path: None,
+			},
+			depends_on: depends_on.iter().map(ToString::to_string).collect(),
+		}
+	}
+
+	#[test]
+	fn dependencies_run_first() {
+		let steps = [
+			step("thumbnails", &["identify"]),
+			step("index", &[]),
+			step("identify", &["index"]),
+			step("tag", &["index"]),
+		];
+
+		assert_eq!(execution_order(&steps), Ok(vec![1, 2, 3, 0]));
+	}
+
+	#[test]
+	fn invalid_graphs() {
+		assert_eq!(execution_order(&[]), Err(GraphError::Empty));
+
+		assert_eq!(
+			execution_order(&[step("a", &[]), step("a", &[])]),
+			Err(GraphError::DuplicatedKey("a".to_string()))
+		);
+
+		assert_eq!(
+			execution_order(&[step("a", &["b"])]),
+			Err(GraphError::UnknownDependency {
+				step: "a".to_string(),
+				dependency: "b".to_string()
+			})
+		);
+
+		assert_eq!(
+			execution_order(&[step("a", &["a"])]),
+			Err(GraphError::SelfDependency("a".to_string()))
+		);
+
+		assert_eq!(
+			execution_order(&[step("a", &[]), step("b", &["c"]), step("c", &["b"])]),
+			Err(GraphError::Cycle(vec!["b".to_string(), "c".to_string()]))
+		);
+	}
+}
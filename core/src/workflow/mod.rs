@@ -0,0 +1,367 @@
+use crate::{
+	library::Library, location::LocationError, old_job::JobManagerError, util::MaybeUndefined,
+};
+
+use sd_core_heavy_lifting::{image_converter::ConversionOptions, job_system::report::ReportError};
+
+use sd_prisma::prisma::{location, tag, workflow, SortOrder};
+use sd_utils::{from_bytes_to_uuid, uuid_to_bytes};
+
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use rspc::ErrorCode;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use thiserror::Error;
+use tracing::debug;
+use uuid::Uuid;
+
+mod actor;
+mod graph;
+mod runner;
+
+pub use actor::{RunTrigger, Workflows, WorkflowsActor};
+pub use graph::{execution_order, GraphError};
+
+#[derive(Error, Debug)]
+pub enum WorkflowError {
+	#[error("workflow not found <id='{0}'>")]
+	NotFound(workflow::id::Type),
+	#[error("workflow is already running <id='{0}'>")]
+	AlreadyRunning(workflow::id::Type),
+	#[error("workflow is disabled <id='{0}'>")]
+	Disabled(workflow::id::Type),
+	#[error("invalid workflow: {0}")]
+	InvalidGraph(#[from] GraphError),
+	#[error("corrupted workflow steps on database <id='{id}'>: {source}")]
+	CorruptedSteps {
+		id: workflow::id::Type,
+		source: serde_json::Error,
+	},
+	#[error("tag not found <id='{0}'>")]
+	TagNotFound(tag::id::Type),
+	#[error("local device not found in the library")]
+	MissingLocalDevice,
+	#[error("step '{0}' was skipped as some of its dependencies didn't complete")]
+	DependencyFailed(String),
+	#[error("step job finished with status {0}")]
+	JobFailed(String),
+
+	#[error("database error: {0}")]
+	Database(#[from] prisma_client_rust::QueryError),
+	#[error("failed to serialize workflow steps: {0}")]
+	Serialization(#[from] serde_json::Error),
+	#[error(transparent)]
+	Location(#[from] LocationError),
+	#[error(transparent)]
+	JobSystem(#[from] sd_core_heavy_lifting::Error),
+	#[error(transparent)]
+	OldJobManager(#[from] JobManagerError),
+	#[error(transparent)]
+	Report(#[from] ReportError),
+	#[error(transparent)]
+	Sync(#[from] sd_core_sync::Error),
+}
+
+impl From<WorkflowError> for rspc::Error {
+	fn from(e: WorkflowError) -> Self {
+		match e {
+			WorkflowError::NotFound(_) | WorkflowError::TagNotFound(_) => {
+				Self::with_cause(ErrorCode::NotFound, e.to_string(), e)
+			}
+
+			WorkflowError::AlreadyRunning(_) => {
+				Self::with_cause(ErrorCode::Conflict, e.to_string(), e)
+			}
+
+			WorkflowError::Disabled(_) | WorkflowError::InvalidGraph(_) => {
+				Self::with_cause(ErrorCode::BadRequest, e.to_string(), e)
+			}
+
+			WorkflowError::Location(e) => e.into(),
+			WorkflowError::JobSystem(e) => e.into(),
+			WorkflowError::OldJobManager(e) => e.into(),
+			WorkflowError::Report(e) => e.into(),
+
+			_ => Self::with_cause(ErrorCode::InternalServerError, e.to_string(), e),
+		}
+	}
+}
+
+/// A single node of the workflow graph, it only runs after all steps it depends on completed
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct WorkflowStep {
+	/// Unique name of the step inside its workflow, used to declare dependencies
+	pub key: String,
+	pub action: StepAction,
+	#[serde(default)]
+	pub depends_on: Vec<String>,
+}
+
+/// The job dispatched by a workflow step
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum StepAction {
+	Index {
+		location_id: location::id::Type,
+		sub_path: Option<PathBuf>,
+	},
+	IdentifyFiles {
+		location_id: location::id::Type,
+		sub_path: Option<PathBuf>,
+	},
+	ProcessMedia {
+		location_id: location::id::Type,
+		sub_path: Option<PathBuf>,
+		#[serde(default)]
+		regenerate_thumbnails: bool,
+	},
+	ConvertImages {
+		selection: FileSelection,
+		/// If not set, each converted image is saved next to its source
+		target: Option<StepTarget>,
+		options: ConversionOptions,
+	},
+	CopyFiles {
+		selection: FileSelection,
+		target: StepTarget,
+	},
+	TagFiles {
+		selection: FileSelection,
+		tag_id: tag::id::Type,
+	},
+}
+
+/// A directory inside a location, relative to the location root
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct StepTarget {
+	pub location_id: location::id::Type,
+	pub sub_path: PathBuf,
+}
+
+/// The indexed files a step works on, resolved right before the step runs so files indexed by
+/// previous steps are included
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct FileSelection {
+	pub location_id: location::id::Type,
+	/// Directory to select files from, recursively, relative to the location root
+	#[serde(default)]
+	pub sub_path: Option<PathBuf>,
+	/// Only select files with these extensions, all files are selected if empty
+	#[serde(default)]
+	pub extensions: Vec<String>,
+	/// Only select files indexed during this workflow run
+	#[serde(default)]
+	pub only_new: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct Workflow {
+	pub id: workflow::id::Type,
+	pub pub_id: Uuid,
+	pub name: Option<String>,
+	pub steps: Vec<WorkflowStep>,
+	pub enabled: bool,
+	pub watch_location_id: Option<location::id::Type>,
+	pub date_created: Option<DateTime<Utc>>,
+	pub date_modified: Option<DateTime<Utc>>,
+}
+
+impl TryFrom<workflow::Data> for Workflow {
+	type Error = WorkflowError;
+
+	fn try_from(data: workflow::Data) -> Result<Self, Self::Error> {
+		Ok(Self {
+			id: data.id,
+			pub_id: from_bytes_to_uuid(&data.pub_id),
+			steps: data
+				.steps
+				.as_deref()
+				.map(serde_json::from_slice)
+				.transpose()
+				.map_err(|source| WorkflowError::CorruptedSteps {
+					id: data.id,
+					source,
+				})?
+				.unwrap_or_default(),
+			name: data.name,
+			enabled: data.enabled,
+			watch_location_id: data.watch_location_id,
+			date_created: data.date_created.map(Into::into),
+			date_modified: data.date_modified.map(Into::into),
+		})
+	}
+}
+
+#[derive(Debug, Clone, Deserialize, Type)]
+pub struct WorkflowCreateArgs {
+	pub name: Option<String>,
+	pub steps: Vec<WorkflowStep>,
+	pub watch_location_id: Option<location::id::Type>,
+}
+
+impl WorkflowCreateArgs {
+	pub async fn create(self, library: &Library) -> Result<Workflow, WorkflowError> {
+		let Self {
+			name,
+			steps,
+			watch_location_id,
+		} = self;
+
+		execution_order(&steps)?;
+
+		let now = Utc::now();
+
+		let mut params = vec![
+			workflow::name::set(name),
+			workflow::steps::set(Some(serde_json::to_vec(&steps)?)),
+			workflow::date_created::set(Some(now.into())),
+			workflow::date_modified::set(Some(now.into())),
+		];
+
+		if let Some(location_id) = watch_location_id {
+			ensure_location_exists(library, location_id).await?;
+			params.push(workflow::watch_location::connect(location::id::equals(
+				location_id,
+			)));
+		}
+
+		let workflow = library
+			.db
+			.workflow()
+			.create(uuid_to_bytes(&Uuid::now_v7()), params)
+			.exec()
+			.await?;
+
+		debug!(
+			workflow_id = workflow.id,
+			steps_count = steps.len(),
+			"Created workflow;"
+		);
+
+		workflow.try_into()
+	}
+}
+
+#[derive(Debug, Clone, Deserialize, Type)]
+pub struct WorkflowUpdateArgs {
+	pub id: workflow::id::Type,
+	pub name: MaybeUndefined<String>,
+	pub steps: Option<Vec<WorkflowStep>>,
+	pub enabled: Option<bool>,
+	pub watch_location_id: MaybeUndefined<location::id::Type>,
+}
+
+impl WorkflowUpdateArgs {
+	pub async fn update(self, library: &Library) -> Result<Workflow, WorkflowError> {
+		let Self {
+			id,
+			name,
+			steps,
+			enabled,
+			watch_location_id,
+		} = self;
+
+		library
+			.db
+			.workflow()
+			.find_unique(workflow::id::equals(id))
+			.select(workflow::select!({ id }))
+			.exec()
+			.await?
+			.ok_or(WorkflowError::NotFound(id))?;
+
+		let mut params = vec![workflow::date_modified::set(Some(Utc::now().into()))];
+
+		if let Some(name) = Option::<Option<String>>::from(name) {
+			params.push(workflow::name::set(name));
+		}
+
+		if let Some(steps) = steps {
+			execution_order(&steps)?;
+			params.push(workflow::steps::set(Some(serde_json::to_vec(&steps)?)));
+		}
+
+		if let Some(enabled) = enabled {
+			params.push(workflow::enabled::set(enabled));
+		}
+
+		match Option::<Option<location::id::Type>>::from(watch_location_id) {
+			Some(Some(location_id)) => {
+				ensure_location_exists(library, location_id).await?;
+				params.push(workflow::watch_location::connect(location::id::equals(
+					location_id,
+				)));
+			}
+			Some(None) => params.push(workflow::watch_location::disconnect()),
+			None => {}
+		}
+
+		library
+			.db
+			.workflow()
+			.update(workflow::id::equals(id), params)
+			.exec()
+			.await?
+			.try_into()
+	}
+}
+
+async fn ensure_location_exists(
+	library: &Library,
+	location_id: location::id::Type,
+) -> Result<(), WorkflowError> {
+	library
+		.db
+		.location()
+		.find_unique(location::id::equals(location_id))
+		.select(location::select!({ id }))
+		.exec()
+		.await?
+		.ok_or(LocationError::IdNotFound(location_id))?;
+
+	Ok(())
+}
+
+pub async fn list(library: &Library) -> Result<Vec<Workflow>, WorkflowError> {
+	library
+		.db
+		.workflow()
+		.find_many(vec![])
+		.order_by(workflow::date_created::order(SortOrder::Asc))
+		.exec()
+		.await?
+		.into_iter()
+		.map(Workflow::try_from)
+		.collect()
+}
+
+pub async fn find(library: &Library, id: workflow::id::Type) -> Result<Workflow, WorkflowError> {
+	library
+		.db
+		.workflow()
+		.find_unique(workflow::id::equals(id))
+		.exec()
+		.await?
+		.ok_or(WorkflowError::NotFound(id))
+		.and_then(TryInto::try_into)
+}
+
+/// Deleting a workflow also deletes its schedules, past runs are kept on the jobs history
+pub async fn delete(library: &Library, id: workflow::id::Type) -> Result<(), WorkflowError> {
+	library
+		.db
+		.workflow()
+		.delete_many(vec![workflow::id::equals(id)])
+		.exec()
+		.await
+		.map_err(Into::into)
+		.and_then(|count| {
+			if count == 0 {
+				Err(WorkflowError::NotFound(id))
+			} else {
+				Ok(())
+			}
+		})
+}
@@ -0,0 +1,546 @@
+use crate::{
+	context::NodeContext,
+	invalidate_query,
+	library::Library,
+	location::{find_location, get_location_path_from_location_id, LocationError},
+	object::{fs::old_copy::OldFileCopierJobInit, tag::assign_to_objects},
+	old_job::OldJob,
+	Node,
+};
+
+use sd_core_file_path_helper::{ensure_sub_path_is_in_location, IsolatedFilePathData};
+use sd_core_heavy_lifting::{
+	file_identifier::FileIdentifier,
+	image_converter::{job::ConversionSources, ImageConverter, OutputDestination},
+	indexer::job::Indexer,
+	job_system::report::{ReportError, ReportInputMetadata, ReportOutputMetadata, Status},
+	media_processor::job::MediaProcessor,
+	JobEnqueuer, JobId, JobName, Report,
+};
+use sd_core_prisma_helpers::location_with_indexer_rules;
+
+use sd_prisma::prisma::{device, file_path, job, location, tag, PrismaClient};
+use sd_utils::{db::maybe_missing, u64_to_frontend};
+
+use std::{
+	collections::HashMap,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use tokio::time::{sleep, Instant};
+use tracing::{debug, error, info, instrument, warn};
+
+use super::{FileSelection, StepAction, StepTarget, Workflow, WorkflowError, WorkflowStep};
+
+/// How often we check the database for the status of the job dispatched by a step
+const STEP_JOB_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Jobs write their report as soon as they're dispatched or queued, if it doesn't show up by
+/// then, the job was lost and waiting any longer would hang the whole run
+const STEP_JOB_REPORT_TIMEOUT: Duration = Duration::from_secs(60);
+
+enum StepOutcome {
+	Completed,
+	Failed,
+	Skipped,
+}
+
+/// Runs every step in the given order, one at a time, waiting for each step's job to finish
+/// before moving on. Steps whose dependencies didn't complete are skipped, all other steps
+/// still run. The run report is finalized with the overall result.
+#[instrument(skip_all, fields(workflow_id = workflow.id, run_id = %run_report.id))]
+pub(super) async fn execute(
+	node: Arc<Node>,
+	library: Arc<Library>,
+	workflow: Workflow,
+	order: Vec<usize>,
+	mut run_report: Report,
+) {
+	let run_started_at = run_report.started_at.unwrap_or_else(Utc::now);
+
+	let mut outcomes = HashMap::with_capacity(order.len());
+	let (mut completed_steps, mut failed_steps, mut skipped_steps) = (0, 0, 0);
+
+	for (position, step_idx) in order.into_iter().enumerate() {
+		let step = &workflow.steps[step_idx];
+		// Children actions are suffixed, so they're grouped with the run report on the jobs list
+		let action = format!("workflow-{}", position + 1);
+
+		let outcome = if let Some(dependency) = step
+			.depends_on
+			.iter()
+			.find(|dependency| !matches!(outcomes.get(dependency.as_str()), Some(true)))
+		{
+			debug!(step = %step.key, %dependency, "Skipping workflow step;");
+
+			record_step_report(
+				&library.db,
+				&run_report,
+				action,
+				step,
+				Status::Canceled,
+				Some(WorkflowError::DependencyFailed(dependency.clone()).to_string()),
+				None,
+			)
+			.await;
+
+			StepOutcome::Skipped
+		} else {
+			run_step(&node, &library, &run_report, action, step, run_started_at).await
+		};
+
+		outcomes.insert(step.key.as_str(), matches!(outcome, StepOutcome::Completed));
+
+		match outcome {
+			StepOutcome::Completed => completed_steps += 1,
+			StepOutcome::Failed => failed_steps += 1,
+			StepOutcome::Skipped => skipped_steps += 1,
+		}
+
+		invalidate_query!(library, "jobs.reports");
+	}
+
+	run_report.status = if failed_steps == 0 && skipped_steps == 0 {
+		Status::Completed
+	} else {
+		run_report.critical_error = Some(format!(
+			"{failed_steps} step(s) failed and {skipped_steps} step(s) were skipped"
+		));
+		Status::Failed
+	};
+	run_report.completed_at = Some(Utc::now());
+	run_report.push_metadata(ReportOutputMetadata::Workflow {
+		completed_steps,
+		failed_steps,
+		skipped_steps,
+	});
+
+	if let Err(e) = run_report.update(&library.db).await {
+		error!(?e, "Failed to update workflow run report;");
+	}
+
+	info!(
+		completed_steps,
+		failed_steps, skipped_steps, "Workflow run finished;"
+	);
+
+	invalidate_query!(library, "jobs.reports");
+}
+
+async fn run_step(
+	node: &Arc<Node>,
+	library: &Arc<Library>,
+	run_report: &Report,
+	action: String,
+	step: &WorkflowStep,
+	run_started_at: DateTime<Utc>,
+) -> StepOutcome {
+	let result = match &step.action {
+		StepAction::TagFiles { selection, tag_id } => {
+			tag_files(library, selection, *tag_id, run_started_at)
+				.await
+				.map(|tagged_objects| {
+					StepRun::Done(ReportOutputMetadata::Tagger {
+						tag_id: *tag_id,
+						tagged_objects: u64_to_frontend(tagged_objects),
+					})
+				})
+		}
+
+		_ => dispatch_step_job(node, library, run_report.id, &action, step, run_started_at)
+			.await
+			.map(StepRun::Dispatched),
+	};
+
+	let result = match result {
+		Ok(StepRun::Dispatched(job_id)) => wait_for_job(node, &library.db, job_id).await,
+
+		Ok(StepRun::Done(metadata)) => {
+			record_step_report(
+				&library.db,
+				run_report,
+				action.clone(),
+				step,
+				Status::Completed,
+				None,
+				Some(metadata),
+			)
+			.await;
+
+			Ok(())
+		}
+
+		Err(e) => {
+			// The step never got a job, so we record its failure on a report of its own
+			record_step_report(
+				&library.db,
+				run_report,
+				action.clone(),
+				step,
+				Status::Failed,
+				Some(e.to_string()),
+				None,
+			)
+			.await;
+
+			Err(e)
+		}
+	};
+
+	match result {
+		Ok(()) => StepOutcome::Completed,
+		Err(e) => {
+			warn!(step = %step.key, ?e, "Workflow step failed;");
+			StepOutcome::Failed
+		}
+	}
+}
+
+enum StepRun {
+	Dispatched(JobId),
+	Done(ReportOutputMetadata),
+}
+
+async fn dispatch_step_job(
+	node: &Arc<Node>,
+	library: &Arc<Library>,
+	run_id: JobId,
+	action: &str,
+	step: &WorkflowStep,
+	run_started_at: DateTime<Utc>,
+) -> Result<JobId, WorkflowError> {
+	let ctx = || NodeContext {
+		node: Arc::clone(node),
+		library: Arc::clone(library),
+	};
+
+	macro_rules! dispatch {
+		($job:expr, $location_id:expr) => {
+			node.job_system
+				.dispatch(
+					JobEnqueuer::new($job)
+						.with_action(action)
+						.with_parent_id(run_id)
+						.with_metadata(ReportInputMetadata::WorkflowStep(step.key.clone())),
+					$location_id,
+					ctx(),
+				)
+				.await
+				.map_err(|e| sd_core_heavy_lifting::Error::from(e).into())
+		};
+	}
+
+	match &step.action {
+		StepAction::Index {
+			location_id,
+			sub_path,
+		} => {
+			let location = find_location(library, *location_id)
+				.include(location_with_indexer_rules::include())
+				.exec()
+				.await?
+				.ok_or(LocationError::IdNotFound(*location_id))?;
+
+			dispatch!(
+				Indexer::new(location, sub_path.clone())
+					.map_err(sd_core_heavy_lifting::Error::from)?,
+				*location_id
+			)
+		}
+
+		StepAction::IdentifyFiles {
+			location_id,
+			sub_path,
+		} => dispatch!(
+			FileIdentifier::new(
+				find_location_data(library, *location_id).await?,
+				sub_path.clone()
+			)
+			.map_err(sd_core_heavy_lifting::Error::from)?,
+			*location_id
+		),
+
+		StepAction::ProcessMedia {
+			location_id,
+			sub_path,
+			regenerate_thumbnails,
+		} => dispatch!(
+			MediaProcessor::new(
+				find_location_data(library, *location_id).await?,
+				sub_path.clone(),
+				*regenerate_thumbnails
+			)
+			.map_err(sd_core_heavy_lifting::Error::from)?,
+			*location_id
+		),
+
+		StepAction::ConvertImages {
+			selection,
+			target,
+			options,
+		} => {
+			let location = find_location_data(library, selection.location_id).await?;
+
+			let destination = if let Some(target) = target {
+				OutputDestination::Directory(resolve_target(library, target).await?)
+			} else {
+				OutputDestination::NextToSource
+			};
+
+			let file_path_ids = select_files(library, selection, run_started_at)
+				.await?
+				.into_iter()
+				.map(|file_path| file_path.id)
+				.collect();
+
+			dispatch!(
+				ImageConverter::new(
+					location,
+					ConversionSources::FilePaths(file_path_ids),
+					destination,
+					options.clone()
+				)
+				.map_err(sd_core_heavy_lifting::Error::from)?,
+				selection.location_id
+			)
+		}
+
+		StepAction::CopyFiles { selection, target } => {
+			let job = OldJob::new(OldFileCopierJobInit {
+				source_location_id: selection.location_id,
+				target_location_id: target.location_id,
+				sources_file_path_ids: select_files(library, selection, run_started_at)
+					.await?
+					.into_iter()
+					.map(|file_path| file_path.id)
+					.collect(),
+				target_location_relative_directory_path: target.sub_path.clone(),
+			})
+			.with_parent(run_id, action.to_string());
+
+			let job_id = job.id();
+			job.spawn(node, library).await?;
+
+			Ok(job_id)
+		}
+
+		StepAction::TagFiles { .. } => unreachable!("tag steps don't run as jobs"),
+	}
+}
+
+/// Waits until the job reaches a final state, paused jobs are still waited on as the user may
+/// resume them
+async fn wait_for_job(
+	node: &Arc<Node>,
+	db: &PrismaClient,
+	job_id: JobId,
+) -> Result<(), WorkflowError> {
+	let dispatched_at = Instant::now();
+
+	loop {
+		sleep(STEP_JOB_POLL_INTERVAL).await;
+
+		let maybe_status = db
+			.job()
+			.find_unique(job::id::equals(job_id.as_bytes().to_vec()))
+			.select(job::select!({ status }))
+			.exec()
+			.await?
+			.and_then(|job| job.status);
+
+		let Some(status) = maybe_status else {
+			if dispatched_at.elapsed() > STEP_JOB_REPORT_TIMEOUT
+				&& !node
+					.job_system
+					.get_active_reports()
+					.await
+					.contains_key(&job_id)
+			{
+				return Err(ReportError::MissingReport(job_id).into());
+			}
+
+			continue;
+		};
+
+		match Status::try_from(status)? {
+			Status::Completed | Status::CompletedWithErrors => return Ok(()),
+			status @ (Status::Canceled | Status::Failed) => {
+				return Err(WorkflowError::JobFailed(format!("{status:?}")))
+			}
+			Status::Queued | Status::Running | Status::Paused => {}
+		}
+	}
+}
+
+/// Records a report for a step that didn't run as a job, so every step shows up on the jobs list
+async fn record_step_report(
+	db: &PrismaClient,
+	run_report: &Report,
+	action: String,
+	step: &WorkflowStep,
+	status: Status,
+	critical_error: Option<String>,
+	output_metadata: Option<ReportOutputMetadata>,
+) {
+	let now = Utc::now();
+
+	let mut report = Report::new(JobId::now_v7(), step_job_name(&step.action));
+	report.action = Some(action);
+	report.parent_id = Some(run_report.id);
+	report.status = status;
+	report.started_at = Some(now);
+	report
+		.metadata
+		.push(ReportInputMetadata::WorkflowStep(step.key.clone()).into());
+
+	if let Err(e) = report.create(db, now).await {
+		error!(?e, step = %step.key, "Failed to create workflow step report;");
+		return;
+	}
+
+	report.critical_error = critical_error;
+	report.completed_at = Some(now);
+	if let Some(metadata) = output_metadata {
+		report.push_metadata(metadata);
+	}
+
+	if let Err(e) = report.update(db).await {
+		error!(?e, step = %step.key, "Failed to update workflow step report;");
+	}
+}
+
+const fn step_job_name(action: &StepAction) -> JobName {
+	match action {
+		StepAction::Index { .. } => JobName::Indexer,
+		StepAction::IdentifyFiles { .. } => JobName::FileIdentifier,
+		StepAction::ProcessMedia { .. } => JobName::MediaProcessor,
+		StepAction::ConvertImages { .. } => JobName::ImageConverter,
+		StepAction::CopyFiles { .. } => JobName::Copy,
+		StepAction::TagFiles { .. } => JobName::Tagger,
+	}
+}
+
+async fn find_location_data(
+	library: &Library,
+	location_id: location::id::Type,
+) -> Result<location::Data, WorkflowError> {
+	find_location(library, location_id)
+		.exec()
+		.await?
+		.ok_or_else(|| LocationError::IdNotFound(location_id).into())
+}
+
+async fn resolve_target(library: &Library, target: &StepTarget) -> Result<PathBuf, WorkflowError> {
+	let location_path = get_location_path_from_location_id(&library.db, target.location_id).await?;
+
+	ensure_sub_path_is_in_location(&location_path, &target.sub_path)
+		.await
+		.map_err(|e| LocationError::from(e).into())
+}
+
+file_path::select!(selected_file_path {
+	id
+	object: select { id pub_id }
+});
+
+/// Resolves the selection to the indexed files matching it at this moment
+async fn select_files(
+	library: &Library,
+	FileSelection {
+		location_id,
+		sub_path,
+		extensions,
+		only_new,
+	}: &FileSelection,
+	run_started_at: DateTime<Utc>,
+) -> Result<Vec<selected_file_path::Data>, WorkflowError> {
+	let mut conditions = vec![
+		file_path::location_id::equals(Some(*location_id)),
+		file_path::is_dir::equals(Some(false)),
+	];
+
+	if let Some(sub_path) = sub_path
+		.as_deref()
+		.filter(|sub_path| *sub_path != Path::new("") && *sub_path != Path::new("/"))
+	{
+		let location_path = get_location_path_from_location_id(&library.db, *location_id).await?;
+		let full_path = ensure_sub_path_is_in_location(&location_path, sub_path)
+			.await
+			.map_err(LocationError::from)?;
+
+		conditions.push(file_path::materialized_path::starts_with(
+			maybe_missing(
+				IsolatedFilePathData::new(*location_id, &location_path, &full_path, true)
+					.map_err(LocationError::from)?
+					.materialized_path_for_children(),
+				"workflow.selection.sub_path",
+			)
+			.map_err(LocationError::from)?,
+		));
+	}
+
+	if !extensions.is_empty() {
+		conditions.push(file_path::extension::in_vec(
+			extensions
+				.iter()
+				.map(|extension| extension.trim_start_matches('.').to_lowercase())
+				.collect(),
+		));
+	}
+
+	if *only_new {
+		conditions.push(file_path::date_indexed::gte(run_started_at.into()));
+	}
+
+	library
+		.db
+		.file_path()
+		.find_many(conditions)
+		.select(selected_file_path::select())
+		.exec()
+		.await
+		.map_err(Into::into)
+}
+
+/// Tags the objects of all selected files, files that weren't identified yet don't have an object
+/// so they're left out. Returns how many objects were tagged.
+async fn tag_files(
+	library: &Library,
+	selection: &FileSelection,
+	tag_id: tag::id::Type,
+	run_started_at: DateTime<Utc>,
+) -> Result<u64, WorkflowError> {
+	let tag = library
+		.db
+		.tag()
+		.find_unique(tag::id::equals(tag_id))
+		.select(tag::select!({ pub_id }))
+		.exec()
+		.await?
+		.ok_or(WorkflowError::TagNotFound(tag_id))?;
+
+	let device_id = library
+		.db
+		.device()
+		.find_unique(device::pub_id::equals(library.sync.device_pub_id.to_db()))
+		.select(device::select!({ id }))
+		.exec()
+		.await?
+		.ok_or(WorkflowError::MissingLocalDevice)?
+		.id;
+
+	let objects = select_files(library, selection, run_started_at)
+		.await?
+		.into_iter()
+		.filter_map(|file_path| file_path.object.map(|object| (object.id, object.pub_id)))
+		.collect::<HashMap<_, _>>();
+
+	let tagged_objects = objects.len() as u64;
+
+	assign_to_objects(library, (tag_id, &tag.pub_id), device_id, objects).await?;
+
+	Ok(tagged_objects)
+}
@@ -47,6 +47,13 @@ impl ExifReader {
 		})?
 	}
 
+	/// The raw TIFF encoded exif data exactly as it was found on the container, useful to
+	/// carry the whole exif block over when re-encoding an image.
+	#[must_use]
+	pub fn raw_bytes(&self) -> &[u8] {
+		self.0.buf()
+	}
+
 	pub(crate) fn get_tag_int(&self, tag: Tag) -> Option<u32> {
 		self.0
 			.get_field(tag, In::PRIMARY)
@@ -95,6 +95,12 @@ where
 		stream: &mut (impl AsyncRead + AsyncWrite + Unpin),
 		mut file: (impl AsyncBufRead + Unpin),
 	) -> Result<(), io::Error> {
+		// Empty files and directories have no data, the receiver skips them too
+		if self.reqs.requests[self.i].size == 0 {
+			self.i += 1;
+			return Ok(());
+		}
+
 		// We manually implement what is basically a `BufReader` so we have more control
 		let mut buf = vec![0u8; self.reqs.block_size.size() as usize];
 		let mut offset: u64 = 0;
@@ -146,7 +152,10 @@ where
 					return Ok(());
 				}
 				// Transfer complete
-				2 => return Ok(()),
+				2 => {
+					self.i += 1;
+					return Ok(());
+				}
 				_ => todo!(),
 			}
 		}
@@ -438,6 +447,62 @@ mod tests {
 		assert_eq!(result, Vec::<u8>::new()); // Cancelled by sender so no data
 	}
 
+	#[tokio::test]
+	async fn test_spaceblock_tree() {
+		let (mut client, mut server) = tokio::io::duplex(64);
+
+		// This is sent out of band of Spaceblock
+		let files = [
+			b"Spacedrive".to_vec(),
+			vec![],
+			vec![],
+			b"Spacedrop".to_vec(),
+		];
+		let req = SpaceblockRequests {
+			id: Uuid::new_v4(),
+			block_size: BlockSize::from_file_size(10),
+			requests: vec![
+				SpaceblockRequest {
+					name: "Shoot/a.txt".to_string(),
+					size: files[0].len() as u64,
+					range: Range::Full,
+				},
+				SpaceblockRequest::directory("Shoot/Empty"),
+				SpaceblockRequest {
+					name: "Shoot/empty.txt".to_string(),
+					size: 0,
+					range: Range::Full,
+				},
+				SpaceblockRequest {
+					name: "Shoot/Raw/b.txt".to_string(),
+					size: files[3].len() as u64,
+					range: Range::Full,
+				},
+			],
+		};
+
+		tokio::spawn({
+			let req = req.clone();
+			let files = files.clone();
+			async move {
+				let mut transfer = Transfer::new(&req, |_| {}, &Default::default());
+				for data in files {
+					transfer
+						.send(&mut client, BufReader::new(Cursor::new(data)))
+						.await
+						.unwrap();
+				}
+			}
+		});
+
+		let mut transfer = Transfer::new(&req, |_| {}, &Default::default());
+		for data in files {
+			let mut result = Vec::new();
+			transfer.receive(&mut server, &mut result).await.unwrap();
+			assert_eq!(result, data);
+		}
+	}
+
 	#[tokio::test]
 	async fn test_msg() {
 		let block = Block {
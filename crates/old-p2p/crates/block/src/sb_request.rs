@@ -69,9 +69,30 @@ pub enum SpaceblockRequestsError {
 	BlockSize(std::io::Error),
 }
 
+/// How the amount of entries is encoded, the original format only fits 255 entries in a byte, so
+/// bigger transfers use a different header, which older peers reject instead of misreading
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestsLen {
+	U8,
+	U32,
+}
+
+impl RequestsLen {
+	/// The smallest encoding fitting the requests
+	#[must_use]
+	pub fn for_requests(requests: &[SpaceblockRequest]) -> Self {
+		if requests.len() <= usize::from(u8::MAX) {
+			Self::U8
+		} else {
+			Self::U32
+		}
+	}
+}
+
 impl SpaceblockRequests {
 	pub async fn from_stream(
 		stream: &mut (impl AsyncRead + Unpin),
+		len_encoding: RequestsLen,
 	) -> Result<Self, SpaceblockRequestsError> {
 		let id = decode::uuid(stream)
 			.await
@@ -81,11 +102,12 @@ impl SpaceblockRequests {
 			.await
 			.map_err(SpaceblockRequestsError::BlockSize)?;
 
-		let size = stream
+		let size = match len_encoding {
 			// Max of 255 files in one request
-			.read_u8()
-			.await
-			.map_err(SpaceblockRequestsError::InvalidLen)?;
+			RequestsLen::U8 => stream.read_u8().await.map(u32::from),
+			RequestsLen::U32 => stream.read_u32_le().await,
+		}
+		.map_err(SpaceblockRequestsError::InvalidLen)?;
 
 		let mut requests = Vec::new();
 		for _i in 0..size {
@@ -100,21 +122,26 @@ impl SpaceblockRequests {
 	}
 
 	#[must_use]
-	pub fn to_bytes(&self) -> Vec<u8> {
+	pub fn to_bytes(&self, len_encoding: RequestsLen) -> Vec<u8> {
 		let Self {
 			id,
 			block_size,
 			requests,
 		} = self;
-		assert!(
-			requests.len() <= 255,
-			"Can't Spacedrop more than 255 files at once!"
-		);
 
 		let mut buf = vec![];
 		encode::uuid(&mut buf, id);
 		buf.append(&mut block_size.to_bytes().to_vec());
-		buf.push(requests.len() as u8);
+		match len_encoding {
+			RequestsLen::U8 => buf.push(
+				u8::try_from(requests.len()).expect("Can't Spacedrop more than 255 files at once!"),
+			),
+			RequestsLen::U32 => buf.extend_from_slice(
+				&u32::try_from(requests.len())
+					.expect("Can't Spacedrop more than 2^32 entries at once!")
+					.to_le_bytes(),
+			),
+		}
 		for request in requests {
 			buf.extend_from_slice(&request.to_bytes());
 		}
@@ -122,7 +149,11 @@ impl SpaceblockRequests {
 	}
 }
 
-/// TODO
+/// A single entry being transferred.
+///
+/// The `name` is a path relative to the destination, using `/` as separator, so directory trees
+/// can be transferred. Directories are sent as entries whose name ends with a `/` and without any
+/// data, so empty directories are preserved.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SpaceblockRequest {
 	pub name: String,
@@ -143,6 +174,25 @@ pub enum SpaceblockRequestError {
 }
 
 impl SpaceblockRequest {
+	#[must_use]
+	pub fn directory(name: impl Into<String>) -> Self {
+		let mut name = name.into();
+		if !name.ends_with('/') {
+			name.push('/');
+		}
+
+		Self {
+			name,
+			size: 0,
+			range: Range::Full,
+		}
+	}
+
+	#[must_use]
+	pub fn is_directory(&self) -> bool {
+		self.name.ends_with('/')
+	}
+
 	pub async fn from_stream(
 		stream: &mut (impl AsyncRead + Unpin),
 	) -> Result<Self, SpaceblockRequestError> {
@@ -202,8 +252,8 @@ mod tests {
 			requests: vec![],
 		};
 
-		let bytes = req.to_bytes();
-		let req2 = SpaceblockRequests::from_stream(&mut Cursor::new(bytes))
+		let bytes = req.to_bytes(RequestsLen::U8);
+		let req2 = SpaceblockRequests::from_stream(&mut Cursor::new(bytes), RequestsLen::U8)
 			.await
 			.unwrap();
 		assert_eq!(req, req2);
@@ -221,8 +271,8 @@ mod tests {
 			}],
 		};
 
-		let bytes = req.to_bytes();
-		let req2 = SpaceblockRequests::from_stream(&mut Cursor::new(bytes))
+		let bytes = req.to_bytes(RequestsLen::U8);
+		let req2 = SpaceblockRequests::from_stream(&mut Cursor::new(bytes), RequestsLen::U8)
 			.await
 			.unwrap();
 		assert_eq!(req, req2);
@@ -259,8 +309,61 @@ mod tests {
 			],
 		};
 
-		let bytes = req.to_bytes();
-		let req2 = SpaceblockRequests::from_stream(&mut Cursor::new(bytes))
+		let bytes = req.to_bytes(RequestsLen::U8);
+		let req2 = SpaceblockRequests::from_stream(&mut Cursor::new(bytes), RequestsLen::U8)
+			.await
+			.unwrap();
+		assert_eq!(req, req2);
+	}
+
+	#[tokio::test]
+	async fn test_spaceblock_requests_tree() {
+		let req = SpaceblockRequests {
+			id: Uuid::new_v4(),
+			block_size: BlockSize::from_file_size(42069),
+			requests: (0..300)
+				.map(|i| SpaceblockRequest {
+					name: format!("Shoot/Raw/{i}.cr3"),
+					size: 42069,
+					range: Range::Full,
+				})
+				.chain([SpaceblockRequest::directory("Shoot/Empty")])
+				.collect(),
+		};
+
+		let len_encoding = RequestsLen::for_requests(&req.requests);
+		assert_eq!(len_encoding, RequestsLen::U32);
+
+		let bytes = req.to_bytes(len_encoding);
+		let req2 = SpaceblockRequests::from_stream(&mut Cursor::new(bytes), len_encoding)
+			.await
+			.unwrap();
+		assert_eq!(req, req2);
+		assert!(req2.requests.last().unwrap().is_directory());
+	}
+
+	#[tokio::test]
+	async fn test_spaceblock_requests_max_original_len() {
+		let req = SpaceblockRequests {
+			id: Uuid::new_v4(),
+			block_size: BlockSize::from_file_size(42069),
+			requests: (0..255)
+				.map(|i| SpaceblockRequest {
+					name: format!("{i}.cr3"),
+					size: 42069,
+					range: Range::Full,
+				})
+				.collect(),
+		};
+
+		let len_encoding = RequestsLen::for_requests(&req.requests);
+		assert_eq!(len_encoding, RequestsLen::U8);
+
+		// Same bytes older peers send and expect
+		let bytes = req.to_bytes(len_encoding);
+		assert_eq!(bytes[16 + req.block_size.to_bytes().len()], 255);
+
+		let req2 = SpaceblockRequests::from_stream(&mut Cursor::new(bytes), len_encoding)
 			.await
 			.unwrap();
 		assert_eq!(req, req2);
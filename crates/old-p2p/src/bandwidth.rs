@@ -0,0 +1,382 @@
+//! Rate limiting of the traffic of all streams.
+//!
+//! Every stream is throttled by a global token bucket and a token bucket of its peer, in each
+//! direction. Streams are tagged with a [`TrafficClass`], and the [`Priority`] of their class
+//! decides how much of a bucket they can use. Lower priority traffic has to leave part of the
+//! bucket untouched, so higher priority traffic can still get through when the limit is reached.
+
+use std::{
+	collections::HashMap,
+	future::Future,
+	pin::Pin,
+	sync::{Arc, Mutex, PoisonError, RwLock},
+	task::{ready, Context, Poll},
+	time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::time::{sleep, Instant, Sleep};
+
+use crate::RemoteIdentity;
+
+/// The shortest time a throttled stream waits for, so it doesn't wake up for a few bytes
+const MIN_WAIT: Duration = Duration::from_millis(5);
+/// The longest time a throttled stream waits for before checking the bucket again, so a very low
+/// rate or a large debt can't overflow the wait and rate changes are picked up
+const MAX_WAIT: Duration = Duration::from_secs(1);
+
+/// What a stream is used for, set by the application once it knows it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TrafficClass {
+	Sync,
+	FileFetch,
+	Spacedrop,
+	/// Streams which haven't been tagged yet, they are small control messages most of the time
+	Other,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub enum Priority {
+	#[default]
+	High,
+	Normal,
+	Low,
+}
+
+impl Priority {
+	/// Fraction of the bucket the traffic must leave untouched
+	fn reserve(self) -> f64 {
+		match self {
+			Self::High => 0.0,
+			Self::Normal => 0.25,
+			Self::Low => 0.5,
+		}
+	}
+}
+
+/// Limits in bytes per second, `None` being unlimited. A limit of zero is treated as unlimited too
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BandwidthLimits {
+	pub upload: Option<u64>,
+	pub download: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Direction {
+	Upload,
+	Download,
+}
+
+#[derive(Debug)]
+struct BucketState {
+	rate: Option<u64>,
+	tokens: f64,
+	last_refill: Instant,
+}
+
+/// A token bucket holding up to a second worth of traffic.
+///
+/// Tokens are taken after the traffic went through, so the bucket can go into debt and the next
+/// caller waits for it to be paid back.
+#[derive(Debug)]
+struct TokenBucket(Mutex<BucketState>);
+
+impl TokenBucket {
+	fn new(rate: Option<u64>) -> Self {
+		let rate = rate.filter(|rate| *rate > 0);
+		Self(Mutex::new(BucketState {
+			rate,
+			tokens: rate.unwrap_or_default() as f64,
+			last_refill: Instant::now(),
+		}))
+	}
+
+	fn set_rate(&self, rate: Option<u64>) {
+		let rate = rate.filter(|rate| *rate > 0);
+		let mut state = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+		if state.rate == rate {
+			return;
+		}
+
+		state.tokens = match (state.rate, rate) {
+			(Some(_), Some(rate)) => state.tokens.min(rate as f64),
+			(_, rate) => rate.unwrap_or_default() as f64,
+		};
+		state.rate = rate;
+		state.last_refill = Instant::now();
+	}
+
+	/// How long to wait before traffic of the given priority can go through
+	fn wait_time(&self, priority: Priority, now: Instant) -> Option<Duration> {
+		let mut state = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+		let rate = state.rate? as f64;
+
+		let elapsed = now
+			.saturating_duration_since(state.last_refill)
+			.as_secs_f64();
+		state.tokens = (state.tokens + elapsed * rate).min(rate);
+		state.last_refill = now;
+
+		let threshold = rate * priority.reserve();
+		(state.tokens <= threshold).then(|| {
+			Duration::try_from_secs_f64((threshold - state.tokens) / rate)
+				.unwrap_or(MAX_WAIT)
+				.clamp(MIN_WAIT, MAX_WAIT)
+		})
+	}
+
+	fn consume(&self, bytes: usize) {
+		let mut state = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+		if state.rate.is_some() {
+			state.tokens -= bytes as f64;
+		}
+	}
+}
+
+#[derive(Debug)]
+struct Buckets {
+	upload: TokenBucket,
+	download: TokenBucket,
+}
+
+impl Buckets {
+	fn new(limits: BandwidthLimits) -> Self {
+		Self {
+			upload: TokenBucket::new(limits.upload),
+			download: TokenBucket::new(limits.download),
+		}
+	}
+
+	fn set_limits(&self, limits: BandwidthLimits) {
+		self.upload.set_rate(limits.upload);
+		self.download.set_rate(limits.download);
+	}
+
+	fn get(&self, direction: Direction) -> &TokenBucket {
+		match direction {
+			Direction::Upload => &self.upload,
+			Direction::Download => &self.download,
+		}
+	}
+}
+
+/// Bandwidth limits of the P2P system, shared by all streams.
+#[derive(Debug)]
+pub struct Bandwidth {
+	global: Buckets,
+	peers: RwLock<HashMap<RemoteIdentity, Arc<Buckets>>>,
+	peer_limits: RwLock<HashMap<RemoteIdentity, BandwidthLimits>>,
+	priorities: RwLock<HashMap<TrafficClass, Priority>>,
+}
+
+impl Default for Bandwidth {
+	fn default() -> Self {
+		Self {
+			global: Buckets::new(BandwidthLimits::default()),
+			peers: Default::default(),
+			peer_limits: Default::default(),
+			priorities: RwLock::new(HashMap::from([
+				(TrafficClass::Sync, Priority::High),
+				(TrafficClass::FileFetch, Priority::Normal),
+				(TrafficClass::Spacedrop, Priority::Low),
+			])),
+		}
+	}
+}
+
+impl Bandwidth {
+	/// Set the limits shared by all peers
+	pub fn set_limits(&self, limits: BandwidthLimits) {
+		self.global.set_limits(limits);
+	}
+
+	/// Set the limits of each peer, peers not in the map are unlimited
+	pub fn set_peer_limits(&self, limits: HashMap<RemoteIdentity, BandwidthLimits>) {
+		for (identity, buckets) in self
+			.peers
+			.read()
+			.unwrap_or_else(PoisonError::into_inner)
+			.iter()
+		{
+			buckets.set_limits(limits.get(identity).copied().unwrap_or_default());
+		}
+
+		*self
+			.peer_limits
+			.write()
+			.unwrap_or_else(PoisonError::into_inner) = limits;
+	}
+
+	pub fn set_priority(&self, class: TrafficClass, priority: Priority) {
+		self.priorities
+			.write()
+			.unwrap_or_else(PoisonError::into_inner)
+			.insert(class, priority);
+	}
+
+	fn priority(&self, class: TrafficClass) -> Priority {
+		self.priorities
+			.read()
+			.unwrap_or_else(PoisonError::into_inner)
+			.get(&class)
+			.copied()
+			.unwrap_or_default()
+	}
+
+	fn peer(&self, identity: RemoteIdentity) -> Arc<Buckets> {
+		if let Some(buckets) = self
+			.peers
+			.read()
+			.unwrap_or_else(PoisonError::into_inner)
+			.get(&identity)
+		{
+			return buckets.clone();
+		}
+
+		let limits = self
+			.peer_limits
+			.read()
+			.unwrap_or_else(PoisonError::into_inner)
+			.get(&identity)
+			.copied()
+			.unwrap_or_default();
+
+		self.peers
+			.write()
+			.unwrap_or_else(PoisonError::into_inner)
+			.entry(identity)
+			.or_insert_with(|| Arc::new(Buckets::new(limits)))
+			.clone()
+	}
+
+	/// Create the throttle of a new stream with the peer
+	pub(crate) fn throttle(self: &Arc<Self>, identity: RemoteIdentity) -> Throttle {
+		Throttle {
+			peer: self.peer(identity),
+			bandwidth: self.clone(),
+			class: TrafficClass::Other,
+			read_sleep: None,
+			write_sleep: None,
+		}
+	}
+}
+
+/// The state of a stream's throttling
+pub(crate) struct Throttle {
+	bandwidth: Arc<Bandwidth>,
+	peer: Arc<Buckets>,
+	pub(crate) class: TrafficClass,
+	read_sleep: Option<Pin<Box<Sleep>>>,
+	write_sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl Throttle {
+	fn poll_ready(&mut self, cx: &mut Context<'_>, direction: Direction) -> Poll<()> {
+		let slot = match direction {
+			Direction::Upload => &mut self.write_sleep,
+			Direction::Download => &mut self.read_sleep,
+		};
+
+		loop {
+			if let Some(timer) = slot {
+				ready!(timer.as_mut().poll(cx));
+				*slot = None;
+			}
+
+			let priority = self.bandwidth.priority(self.class);
+			let now = Instant::now();
+			let wait = [
+				self.bandwidth.global.get(direction),
+				self.peer.get(direction),
+			]
+			.into_iter()
+			.filter_map(|bucket| bucket.wait_time(priority, now))
+			.max();
+
+			match wait {
+				Some(wait) => *slot = Some(Box::pin(sleep(wait))),
+				None => return Poll::Ready(()),
+			}
+		}
+	}
+
+	fn consume(&self, direction: Direction, bytes: usize) {
+		self.bandwidth.global.get(direction).consume(bytes);
+		self.peer.get(direction).consume(bytes);
+	}
+
+	pub(crate) fn poll_read_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+		self.poll_ready(cx, Direction::Download)
+	}
+
+	pub(crate) fn poll_write_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+		self.poll_ready(cx, Direction::Upload)
+	}
+
+	pub(crate) fn consume_read(&self, bytes: usize) {
+		self.consume(Direction::Download, bytes);
+	}
+
+	pub(crate) fn consume_write(&self, bytes: usize) {
+		self.consume(Direction::Upload, bytes);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_token_bucket() {
+		let now = Instant::now();
+		let bucket = TokenBucket::new(Some(1000));
+
+		assert_eq!(bucket.wait_time(Priority::Low, now), None);
+
+		bucket.consume(600);
+		// Low priority traffic must leave half of the bucket untouched
+		assert!(bucket.wait_time(Priority::Low, now).is_some());
+		assert_eq!(bucket.wait_time(Priority::High, now), None);
+
+		bucket.consume(1400);
+		let wait = bucket.wait_time(Priority::High, now).unwrap();
+		assert!(wait >= Duration::from_millis(999) && wait <= Duration::from_millis(1001));
+		assert_eq!(
+			bucket.wait_time(Priority::High, now + Duration::from_millis(1100)),
+			None
+		);
+
+		bucket.set_rate(None);
+		bucket.consume(usize::MAX);
+		assert_eq!(bucket.wait_time(Priority::Low, now), None);
+	}
+
+	#[test]
+	fn zero_rate_is_unlimited() {
+		let now = Instant::now();
+		let bucket = TokenBucket::new(Some(0));
+
+		bucket.consume(1000);
+		assert_eq!(bucket.wait_time(Priority::Low, now), None);
+
+		bucket.set_rate(Some(1000));
+		bucket.set_rate(Some(0));
+		bucket.consume(1000);
+		assert_eq!(bucket.wait_time(Priority::Low, now), None);
+	}
+
+	#[test]
+	fn tiny_rate_waits_are_bounded() {
+		let now = Instant::now();
+		let bucket = TokenBucket::new(Some(1));
+
+		bucket.consume(usize::MAX);
+		assert_eq!(bucket.wait_time(Priority::High, now), Some(MAX_WAIT));
+		assert_eq!(
+			bucket.wait_time(Priority::Low, now + Duration::from_secs(10)),
+			Some(MAX_WAIT)
+		);
+	}
+}
@@ -1,6 +1,7 @@
 //! Rust Peer to Peer Networking Library
 #![warn(clippy::all, clippy::unwrap_used, clippy::panic)]
 
+mod bandwidth;
 pub(crate) mod hook;
 pub mod hooks;
 mod identity;
@@ -9,6 +10,7 @@ mod peer;
 mod smart_guards;
 mod stream;
 
+pub use bandwidth::{Bandwidth, BandwidthLimits, Priority, TrafficClass};
 pub use hook::{HookEvent, HookId, ListenerId, ShutdownGuard};
 pub use identity::{Identity, IdentityErr, RemoteIdentity};
 pub use p2p::{Listener, P2P};
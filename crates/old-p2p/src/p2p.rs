@@ -13,6 +13,7 @@ use tokio::time::timeout;
 use tracing::info;
 
 use crate::{
+	bandwidth::Bandwidth,
 	hook::{HandlerFn, Hook, HookEvent, ListenerData, ListenerId, ShutdownGuard},
 	smart_guards::SmartWriteGuard,
 	HookId, Identity, Peer, PeerConnectionCandidate, RemoteIdentity, UnicastStream,
@@ -37,6 +38,8 @@ pub struct P2P {
 	pub(crate) peers: RwLock<HashMap<RemoteIdentity, Arc<Peer>>>,
 	/// Hooks can be registered to react to state changes in the P2P system.
 	pub(crate) hooks: RwLock<StableVec<Hook>>,
+	/// Bandwidth limits applied to all streams.
+	bandwidth: Arc<Bandwidth>,
 }
 
 impl P2P {
@@ -63,6 +66,7 @@ impl P2P {
 			peers: Default::default(),
 			handler_tx,
 			hooks: Default::default(),
+			bandwidth: Default::default(),
 		})
 	}
 
@@ -71,6 +75,11 @@ impl P2P {
 		self.app_name
 	}
 
+	/// The bandwidth limits applied to all streams.
+	pub fn bandwidth(&self) -> &Arc<Bandwidth> {
+		&self.bandwidth
+	}
+
 	/// The identifier of this node that can *MUST* be kept secret.
 	/// This is a private key in crypto terms.
 	pub fn identity(&self) -> &Identity {
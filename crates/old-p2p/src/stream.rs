@@ -1,13 +1,16 @@
 use std::{
 	fmt, io,
 	pin::Pin,
-	task::{Context, Poll},
+	task::{ready, Context, Poll},
 };
 
 use sync_wrapper::SyncWrapper;
 use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
 
-use crate::RemoteIdentity;
+use crate::{
+	bandwidth::{Throttle, TrafficClass},
+	RemoteIdentity,
+};
 
 trait IoStream: AsyncRead + AsyncWrite {}
 impl<S: AsyncRead + AsyncWrite> IoStream for S {}
@@ -16,6 +19,7 @@ impl<S: AsyncRead + AsyncWrite> IoStream for S {}
 pub struct UnicastStream {
 	io: SyncWrapper<Pin<Box<dyn IoStream + Send>>>,
 	remote: RemoteIdentity,
+	throttle: Option<Throttle>,
 }
 
 impl fmt::Debug for UnicastStream {
@@ -31,6 +35,19 @@ impl UnicastStream {
 		Self {
 			io: SyncWrapper::new(Box::pin(io)),
 			remote,
+			throttle: None,
+		}
+	}
+
+	pub(crate) fn with_throttle(mut self, throttle: Throttle) -> Self {
+		self.throttle = Some(throttle);
+		self
+	}
+
+	/// Tag the stream with what it's used for, so the bandwidth limits use the priority of its class.
+	pub fn set_traffic_class(&mut self, class: TrafficClass) {
+		if let Some(throttle) = &mut self.throttle {
+			throttle.class = class;
 		}
 	}
 
@@ -50,9 +67,19 @@ impl AsyncRead for UnicastStream {
 		cx: &mut Context<'_>,
 		buf: &mut ReadBuf<'_>,
 	) -> Poll<io::Result<()>> {
-		Pin::new(&mut self.get_mut().io)
-			.get_pin_mut()
-			.poll_read(cx, buf)
+		let this = self.get_mut();
+		if let Some(throttle) = &mut this.throttle {
+			ready!(throttle.poll_read_ready(cx));
+		}
+
+		let filled = buf.filled().len();
+		ready!(Pin::new(&mut this.io).get_pin_mut().poll_read(cx, buf))?;
+
+		if let Some(throttle) = &this.throttle {
+			throttle.consume_read(buf.filled().len() - filled);
+		}
+
+		Poll::Ready(Ok(()))
 	}
 }
 
@@ -62,9 +89,18 @@ impl AsyncWrite for UnicastStream {
 		cx: &mut Context<'_>,
 		buf: &[u8],
 	) -> Poll<io::Result<usize>> {
-		Pin::new(&mut self.get_mut().io)
-			.get_pin_mut()
-			.poll_write(cx, buf)
+		let this = self.get_mut();
+		if let Some(throttle) = &mut this.throttle {
+			ready!(throttle.poll_write_ready(cx));
+		}
+
+		let written = ready!(Pin::new(&mut this.io).get_pin_mut().poll_write(cx, buf))?;
+
+		if let Some(throttle) = &this.throttle {
+			throttle.consume_write(written);
+		}
+
+		Poll::Ready(Ok(written))
 	}
 
 	fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
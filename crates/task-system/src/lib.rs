@@ -11,6 +11,7 @@
 //! - Gracefully pause and cancel tasks;
 //! - Forced abortion of tasks;
 //! - Prioritizing tasks that will suspend running tasks without priority;
+//! - Changing the number of active workers on runtime and limiting how many tasks of a group run at the same time;
 //! - When the system is shutdown, it will return all pending and running tasks to theirs dispatchers, so the user can store them on disk or any other storage to be re-dispatched later;
 //!
 //!
@@ -88,6 +89,7 @@
 #![allow(clippy::missing_errors_doc, clippy::module_name_repetitions)]
 
 mod error;
+mod limits;
 mod message;
 mod system;
 mod task;
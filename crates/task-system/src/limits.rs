@@ -0,0 +1,139 @@
+use std::{
+	collections::HashMap,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc, Mutex, PoisonError,
+	},
+};
+
+use tracing::trace;
+
+/// Limits that can be changed while the system is running, shared between the system,
+/// its dispatchers and all workers.
+///
+/// Workers with an id greater or equal than the active workers count are parked: they finish
+/// their current task and stop picking new ones, so their queued tasks get stolen by active workers.
+#[derive(Debug)]
+pub struct Limits {
+	max_workers: usize,
+	default_workers: usize,
+	active_workers: AtomicUsize,
+	groups: Mutex<HashMap<&'static str, GroupUsage>>,
+}
+
+#[derive(Debug, Default)]
+struct GroupUsage {
+	limit: Option<usize>,
+	running: usize,
+}
+
+/// Holding a permit means that a task of the group is running, the slot is released on drop
+#[derive(Debug)]
+pub struct GroupPermit {
+	limits: Arc<Limits>,
+	group: &'static str,
+}
+
+impl Limits {
+	pub fn new(max_workers: usize, default_workers: usize) -> Self {
+		let default_workers = default_workers.clamp(1, max_workers);
+
+		Self {
+			max_workers,
+			default_workers,
+			active_workers: AtomicUsize::new(default_workers),
+			groups: Mutex::default(),
+		}
+	}
+
+	pub const fn max_workers(&self) -> usize {
+		self.max_workers
+	}
+
+	pub const fn default_workers(&self) -> usize {
+		self.default_workers
+	}
+
+	pub fn active_workers(&self) -> usize {
+		self.active_workers.load(Ordering::Acquire)
+	}
+
+	/// Returns the applied count, as it's clamped between 1 and the maximum workers count
+	pub fn set_active_workers(&self, count: usize) -> usize {
+		let count = count.clamp(1, self.max_workers);
+		self.active_workers.store(count, Ordering::Release);
+		count
+	}
+
+	pub fn is_parked(&self, worker_id: usize) -> bool {
+		worker_id >= self.active_workers()
+	}
+
+	pub fn set_group_limit(&self, group: &'static str, limit: Option<usize>) {
+		let mut groups = self.groups.lock().unwrap_or_else(PoisonError::into_inner);
+
+		// A zero limit would block the group forever, so we treat it as 1
+		let limit = limit.map(|limit| limit.max(1));
+
+		trace!(%group, ?limit, "Changing concurrency limit of tasks group");
+
+		groups.entry(group).or_default().limit = limit;
+	}
+
+	pub fn group_limit(&self, group: &'static str) -> Option<usize> {
+		self.groups
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.get(group)
+			.and_then(|usage| usage.limit)
+	}
+
+	/// Tries to take a slot for a task of this group, failing if the group already
+	/// has as many running tasks as its limit
+	pub fn try_acquire(self: &Arc<Self>, group: &'static str) -> Option<GroupPermit> {
+		let mut groups = self.groups.lock().unwrap_or_else(PoisonError::into_inner);
+		let usage = groups.entry(group).or_default();
+
+		if usage.limit.is_some_and(|limit| usage.running >= limit) {
+			return None;
+		}
+
+		usage.running += 1;
+		drop(groups);
+
+		Some(GroupPermit {
+			limits: Arc::clone(self),
+			group,
+		})
+	}
+
+	/// Takes a slot for a task of this group even if the group is over its limit, used by
+	/// priority tasks as they must run right away
+	pub fn acquire(self: &Arc<Self>, group: &'static str) -> GroupPermit {
+		self.groups
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.entry(group)
+			.or_default()
+			.running += 1;
+
+		GroupPermit {
+			limits: Arc::clone(self),
+			group,
+		}
+	}
+}
+
+impl Drop for GroupPermit {
+	fn drop(&mut self) {
+		if let Some(usage) = self
+			.limits
+			.groups
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.get_mut(self.group)
+		{
+			usage.running = usage.running.saturating_sub(1);
+		}
+	}
+}
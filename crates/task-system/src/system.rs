@@ -19,6 +19,7 @@ use tracing::{error, info, instrument, trace, warn, Instrument};
 
 use super::{
 	error::{DispatcherShutdownError, RunError, SystemError},
+	limits::Limits,
 	message::SystemMessage,
 	task::{IntoTask, Task, TaskHandle, TaskId, TaskWorktable},
 	worker::{AtomicWorkerId, WorkStealer, Worker, WorkerBuilder},
@@ -33,25 +34,27 @@ pub struct System<E: RunError> {
 	workers: Arc<Vec<Worker<E>>>,
 	msgs_tx: chan::Sender<SystemMessage>,
 	dispatcher: BaseDispatcher<E>,
+	limits: Arc<Limits>,
 	handle: RefCell<Option<JoinHandle<()>>>,
 	has_shutdown: Arc<AtomicBool>,
 }
 
 impl<E: RunError> System<E> {
 	/// Created a new task system with a number of workers equal to the available parallelism in the user's machine.
+	///
+	/// Only half of them are active by default, the active count can be changed on runtime with
+	/// [`System::set_workers_count`].
 	pub fn new() -> Self {
-		// TODO: Using only the half of available cores, make this configurable on runtime in the future
-		let workers_count = usize::max(
-			std::thread::available_parallelism().map_or_else(
-				|e| {
-					error!(?e, "Failed to get available parallelism in the job system");
-					1
-				},
-				NonZeroUsize::get,
-			) / 2,
-			1,
+		let workers_count = std::thread::available_parallelism().map_or_else(
+			|e| {
+				error!(?e, "Failed to get available parallelism in the job system");
+				1
+			},
+			NonZeroUsize::get,
 		);
 
+		let limits = Arc::new(Limits::new(workers_count, workers_count / 2));
+
 		let (msgs_tx, msgs_rx) = chan::bounded(8);
 		let system_comm = SystemComm(msgs_tx.clone());
 
@@ -66,7 +69,13 @@ impl<E: RunError> System<E> {
 		let workers = Arc::new(
 			workers_builders
 				.into_iter()
-				.map(|builder| builder.build(system_comm.clone(), task_stealer.clone()))
+				.map(|builder| {
+					builder.build(
+						system_comm.clone(),
+						task_stealer.clone(),
+						Arc::clone(&limits),
+					)
+				})
 				.collect::<Vec<_>>(),
 		);
 
@@ -96,7 +105,11 @@ impl<E: RunError> System<E> {
 			}
 		});
 
-		info!(%workers_count, "Task system online!");
+		info!(
+			%workers_count,
+			active_workers_count = limits.active_workers(),
+			"Task system online!"
+		);
 
 		let has_shutdown = Arc::new(AtomicBool::new(false));
 
@@ -107,16 +120,54 @@ impl<E: RunError> System<E> {
 				workers,
 				idle_workers,
 				last_worker_id: Arc::new(AtomicWorkerId::new(0)),
+				limits: Arc::clone(&limits),
 				has_shutdown: Arc::clone(&has_shutdown),
 			},
+			limits,
 			handle: RefCell::new(Some(handle)),
 			has_shutdown,
 		}
 	}
 
-	/// Returns the number of workers in the system.
+	/// Returns the number of active workers in the system, the ones that are allowed to pick new tasks.
 	pub fn workers_count(&self) -> usize {
-		self.workers.len()
+		self.limits.active_workers()
+	}
+
+	/// Returns the maximum number of workers that can be active at the same time, which is the
+	/// available parallelism in the user's machine.
+	pub fn max_workers_count(&self) -> usize {
+		self.limits.max_workers()
+	}
+
+	/// Returns the number of active workers the system starts with, half of the available parallelism.
+	pub fn default_workers_count(&self) -> usize {
+		self.limits.default_workers()
+	}
+
+	/// Changes the number of active workers, clamped between 1 and [`System::max_workers_count`],
+	/// returning the applied count.
+	///
+	/// When reducing it, the exceeding workers finish their current task and their pending tasks are
+	/// stolen by the remaining ones, no running task is interrupted.
+	pub fn set_workers_count(&self, count: usize) -> usize {
+		let applied = self.limits.set_active_workers(count);
+		info!(
+			active_workers_count = applied,
+			"Task system workers count changed"
+		);
+		applied
+	}
+
+	/// Limits how many tasks of a [concurrency group](Task::concurrency_group) can run at the same
+	/// time, `None` removes the limit. Priority tasks ignore this limit.
+	pub fn set_concurrency_limit(&self, group: &'static str, limit: Option<usize>) {
+		self.limits.set_group_limit(group, limit);
+	}
+
+	/// Returns the current limit of running tasks for a concurrency group, if any.
+	pub fn concurrency_limit(&self, group: &'static str) -> Option<usize> {
+		self.limits.group_limit(group)
 	}
 
 	/// Dispatches a task to the system, the task will be assigned to a worker and executed as soon as possible.
@@ -559,6 +610,7 @@ pub struct BaseDispatcher<E: RunError> {
 	workers: Arc<Vec<Worker<E>>>,
 	idle_workers: Arc<Vec<AtomicBool>>,
 	last_worker_id: Arc<AtomicWorkerId>,
+	limits: Arc<Limits>,
 	has_shutdown: Arc<AtomicBool>,
 }
 
@@ -612,6 +664,7 @@ impl<E: RunError> Clone for BaseDispatcher<E> {
 			workers: Arc::clone(&self.workers),
 			idle_workers: Arc::clone(&self.idle_workers),
 			last_worker_id: Arc::clone(&self.last_worker_id),
+			limits: Arc::clone(&self.limits),
 			has_shutdown: Arc::clone(&self.has_shutdown),
 		}
 	}
@@ -629,12 +682,9 @@ impl<E: RunError> Dispatcher<E> for BaseDispatcher<E> {
 			return Err(DispatcherShutdownError(vec![task]));
 		}
 
-		let worker_id = self
-				.last_worker_id
-				.fetch_update(Ordering::Release, Ordering::Acquire, |last_worker_id| {
-					Some((last_worker_id + 1) % self.workers.len())
-				})
-				.expect("we hardcoded the update function to always return Some(next_worker_id) through dispatcher");
+		// Round robin only between active workers, parked ones would just hold the task
+		let worker_id =
+			self.last_worker_id.fetch_add(1, Ordering::AcqRel) % self.limits.active_workers();
 
 		trace!(%worker_id, task_id = %task.id(), "Dispatching task to worker");
 
@@ -655,7 +705,7 @@ impl<E: RunError> Dispatcher<E> for BaseDispatcher<E> {
 
 		let (handles, workers_ids_set) = into_tasks
 			.into_iter()
-			.zip((0..self.workers.len()).cycle())
+			.zip((0..self.limits.active_workers()).cycle())
 			.map(|(task, worker_id)| async move {
 				(self.workers[worker_id].add_task(task).await, worker_id)
 			})
@@ -674,9 +724,9 @@ impl<E: RunError> Dispatcher<E> for BaseDispatcher<E> {
 }
 
 impl<E: RunError> BaseDispatcher<E> {
-	/// Returns the number of workers in the system.
+	/// Returns the number of active workers in the system.
 	#[must_use]
 	pub fn workers_count(&self) -> usize {
-		self.workers.len()
+		self.limits.active_workers()
 	}
 }
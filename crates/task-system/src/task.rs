@@ -155,6 +155,14 @@ pub trait Task<E: RunError>: Downcast + Send + Sync + 'static {
 		None
 	}
 
+	/// Tasks can declare a concurrency group, so the amount of tasks of this group running at the same time
+	/// can be limited with [`System::set_concurrency_limit`](crate::TaskSystem::set_concurrency_limit). This is
+	/// useful for tasks that already use many threads or a lot of memory by themselves, like video decoding.
+	/// Tasks without a group are only limited by the number of active workers.
+	fn concurrency_group(&self) -> Option<&'static str> {
+		None
+	}
+
 	/// This method represent the work that should be done by the worker, it will be called by the
 	/// worker when there is a slot available in its internal queue.
 	/// We receive a `&mut self` so any internal data can be mutated on each `run` invocation.
@@ -890,6 +898,11 @@ impl<E: RunError> TaskWorkState<E> {
 	pub fn kind(&self) -> PendingTaskKind {
 		PendingTaskKind::with_priority(self.task.with_priority())
 	}
+
+	#[inline]
+	pub fn concurrency_group(&self) -> Option<&'static str> {
+		self.task.concurrency_group()
+	}
 }
 
 #[derive(Debug)]
@@ -10,6 +10,7 @@ use tracing::{error, info, instrument, trace, warn, Instrument};
 
 use super::{
 	error::{RunError, SystemError},
+	limits::Limits,
 	message::{StoleTaskMessage, TaskRunnerOutput, WorkerMessage},
 	system::SystemComm,
 	task::{
@@ -53,8 +54,17 @@ impl<E: RunError> WorkerBuilder<E> {
 		)
 	}
 
-	#[instrument(name = "task_system_worker", skip(self, system_comm, task_stealer), fields(worker_id = self.id))]
-	pub fn build(self, system_comm: SystemComm, task_stealer: WorkStealer<E>) -> Worker<E> {
+	#[instrument(
+		name = "task_system_worker",
+		skip(self, system_comm, task_stealer, limits),
+		fields(worker_id = self.id)
+	)]
+	pub fn build(
+		self,
+		system_comm: SystemComm,
+		task_stealer: WorkStealer<E>,
+		limits: Arc<Limits>,
+	) -> Worker<E> {
 		let Self {
 			id,
 			msgs_tx,
@@ -70,6 +80,7 @@ impl<E: RunError> WorkerBuilder<E> {
 					id,
 					system_comm.clone(),
 					task_stealer.clone(),
+					Arc::clone(&limits),
 					msgs_rx.clone(),
 				))
 				.await
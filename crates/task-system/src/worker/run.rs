@@ -1,4 +1,4 @@
-use std::pin::pin;
+use std::{pin::pin, sync::Arc};
 
 use async_channel as chan;
 use futures::StreamExt;
@@ -10,6 +10,7 @@ use tracing::{debug, error, instrument, trace, warn};
 use super::{
 	super::{
 		error::RunError,
+		limits::Limits,
 		message::{StoleTaskMessage, TaskOutputMessage, WorkerMessage},
 		system::SystemComm,
 	},
@@ -24,15 +25,16 @@ enum StreamMessage<E: RunError> {
 	IdleCheck,
 }
 
-#[instrument(skip(system_comm, work_stealer, msgs_rx))]
+#[instrument(skip(system_comm, work_stealer, limits, msgs_rx))]
 pub(super) async fn run<E: RunError>(
 	worker_id: WorkerId,
 	system_comm: SystemComm,
 	work_stealer: WorkStealer<E>,
+	limits: Arc<Limits>,
 	msgs_rx: chan::Receiver<WorkerMessage<E>>,
 ) {
 	let (mut runner, stole_task_rx, task_output_rx) =
-		Runner::new(worker_id, work_stealer, system_comm);
+		Runner::new(worker_id, work_stealer, system_comm, limits);
 
 	let mut idle_checker_interval = interval_at(Instant::now(), ONE_SECOND);
 	idle_checker_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
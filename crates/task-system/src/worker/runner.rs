@@ -25,6 +25,7 @@ use tracing::{debug, error, instrument, trace, warn, Instrument};
 use super::{
 	super::{
 		error::{RunError, SystemError},
+		limits::{GroupPermit, Limits},
 		message::{StoleTaskMessage, TaskOutputMessage},
 		system::SystemComm,
 		task::{
@@ -56,6 +57,13 @@ struct RunningTask {
 	id: TaskId,
 	kind: PendingTaskKind,
 	handle: JoinHandle<Result<(), Box<dyn Any + Send>>>,
+	// Released when the task stops running
+	_permit: Option<GroupPermit>,
+}
+
+enum Admission {
+	Allowed(Option<GroupPermit>),
+	Throttled,
 }
 
 enum WaitingSuspendedTask {
@@ -73,6 +81,7 @@ pub(super) struct Runner<E: RunError> {
 	worker_id: WorkerId,
 	system_comm: SystemComm,
 	work_stealer: WorkStealer<E>,
+	limits: Arc<Limits>,
 	task_kinds: HashMap<TaskId, PendingTaskKind>,
 	tasks: VecDeque<TaskWorkState<E>>,
 	paused_tasks: HashMap<TaskId, TaskWorkState<E>>,
@@ -102,6 +111,7 @@ impl<E: RunError> Runner<E> {
 		worker_id: WorkerId,
 		work_stealer: WorkStealer<E>,
 		system_comm: SystemComm,
+		limits: Arc<Limits>,
 	) -> RunnerCreate<E> {
 		let (stolen_task_tx, stolen_task_rx) = chan::bounded(2);
 		let (task_output_tx, task_output_rx) = chan::bounded(8);
@@ -111,6 +121,7 @@ impl<E: RunError> Runner<E> {
 				worker_id,
 				system_comm,
 				work_stealer,
+				limits,
 				task_kinds: HashMap::with_capacity(TASK_QUEUE_INITIAL_SIZE),
 				tasks: VecDeque::with_capacity(TASK_QUEUE_INITIAL_SIZE),
 				paused_tasks: HashMap::new(),
@@ -375,13 +386,14 @@ impl<E: RunError> Runner<E> {
 		false
 	}
 
-	#[instrument(skip(self, task_work_state))]
+	#[instrument(skip(self, task_work_state, permit))]
 	#[inline]
 	fn add_task_when_idle(
 		&mut self,
 		task_id: TaskId,
 		task_kind: PendingTaskKind,
 		task_work_state: TaskWorkState<E>,
+		permit: Option<GroupPermit>,
 	) {
 		trace!("Idle worker will process the new task");
 		let handle = self.spawn_task_runner(task_id, task_work_state);
@@ -390,6 +402,7 @@ impl<E: RunError> Runner<E> {
 			id: task_id,
 			kind: task_kind,
 			handle,
+			_permit: permit,
 		});
 
 		// Doesn't need to report working back to system as it already registered
@@ -461,8 +474,17 @@ impl<E: RunError> Runner<E> {
 		task_work_state: TaskWorkState<E>,
 	) -> TaskAddStatus {
 		if self.is_idle {
-			self.add_task_when_idle(task_id, task_kind, task_work_state);
-			TaskAddStatus::Running
+			match self.admit(task_kind, &task_work_state) {
+				Admission::Allowed(permit) => {
+					self.add_task_when_idle(task_id, task_kind, task_work_state, permit);
+					TaskAddStatus::Running
+				}
+				Admission::Throttled => {
+					trace!("Worker is idle but limits don't allow running the task now");
+					self.enqueue(task_kind, task_work_state);
+					TaskAddStatus::Enqueued
+				}
+			}
 		} else {
 			trace!("Worker is busy");
 
@@ -588,27 +610,11 @@ impl<E: RunError> Runner<E> {
 		} = self;
 
 		if is_idle {
-			trace!("Worker is idle, no tasks to shutdown");
+			trace!("Worker is idle, only throttled and paused tasks to shutdown");
 			assert!(
 				current_task_handle.is_none(),
 				"can't shutdown with a running task if we're idle"
 			);
-			assert!(
-				tasks.is_empty(),
-				"can't shutdown with pending tasks if we're idle"
-			);
-			assert!(
-				priority_tasks.is_empty(),
-				"can't shutdown with priority tasks if we're idle"
-			);
-			assert!(
-				suspended_task.is_none(),
-				"can't shutdown with a suspended task if we're idle"
-			);
-
-			paused_tasks
-				.into_values()
-				.for_each(send_shutdown_task_response);
 		} else {
 			trace!("Worker is busy, will shutdown tasks");
 
@@ -648,15 +654,16 @@ impl<E: RunError> Runner<E> {
 				)
 				.await;
 			}
-
-			priority_tasks
-				.into_iter()
-				.chain(suspended_task.into_iter())
-				.chain(paused_tasks.into_values())
-				.chain(tasks.into_iter())
-				.for_each(send_shutdown_task_response);
 		}
 
+		// Idle workers can still hold tasks that the worker limits didn't allow to run
+		priority_tasks
+			.into_iter()
+			.chain(suspended_task.into_iter())
+			.chain(paused_tasks.into_values())
+			.chain(tasks.into_iter())
+			.for_each(send_shutdown_task_response);
+
 		trace!("Worker shutdown process completed");
 
 		if tx.send(()).is_err() {
@@ -721,6 +728,78 @@ impl<E: RunError> Runner<E> {
 		}
 	}
 
+	/// Checks if the worker limits allow the task to run right now. Priority tasks always run, even on
+	/// parked workers or saturated groups, as the user is actively waiting for them.
+	fn admit(&self, task_kind: PendingTaskKind, task_work_state: &TaskWorkState<E>) -> Admission {
+		let group = task_work_state.concurrency_group();
+
+		if task_kind == PendingTaskKind::Priority {
+			return Admission::Allowed(group.map(|group| self.limits.acquire(group)));
+		}
+
+		if self.limits.is_parked(self.worker_id) {
+			return Admission::Throttled;
+		}
+
+		group.map_or(Admission::Allowed(None), |group| {
+			self.limits
+				.try_acquire(group)
+				.map_or(Admission::Throttled, |permit| {
+					Admission::Allowed(Some(permit))
+				})
+		})
+	}
+
+	fn enqueue(&mut self, task_kind: PendingTaskKind, task_work_state: TaskWorkState<E>) {
+		match task_kind {
+			PendingTaskKind::Priority => self.priority_tasks.push_back(task_work_state),
+			PendingTaskKind::Normal | PendingTaskKind::Suspended => {
+				self.tasks.push_back(task_work_state);
+			}
+		}
+	}
+
+	fn has_pending_tasks(&self) -> bool {
+		!self.tasks.is_empty() || !self.priority_tasks.is_empty() || self.suspended_task.is_some()
+	}
+
+	/// Like [`Self::get_next_task`], but skipping tasks that the worker limits don't allow to run
+	/// right now, they keep their place in the queue
+	fn get_next_runnable_task(
+		&mut self,
+	) -> Option<(PendingTaskKind, TaskWorkState<E>, Option<GroupPermit>)> {
+		if let Some(task) = self.priority_tasks.pop_front() {
+			let Admission::Allowed(permit) = self.admit(PendingTaskKind::Priority, &task) else {
+				unreachable!("priority tasks are always admitted");
+			};
+			return Some((PendingTaskKind::Priority, task, permit));
+		}
+
+		if let Some(task) = self.suspended_task.take() {
+			match self.admit(PendingTaskKind::Suspended, &task) {
+				Admission::Allowed(permit) => {
+					task.worktable.set_unpause();
+					return Some((PendingTaskKind::Suspended, task, permit));
+				}
+				Admission::Throttled => self.suspended_task = Some(task),
+			}
+		}
+
+		self.tasks
+			.iter()
+			.enumerate()
+			.find_map(
+				|(idx, task)| match self.admit(PendingTaskKind::Normal, task) {
+					Admission::Allowed(permit) => Some((idx, permit)),
+					Admission::Throttled => None,
+				},
+			)
+			.map(|(idx, permit)| {
+				let task = self.tasks.remove(idx).expect("we just checked it");
+				(PendingTaskKind::Normal, task, permit)
+			})
+	}
+
 	pub(super) fn get_next_task(&mut self) -> Option<(PendingTaskKind, TaskWorkState<E>)> {
 		if let Some(task) = self.priority_tasks.pop_front() {
 			return Some((PendingTaskKind::Priority, task));
@@ -827,7 +906,7 @@ impl<E: RunError> Runner<E> {
 			}
 		}
 
-		if let Some((next_task_kind, task_work_state)) = self.get_next_task() {
+		if let Some((next_task_kind, task_work_state, permit)) = self.get_next_runnable_task() {
 			let next_task_id = task_work_state.id();
 
 			trace!(%next_task_id, ?next_task_kind, "Dispatching next task");
@@ -838,12 +917,17 @@ impl<E: RunError> Runner<E> {
 				id: next_task_id,
 				kind: next_task_kind,
 				handle,
+				_permit: permit,
 			});
 		} else {
 			self.is_idle = true;
 			self.system_comm.idle_report(self.worker_id);
 
-			if self.current_steal_task_handle.is_none() {
+			// Parked workers don't steal, and workers holding throttled tasks have work to do later
+			if self.current_steal_task_handle.is_none()
+				&& !self.has_pending_tasks()
+				&& !self.limits.is_parked(self.worker_id)
+			{
 				self.current_steal_task_handle = Some(dispatch_steal_request(
 					self.worker_id,
 					self.work_stealer.clone(),
@@ -901,11 +985,29 @@ impl<E: RunError> Runner<E> {
 	#[instrument(skip(self))]
 	pub(super) fn idle_check(&mut self) {
 		if self.is_idle {
-			if self.current_steal_task_handle.is_none() {
+			if self.has_pending_tasks() {
+				// Limits may have changed since these tasks were throttled
+				self.run_pending_task();
+			} else if self.current_steal_task_handle.is_none()
+				&& !self.limits.is_parked(self.worker_id)
+			{
 				self.steal_attempt();
 			}
 
-			self.idle_memory_cleanup();
+			if self.is_idle && !self.has_pending_tasks() {
+				self.idle_memory_cleanup();
+			}
+		}
+	}
+
+	fn run_pending_task(&mut self) {
+		if let Some((task_kind, task_work_state, permit)) = self.get_next_runnable_task() {
+			let task_id = task_work_state.id();
+
+			trace!(%task_id, ?task_kind, "Idle worker will run a previously throttled task");
+
+			self.system_comm.working_report(self.worker_id);
+			self.add_task_when_idle(task_id, task_kind, task_work_state, permit);
 		}
 	}
 
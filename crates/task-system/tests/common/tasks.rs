@@ -1,5 +1,9 @@
 use std::{
 	future::{pending, IntoFuture},
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc,
+	},
 	time::Duration,
 };
 
@@ -339,3 +343,55 @@ impl Task<SampleError> for WaitSignalTask {
 		}
 	}
 }
+
+/// Counts how many tasks of the same group are running at the same time
+#[derive(Debug, Default)]
+pub struct GroupCounter {
+	running: AtomicUsize,
+	max_running: AtomicUsize,
+}
+
+impl GroupCounter {
+	pub fn max_running(&self) -> usize {
+		self.max_running.load(Ordering::Acquire)
+	}
+}
+
+#[derive(Debug)]
+pub struct GroupTask {
+	id: TaskId,
+	counter: Arc<GroupCounter>,
+}
+
+impl GroupTask {
+	pub fn new(counter: Arc<GroupCounter>) -> Self {
+		Self {
+			id: TaskId::new_v4(),
+			counter,
+		}
+	}
+}
+
+#[async_trait]
+impl Task<SampleError> for GroupTask {
+	fn id(&self) -> TaskId {
+		self.id
+	}
+
+	fn concurrency_group(&self) -> Option<&'static str> {
+		Some("sample_group")
+	}
+
+	async fn run(&mut self, _interrupter: &Interrupter) -> Result<ExecStatus, SampleError> {
+		let running = self.counter.running.fetch_add(1, Ordering::AcqRel) + 1;
+		self.counter
+			.max_running
+			.fetch_max(running, Ordering::AcqRel);
+
+		sleep(Duration::from_millis(20)).await;
+
+		self.counter.running.fetch_sub(1, Ordering::AcqRel);
+
+		Ok(ExecStatus::Done(TaskOutput::Empty))
+	}
+}
@@ -1,6 +1,6 @@
 use sd_task_system::{TaskHandle, TaskOutput, TaskStatus, TaskSystem};
 
-use std::{collections::VecDeque, time::Duration};
+use std::{collections::VecDeque, sync::Arc, time::Duration};
 
 use futures_concurrency::future::Join;
 use rand::Rng;
@@ -14,7 +14,8 @@ mod common;
 use common::{
 	actors::SampleActor,
 	tasks::{
-		BogusTask, BrokenTask, NeverTask, PauseOnceTask, ReadyTask, SampleError, WaitSignalTask,
+		BogusTask, BrokenTask, GroupCounter, GroupTask, NeverTask, PauseOnceTask, ReadyTask,
+		SampleError, WaitSignalTask,
 	},
 };
 
@@ -303,3 +304,55 @@ async fn steal_test() {
 
 	system.shutdown().await;
 }
+
+#[tokio::test]
+#[traced_test]
+async fn concurrency_limit_test() {
+	let system = TaskSystem::new();
+
+	system.set_workers_count(system.max_workers_count());
+	system.set_concurrency_limit("sample_group", Some(1));
+
+	let counter = Arc::new(GroupCounter::default());
+
+	let handles = system
+		.dispatch_many((0..32).map(|_| GroupTask::new(Arc::clone(&counter))))
+		.await
+		.unwrap();
+
+	handles.join().await.into_iter().for_each(|res| {
+		assert!(matches!(res, Ok(TaskStatus::Done(_))));
+	});
+
+	assert_eq!(counter.max_running(), 1);
+
+	system.shutdown().await;
+}
+
+#[tokio::test]
+#[traced_test]
+async fn workers_scaling_test() {
+	let system = TaskSystem::new();
+
+	assert_eq!(system.set_workers_count(0), 1);
+	assert_eq!(system.workers_count(), 1);
+
+	let handles = system
+		.dispatch_many((0..64).map(|_| ReadyTask::default()))
+		.await
+		.unwrap();
+
+	let max_workers_count = system.max_workers_count();
+
+	// Growing back while tasks are running must not lose any of them
+	assert_eq!(
+		system.set_workers_count(max_workers_count + 1),
+		max_workers_count
+	);
+
+	handles.join().await.into_iter().for_each(|res| {
+		assert!(matches!(res, Ok(TaskStatus::Done(_))));
+	});
+
+	system.shutdown().await;
+}
@@ -227,7 +227,7 @@ const SpacedropNodes = () => {
 			disabled={spacedrop.isPending}
 			onClick={async () => {
 				spacedrop.mutateAsync({
-					identity: id,
+					identities: [id],
 					file_path: await getPaths([...explorer.selectedItems])
 				});
 			}}
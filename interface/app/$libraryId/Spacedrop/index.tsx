@@ -100,7 +100,7 @@ export function Spacedrop({ triggerClose }: { triggerClose: () => void }) {
 
 		doSpacedrop
 			.mutateAsync({
-				identity: id,
+				identities: [id],
 				file_path: files
 			})
 			.then(() => triggerClose());
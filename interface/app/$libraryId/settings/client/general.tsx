@@ -97,7 +97,8 @@ export const Component = () => {
 				p2p_relay_disabled: null,
 				p2p_discovery: null,
 				p2p_remote_access: null,
-				p2p_manual_peers: null
+				p2p_manual_peers: null,
+				p2p_allow_untrusted_spacedrop: null
 				// image_labeler_version: value.image_labeler_version ?? null
 			});
 